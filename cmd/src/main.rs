@@ -10,11 +10,13 @@
 mod tcp;
 
 use clap::Parser;
-use context::Context;
+use common::time::Duration;
+use context::{Context, TableMeta};
 use mimalloc::MiMalloc;
 use query::QueryServer;
+use std::path::PathBuf;
 use std::sync::Arc;
-use storage::StorageServer;
+use storage::{Options, StorageServer, SyncPolicy, WalOptions};
 use tokio::runtime;
 use tracing::{debug, info};
 
@@ -36,6 +38,28 @@ struct Args {
     // HTTP Server cores.
     #[clap(long, default_value_t = default_cores())]
     server_cores: usize,
+    /// Directory to persist archived data to, data is kept in memory only if unset.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+    /// Directory of the write-ahead log, writes are not logged if unset.
+    #[clap(long)]
+    wal_dir: Option<PathBuf>,
+    /// When the write-ahead log is flushed: `always`, `never` or every given milliseconds.
+    #[clap(long, default_value = "1000")]
+    wal_sync: SyncPolicy,
+    /// Snapshot directory to restore the storage from on startup.
+    #[clap(long)]
+    restore: Option<PathBuf>,
+    /// Default retention of tables, e.g. `15d`, samples are kept forever if unset.
+    #[clap(long)]
+    retention: Option<Duration>,
+    /// File declaring table metadata, `[table]` headers followed by `key = value` lines.
+    #[clap(long)]
+    table_config: Option<PathBuf>,
+    /// Bytes of memory tables may use, mutable chunks are archived early and writes are
+    /// rejected over it.
+    #[clap(long)]
+    memory_limit: Option<usize>,
 }
 
 fn default_cores() -> usize {
@@ -53,7 +77,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Storage component uses {} cores", args.storage_cores);
 
     let cores = (0..args.storage_cores).map(|id| id * 2).collect::<Vec<_>>();
-    let storage = Arc::new(StorageServer::new(&cores, Arc::new(Context::new())));
+    let options = Options {
+        data_dir: args.data_dir,
+        wal: args.wal_dir.map(|dir| WalOptions {
+            sync: args.wal_sync,
+            ..WalOptions::new(dir)
+        }),
+        restore: args.restore,
+        expire_interval: None,
+        memory_limit: args.memory_limit,
+    };
+    let context = Arc::new(Context::new());
+    context.set_default_retention(args.retention);
+    let storage = Arc::new(StorageServer::with_options(
+        &cores,
+        Arc::clone(&context),
+        options,
+    ));
+    // declared after the storage restored its tables, to take precedence over persisted ones
+    if let Some(path) = args.table_config {
+        for (name, config) in TableMeta::parse_config(&std::fs::read_to_string(path)?)? {
+            context
+                .configure_table(&name, &config)
+                .map_err(|err| format!("table {:?}: {}", name, err))?;
+        }
+    }
 
     let runtime = runtime::Builder::new_multi_thread()
         .worker_threads(args.server_cores)
@@ -67,6 +115,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 addr,
                 Arc::clone(&storage),
                 Arc::new(QueryServer::new(Arc::clone(&storage))),
+                context,
             )
             .await?,
         );
@@ -1,3 +1,8 @@
+use common::time::Instant;
+use context::{Context, TableMeta};
+use futures::{Stream, StreamExt};
+use ql::promql::parse;
+use ql::rosetta::{Node, Range};
 use query::QueryServer;
 use std::io;
 use std::io::ErrorKind;
@@ -5,17 +10,40 @@ use std::sync::Arc;
 use storage::StorageServer;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 const MAGIC_CODE: u64 = 0x9d2bd00b191c59e9;
 
 const MAX_MESSAGE_SIZE: u64 = 1 << 16;
 
+// A connection starts with `MAGIC_CODE`, followed by requests of an op code (u16), the length of
+// the body (u64) and the body. Integers are big endian and lengths count bytes.
+//
+// | op | request                       | reply                                             |
+// |----|-------------------------------|---------------------------------------------------|
+// | 0  | flatbuffers `WriteRequest`    | none                                              |
+// | 1  | flatbuffers `QueryRequest`    | length, Arrow IPC file of the result              |
+// | 2  | table config                  | length, error message                             |
+// | 3  | range and selector            | deleted samples (u64), length, error message      |
+// | 4  | empty                         | length, memory report                             |
+// | 5  | empty                         | length, metrics report                            |
+// | 6  | flatbuffers `QueryRequest`    | pieces of the Arrow IPC file of the result as it  |
+// |    |                               | is evaluated, each a length and the bytes, ended  |
+// |    |                               | by a zero length                                  |
+const OP_WRITE: u16 = 0;
+const OP_QUERY: u16 = 1;
+const OP_DECLARE_TABLES: u16 = 2;
+const OP_DELETE: u16 = 3;
+const OP_MEMORY: u16 = 4;
+const OP_METRICS: u16 = 5;
+const OP_QUERY_STREAM: u16 = 6;
+
 #[derive(Debug)]
 pub struct Server {
     listener: TcpListener,
     storage: Arc<StorageServer>,
     query: Arc<QueryServer>,
+    context: Arc<Context>,
 }
 
 impl Server {
@@ -23,11 +51,13 @@ impl Server {
         addr: A,
         storage: Arc<StorageServer>,
         query: Arc<QueryServer>,
+        context: Arc<Context>,
     ) -> io::Result<Self> {
         Ok(Self {
             listener: TcpListener::bind(addr).await?,
             storage,
             query,
+            context,
         })
     }
 
@@ -67,20 +97,45 @@ impl Server {
             buf.resize(len as usize, 0);
             socket.read_exact(&mut buf).await?;
             match op {
-                0 => {
+                OP_WRITE => {
                     let request = flat::write::root_as_write_request(&buf).map_err(|err| {
                         io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", err))
                     })?;
                     self.storage.write(request).await;
                 }
-                1 => {
-                    let request = flat::query::root_as_query_request(&buf).map_err(|err| {
-                        io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", err))
-                    })?;
-                    let result = self.query.query(request).await.unwrap();
+                OP_QUERY => {
+                    let result = self.query(&buf).await?;
                     socket.write_u64(result.len() as u64).await?;
                     socket.write_all(&result).await?;
                 }
+                OP_QUERY_STREAM => self.query_stream(&buf, socket).await?,
+                OP_DECLARE_TABLES => {
+                    let message = match self.declare_tables(&buf) {
+                        Ok(()) => String::new(),
+                        Err(err) => err,
+                    };
+                    socket.write_u64(message.len() as u64).await?;
+                    socket.write_all(message.as_bytes()).await?;
+                }
+                OP_DELETE => {
+                    let (deleted, message) = match self.delete(&buf).await {
+                        Ok(deleted) => (deleted, String::new()),
+                        Err(err) => (0, err),
+                    };
+                    socket.write_u64(deleted).await?;
+                    socket.write_u64(message.len() as u64).await?;
+                    socket.write_all(message.as_bytes()).await?;
+                }
+                OP_MEMORY => {
+                    let report = self.memory_report().await;
+                    socket.write_u64(report.len() as u64).await?;
+                    socket.write_all(report.as_bytes()).await?;
+                }
+                OP_METRICS => {
+                    let report = self.metrics_report();
+                    socket.write_u64(report.len() as u64).await?;
+                    socket.write_all(report.as_bytes()).await?;
+                }
                 _ => {
                     error!("unexpected operation code: {:?}", op);
                     return Ok(());
@@ -89,4 +144,113 @@ impl Server {
             buf.clear();
         }
     }
+
+    /// Evaluates a query, returning the Arrow IPC file of the result as a whole.
+    async fn query(&self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        let mut pieces = self.query_pieces(buf).await?;
+        let mut result = Vec::new();
+        while let Some(piece) = pieces.next().await {
+            result.extend(piece?);
+        }
+        Ok(result)
+    }
+
+    /// Replies with the Arrow IPC file of the result of a query in pieces as it is evaluated,
+    /// each prefixed with its length, and an empty piece at the end.
+    async fn query_stream(&self, buf: &[u8], socket: &mut TcpStream) -> io::Result<()> {
+        let mut pieces = self.query_pieces(buf).await?;
+        while let Some(piece) = pieces.next().await {
+            let piece = piece?;
+            if piece.is_empty() {
+                continue;
+            }
+            socket.write_u64(piece.len() as u64).await?;
+            socket.write_all(&piece).await?;
+        }
+        socket.write_u64(0).await
+    }
+
+    async fn query_pieces(
+        &self,
+        buf: &[u8],
+    ) -> io::Result<impl Stream<Item = io::Result<Vec<u8>>> + Unpin + '_> {
+        let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let request =
+            flat::query::root_as_query_request(buf).map_err(|err| invalid(format!("{:?}", err)))?;
+        let pieces = self
+            .query
+            .query(request)
+            .await
+            .map_err(|err| invalid(err.to_string()))?;
+        Ok(pieces.map(move |piece| piece.map_err(|err| invalid(err.to_string()))))
+    }
+
+    /// Declares the tables of a config in the format of `--table-config`, replying with an
+    /// error message that is empty on success.
+    fn declare_tables(&self, buf: &[u8]) -> Result<(), String> {
+        let config = std::str::from_utf8(buf).map_err(|err| err.to_string())?;
+        for (name, config) in TableMeta::parse_config(config)? {
+            let schema = self
+                .context
+                .configure_table(&name, &config)
+                .map_err(|err| format!("table {:?}: {}", name, err))?;
+            info!("declare table {:?}: {:?}", name, schema.meta);
+        }
+        Ok(())
+    }
+
+    /// Deletes the samples of the series a selector matches, replying with how many were
+    /// deleted and an error message that is empty on success. The request is the start and
+    /// end of the range in milliseconds, `i64::MIN` and `i64::MAX` leaving it open, followed
+    /// by the selector.
+    async fn delete(&self, buf: &[u8]) -> Result<u64, String> {
+        if buf.len() < 16 {
+            return Err(String::from("delete request is too short"));
+        }
+        let bound = |bytes: &[u8]| {
+            let millis = i64::from_be_bytes(bytes.try_into().unwrap());
+            (millis != i64::MIN && millis != i64::MAX).then(|| Instant::from_millis(millis))
+        };
+        let range = Range {
+            start: bound(&buf[..8]),
+            end: bound(&buf[8..16]),
+        };
+        let selector = std::str::from_utf8(&buf[16..]).map_err(|err| err.to_string())?;
+        let expr = match parse(selector).map_err(|err| err.to_string())? {
+            Node::Expr(expr) => expr,
+            _ => return Err(format!("{:?} is not a selector", selector)),
+        };
+        info!("delete {:?} within {:?}", selector, range);
+        self.storage
+            .delete(&expr.resource.resource, &expr.filters, range)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    /// Reports the write counters of the storage as `<name> <value>` lines.
+    fn metrics_report(&self) -> String {
+        let counters = self.storage.write_metrics().counters();
+        let lines = counters
+            .iter()
+            .map(|(name, value)| format!("{} {}", name, value))
+            .collect::<Vec<_>>();
+        lines.join("\n")
+    }
+
+    /// Reports the heap usage of the storage as `shard <id> <bytes>` lines followed by
+    /// `table <name> <mutable bytes> <archived bytes>` lines.
+    async fn memory_report(&self) -> String {
+        let usage = self.storage.memory_usage().await;
+        let mut lines = Vec::with_capacity(usage.shards.len() + usage.tables.len());
+        for (id, used) in usage.shards.iter().enumerate() {
+            lines.push(format!("shard {} {}", id, used));
+        }
+        for (name, memory) in &usage.tables {
+            lines.push(format!(
+                "table {} {} {}",
+                name, memory.mutable, memory.archived
+            ));
+        }
+        lines.join("\n")
+    }
 }
@@ -127,6 +127,21 @@ pub mod query {
             args: &'args QueryRequestArgs<'args>,
         ) -> flatbuffers::WIPOffset<QueryRequest<'bldr>> {
             let mut builder = QueryRequestBuilder::new(_fbb);
+            if let Some(x) = args.lookback {
+                builder.add_lookback(x);
+            }
+            if let Some(x) = args.time {
+                builder.add_time(x);
+            }
+            if let Some(x) = args.step {
+                builder.add_step(x);
+            }
+            if let Some(x) = args.end {
+                builder.add_end(x);
+            }
+            if let Some(x) = args.start {
+                builder.add_start(x);
+            }
             if let Some(x) = args.q {
                 builder.add_q(x);
             }
@@ -136,6 +151,11 @@ pub mod query {
 
         pub const VT_LANGUAGE: flatbuffers::VOffsetT = 4;
         pub const VT_Q: flatbuffers::VOffsetT = 6;
+        pub const VT_START: flatbuffers::VOffsetT = 8;
+        pub const VT_END: flatbuffers::VOffsetT = 10;
+        pub const VT_STEP: flatbuffers::VOffsetT = 12;
+        pub const VT_TIME: flatbuffers::VOffsetT = 14;
+        pub const VT_LOOKBACK: flatbuffers::VOffsetT = 16;
 
         #[inline]
         pub fn language(&self) -> Language {
@@ -149,6 +169,26 @@ pub mod query {
                 .get::<flatbuffers::ForwardsUOffset<&str>>(QueryRequest::VT_Q, None)
                 .unwrap()
         }
+        #[inline]
+        pub fn start(&self) -> Option<i64> {
+            self._tab.get::<i64>(QueryRequest::VT_START, None)
+        }
+        #[inline]
+        pub fn end(&self) -> Option<i64> {
+            self._tab.get::<i64>(QueryRequest::VT_END, None)
+        }
+        #[inline]
+        pub fn step(&self) -> Option<i64> {
+            self._tab.get::<i64>(QueryRequest::VT_STEP, None)
+        }
+        #[inline]
+        pub fn time(&self) -> Option<i64> {
+            self._tab.get::<i64>(QueryRequest::VT_TIME, None)
+        }
+        #[inline]
+        pub fn lookback(&self) -> Option<i64> {
+            self._tab.get::<i64>(QueryRequest::VT_LOOKBACK, None)
+        }
     }
 
     impl flatbuffers::Verifiable for QueryRequest<'_> {
@@ -161,6 +201,11 @@ pub mod query {
             v.visit_table(pos)?
                 .visit_field::<Language>(&"language", Self::VT_LANGUAGE, false)?
                 .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"q", Self::VT_Q, true)?
+                .visit_field::<i64>(&"start", Self::VT_START, false)?
+                .visit_field::<i64>(&"end", Self::VT_END, false)?
+                .visit_field::<i64>(&"step", Self::VT_STEP, false)?
+                .visit_field::<i64>(&"time", Self::VT_TIME, false)?
+                .visit_field::<i64>(&"lookback", Self::VT_LOOKBACK, false)?
                 .finish();
             Ok(())
         }
@@ -168,6 +213,11 @@ pub mod query {
     pub struct QueryRequestArgs<'a> {
         pub language: Language,
         pub q: Option<flatbuffers::WIPOffset<&'a str>>,
+        pub start: Option<i64>,
+        pub end: Option<i64>,
+        pub step: Option<i64>,
+        pub time: Option<i64>,
+        pub lookback: Option<i64>,
     }
     impl<'a> Default for QueryRequestArgs<'a> {
         #[inline]
@@ -175,6 +225,11 @@ pub mod query {
             QueryRequestArgs {
                 language: Language::PromQL,
                 q: None, // required field
+                start: None,
+                end: None,
+                step: None,
+                time: None,
+                lookback: None,
             }
         }
     }
@@ -194,6 +249,30 @@ pub mod query {
                 .push_slot_always::<flatbuffers::WIPOffset<_>>(QueryRequest::VT_Q, q);
         }
         #[inline]
+        pub fn add_start(&mut self, start: i64) {
+            self.fbb_
+                .push_slot_always::<i64>(QueryRequest::VT_START, start);
+        }
+        #[inline]
+        pub fn add_end(&mut self, end: i64) {
+            self.fbb_.push_slot_always::<i64>(QueryRequest::VT_END, end);
+        }
+        #[inline]
+        pub fn add_step(&mut self, step: i64) {
+            self.fbb_
+                .push_slot_always::<i64>(QueryRequest::VT_STEP, step);
+        }
+        #[inline]
+        pub fn add_time(&mut self, time: i64) {
+            self.fbb_
+                .push_slot_always::<i64>(QueryRequest::VT_TIME, time);
+        }
+        #[inline]
+        pub fn add_lookback(&mut self, lookback: i64) {
+            self.fbb_
+                .push_slot_always::<i64>(QueryRequest::VT_LOOKBACK, lookback);
+        }
+        #[inline]
         pub fn new(
             _fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>,
         ) -> QueryRequestBuilder<'a, 'b> {
@@ -216,6 +295,11 @@ pub mod query {
             let mut ds = f.debug_struct("QueryRequest");
             ds.field("language", &self.language());
             ds.field("q", &self.q());
+            ds.field("start", &self.start());
+            ds.field("end", &self.end());
+            ds.field("step", &self.step());
+            ds.field("time", &self.time());
+            ds.field("lookback", &self.lookback());
             ds.finish()
         }
     }
@@ -134,15 +134,895 @@ pub mod write {
         }
     }
 
+    // struct BoolSample, aligned to 8
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq)]
+    pub struct BoolSample(pub [u8; 16]);
+    impl Default for BoolSample {
+        fn default() -> Self {
+            Self([0; 16])
+        }
+    }
+    impl std::fmt::Debug for BoolSample {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("BoolSample")
+                .field("value", &self.value())
+                .field("timestamp", &self.timestamp())
+                .finish()
+        }
+    }
+
+    impl flatbuffers::SimpleToVerifyInSlice for BoolSample {}
+    impl flatbuffers::SafeSliceAccess for BoolSample {}
+    impl<'a> flatbuffers::Follow<'a> for BoolSample {
+        type Inner = &'a BoolSample;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            <&'a BoolSample>::follow(buf, loc)
+        }
+    }
+    impl<'a> flatbuffers::Follow<'a> for &'a BoolSample {
+        type Inner = &'a BoolSample;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            flatbuffers::follow_cast_ref::<BoolSample>(buf, loc)
+        }
+    }
+    impl<'b> flatbuffers::Push for BoolSample {
+        type Output = BoolSample;
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(self as *const BoolSample as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+    impl<'b> flatbuffers::Push for &'b BoolSample {
+        type Output = BoolSample;
+
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(*self as *const BoolSample as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+
+    impl<'a> flatbuffers::Verifiable for BoolSample {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.in_buffer::<Self>(pos)
+        }
+    }
+    impl<'a> BoolSample {
+        #[allow(clippy::too_many_arguments)]
+        pub fn new(value: bool, timestamp: i64) -> Self {
+            let mut s = Self([0; 16]);
+            s.set_value(value);
+            s.set_timestamp(timestamp);
+            s
+        }
+
+        pub fn value(&self) -> bool {
+            let mut mem = core::mem::MaybeUninit::<bool>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[0..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<bool>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_value(&mut self, x: bool) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const bool as *const u8,
+                    self.0[0..].as_mut_ptr(),
+                    core::mem::size_of::<bool>(),
+                );
+            }
+        }
+
+        pub fn timestamp(&self) -> i64 {
+            let mut mem = core::mem::MaybeUninit::<i64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[8..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<i64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_timestamp(&mut self, x: i64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const i64 as *const u8,
+                    self.0[8..].as_mut_ptr(),
+                    core::mem::size_of::<i64>(),
+                );
+            }
+        }
+    }
+
+    // struct Bucket, aligned to 8
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq)]
+    pub struct Bucket(pub [u8; 16]);
+    impl Default for Bucket {
+        fn default() -> Self {
+            Self([0; 16])
+        }
+    }
+    impl std::fmt::Debug for Bucket {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("Bucket")
+                .field("upper_bound", &self.upper_bound())
+                .field("count", &self.count())
+                .finish()
+        }
+    }
+
+    impl flatbuffers::SimpleToVerifyInSlice for Bucket {}
+    impl flatbuffers::SafeSliceAccess for Bucket {}
+    impl<'a> flatbuffers::Follow<'a> for Bucket {
+        type Inner = &'a Bucket;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            <&'a Bucket>::follow(buf, loc)
+        }
+    }
+    impl<'a> flatbuffers::Follow<'a> for &'a Bucket {
+        type Inner = &'a Bucket;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            flatbuffers::follow_cast_ref::<Bucket>(buf, loc)
+        }
+    }
+    impl<'b> flatbuffers::Push for Bucket {
+        type Output = Bucket;
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(self as *const Bucket as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+    impl<'b> flatbuffers::Push for &'b Bucket {
+        type Output = Bucket;
+
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(*self as *const Bucket as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+
+    impl<'a> flatbuffers::Verifiable for Bucket {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.in_buffer::<Self>(pos)
+        }
+    }
+    impl<'a> Bucket {
+        #[allow(clippy::too_many_arguments)]
+        pub fn new(upper_bound: f64, count: f64) -> Self {
+            let mut s = Self([0; 16]);
+            s.set_upper_bound(upper_bound);
+            s.set_count(count);
+            s
+        }
+
+        pub fn upper_bound(&self) -> f64 {
+            let mut mem = core::mem::MaybeUninit::<f64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[0..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<f64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_upper_bound(&mut self, x: f64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const f64 as *const u8,
+                    self.0[0..].as_mut_ptr(),
+                    core::mem::size_of::<f64>(),
+                );
+            }
+        }
+
+        pub fn count(&self) -> f64 {
+            let mut mem = core::mem::MaybeUninit::<f64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[8..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<f64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_count(&mut self, x: f64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const f64 as *const u8,
+                    self.0[8..].as_mut_ptr(),
+                    core::mem::size_of::<f64>(),
+                );
+            }
+        }
+    }
+
+    // struct ExponentialBucket, aligned to 8
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq)]
+    pub struct ExponentialBucket(pub [u8; 16]);
+    impl Default for ExponentialBucket {
+        fn default() -> Self {
+            Self([0; 16])
+        }
+    }
+    impl std::fmt::Debug for ExponentialBucket {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("ExponentialBucket")
+                .field("index", &self.index())
+                .field("count", &self.count())
+                .finish()
+        }
+    }
+
+    impl flatbuffers::SimpleToVerifyInSlice for ExponentialBucket {}
+    impl flatbuffers::SafeSliceAccess for ExponentialBucket {}
+    impl<'a> flatbuffers::Follow<'a> for ExponentialBucket {
+        type Inner = &'a ExponentialBucket;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            <&'a ExponentialBucket>::follow(buf, loc)
+        }
+    }
+    impl<'a> flatbuffers::Follow<'a> for &'a ExponentialBucket {
+        type Inner = &'a ExponentialBucket;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            flatbuffers::follow_cast_ref::<ExponentialBucket>(buf, loc)
+        }
+    }
+    impl<'b> flatbuffers::Push for ExponentialBucket {
+        type Output = ExponentialBucket;
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(
+                    self as *const ExponentialBucket as *const u8,
+                    Self::size(),
+                )
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+    impl<'b> flatbuffers::Push for &'b ExponentialBucket {
+        type Output = ExponentialBucket;
+
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(
+                    *self as *const ExponentialBucket as *const u8,
+                    Self::size(),
+                )
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+
+    impl<'a> flatbuffers::Verifiable for ExponentialBucket {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.in_buffer::<Self>(pos)
+        }
+    }
+    impl<'a> ExponentialBucket {
+        #[allow(clippy::too_many_arguments)]
+        pub fn new(index: i32, count: f64) -> Self {
+            let mut s = Self([0; 16]);
+            s.set_index(index);
+            s.set_count(count);
+            s
+        }
+
+        pub fn index(&self) -> i32 {
+            let mut mem = core::mem::MaybeUninit::<i32>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[0..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<i32>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_index(&mut self, x: i32) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const i32 as *const u8,
+                    self.0[0..].as_mut_ptr(),
+                    core::mem::size_of::<i32>(),
+                );
+            }
+        }
+
+        pub fn count(&self) -> f64 {
+            let mut mem = core::mem::MaybeUninit::<f64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[8..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<f64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_count(&mut self, x: f64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const f64 as *const u8,
+                    self.0[8..].as_mut_ptr(),
+                    core::mem::size_of::<f64>(),
+                );
+            }
+        }
+    }
+
+    // struct Quantile, aligned to 8
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq)]
+    pub struct Quantile(pub [u8; 16]);
+    impl Default for Quantile {
+        fn default() -> Self {
+            Self([0; 16])
+        }
+    }
+    impl std::fmt::Debug for Quantile {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("Quantile")
+                .field("quantile", &self.quantile())
+                .field("value", &self.value())
+                .finish()
+        }
+    }
+
+    impl flatbuffers::SimpleToVerifyInSlice for Quantile {}
+    impl flatbuffers::SafeSliceAccess for Quantile {}
+    impl<'a> flatbuffers::Follow<'a> for Quantile {
+        type Inner = &'a Quantile;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            <&'a Quantile>::follow(buf, loc)
+        }
+    }
+    impl<'a> flatbuffers::Follow<'a> for &'a Quantile {
+        type Inner = &'a Quantile;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            flatbuffers::follow_cast_ref::<Quantile>(buf, loc)
+        }
+    }
+    impl<'b> flatbuffers::Push for Quantile {
+        type Output = Quantile;
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(self as *const Quantile as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+    impl<'b> flatbuffers::Push for &'b Quantile {
+        type Output = Quantile;
+
+        #[inline]
+        fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+            let src = unsafe {
+                ::std::slice::from_raw_parts(*self as *const Quantile as *const u8, Self::size())
+            };
+            dst.copy_from_slice(src);
+        }
+    }
+
+    impl<'a> flatbuffers::Verifiable for Quantile {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.in_buffer::<Self>(pos)
+        }
+    }
+    impl<'a> Quantile {
+        #[allow(clippy::too_many_arguments)]
+        pub fn new(quantile: f64, value: f64) -> Self {
+            let mut s = Self([0; 16]);
+            s.set_quantile(quantile);
+            s.set_value(value);
+            s
+        }
+
+        pub fn quantile(&self) -> f64 {
+            let mut mem = core::mem::MaybeUninit::<f64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[0..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<f64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_quantile(&mut self, x: f64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const f64 as *const u8,
+                    self.0[0..].as_mut_ptr(),
+                    core::mem::size_of::<f64>(),
+                );
+            }
+        }
+
+        pub fn value(&self) -> f64 {
+            let mut mem = core::mem::MaybeUninit::<f64>::uninit();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.0[8..].as_ptr(),
+                    mem.as_mut_ptr() as *mut u8,
+                    core::mem::size_of::<f64>(),
+                );
+                mem.assume_init()
+            }
+            .from_little_endian()
+        }
+
+        pub fn set_value(&mut self, x: f64) {
+            let x_le = x.to_little_endian();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    &x_le as *const f64 as *const u8,
+                    self.0[8..].as_mut_ptr(),
+                    core::mem::size_of::<f64>(),
+                );
+            }
+        }
+    }
+
     pub enum LabelOffset {}
     #[derive(Copy, Clone, PartialEq)]
 
-    pub struct Label<'a> {
+    pub struct Label<'a> {
+        pub _tab: flatbuffers::Table<'a>,
+    }
+
+    impl<'a> flatbuffers::Follow<'a> for Label<'a> {
+        type Inner = Label<'a>;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            Self {
+                _tab: flatbuffers::Table { buf, loc },
+            }
+        }
+    }
+
+    impl<'a> Label<'a> {
+        #[inline]
+        pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+            Label { _tab: table }
+        }
+        #[allow(unused_mut)]
+        pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+            _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+            args: &'args LabelArgs<'args>,
+        ) -> flatbuffers::WIPOffset<Label<'bldr>> {
+            let mut builder = LabelBuilder::new(_fbb);
+            if let Some(x) = args.value {
+                builder.add_value(x);
+            }
+            if let Some(x) = args.name {
+                builder.add_name(x);
+            }
+            builder.finish()
+        }
+
+        pub const VT_NAME: flatbuffers::VOffsetT = 4;
+        pub const VT_VALUE: flatbuffers::VOffsetT = 6;
+
+        #[inline]
+        pub fn name(&self) -> &'a str {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<&str>>(Label::VT_NAME, None)
+                .unwrap()
+        }
+        #[inline]
+        pub fn value(&self) -> &'a str {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<&str>>(Label::VT_VALUE, None)
+                .unwrap()
+        }
+    }
+
+    impl flatbuffers::Verifiable for Label<'_> {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.visit_table(pos)?
+                .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"name", Self::VT_NAME, true)?
+                .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"value", Self::VT_VALUE, true)?
+                .finish();
+            Ok(())
+        }
+    }
+    pub struct LabelArgs<'a> {
+        pub name: Option<flatbuffers::WIPOffset<&'a str>>,
+        pub value: Option<flatbuffers::WIPOffset<&'a str>>,
+    }
+    impl<'a> Default for LabelArgs<'a> {
+        #[inline]
+        fn default() -> Self {
+            LabelArgs {
+                name: None,  // required field
+                value: None, // required field
+            }
+        }
+    }
+    pub struct LabelBuilder<'a: 'b, 'b> {
+        fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+        start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+    }
+    impl<'a: 'b, 'b> LabelBuilder<'a, 'b> {
+        #[inline]
+        pub fn add_name(&mut self, name: flatbuffers::WIPOffset<&'b str>) {
+            self.fbb_
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(Label::VT_NAME, name);
+        }
+        #[inline]
+        pub fn add_value(&mut self, value: flatbuffers::WIPOffset<&'b str>) {
+            self.fbb_
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(Label::VT_VALUE, value);
+        }
+        #[inline]
+        pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> LabelBuilder<'a, 'b> {
+            let start = _fbb.start_table();
+            LabelBuilder {
+                fbb_: _fbb,
+                start_: start,
+            }
+        }
+        #[inline]
+        pub fn finish(self) -> flatbuffers::WIPOffset<Label<'a>> {
+            let o = self.fbb_.end_table(self.start_);
+            self.fbb_.required(o, Label::VT_NAME, "name");
+            self.fbb_.required(o, Label::VT_VALUE, "value");
+            flatbuffers::WIPOffset::new(o.value())
+        }
+    }
+
+    impl std::fmt::Debug for Label<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut ds = f.debug_struct("Label");
+            ds.field("name", &self.name());
+            ds.field("value", &self.value());
+            ds.finish()
+        }
+    }
+    pub enum HistogramOffset {}
+    #[derive(Copy, Clone, PartialEq)]
+
+    pub struct Histogram<'a> {
+        pub _tab: flatbuffers::Table<'a>,
+    }
+
+    impl<'a> flatbuffers::Follow<'a> for Histogram<'a> {
+        type Inner = Histogram<'a>;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            Self {
+                _tab: flatbuffers::Table { buf, loc },
+            }
+        }
+    }
+
+    impl<'a> Histogram<'a> {
+        #[inline]
+        pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+            Histogram { _tab: table }
+        }
+        #[allow(unused_mut)]
+        pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+            _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+            args: &'args HistogramArgs<'args>,
+        ) -> flatbuffers::WIPOffset<Histogram<'bldr>> {
+            let mut builder = HistogramBuilder::new(_fbb);
+            builder.add_zero_count(args.zero_count);
+            builder.add_zero_threshold(args.zero_threshold);
+            builder.add_sum(args.sum);
+            builder.add_count(args.count);
+            builder.add_timestamp(args.timestamp);
+            if let Some(x) = args.negative_buckets {
+                builder.add_negative_buckets(x);
+            }
+            if let Some(x) = args.positive_buckets {
+                builder.add_positive_buckets(x);
+            }
+            builder.add_schema(args.schema);
+            if let Some(x) = args.buckets {
+                builder.add_buckets(x);
+            }
+            builder.finish()
+        }
+
+        pub const VT_TIMESTAMP: flatbuffers::VOffsetT = 4;
+        pub const VT_COUNT: flatbuffers::VOffsetT = 6;
+        pub const VT_SUM: flatbuffers::VOffsetT = 8;
+        pub const VT_BUCKETS: flatbuffers::VOffsetT = 10;
+        pub const VT_SCHEMA: flatbuffers::VOffsetT = 12;
+        pub const VT_ZERO_THRESHOLD: flatbuffers::VOffsetT = 14;
+        pub const VT_ZERO_COUNT: flatbuffers::VOffsetT = 16;
+        pub const VT_POSITIVE_BUCKETS: flatbuffers::VOffsetT = 18;
+        pub const VT_NEGATIVE_BUCKETS: flatbuffers::VOffsetT = 20;
+
+        #[inline]
+        pub fn timestamp(&self) -> i64 {
+            self._tab
+                .get::<i64>(Histogram::VT_TIMESTAMP, Some(0))
+                .unwrap()
+        }
+        #[inline]
+        pub fn count(&self) -> f64 {
+            self._tab
+                .get::<f64>(Histogram::VT_COUNT, Some(0.0))
+                .unwrap()
+        }
+        #[inline]
+        pub fn sum(&self) -> f64 {
+            self._tab.get::<f64>(Histogram::VT_SUM, Some(0.0)).unwrap()
+        }
+        #[inline]
+        pub fn buckets(&self) -> Option<&'a [Bucket]> {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, Bucket>>>(
+                    Histogram::VT_BUCKETS,
+                    None,
+                )
+                .map(|v| v.safe_slice())
+        }
+        #[inline]
+        pub fn schema(&self) -> i32 {
+            self._tab.get::<i32>(Histogram::VT_SCHEMA, Some(0)).unwrap()
+        }
+        #[inline]
+        pub fn zero_threshold(&self) -> f64 {
+            self._tab
+                .get::<f64>(Histogram::VT_ZERO_THRESHOLD, Some(0.0))
+                .unwrap()
+        }
+        #[inline]
+        pub fn zero_count(&self) -> f64 {
+            self._tab
+                .get::<f64>(Histogram::VT_ZERO_COUNT, Some(0.0))
+                .unwrap()
+        }
+        #[inline]
+        pub fn positive_buckets(&self) -> Option<&'a [ExponentialBucket]> {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, ExponentialBucket>>>(
+                    Histogram::VT_POSITIVE_BUCKETS,
+                    None,
+                )
+                .map(|v| v.safe_slice())
+        }
+        #[inline]
+        pub fn negative_buckets(&self) -> Option<&'a [ExponentialBucket]> {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, ExponentialBucket>>>(
+                    Histogram::VT_NEGATIVE_BUCKETS,
+                    None,
+                )
+                .map(|v| v.safe_slice())
+        }
+    }
+
+    impl flatbuffers::Verifiable for Histogram<'_> {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.visit_table(pos)?
+                .visit_field::<i64>(&"timestamp", Self::VT_TIMESTAMP, false)?
+                .visit_field::<f64>(&"count", Self::VT_COUNT, false)?
+                .visit_field::<f64>(&"sum", Self::VT_SUM, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, Bucket>>>(&"buckets", Self::VT_BUCKETS, false)?
+                .visit_field::<i32>(&"schema", Self::VT_SCHEMA, false)?
+                .visit_field::<f64>(&"zero_threshold", Self::VT_ZERO_THRESHOLD, false)?
+                .visit_field::<f64>(&"zero_count", Self::VT_ZERO_COUNT, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, ExponentialBucket>>>(&"positive_buckets", Self::VT_POSITIVE_BUCKETS, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, ExponentialBucket>>>(&"negative_buckets", Self::VT_NEGATIVE_BUCKETS, false)?
+                .finish();
+            Ok(())
+        }
+    }
+    pub struct HistogramArgs<'a> {
+        pub timestamp: i64,
+        pub count: f64,
+        pub sum: f64,
+        pub buckets: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, Bucket>>>,
+        pub schema: i32,
+        pub zero_threshold: f64,
+        pub zero_count: f64,
+        pub positive_buckets:
+            Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, ExponentialBucket>>>,
+        pub negative_buckets:
+            Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, ExponentialBucket>>>,
+    }
+    impl<'a> Default for HistogramArgs<'a> {
+        #[inline]
+        fn default() -> Self {
+            HistogramArgs {
+                timestamp: 0,
+                count: 0.0,
+                sum: 0.0,
+                buckets: None,
+                schema: 0,
+                zero_threshold: 0.0,
+                zero_count: 0.0,
+                positive_buckets: None,
+                negative_buckets: None,
+            }
+        }
+    }
+    pub struct HistogramBuilder<'a: 'b, 'b> {
+        fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+        start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+    }
+    impl<'a: 'b, 'b> HistogramBuilder<'a, 'b> {
+        #[inline]
+        pub fn add_timestamp(&mut self, timestamp: i64) {
+            self.fbb_
+                .push_slot::<i64>(Histogram::VT_TIMESTAMP, timestamp, 0);
+        }
+        #[inline]
+        pub fn add_count(&mut self, count: f64) {
+            self.fbb_.push_slot::<f64>(Histogram::VT_COUNT, count, 0.0);
+        }
+        #[inline]
+        pub fn add_sum(&mut self, sum: f64) {
+            self.fbb_.push_slot::<f64>(Histogram::VT_SUM, sum, 0.0);
+        }
+        #[inline]
+        pub fn add_buckets(
+            &mut self,
+            buckets: flatbuffers::WIPOffset<flatbuffers::Vector<'b, Bucket>>,
+        ) {
+            self.fbb_
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(Histogram::VT_BUCKETS, buckets);
+        }
+        #[inline]
+        pub fn add_schema(&mut self, schema: i32) {
+            self.fbb_.push_slot::<i32>(Histogram::VT_SCHEMA, schema, 0);
+        }
+        #[inline]
+        pub fn add_zero_threshold(&mut self, zero_threshold: f64) {
+            self.fbb_
+                .push_slot::<f64>(Histogram::VT_ZERO_THRESHOLD, zero_threshold, 0.0);
+        }
+        #[inline]
+        pub fn add_zero_count(&mut self, zero_count: f64) {
+            self.fbb_
+                .push_slot::<f64>(Histogram::VT_ZERO_COUNT, zero_count, 0.0);
+        }
+        #[inline]
+        pub fn add_positive_buckets(
+            &mut self,
+            positive_buckets: flatbuffers::WIPOffset<flatbuffers::Vector<'b, ExponentialBucket>>,
+        ) {
+            self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+                Histogram::VT_POSITIVE_BUCKETS,
+                positive_buckets,
+            );
+        }
+        #[inline]
+        pub fn add_negative_buckets(
+            &mut self,
+            negative_buckets: flatbuffers::WIPOffset<flatbuffers::Vector<'b, ExponentialBucket>>,
+        ) {
+            self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+                Histogram::VT_NEGATIVE_BUCKETS,
+                negative_buckets,
+            );
+        }
+        #[inline]
+        pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HistogramBuilder<'a, 'b> {
+            let start = _fbb.start_table();
+            HistogramBuilder {
+                fbb_: _fbb,
+                start_: start,
+            }
+        }
+        #[inline]
+        pub fn finish(self) -> flatbuffers::WIPOffset<Histogram<'a>> {
+            let o = self.fbb_.end_table(self.start_);
+            flatbuffers::WIPOffset::new(o.value())
+        }
+    }
+
+    impl std::fmt::Debug for Histogram<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut ds = f.debug_struct("Histogram");
+            ds.field("timestamp", &self.timestamp());
+            ds.field("count", &self.count());
+            ds.field("sum", &self.sum());
+            ds.field("buckets", &self.buckets());
+            ds.field("schema", &self.schema());
+            ds.field("zero_threshold", &self.zero_threshold());
+            ds.field("zero_count", &self.zero_count());
+            ds.field("positive_buckets", &self.positive_buckets());
+            ds.field("negative_buckets", &self.negative_buckets());
+            ds.finish()
+        }
+    }
+    pub enum SummaryOffset {}
+    #[derive(Copy, Clone, PartialEq)]
+
+    pub struct Summary<'a> {
         pub _tab: flatbuffers::Table<'a>,
     }
 
-    impl<'a> flatbuffers::Follow<'a> for Label<'a> {
-        type Inner = Label<'a>;
+    impl<'a> flatbuffers::Follow<'a> for Summary<'a> {
+        type Inner = Summary<'a>;
         #[inline]
         fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
             Self {
@@ -151,44 +1031,197 @@ pub mod write {
         }
     }
 
-    impl<'a> Label<'a> {
+    impl<'a> Summary<'a> {
         #[inline]
         pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
-            Label { _tab: table }
+            Summary { _tab: table }
         }
         #[allow(unused_mut)]
         pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
             _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
-            args: &'args LabelArgs<'args>,
-        ) -> flatbuffers::WIPOffset<Label<'bldr>> {
-            let mut builder = LabelBuilder::new(_fbb);
+            args: &'args SummaryArgs<'args>,
+        ) -> flatbuffers::WIPOffset<Summary<'bldr>> {
+            let mut builder = SummaryBuilder::new(_fbb);
+            builder.add_sum(args.sum);
+            builder.add_count(args.count);
+            builder.add_timestamp(args.timestamp);
+            if let Some(x) = args.quantiles {
+                builder.add_quantiles(x);
+            }
+            builder.finish()
+        }
+
+        pub const VT_TIMESTAMP: flatbuffers::VOffsetT = 4;
+        pub const VT_COUNT: flatbuffers::VOffsetT = 6;
+        pub const VT_SUM: flatbuffers::VOffsetT = 8;
+        pub const VT_QUANTILES: flatbuffers::VOffsetT = 10;
+
+        #[inline]
+        pub fn timestamp(&self) -> i64 {
+            self._tab
+                .get::<i64>(Summary::VT_TIMESTAMP, Some(0))
+                .unwrap()
+        }
+        #[inline]
+        pub fn count(&self) -> f64 {
+            self._tab.get::<f64>(Summary::VT_COUNT, Some(0.0)).unwrap()
+        }
+        #[inline]
+        pub fn sum(&self) -> f64 {
+            self._tab.get::<f64>(Summary::VT_SUM, Some(0.0)).unwrap()
+        }
+        #[inline]
+        pub fn quantiles(&self) -> Option<&'a [Quantile]> {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, Quantile>>>(
+                    Summary::VT_QUANTILES,
+                    None,
+                )
+                .map(|v| v.safe_slice())
+        }
+    }
+
+    impl flatbuffers::Verifiable for Summary<'_> {
+        #[inline]
+        fn run_verifier(
+            v: &mut flatbuffers::Verifier,
+            pos: usize,
+        ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+            use self::flatbuffers::Verifiable;
+            v.visit_table(pos)?
+                .visit_field::<i64>(&"timestamp", Self::VT_TIMESTAMP, false)?
+                .visit_field::<f64>(&"count", Self::VT_COUNT, false)?
+                .visit_field::<f64>(&"sum", Self::VT_SUM, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, Quantile>>>(
+                    &"quantiles",
+                    Self::VT_QUANTILES,
+                    false,
+                )?
+                .finish();
+            Ok(())
+        }
+    }
+    pub struct SummaryArgs<'a> {
+        pub timestamp: i64,
+        pub count: f64,
+        pub sum: f64,
+        pub quantiles: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, Quantile>>>,
+    }
+    impl<'a> Default for SummaryArgs<'a> {
+        #[inline]
+        fn default() -> Self {
+            SummaryArgs {
+                timestamp: 0,
+                count: 0.0,
+                sum: 0.0,
+                quantiles: None,
+            }
+        }
+    }
+    pub struct SummaryBuilder<'a: 'b, 'b> {
+        fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+        start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+    }
+    impl<'a: 'b, 'b> SummaryBuilder<'a, 'b> {
+        #[inline]
+        pub fn add_timestamp(&mut self, timestamp: i64) {
+            self.fbb_
+                .push_slot::<i64>(Summary::VT_TIMESTAMP, timestamp, 0);
+        }
+        #[inline]
+        pub fn add_count(&mut self, count: f64) {
+            self.fbb_.push_slot::<f64>(Summary::VT_COUNT, count, 0.0);
+        }
+        #[inline]
+        pub fn add_sum(&mut self, sum: f64) {
+            self.fbb_.push_slot::<f64>(Summary::VT_SUM, sum, 0.0);
+        }
+        #[inline]
+        pub fn add_quantiles(
+            &mut self,
+            quantiles: flatbuffers::WIPOffset<flatbuffers::Vector<'b, Quantile>>,
+        ) {
+            self.fbb_
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(Summary::VT_QUANTILES, quantiles);
+        }
+        #[inline]
+        pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> SummaryBuilder<'a, 'b> {
+            let start = _fbb.start_table();
+            SummaryBuilder {
+                fbb_: _fbb,
+                start_: start,
+            }
+        }
+        #[inline]
+        pub fn finish(self) -> flatbuffers::WIPOffset<Summary<'a>> {
+            let o = self.fbb_.end_table(self.start_);
+            flatbuffers::WIPOffset::new(o.value())
+        }
+    }
+
+    impl std::fmt::Debug for Summary<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut ds = f.debug_struct("Summary");
+            ds.field("timestamp", &self.timestamp());
+            ds.field("count", &self.count());
+            ds.field("sum", &self.sum());
+            ds.field("quantiles", &self.quantiles());
+            ds.finish()
+        }
+    }
+    pub enum StringSampleOffset {}
+    #[derive(Copy, Clone, PartialEq)]
+
+    pub struct StringSample<'a> {
+        pub _tab: flatbuffers::Table<'a>,
+    }
+
+    impl<'a> flatbuffers::Follow<'a> for StringSample<'a> {
+        type Inner = StringSample<'a>;
+        #[inline]
+        fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            Self {
+                _tab: flatbuffers::Table { buf, loc },
+            }
+        }
+    }
+
+    impl<'a> StringSample<'a> {
+        #[inline]
+        pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+            StringSample { _tab: table }
+        }
+        #[allow(unused_mut)]
+        pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+            _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+            args: &'args StringSampleArgs<'args>,
+        ) -> flatbuffers::WIPOffset<StringSample<'bldr>> {
+            let mut builder = StringSampleBuilder::new(_fbb);
+            builder.add_timestamp(args.timestamp);
             if let Some(x) = args.value {
                 builder.add_value(x);
             }
-            if let Some(x) = args.name {
-                builder.add_name(x);
-            }
             builder.finish()
         }
 
-        pub const VT_NAME: flatbuffers::VOffsetT = 4;
+        pub const VT_TIMESTAMP: flatbuffers::VOffsetT = 4;
         pub const VT_VALUE: flatbuffers::VOffsetT = 6;
 
         #[inline]
-        pub fn name(&self) -> &'a str {
+        pub fn timestamp(&self) -> i64 {
             self._tab
-                .get::<flatbuffers::ForwardsUOffset<&str>>(Label::VT_NAME, None)
+                .get::<i64>(StringSample::VT_TIMESTAMP, Some(0))
                 .unwrap()
         }
         #[inline]
         pub fn value(&self) -> &'a str {
             self._tab
-                .get::<flatbuffers::ForwardsUOffset<&str>>(Label::VT_VALUE, None)
+                .get::<flatbuffers::ForwardsUOffset<&str>>(StringSample::VT_VALUE, None)
                 .unwrap()
         }
     }
 
-    impl flatbuffers::Verifiable for Label<'_> {
+    impl flatbuffers::Verifiable for StringSample<'_> {
         #[inline]
         fn run_verifier(
             v: &mut flatbuffers::Verifier,
@@ -196,61 +1229,62 @@ pub mod write {
         ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
             use self::flatbuffers::Verifiable;
             v.visit_table(pos)?
-                .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"name", Self::VT_NAME, true)?
+                .visit_field::<i64>(&"timestamp", Self::VT_TIMESTAMP, false)?
                 .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"value", Self::VT_VALUE, true)?
                 .finish();
             Ok(())
         }
     }
-    pub struct LabelArgs<'a> {
-        pub name: Option<flatbuffers::WIPOffset<&'a str>>,
+    pub struct StringSampleArgs<'a> {
+        pub timestamp: i64,
         pub value: Option<flatbuffers::WIPOffset<&'a str>>,
     }
-    impl<'a> Default for LabelArgs<'a> {
+    impl<'a> Default for StringSampleArgs<'a> {
         #[inline]
         fn default() -> Self {
-            LabelArgs {
-                name: None,  // required field
+            StringSampleArgs {
+                timestamp: 0,
                 value: None, // required field
             }
         }
     }
-    pub struct LabelBuilder<'a: 'b, 'b> {
+    pub struct StringSampleBuilder<'a: 'b, 'b> {
         fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
         start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
     }
-    impl<'a: 'b, 'b> LabelBuilder<'a, 'b> {
+    impl<'a: 'b, 'b> StringSampleBuilder<'a, 'b> {
         #[inline]
-        pub fn add_name(&mut self, name: flatbuffers::WIPOffset<&'b str>) {
+        pub fn add_timestamp(&mut self, timestamp: i64) {
             self.fbb_
-                .push_slot_always::<flatbuffers::WIPOffset<_>>(Label::VT_NAME, name);
+                .push_slot::<i64>(StringSample::VT_TIMESTAMP, timestamp, 0);
         }
         #[inline]
         pub fn add_value(&mut self, value: flatbuffers::WIPOffset<&'b str>) {
             self.fbb_
-                .push_slot_always::<flatbuffers::WIPOffset<_>>(Label::VT_VALUE, value);
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(StringSample::VT_VALUE, value);
         }
         #[inline]
-        pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> LabelBuilder<'a, 'b> {
+        pub fn new(
+            _fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+        ) -> StringSampleBuilder<'a, 'b> {
             let start = _fbb.start_table();
-            LabelBuilder {
+            StringSampleBuilder {
                 fbb_: _fbb,
                 start_: start,
             }
         }
         #[inline]
-        pub fn finish(self) -> flatbuffers::WIPOffset<Label<'a>> {
+        pub fn finish(self) -> flatbuffers::WIPOffset<StringSample<'a>> {
             let o = self.fbb_.end_table(self.start_);
-            self.fbb_.required(o, Label::VT_NAME, "name");
-            self.fbb_.required(o, Label::VT_VALUE, "value");
+            self.fbb_.required(o, StringSample::VT_VALUE, "value");
             flatbuffers::WIPOffset::new(o.value())
         }
     }
 
-    impl std::fmt::Debug for Label<'_> {
+    impl std::fmt::Debug for StringSample<'_> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            let mut ds = f.debug_struct("Label");
-            ds.field("name", &self.name());
+            let mut ds = f.debug_struct("StringSample");
+            ds.field("timestamp", &self.timestamp());
             ds.field("value", &self.value());
             ds.finish()
         }
@@ -283,6 +1317,18 @@ pub mod write {
             args: &'args TimeseriesArgs<'args>,
         ) -> flatbuffers::WIPOffset<Timeseries<'bldr>> {
             let mut builder = TimeseriesBuilder::new(_fbb);
+            if let Some(x) = args.string_samples {
+                builder.add_string_samples(x);
+            }
+            if let Some(x) = args.bool_samples {
+                builder.add_bool_samples(x);
+            }
+            if let Some(x) = args.summaries {
+                builder.add_summaries(x);
+            }
+            if let Some(x) = args.histograms {
+                builder.add_histograms(x);
+            }
             if let Some(x) = args.samples {
                 builder.add_samples(x);
             }
@@ -294,6 +1340,10 @@ pub mod write {
 
         pub const VT_LABELS: flatbuffers::VOffsetT = 4;
         pub const VT_SAMPLES: flatbuffers::VOffsetT = 6;
+        pub const VT_HISTOGRAMS: flatbuffers::VOffsetT = 8;
+        pub const VT_SUMMARIES: flatbuffers::VOffsetT = 10;
+        pub const VT_BOOL_SAMPLES: flatbuffers::VOffsetT = 12;
+        pub const VT_STRING_SAMPLES: flatbuffers::VOffsetT = 14;
 
         #[inline]
         pub fn labels(&self) -> flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Label<'a>>> {
@@ -313,6 +1363,40 @@ pub mod write {
                 .map(|v| v.safe_slice())
                 .unwrap()
         }
+        #[inline]
+        pub fn histograms(
+            &self,
+        ) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Histogram<'a>>>> {
+            self._tab.get::<flatbuffers::ForwardsUOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Histogram>>,
+            >>(Timeseries::VT_HISTOGRAMS, None)
+        }
+        #[inline]
+        pub fn summaries(
+            &self,
+        ) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Summary<'a>>>> {
+            self._tab.get::<flatbuffers::ForwardsUOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Summary>>,
+            >>(Timeseries::VT_SUMMARIES, None)
+        }
+        #[inline]
+        pub fn bool_samples(&self) -> Option<&'a [BoolSample]> {
+            self._tab
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, BoolSample>>>(
+                    Timeseries::VT_BOOL_SAMPLES,
+                    None,
+                )
+                .map(|v| v.safe_slice())
+        }
+        #[inline]
+        pub fn string_samples(
+            &self,
+        ) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<StringSample<'a>>>>
+        {
+            self._tab.get::<flatbuffers::ForwardsUOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<StringSample>>,
+            >>(Timeseries::VT_STRING_SAMPLES, None)
+        }
     }
 
     impl flatbuffers::Verifiable for Timeseries<'_> {
@@ -331,6 +1415,20 @@ pub mod write {
                     Self::VT_SAMPLES,
                     true,
                 )?
+                .visit_field::<flatbuffers::ForwardsUOffset<
+                    flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<Histogram>>,
+                >>(&"histograms", Self::VT_HISTOGRAMS, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<
+                    flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<Summary>>,
+                >>(&"summaries", Self::VT_SUMMARIES, false)?
+                .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, BoolSample>>>(
+                    &"bool_samples",
+                    Self::VT_BOOL_SAMPLES,
+                    false,
+                )?
+                .visit_field::<flatbuffers::ForwardsUOffset<
+                    flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<StringSample>>,
+                >>(&"string_samples", Self::VT_STRING_SAMPLES, false)?
                 .finish();
             Ok(())
         }
@@ -342,6 +1440,22 @@ pub mod write {
             >,
         >,
         pub samples: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, Sample>>>,
+        pub histograms: Option<
+            flatbuffers::WIPOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Histogram<'a>>>,
+            >,
+        >,
+        pub summaries: Option<
+            flatbuffers::WIPOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Summary<'a>>>,
+            >,
+        >,
+        pub bool_samples: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, BoolSample>>>,
+        pub string_samples: Option<
+            flatbuffers::WIPOffset<
+                flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<StringSample<'a>>>,
+            >,
+        >,
     }
     impl<'a> Default for TimeseriesArgs<'a> {
         #[inline]
@@ -349,6 +1463,10 @@ pub mod write {
             TimeseriesArgs {
                 labels: None,  // required field
                 samples: None, // required field
+                histograms: None,
+                summaries: None,
+                bool_samples: None,
+                string_samples: None,
             }
         }
     }
@@ -376,6 +1494,50 @@ pub mod write {
                 .push_slot_always::<flatbuffers::WIPOffset<_>>(Timeseries::VT_SAMPLES, samples);
         }
         #[inline]
+        pub fn add_histograms(
+            &mut self,
+            histograms: flatbuffers::WIPOffset<
+                flatbuffers::Vector<'b, flatbuffers::ForwardsUOffset<Histogram<'b>>>,
+            >,
+        ) {
+            self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+                Timeseries::VT_HISTOGRAMS,
+                histograms,
+            );
+        }
+        #[inline]
+        pub fn add_summaries(
+            &mut self,
+            summaries: flatbuffers::WIPOffset<
+                flatbuffers::Vector<'b, flatbuffers::ForwardsUOffset<Summary<'b>>>,
+            >,
+        ) {
+            self.fbb_
+                .push_slot_always::<flatbuffers::WIPOffset<_>>(Timeseries::VT_SUMMARIES, summaries);
+        }
+        #[inline]
+        pub fn add_bool_samples(
+            &mut self,
+            bool_samples: flatbuffers::WIPOffset<flatbuffers::Vector<'b, BoolSample>>,
+        ) {
+            self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+                Timeseries::VT_BOOL_SAMPLES,
+                bool_samples,
+            );
+        }
+        #[inline]
+        pub fn add_string_samples(
+            &mut self,
+            string_samples: flatbuffers::WIPOffset<
+                flatbuffers::Vector<'b, flatbuffers::ForwardsUOffset<StringSample<'b>>>,
+            >,
+        ) {
+            self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+                Timeseries::VT_STRING_SAMPLES,
+                string_samples,
+            );
+        }
+        #[inline]
         pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> TimeseriesBuilder<'a, 'b> {
             let start = _fbb.start_table();
             TimeseriesBuilder {
@@ -397,6 +1559,10 @@ pub mod write {
             let mut ds = f.debug_struct("Timeseries");
             ds.field("labels", &self.labels());
             ds.field("samples", &self.samples());
+            ds.field("histograms", &self.histograms());
+            ds.field("summaries", &self.summaries());
+            ds.field("bool_samples", &self.bool_samples());
+            ds.field("string_samples", &self.string_samples());
             ds.finish()
         }
     }
@@ -2,20 +2,181 @@ use arrow2::datatypes::{DataType, Field};
 use common::time::{Duration, Instant};
 use common::util::IndexMap;
 use common::{Label, LabelType, LabelValue, Scalar, ScalarType, ScalarValue};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::sync::Arc;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Scalar column holding the exact timestamp of every sample of tables aligned with
+/// [`Alignment::Exact`].
+pub const TIMESTAMP_SCALAR: &str = "__timestamp__";
+/// The names of the list items of histogram and summary columns, which are both encoded bytes.
+pub const HISTOGRAM_ITEM: &str = "histogram";
+pub const SUMMARY_ITEM: &str = "summary";
+/// How many samples the first write of a table needs for its time interval to be inferred.
+const INFER_MIN_SAMPLES: usize = 8;
 
 pub const DEFAULT: TableMeta = TableMeta {
     series_len: 120,
     time_interval: Duration::SECOND,
     mutable_chunk_num: 5,
+    immutable_chunk_num: 30,
+    retention: None,
+    alignment: Alignment::LastWrite,
+    out_of_order: Duration::from_millis(0),
+    future_tolerance: Duration::from_millis(60 * 60 * 1000),
+    max_series: None,
+    max_label_values: None,
+    rollups: Vec::new(),
 };
 
-#[derive(Debug)]
+/// How samples are stored into the slot of `time_interval` their timestamp falls into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// The last sample of a slot overwrites earlier ones.
+    LastWrite,
+    /// Later samples of a slot are dropped.
+    FirstWrite,
+    Sum,
+    Min,
+    Max,
+    /// Stores how many samples fell into a slot instead of their values.
+    Count,
+    /// Last write wins, with the timestamp of the sample kept in the [`TIMESTAMP_SCALAR`]
+    /// column.
+    Exact,
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(Self::LastWrite),
+            "first" => Ok(Self::FirstWrite),
+            "sum" => Ok(Self::Sum),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "count" => Ok(Self::Count),
+            "exact" => Ok(Self::Exact),
+            _ => Err(format!("unknown alignment {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LastWrite => "last",
+            Self::FirstWrite => "first",
+            Self::Sum => "sum",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Count => "count",
+            Self::Exact => "exact",
+        })
+    }
+}
+
+/// Downsamples the samples of a table to one per `time_interval` once its chunks are archived,
+/// into a table per aggregate, see [`Rollup::table_name`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rollup {
+    pub time_interval: Duration,
+    pub aggregates: Vec<Alignment>,
+    /// How long rolled up samples are kept, falls back to the default retention if unset.
+    pub retention: Option<Duration>,
+}
+
+impl Rollup {
+    /// The table samples of table `name` aggregated by `aggregate` are rolled up into, like
+    /// `cpu:1m:max`.
+    pub fn table_name(&self, name: &str, aggregate: Alignment) -> String {
+        format!("{}:{}:{}", name, self.time_interval, aggregate)
+    }
+
+    /// The metadata of the rollup table of `aggregate`, whose chunks hold as many samples as
+    /// the ones of the rolled up table described by `meta`.
+    pub fn meta(&self, meta: &TableMeta, aggregate: Alignment) -> TableMeta {
+        TableMeta {
+            time_interval: self.time_interval,
+            retention: self.retention,
+            alignment: aggregate,
+            out_of_order: Duration::from_millis(0),
+            rollups: Vec::new(),
+            ..meta.clone()
+        }
+    }
+}
+
+impl FromStr for Rollup {
+    type Err = String;
+
+    /// Parses `interval:aggregate,...[:retention]`, like `1m:min,max,sum,count:30d`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let time_interval = parts
+            .next()
+            .filter(|part| !part.is_empty())
+            .ok_or_else(|| format!("missing interval in rollup {:?}", s))?
+            .parse()?;
+        let aggregates = parts
+            .next()
+            .ok_or_else(|| format!("missing aggregates in rollup {:?}", s))?
+            .split(',')
+            .map(|aggregate| aggregate.trim().parse())
+            .collect::<Result<_, _>>()?;
+        let retention = parts.next().map(str::parse).transpose()?;
+        if parts.next().is_some() {
+            return Err(format!("invalid rollup {:?}", s));
+        }
+        Ok(Self {
+            time_interval,
+            aggregates,
+            retention,
+        })
+    }
+}
+
+impl fmt::Display for Rollup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.time_interval)?;
+        for (i, aggregate) in self.aggregates.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", aggregate)?;
+        }
+        match self.retention {
+            Some(retention) => write!(f, ":{}", retention),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TableMeta {
     pub series_len: u32,
     pub time_interval: Duration,
     pub mutable_chunk_num: u32,
+    /// How many archived chunks are kept in memory once they have been persisted to files.
+    pub immutable_chunk_num: u32,
+    /// How long samples are kept, falls back to the default retention of the context if unset.
+    pub retention: Option<Duration>,
+    pub alignment: Alignment,
+    /// How far behind the mutable chunks late samples are still accepted into archived ones.
+    pub out_of_order: Duration,
+    /// How far ahead of the wall clock samples are accepted.
+    pub future_tolerance: Duration,
+    /// How many series the table holds at most in a chunk window, split evenly between shards.
+    /// Writes of more series are rejected.
+    pub max_series: Option<u32>,
+    /// How many distinct values a label of a chunk window holds at most, writes of series with
+    /// more values are rejected.
+    pub max_label_values: Option<u32>,
+    /// Coarser copies of the table written as its chunks are archived, finest first.
+    pub rollups: Vec<Rollup>,
 }
 
 impl TableMeta {
@@ -23,20 +184,359 @@ impl TableMeta {
     pub fn chunk_duration(&self) -> Duration {
         self.time_interval * self.series_len
     }
+
+    /// Whether chunks of `self` and `other` cover the same windows with the same series.
+    pub fn same_layout(&self, other: &Self) -> bool {
+        self.time_interval == other.time_interval && self.series_len == other.series_len
+    }
+
+    /// Sets field `key` from `value`. Durations are either milliseconds or a number followed by
+    /// a unit, like `15s`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("invalid value {:?} of {:?}", value, key);
+        let duration = || match value.parse::<i64>() {
+            Ok(millis) => Ok(Duration::from_millis(millis)),
+            Err(_) => value.parse::<Duration>(),
+        };
+        match key {
+            "series_len" => self.series_len = value.parse().map_err(|_| invalid())?,
+            "time_interval" => self.time_interval = duration()?,
+            "mutable_chunk_num" => self.mutable_chunk_num = value.parse().map_err(|_| invalid())?,
+            "immutable_chunk_num" => {
+                self.immutable_chunk_num = value.parse().map_err(|_| invalid())?
+            }
+            "retention" => self.retention = Some(duration()?),
+            "alignment" => self.alignment = value.parse()?,
+            "out_of_order" => self.out_of_order = duration()?,
+            "future_tolerance" => self.future_tolerance = duration()?,
+            "max_series" => self.max_series = Some(value.parse().map_err(|_| invalid())?),
+            "max_label_values" => {
+                self.max_label_values = Some(value.parse().map_err(|_| invalid())?)
+            }
+            // every rollup line adds a rollup
+            "rollup" => {
+                self.rollups.push(value.parse()?);
+                self.rollups.sort_by_key(|rollup| rollup.time_interval);
+            }
+            _ => return Err(format!("unknown table option {:?}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.series_len == 0 {
+            return Err(String::from("series_len must be positive"));
+        }
+        if self.time_interval.as_millis() <= 0 {
+            return Err(String::from("time_interval must be positive"));
+        }
+        if self.mutable_chunk_num == 0 {
+            return Err(String::from("mutable_chunk_num must be positive"));
+        }
+        if self.out_of_order.as_millis() < 0 || self.future_tolerance.as_millis() < 0 {
+            return Err(String::from(
+                "out_of_order and future_tolerance must not be negative",
+            ));
+        }
+        for rollup in &self.rollups {
+            if rollup.time_interval <= self.time_interval
+                || rollup.time_interval.as_millis() % self.time_interval.as_millis() != 0
+            {
+                return Err(format!(
+                    "rollup interval {} must be a multiple of time_interval {}",
+                    rollup.time_interval, self.time_interval
+                ));
+            }
+            if rollup.aggregates.contains(&Alignment::Exact) {
+                return Err(String::from("rollups can not be aligned exactly"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses table declarations, each a `[table]` header followed by `key = value` lines of
+    /// the fields to override, see [`TableMeta::set`]. Lines starting with `#` are comments.
+    pub fn parse_config(config: &str) -> Result<Vec<(String, TableConfig)>, String> {
+        let mut tables: Vec<(String, TableConfig)> = Vec::new();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |err: String| format!("line {}: {}", number + 1, err);
+            if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                tables.push((name.trim().to_owned(), TableConfig::default()));
+                continue;
+            }
+            let (_, config) = tables
+                .last_mut()
+                .ok_or_else(|| error(String::from("option outside of a table")))?;
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expect `key = value`, found {:?}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            // checked against the defaults, the declared table may hold other values
+            let mut checked = DEFAULT;
+            checked.set(key, value).map_err(error)?;
+            config.options.push((key.to_owned(), value.to_owned()));
+        }
+        Ok(tables)
+    }
+
+    /// Takes the median spacing between the samples of a write as time interval, rounded to
+    /// whole seconds, or minutes from a minute on, so that a jittered sample does not make it
+    /// tiny. Writes of too few samples to tell leave it as it is.
+    pub fn infer(mut self, scalars: &[(Instant, Vec<Scalar>)]) -> Self {
+        let mut timestamps = scalars
+            .iter()
+            .map(|(timestamp, _)| timestamp.as_millis())
+            .collect::<Vec<_>>();
+        timestamps.sort_unstable();
+        timestamps.dedup();
+        if timestamps.len() < INFER_MIN_SAMPLES {
+            return self;
+        }
+        let mut spacings = timestamps
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .collect::<Vec<_>>();
+        spacings.sort_unstable();
+        let median = spacings[spacings.len() / 2];
+        let unit = if median >= 60_000 { 60_000 } else { 1000 };
+        let interval = ((median + unit / 2) / unit).max(1) * unit;
+        self.time_interval = Duration::from_millis(interval);
+        self
+    }
+}
+
+/// The fields a table declaration overrides, in the order they were declared.
+#[derive(Debug, Clone, Default)]
+pub struct TableConfig {
+    options: Vec<(String, String)>,
+}
+
+impl TableConfig {
+    /// Overrides the declared fields of `meta`, leaving the others as they are.
+    pub fn apply(&self, meta: &mut TableMeta) -> Result<(), String> {
+        for (key, value) in &self.options {
+            // a rollup declared again is not added twice
+            if key == "rollup" && meta.rollups.contains(&value.parse()?) {
+                continue;
+            }
+            meta.set(key, value)?;
+        }
+        meta.validate()
+    }
 }
 
+/// The name of a scalar column with the type of its values.
+pub type ScalarColumnType = ScalarType<String, String, String, String, String, String>;
+
 #[derive(Debug)]
 pub struct Schema {
-    pub labels: Vec<LabelType<String>>,
-    pub scalars: IndexMap<String, ScalarType<String, String>>,
+    pub version: u32,
+    pub labels: IndexMap<String, LabelType<String>>,
+    pub scalars: IndexMap<String, ScalarColumnType>,
     pub label_arrows: Vec<Field>,
     pub scalar_arrows: Vec<Field>,
     pub meta: TableMeta,
 }
 
+impl Schema {
+    pub fn new(meta: TableMeta) -> Self {
+        Self {
+            version: 0,
+            labels: IndexMap::new(),
+            scalars: IndexMap::new(),
+            label_arrows: Vec::new(),
+            scalar_arrows: Vec::new(),
+            meta,
+        }
+    }
+
+    /// Whether every label and scalar name of a write already has a column in this schema.
+    pub fn contains(&self, labels: &[Label], scalars: &[(Instant, Vec<Scalar>)]) -> bool {
+        labels
+            .iter()
+            .all(|label| self.labels.get_id(label.name).is_some())
+            && scalars.iter().all(|(_, scalars)| {
+                scalars
+                    .iter()
+                    .all(|scalar| self.scalars.get_id(scalar.name.as_str()).is_some())
+            })
+    }
+
+    /// Returns the next version of this schema with columns appended for every unknown label and
+    /// scalar name. Existing columns keep their ids and types.
+    pub fn evolve(&self, labels: &[Label], scalars: &[(Instant, Vec<Scalar>)]) -> Self {
+        let mut label_columns = self.labels.clone();
+        let mut label_arrows = self.label_arrows.clone();
+        for label in labels {
+            if label_columns.get_id(label.name).is_some() {
+                continue;
+            }
+            let column = match label.value {
+                LabelValue::String(_) => LabelType::String(label.name.to_owned()),
+            };
+            label_arrows.push(Self::label_arrow(&column));
+            label_columns.insert(label.name.to_owned(), column);
+        }
+
+        let mut scalar_columns = self.scalars.clone();
+        let mut scalar_arrows = self.scalar_arrows.clone();
+        for scalar in scalars.iter().flat_map(|(_, scalars)| scalars) {
+            if scalar_columns.get_id(scalar.name.as_str()).is_some() {
+                continue;
+            }
+            let column = match scalar.value {
+                ScalarValue::Int(_) => ScalarType::Int(scalar.name.to_owned()),
+                ScalarValue::Float(_) => ScalarType::Float(scalar.name.to_owned()),
+                ScalarValue::Histogram(_) => ScalarType::Histogram(scalar.name.to_owned()),
+                ScalarValue::Summary(_) => ScalarType::Summary(scalar.name.to_owned()),
+                ScalarValue::Bool(_) => ScalarType::Bool(scalar.name.to_owned()),
+                ScalarValue::String(_) => ScalarType::String(scalar.name.to_owned()),
+            };
+            scalar_arrows.push(Self::scalar_arrow(&column));
+            scalar_columns.insert(scalar.name.to_owned(), column);
+        }
+
+        Self {
+            version: self.version + 1,
+            labels: label_columns,
+            scalars: scalar_columns,
+            label_arrows,
+            scalar_arrows,
+            meta: self.meta.clone(),
+        }
+    }
+
+    /// Returns the next version of this schema with the same columns and `meta`, plus the
+    /// timestamp column if samples are to be aligned exactly.
+    pub fn with_meta(&self, meta: TableMeta) -> Self {
+        let mut scalars = self.scalars.clone();
+        let mut scalar_arrows = self.scalar_arrows.clone();
+        if meta.alignment == Alignment::Exact && scalars.get_id(TIMESTAMP_SCALAR).is_none() {
+            let column = ScalarType::Int(String::from(TIMESTAMP_SCALAR));
+            scalar_arrows.push(Self::scalar_arrow(&column));
+            scalars.insert(String::from(TIMESTAMP_SCALAR), column);
+        }
+        Self {
+            version: self.version + 1,
+            labels: self.labels.clone(),
+            scalars,
+            label_arrows: self.label_arrows.clone(),
+            scalar_arrows,
+            meta,
+        }
+    }
+
+    /// Encodes this schema as `key=value` lines, to be embedded in file metadata.
+    pub fn encode(&self) -> String {
+        let mut lines = vec![
+            format!("version={}", self.version),
+            format!("series_len={}", self.meta.series_len),
+            format!("time_interval={}", self.meta.time_interval.as_millis()),
+            format!("mutable_chunk_num={}", self.meta.mutable_chunk_num),
+            format!("immutable_chunk_num={}", self.meta.immutable_chunk_num),
+        ];
+        if let Some(retention) = self.meta.retention {
+            lines.push(format!("retention={}", retention.as_millis()));
+        }
+        lines.push(format!("alignment={}", self.meta.alignment));
+        lines.push(format!(
+            "out_of_order={}",
+            self.meta.out_of_order.as_millis()
+        ));
+        lines.push(format!(
+            "future_tolerance={}",
+            self.meta.future_tolerance.as_millis()
+        ));
+        if let Some(max_series) = self.meta.max_series {
+            lines.push(format!("max_series={}", max_series));
+        }
+        if let Some(max_label_values) = self.meta.max_label_values {
+            lines.push(format!("max_label_values={}", max_label_values));
+        }
+        for rollup in &self.meta.rollups {
+            lines.push(format!("rollup={}", rollup));
+        }
+        for label in self.labels.iter() {
+            match label {
+                LabelType::String(name) => lines.push(format!("label.string={}", name)),
+            }
+        }
+        for scalar in self.scalars.iter() {
+            match scalar {
+                ScalarType::Int(name) => lines.push(format!("scalar.int={}", name)),
+                ScalarType::Float(name) => lines.push(format!("scalar.float={}", name)),
+                ScalarType::Histogram(name) => lines.push(format!("scalar.histogram={}", name)),
+                ScalarType::Summary(name) => lines.push(format!("scalar.summary={}", name)),
+                ScalarType::Bool(name) => lines.push(format!("scalar.bool={}", name)),
+                ScalarType::String(name) => lines.push(format!("scalar.string={}", name)),
+            }
+        }
+        lines.join("\n")
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let mut schema = Self::new(DEFAULT);
+        for line in encoded.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "version" => schema.version = value.parse().ok()?,
+                "label.string" => {
+                    let column = LabelType::String(value.to_owned());
+                    schema.label_arrows.push(Self::label_arrow(&column));
+                    schema.labels.insert(value.to_owned(), column);
+                }
+                "scalar.int" | "scalar.float" | "scalar.histogram" | "scalar.summary"
+                | "scalar.bool" | "scalar.string" => {
+                    let name = value.to_owned();
+                    let column = match key {
+                        "scalar.int" => ScalarType::Int(name),
+                        "scalar.float" => ScalarType::Float(name),
+                        "scalar.histogram" => ScalarType::Histogram(name),
+                        "scalar.summary" => ScalarType::Summary(name),
+                        "scalar.bool" => ScalarType::Bool(name),
+                        _ => ScalarType::String(name),
+                    };
+                    schema.scalar_arrows.push(Self::scalar_arrow(&column));
+                    schema.scalars.insert(value.to_owned(), column);
+                }
+                _ => schema.meta.set(key, value).ok()?,
+            }
+        }
+        Some(schema)
+    }
+
+    fn label_arrow(column: &LabelType<String>) -> Field {
+        match column {
+            LabelType::String(name) => Field::new(name, DataType::Utf8, true),
+        }
+    }
+
+    fn scalar_arrow(column: &ScalarColumnType) -> Field {
+        let (name, item, arrow) = match column {
+            ScalarType::Int(name) => (name, "item", DataType::Int64),
+            ScalarType::Float(name) => (name, "item", DataType::Float64),
+            // histograms and summaries are kept encoded, see `common::histogram`
+            ScalarType::Histogram(name) => (name, HISTOGRAM_ITEM, DataType::LargeBinary),
+            ScalarType::Summary(name) => (name, SUMMARY_ITEM, DataType::LargeBinary),
+            ScalarType::Bool(name) => (name, "item", DataType::Boolean),
+            ScalarType::String(name) => (name, "item", DataType::Utf8),
+        };
+        Field::new(
+            name,
+            DataType::List(Box::new(Field::new(item, arrow, true))),
+            true,
+        )
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Context {
     schemas: DashMap<String, Arc<Schema>>,
+    default_retention: RwLock<Option<Duration>>,
 }
 
 impl Context {
@@ -44,6 +544,115 @@ impl Context {
         Default::default()
     }
 
+    /// Keeps samples of tables without a retention of their own for `retention`, forever if
+    /// `None`.
+    pub fn set_default_retention(&self, retention: Option<Duration>) {
+        *self.default_retention.write().unwrap() = retention;
+    }
+
+    /// Sets the retention of table `name`, `None` falls back to the default retention.
+    pub fn set_retention(&self, name: &str, retention: Option<Duration>) -> Arc<Schema> {
+        self.update_meta(name, |meta| meta.retention = retention)
+    }
+
+    /// Declares the metadata of table `name`. Tables already holding samples switch to the new
+    /// chunk layout from their next write on, archiving their mutable chunks.
+    pub fn declare_table(&self, name: &str, meta: TableMeta) -> Arc<Schema> {
+        self.update_meta(name, |declared| *declared = meta)
+    }
+
+    /// Overrides the fields `config` declares of table `name`, keeping the others as they were
+    /// declared or inferred before. Leaves the table as it is if the result is invalid.
+    pub fn configure_table(&self, name: &str, config: &TableConfig) -> Result<Arc<Schema>, String> {
+        let schema = match self.schemas.entry(String::from(name)) {
+            Entry::Occupied(mut entry) => {
+                let mut meta = entry.get().meta.clone();
+                config.apply(&mut meta)?;
+                let schema = Arc::new(entry.get().with_meta(meta));
+                entry.insert(Arc::clone(&schema));
+                schema
+            }
+            Entry::Vacant(entry) => {
+                let mut meta = DEFAULT;
+                config.apply(&mut meta)?;
+                Arc::clone(entry.insert(Arc::new(Schema::new(meta))).value())
+            }
+        };
+        Ok(schema)
+    }
+
+    fn update_meta<F>(&self, name: &str, f: F) -> Arc<Schema>
+    where
+        F: FnOnce(&mut TableMeta),
+    {
+        let mut schema = self
+            .schemas
+            .entry(String::from(name))
+            .or_insert_with(|| Arc::new(Schema::new(DEFAULT)));
+        let mut meta = schema.meta.clone();
+        f(&mut meta);
+        *schema = Arc::new(schema.with_meta(meta));
+        Arc::clone(&*schema)
+    }
+
+    /// How long samples of table `name` are kept, `None` for forever.
+    pub fn retention(&self, name: &str) -> Option<Duration> {
+        self.schemas
+            .get(name)
+            .and_then(|schema| schema.meta.retention)
+            .or(*self.default_retention.read().unwrap())
+    }
+
+    /// Picks the table to answer a query over table `name` from `start` on, with one point per
+    /// `step` whose samples are aggregated as `aggregate` says. That is the coarsest rollup
+    /// keeping `aggregate` whose interval fits in `step` and whose retention at `now` still
+    /// reaches back to `start`. Without a step, the finest such table is picked, `name` itself
+    /// if it retains samples from `start` on. Rollups lag behind the table by its mutable
+    /// chunks, which are rolled up once archived, so rollups are scanned along with them.
+    pub fn pick_table(
+        &self,
+        name: &str,
+        start: Option<Instant>,
+        step: Option<Duration>,
+        aggregate: Alignment,
+        now: Instant,
+    ) -> String {
+        let schema = match self.get_schema(name) {
+            Some(schema) if !schema.meta.rollups.is_empty() => schema,
+            _ => return String::from(name),
+        };
+        let covers = |retention: Option<Duration>| match (retention, start) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(retention), Some(start)) => start >= now - retention,
+        };
+        let default_retention = *self.default_retention.read().unwrap();
+        // rollups are sorted from the finest
+        schema
+            .meta
+            .rollups
+            .iter()
+            .rev()
+            .filter(|rollup| rollup.aggregates.contains(&aggregate))
+            .filter(|rollup| matches!(step, Some(step) if rollup.time_interval <= step))
+            .find(|rollup| covers(rollup.retention.or(default_retention)))
+            .map(|rollup| rollup.table_name(name, aggregate))
+            .or_else(|| {
+                if covers(self.retention(name)) {
+                    return None;
+                }
+                // samples older than the retention of the table are only left in rollups
+                schema
+                    .meta
+                    .rollups
+                    .iter()
+                    .filter(|rollup| rollup.aggregates.contains(&aggregate))
+                    .find(|rollup| covers(rollup.retention.or(default_retention)))
+                    .map(|rollup| rollup.table_name(name, aggregate))
+            })
+            .unwrap_or_else(|| String::from(name))
+    }
+
     pub fn get_schema_or_else<F>(&self, name: &str, f: F) -> Arc<Schema>
     where
         F: FnOnce() -> Schema,
@@ -60,40 +669,39 @@ impl Context {
         self.schemas.get(name).map(|s| Arc::clone(s.value()))
     }
 
-    pub fn create_schema(labels: &[Label], scalars: &[(Instant, Vec<Scalar>)]) -> Schema {
-        let mut label_columns = Vec::new();
-        let mut label_arrows = Vec::new();
-        for label in labels {
-            match label.value {
-                LabelValue::String(_) => {
-                    label_columns.push(LabelType::String(label.name.to_owned()));
-                    label_arrows.push(Field::new(label.name.to_owned(), DataType::Utf8, true));
-                }
-            }
+    /// Replaces the schema of table `name` with one that also covers the given write, unless a
+    /// concurrent writer already did so.
+    pub fn evolve_schema(
+        &self,
+        name: &str,
+        labels: &[Label],
+        scalars: &[(Instant, Vec<Scalar>)],
+    ) -> Arc<Schema> {
+        let mut schema = self
+            .schemas
+            .entry(String::from(name))
+            .or_insert_with(|| Arc::new(Self::create_schema(labels, scalars)));
+        if !schema.contains(labels, scalars) {
+            *schema = Arc::new(schema.evolve(labels, scalars));
         }
+        Arc::clone(&*schema)
+    }
 
-        let mut scalar_columns = IndexMap::new();
-        let mut scalar_arrows = Vec::new();
-        for scalar in &scalars[0].1 {
-            let (column, arrow) = match scalar.value {
-                ScalarValue::Int(_) => (ScalarType::Int(scalar.name.to_owned()), DataType::Int64),
-                ScalarValue::Float(_) => {
-                    (ScalarType::Float(scalar.name.to_owned()), DataType::Float64)
-                }
-            };
-            scalar_columns.insert(scalar.name.to_owned(), column);
-            scalar_arrows.push(Field::new(
-                scalar.name.to_owned(),
-                DataType::List(Box::new(Field::new("item", arrow, true))),
-                false,
-            ));
-        }
-        Schema {
-            labels: label_columns,
-            scalars: scalar_columns,
-            label_arrows,
-            scalar_arrows,
-            meta: DEFAULT,
+    /// Registers a schema recovered from disk, keeping whichever of it and the known schema of
+    /// table `name` is newer.
+    pub fn restore_schema(&self, name: &str, schema: Schema) -> Arc<Schema> {
+        let mut known = self
+            .schemas
+            .entry(String::from(name))
+            .or_insert_with(|| Arc::new(Schema::new(DEFAULT)));
+        if known.version < schema.version {
+            *known = Arc::new(schema);
         }
+        Arc::clone(&*known)
+    }
+
+    /// Creates the schema of an undeclared table from its first write.
+    pub fn create_schema(labels: &[Label], scalars: &[(Instant, Vec<Scalar>)]) -> Schema {
+        Schema::new(DEFAULT.infer(scalars)).evolve(labels, scalars)
     }
 }
@@ -0,0 +1,301 @@
+/// Observations bucketed by value, counted since the series started like Prometheus histograms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    pub count: f64,
+    pub sum: f64,
+    pub buckets: Buckets,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Buckets {
+    /// Upper bounds in ascending order, each with how many observations are less than or equal
+    /// to it.
+    Explicit(Vec<(f64, f64)>),
+    /// Sparse exponential buckets, each an index with how many observations fall into it.
+    /// Positive bucket `index` covers `(base^(index-1), base^index]` with
+    /// `base = 2^(2^-schema)`, negative ones mirror them.
+    Exponential {
+        schema: i32,
+        zero_threshold: f64,
+        zero_count: f64,
+        positive: Vec<(i32, f64)>,
+        negative: Vec<(i32, f64)>,
+    },
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self::Explicit(Vec::new())
+    }
+}
+
+/// Quantiles of observations computed by clients, like Prometheus summaries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub count: f64,
+    pub sum: f64,
+    /// Quantiles in ascending order, each with its value.
+    pub quantiles: Vec<(f64, f64)>,
+}
+
+impl Histogram {
+    /// Estimates the `q` quantile of the observations, interpolating linearly within the bucket
+    /// it falls into as Prometheus does. `NaN` if there is no observation.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if q.is_nan() || q < 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        if q > 1.0 {
+            return f64::INFINITY;
+        }
+        let buckets = self.cumulative();
+        let total = match buckets.last() {
+            Some((_, total)) if *total > 0.0 => *total,
+            _ => return f64::NAN,
+        };
+        let rank = q * total;
+        let position = buckets
+            .iter()
+            .position(|(_, count)| *count >= rank)
+            .unwrap_or(buckets.len() - 1);
+        let (upper, count) = buckets[position];
+        if upper.is_infinite() {
+            // the best estimate within the +Inf bucket is the highest finite bound
+            return if position > 0 {
+                buckets[position - 1].0
+            } else {
+                upper
+            };
+        }
+        let (lower, below) = match position {
+            0 if upper <= 0.0 => return upper,
+            0 => (0.0, 0.0),
+            _ => buckets[position - 1],
+        };
+        if count == below {
+            return upper;
+        }
+        lower + (upper - lower) * (rank - below) / (count - below)
+    }
+
+    /// The buckets as ascending upper bounds with cumulative counts.
+    fn cumulative(&self) -> Vec<(f64, f64)> {
+        match &self.buckets {
+            Buckets::Explicit(buckets) => buckets.clone(),
+            Buckets::Exponential {
+                schema,
+                zero_threshold,
+                zero_count,
+                positive,
+                negative,
+            } => {
+                let base = 2f64.powf(2f64.powi(-schema));
+                let mut buckets = Vec::with_capacity(positive.len() + negative.len() + 1);
+                // negative buckets from the most negative, bounded by their upper end
+                let mut negative = negative.clone();
+                negative.sort_by_key(|(index, _)| -index);
+                for (index, count) in negative {
+                    buckets.push((-base.powi(index - 1), count));
+                }
+                buckets.push((*zero_threshold, *zero_count));
+                let mut positive = positive.clone();
+                positive.sort_by_key(|(index, _)| *index);
+                buckets.extend(
+                    positive
+                        .into_iter()
+                        .map(|(index, count)| (base.powi(index), count)),
+                );
+                let mut total = 0.0;
+                for (_, count) in buckets.iter_mut() {
+                    total += *count;
+                    *count = total;
+                }
+                buckets
+            }
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        put_f64(buf, self.count);
+        put_f64(buf, self.sum);
+        match &self.buckets {
+            Buckets::Explicit(buckets) => {
+                buf.push(0);
+                put_pairs(buf, buckets, put_f64);
+            }
+            Buckets::Exponential {
+                schema,
+                zero_threshold,
+                zero_count,
+                positive,
+                negative,
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(&schema.to_le_bytes());
+                put_f64(buf, *zero_threshold);
+                put_f64(buf, *zero_count);
+                put_pairs(buf, positive, |buf, index| {
+                    buf.extend_from_slice(&index.to_le_bytes())
+                });
+                put_pairs(buf, negative, |buf, index| {
+                    buf.extend_from_slice(&index.to_le_bytes())
+                });
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let mut reader = Reader(buf);
+        let count = reader.f64()?;
+        let sum = reader.f64()?;
+        let buckets = match reader.u8()? {
+            0 => Buckets::Explicit(reader.pairs(Reader::f64)?),
+            1 => Buckets::Exponential {
+                schema: reader.i32()?,
+                zero_threshold: reader.f64()?,
+                zero_count: reader.f64()?,
+                positive: reader.pairs(Reader::i32)?,
+                negative: reader.pairs(Reader::i32)?,
+            },
+            _ => return None,
+        };
+        Some(Self {
+            count,
+            sum,
+            buckets,
+        })
+    }
+}
+
+impl Summary {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        put_f64(buf, self.count);
+        put_f64(buf, self.sum);
+        put_pairs(buf, &self.quantiles, put_f64);
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let mut reader = Reader(buf);
+        Some(Self {
+            count: reader.f64()?,
+            sum: reader.f64()?,
+            quantiles: reader.pairs(Reader::f64)?,
+        })
+    }
+}
+
+fn put_f64(buf: &mut Vec<u8>, v: f64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Puts the length of `pairs`, then each key followed by its value.
+fn put_pairs<K: Copy>(buf: &mut Vec<u8>, pairs: &[(K, f64)], put_key: fn(&mut Vec<u8>, K)) {
+    buf.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+    for (key, value) in pairs {
+        put_key(buf, *key);
+        put_f64(buf, *value);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.0.len() < N {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        bytes.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.array::<1>().map(|bytes| bytes[0])
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.array().map(i32::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.array().map(f64::from_le_bytes)
+    }
+
+    fn pairs<K>(&mut self, key: fn(&mut Self) -> Option<K>) -> Option<Vec<(K, f64)>> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        let mut pairs = Vec::with_capacity(len.min(self.0.len()));
+        for _ in 0..len {
+            pairs.push((key(self)?, self.f64()?));
+        }
+        Some(pairs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::histogram::{Buckets, Histogram, Summary};
+
+    fn explicit() -> Histogram {
+        Histogram {
+            count: 10.0,
+            sum: 25.0,
+            buckets: Buckets::Explicit(vec![
+                (1.0, 2.0),
+                (2.0, 6.0),
+                (4.0, 8.0),
+                (f64::INFINITY, 10.0),
+            ]),
+        }
+    }
+
+    fn exponential() -> Histogram {
+        Histogram {
+            count: 6.0,
+            sum: 12.0,
+            buckets: Buckets::Exponential {
+                schema: 0,
+                zero_threshold: 0.001,
+                zero_count: 1.0,
+                positive: vec![(2, 2.0), (1, 2.0)],
+                negative: vec![(1, 1.0)],
+            },
+        }
+    }
+
+    #[test]
+    fn histogram_quantile() {
+        let histogram = explicit();
+        assert_eq!(histogram.quantile(0.1), 0.5);
+        assert_eq!(histogram.quantile(0.5), 1.75);
+        // falls into the +Inf bucket
+        assert_eq!(histogram.quantile(0.95), 4.0);
+        assert_eq!(histogram.quantile(-0.5), f64::NEG_INFINITY);
+        assert_eq!(histogram.quantile(1.5), f64::INFINITY);
+        assert!(Histogram::default().quantile(0.5).is_nan());
+
+        let histogram = exponential();
+        assert_eq!(histogram.quantile(0.1), -1.0);
+        assert!((histogram.quantile(0.5) - 1.0005).abs() < 1e-9);
+        assert_eq!(histogram.quantile(1.0), 4.0);
+    }
+
+    #[test]
+    fn histogram_codec() {
+        for histogram in [Histogram::default(), explicit(), exponential()] {
+            let mut buf = Vec::new();
+            histogram.encode(&mut buf);
+            assert_eq!(Histogram::decode(&buf), Some(histogram));
+            assert_eq!(Histogram::decode(&buf[..buf.len() - 1]), None);
+        }
+
+        let summary = Summary {
+            count: 3.0,
+            sum: 6.0,
+            quantiles: vec![(0.5, 2.0), (0.99, 3.0)],
+        };
+        let mut buf = Vec::new();
+        summary.encode(&mut buf);
+        assert_eq!(Summary::decode(&buf), Some(summary));
+        assert_eq!(Summary::decode(&buf[..4]), None);
+    }
+}
@@ -1,46 +1,75 @@
 #![feature(const_fn_trait_bound)]
 
-use num_traits::AsPrimitive;
-
+pub mod histogram;
 pub mod time;
 pub mod util;
 
+use crate::histogram::{Histogram, Summary};
+use std::num::{ParseFloatError, ParseIntError};
+
 #[derive(Debug)]
-pub enum ScalarType<I, F> {
+pub enum ScalarType<I, F, H, S, B, T> {
     Int(I),
     Float(F),
+    Histogram(H),
+    Summary(S),
+    Bool(B),
+    String(T),
 }
 
-impl<I: Clone, F: Clone> Clone for ScalarType<I, F> {
+impl<I: Clone, F: Clone, H: Clone, S: Clone, B: Clone, T: Clone> Clone
+    for ScalarType<I, F, H, S, B, T>
+{
     fn clone(&self) -> Self {
         match &self {
             ScalarType::Int(s) => ScalarType::Int(s.clone()),
             ScalarType::Float(s) => ScalarType::Float(s.clone()),
+            ScalarType::Histogram(s) => ScalarType::Histogram(s.clone()),
+            ScalarType::Summary(s) => ScalarType::Summary(s.clone()),
+            ScalarType::Bool(s) => ScalarType::Bool(s.clone()),
+            ScalarType::String(s) => ScalarType::String(s.clone()),
         }
     }
 }
 
-impl<I: Copy, F: Copy> Copy for ScalarType<I, F> {}
+impl<I: Copy, F: Copy, H: Copy, S: Copy, B: Copy, T: Copy> Copy for ScalarType<I, F, H, S, B, T> {}
 
-impl<I: AsPrimitive<i64>, F: AsPrimitive<i64>> From<ScalarType<I, F>> for i64 {
-    fn from(s: ScalarType<I, F>) -> Self {
-        match s {
-            ScalarType::Int(s) => s.as_(),
-            ScalarType::Float(s) => s.as_(),
-        }
+/// The type of a scalar column without its name.
+pub type ScalarKind = ScalarType<(), (), (), (), (), ()>;
+
+/// Histograms and summaries convert to how many observations they hold, booleans to 0 or 1
+/// and strings to the number they spell, an error if they do not.
+impl TryFrom<&ScalarValue> for i64 {
+    type Error = ParseIntError;
+
+    fn try_from(s: &ScalarValue) -> Result<Self, Self::Error> {
+        Ok(match s {
+            ScalarType::Int(s) => *s,
+            ScalarType::Float(s) => *s as i64,
+            ScalarType::Histogram(s) => s.count as i64,
+            ScalarType::Summary(s) => s.count as i64,
+            ScalarType::Bool(s) => *s as i64,
+            ScalarType::String(s) => s.parse()?,
+        })
     }
 }
 
-impl<I: AsPrimitive<f64>, F: AsPrimitive<f64>> From<ScalarType<I, F>> for f64 {
-    fn from(s: ScalarType<I, F>) -> Self {
-        match s {
-            ScalarType::Int(s) => s.as_(),
-            ScalarType::Float(s) => s.as_(),
-        }
+impl TryFrom<&ScalarValue> for f64 {
+    type Error = ParseFloatError;
+
+    fn try_from(s: &ScalarValue) -> Result<Self, Self::Error> {
+        Ok(match s {
+            ScalarType::Int(s) => *s as f64,
+            ScalarType::Float(s) => *s,
+            ScalarType::Histogram(s) => s.count,
+            ScalarType::Summary(s) => s.count,
+            ScalarType::Bool(s) => *s as u8 as f64,
+            ScalarType::String(s) => s.parse()?,
+        })
     }
 }
 
-pub type ScalarValue = ScalarType<i64, f64>;
+pub type ScalarValue = ScalarType<i64, f64, Histogram, Summary, bool, String>;
 
 #[derive(Debug)]
 pub struct Scalar {
@@ -2,13 +2,24 @@ use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
 use std::time::UNIX_EPOCH;
 
 const MILLIS_PER_SEC: i64 = 1_000;
 
+/// Units of durations with their length in milliseconds, shortest first.
+const UNITS: [(&str, i64); 6] = [
+    ("ms", 1),
+    ("s", MILLIS_PER_SEC),
+    ("m", 60 * MILLIS_PER_SEC),
+    ("h", 60 * 60 * MILLIS_PER_SEC),
+    ("d", 24 * 60 * 60 * MILLIS_PER_SEC),
+    ("w", 7 * 24 * 60 * 60 * MILLIS_PER_SEC),
+];
+
 pub const EPOCH: Instant = Instant { millis: 0 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Duration {
     millis: i64,
 }
@@ -18,12 +29,49 @@ impl Duration {
         millis: MILLIS_PER_SEC,
     };
 
+    #[inline]
+    pub const fn from_millis(m: i64) -> Self {
+        Self { millis: m }
+    }
+
     #[inline]
     pub fn as_millis(&self) -> i64 {
         self.millis
     }
 }
 
+impl FromStr for Duration {
+    type Err = String;
+
+    /// Parses an integer followed by one of the units `ms`, `s`, `m`, `h`, `d` or `w`, e.g. `15d`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| format!("missing unit in duration {:?}", s))?;
+        let (value, unit) = s.split_at(split);
+        let value: i64 = value
+            .parse()
+            .map_err(|_| format!("invalid duration {:?}", s))?;
+        let (_, millis) = UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(|| format!("unknown unit {:?} in duration {:?}", unit, s))?;
+        Ok(Self::from_millis(value * millis))
+    }
+}
+
+impl fmt::Display for Duration {
+    /// Formats in the largest unit the duration is a whole number of, the inverse of parsing.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (unit, millis) = UNITS
+            .iter()
+            .rev()
+            .find(|(_, millis)| self.millis % millis == 0)
+            .unwrap();
+        write!(f, "{}{}", self.millis / millis, unit)
+    }
+}
+
 impl Mul<Duration> for isize {
     type Output = Duration;
 
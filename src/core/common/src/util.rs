@@ -5,7 +5,7 @@ use std::ops::Index;
 use std::slice::{Iter, IterMut};
 use std::vec::IntoIter;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IndexMap<K, V> {
     value: Vec<V>,
     index: HashMap<K, usize>,
@@ -86,6 +86,13 @@ where
     pub fn first(&self) -> Option<&V> {
         self.value.first()
     }
+
+    /// Returns the keys in insertion order.
+    pub fn keys(&self) -> Vec<&K> {
+        let mut keys = self.index.iter().collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|(_, id)| **id);
+        keys.into_iter().map(|(key, _)| key).collect()
+    }
 }
 
 impl<K, V> Index<usize> for IndexMap<K, V> {
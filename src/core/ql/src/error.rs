@@ -6,4 +6,8 @@ pub enum Error {
     InternalError { err: String },
     #[snafu(display("query does not have a metric name"))]
     NoName,
+    #[snafu(display("invalid arguments of {}", name))]
+    InvalidArguments { name: String },
+    #[snafu(display("invalid operands of {}", operator))]
+    InvalidOperands { operator: String },
 }
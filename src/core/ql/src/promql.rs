@@ -1,57 +1,247 @@
 use crate::error::Error;
 use crate::rosetta::{
-    AggregateAction, Aggregation, Expr, Function, Matcher, MatcherOp, Pipeline, Projection, Range,
-    Resource,
+    AggregateAction, Aggregation, BinaryOperator, Expr, Function, GroupSide, Matcher, MatcherOp,
+    MatchingAction, Node, Parameter, Pipeline, Projection, Resource, VectorMatching,
 };
-use common::time::{Instant, EPOCH};
+use common::time::Duration;
 use common::LabelType;
-use promql::{AggregationAction, LabelMatchOp, Node, Vector};
+use promql::{
+    AggregationAction, AggregationMod, LabelMatchOp, Node as Ast, Op, OpGroupSide, OpMod,
+    OpModAction, Vector,
+};
 
-pub fn parse(q: &str) -> Result<Expr, Error> {
+pub fn parse(q: &str) -> Result<Node, Error> {
     let ast = promql::parse(q.as_ref(), false).map_err(|err| Error::InternalError {
         err: format!("{:?}", err),
     })?;
-    match ast {
-        Node::Vector(vector) => translate_vector(vector, None, None),
-        Node::Function {
+    translate(ast)
+}
+
+fn translate(node: Ast) -> Result<Node, Error> {
+    match node {
+        Ast::Vector(vector) => Ok(Node::Expr(translate_vector(vector)?)),
+        Ast::Scalar(number) => Ok(Node::Number(widen(number))),
+        Ast::String(string) => Ok(Node::String(string)),
+        Ast::Negation(node) => Ok(Node::Negation(Box::new(translate(*node)?))),
+        Ast::Operator { x, op, y } => translate_operator(*x, op, *y),
+        Ast::Function {
             name,
             args,
             aggregation,
-        } => match args.into_iter().next().unwrap() {
-            Node::Vector(vector) => {
-                let aggregation = aggregation.map(|a| {
-                    let action = match a.action {
-                        AggregationAction::Without => AggregateAction::Without,
-                        AggregationAction::By => AggregateAction::With,
-                    };
-                    Aggregation {
-                        action,
-                        labels: a.labels,
-                    }
-                });
-                translate_vector(vector, Some(name), aggregation)
-            }
-            _ => {
-                unimplemented!()
-            }
+        } if is_aggregation(&name) => translate_aggregation(name, args, aggregation),
+        Ast::Function {
+            name,
+            args,
+            aggregation: None,
+        } => translate_function(name, args),
+        Ast::Function { name, .. } => Err(Error::InvalidArguments { name }),
+    }
+}
+
+fn is_aggregation(name: &str) -> bool {
+    matches!(
+        name,
+        "sum"
+            | "avg"
+            | "min"
+            | "max"
+            | "count"
+            | "stddev"
+            | "stdvar"
+            | "group"
+            | "count_values"
+            | "topk"
+            | "bottomk"
+            | "quantile"
+    )
+}
+
+/// f32 literals are widened through their shortest representation, so 0.9 stays 0.9.
+fn widen(number: f32) -> f64 {
+    number.to_string().parse().unwrap_or(number as f64)
+}
+
+/// Translates the expression aggregated by the operator `name`, which takes the parameter
+/// of operators having one first. Aggregations of a selection join it.
+fn translate_aggregation(
+    name: String,
+    args: Vec<Ast>,
+    modifier: Option<AggregationMod>,
+) -> Result<Node, Error> {
+    let invalid = |name: String| Error::InvalidArguments { name };
+    let mut args = args.into_iter();
+    let parameter = match name.as_str() {
+        "topk" | "bottomk" | "quantile" => match args.next() {
+            Some(Ast::Scalar(number)) => Some(Parameter::Number(widen(number))),
+            _ => return Err(invalid(name)),
+        },
+        "count_values" => match args.next() {
+            Some(Ast::String(label)) => Some(Parameter::String(label)),
+            _ => return Err(invalid(name)),
+        },
+        _ => None,
+    };
+    let node = match (args.next(), args.next()) {
+        (Some(arg), None) => translate(arg)?,
+        _ => return Err(invalid(name)),
+    };
+    if is_scalar(&node) || matches!(node, Node::String(_)) {
+        return Err(invalid(name));
+    }
+    let (action, labels) = match modifier {
+        // without a modifier every series is aggregated together
+        None => (AggregateAction::With, Vec::new()),
+        Some(modifier) => match modifier.action {
+            AggregationAction::Without => (AggregateAction::Without, modifier.labels),
+            AggregationAction::By => (AggregateAction::With, modifier.labels),
         },
-        _ => unimplemented!(),
+    };
+    let aggregation = Aggregation {
+        operator: name,
+        parameter,
+        action,
+        labels,
+    };
+    match node {
+        Node::Expr(mut expr) if expr.aggregation.is_none() => {
+            expr.aggregation = Some(aggregation);
+            Ok(Node::Expr(expr))
+        }
+        node => Ok(Node::Aggregation {
+            aggregation,
+            node: Box::new(node),
+        }),
     }
 }
 
-fn translate_vector(
-    vector: Vector,
-    function: Option<String>,
-    aggregation: Option<Aggregation>,
-) -> Result<Expr, Error> {
-    let range = Range {
-        start: vector
-            .range
-            .map(|sec| EPOCH + (Instant::now() - Instant::from_millis(sec as i64 * 1000))),
-        end: None,
+/// Translates the function `name` of `args`. A function of a single selection not aggregated
+/// yet and literals joins the pipeline of its projections, with the literals as parameters.
+fn translate_function(name: String, args: Vec<Ast>) -> Result<Node, Error> {
+    let args = args
+        .into_iter()
+        .map(translate)
+        .collect::<Result<Vec<_>, _>>()?;
+    let selections = args
+        .iter()
+        .filter(|arg| matches!(arg, Node::Expr(expr) if expr.aggregation.is_none()))
+        .count();
+    let literals = args
+        .iter()
+        .filter(|arg| matches!(arg, Node::Number(_) | Node::String(_)))
+        .count();
+    if selections != 1 || selections + literals != args.len() {
+        return Ok(Node::Function { name, args });
+    }
+    let mut selection = None;
+    let mut parameters = Vec::with_capacity(literals);
+    for arg in args {
+        match arg {
+            Node::Expr(expr) => selection = Some(expr),
+            Node::Number(number) => parameters.push(Parameter::Number(number)),
+            Node::String(string) => parameters.push(Parameter::String(string)),
+            _ => unreachable!(),
+        }
+    }
+    if name == "quantile_over_time" && !matches!(parameters.as_slice(), [Parameter::Number(_)]) {
+        return Err(Error::InvalidArguments { name });
+    }
+    let mut expr = selection.unwrap();
+    for projection in &mut expr.projection {
+        projection.pipeline.functions.push(Function {
+            name: name.clone(),
+            parameters: parameters.clone(),
+        });
+    }
+    Ok(Node::Expr(expr))
+}
+
+/// Whether `node` evaluates to a scalar rather than to series.
+fn is_scalar(node: &Node) -> bool {
+    match node {
+        Node::Number(_) => true,
+        Node::Negation(node) => is_scalar(node),
+        Node::Function { name, .. } => matches!(name.as_str(), "scalar" | "time" | "pi"),
+        Node::Binary { lhs, rhs, .. } => is_scalar(lhs) && is_scalar(rhs),
+        _ => false,
+    }
+}
+
+/// Translates the binary operator `op` of `x` and `y`. Set operators only apply to series,
+/// comparisons of scalars need the `bool` modifier, and matching modifiers need series on both
+/// sides.
+fn translate_operator(x: Ast, op: Op, y: Ast) -> Result<Node, Error> {
+    let (operator, return_bool, modifier) = match op {
+        Op::Pow(modifier) => (BinaryOperator::Pow, false, modifier),
+        Op::Mul(modifier) => (BinaryOperator::Mul, false, modifier),
+        Op::Div(modifier) => (BinaryOperator::Div, false, modifier),
+        Op::Mod(modifier) => (BinaryOperator::Mod, false, modifier),
+        Op::Plus(modifier) => (BinaryOperator::Add, false, modifier),
+        Op::Minus(modifier) => (BinaryOperator::Sub, false, modifier),
+        Op::Eq(return_bool, modifier) => (BinaryOperator::Eq, return_bool, modifier),
+        Op::Ne(return_bool, modifier) => (BinaryOperator::Ne, return_bool, modifier),
+        Op::Lt(return_bool, modifier) => (BinaryOperator::Lt, return_bool, modifier),
+        Op::Gt(return_bool, modifier) => (BinaryOperator::Gt, return_bool, modifier),
+        Op::Le(return_bool, modifier) => (BinaryOperator::Le, return_bool, modifier),
+        Op::Ge(return_bool, modifier) => (BinaryOperator::Ge, return_bool, modifier),
+        Op::And(modifier) => (BinaryOperator::And, false, modifier),
+        Op::Or(modifier) => (BinaryOperator::Or, false, modifier),
+        Op::Unless(modifier) => (BinaryOperator::Unless, false, modifier),
+    };
+    let (lhs, rhs) = (translate(x)?, translate(y)?);
+    let invalid = || Error::InvalidOperands {
+        operator: String::from(operator.as_str()),
+    };
+    if matches!(lhs, Node::String(_)) || matches!(rhs, Node::String(_)) {
+        return Err(invalid());
+    }
+    let scalars = is_scalar(&lhs) || is_scalar(&rhs);
+    if operator.is_set() && scalars
+        || operator.is_comparison() && is_scalar(&lhs) && is_scalar(&rhs) && !return_bool
+        || modifier.is_some() && scalars
+    {
+        return Err(invalid());
+    }
+    let matching = match modifier {
+        None => None,
+        Some(modifier) => Some(translate_matching(modifier, operator).ok_or_else(invalid)?),
     };
+    Ok(Node::Binary {
+        operator,
+        return_bool,
+        matching,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    })
+}
+
+/// The vector matching of the modifier of `operator`, `None` if set operators are grouped.
+fn translate_matching(modifier: OpMod, operator: BinaryOperator) -> Option<VectorMatching> {
+    let group = match modifier.group {
+        None => None,
+        Some(_) if operator.is_set() => return None,
+        Some(group) => {
+            let side = match group.side {
+                OpGroupSide::Left => GroupSide::Left,
+                OpGroupSide::Right => GroupSide::Right,
+            };
+            Some((side, group.labels))
+        }
+    };
+    let action = match modifier.action {
+        OpModAction::RestrictTo => MatchingAction::On,
+        OpModAction::Ignore => MatchingAction::Ignoring,
+    };
+    Some(VectorMatching {
+        action,
+        labels: modifier.labels,
+        group,
+    })
+}
+
+fn translate_vector(vector: Vector) -> Result<Expr, Error> {
+    let seconds = |seconds: usize| Duration::from_millis(seconds as i64 * 1000);
     let mut name = None;
-    let mut filters = Vec::with_capacity(vector.labels.len() - 1);
+    let mut filters = Vec::with_capacity(vector.labels.len().saturating_sub(1));
     for label in vector.labels {
         if label.name == "__name__" {
             name = Some(label.value);
@@ -70,8 +260,6 @@ fn translate_vector(
         }
     }
 
-    let functions = function.map_or_else(Vec::new, |name| vec![Function { name }]);
-
     Ok(Expr {
         resource: Resource {
             catalog: None,
@@ -79,21 +267,34 @@ fn translate_vector(
             resource: name.ok_or(Error::NoName)?,
         },
         filters,
-        range,
+        window: vector.range.map(seconds),
+        offset: vector.offset.map(seconds),
         projection: vec![Projection {
             name: String::from("value"),
             pipeline: Pipeline {
-                functions,
+                functions: Vec::new(),
                 breaker: None,
             },
         }],
-        aggregation,
+        aggregation: None,
     })
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::promql::parse;
+    use crate::rosetta::{
+        AggregateAction, BinaryOperator, Expr, GroupSide, MatchingAction, Node, Parameter,
+    };
+    use common::time::Duration;
+
+    fn expr(query: &str) -> Expr {
+        match parse(query).unwrap() {
+            Node::Expr(expr) => expr,
+            node => panic!("{:?} is not a selection", node),
+        }
+    }
 
     #[test]
     fn it_works() {
@@ -101,4 +302,130 @@ mod tests {
         let expr = parse(query).unwrap();
         println!("{:?}", expr);
     }
+
+    #[test]
+    fn aggregations() {
+        let expr = expr("sum without (env) (rate(requests[5m]))");
+        let aggregation = expr.aggregation.unwrap();
+        assert_eq!(aggregation.operator, "sum");
+        assert!(matches!(aggregation.action, AggregateAction::Without));
+        assert_eq!(aggregation.labels, vec!["env"]);
+        assert_eq!(expr.projection[0].pipeline.functions[0].name, "rate");
+
+        let aggregation = self::expr("topk(3, requests)").aggregation.unwrap();
+        assert_eq!(aggregation.parameter, Some(Parameter::Number(3.0)));
+        assert!(aggregation.labels.is_empty());
+        let aggregation = self::expr("quantile(0.9, requests)").aggregation;
+        assert_eq!(aggregation.unwrap().parameter, Some(Parameter::Number(0.9)));
+        let aggregation = self::expr("count_values(\"state\", doors)").aggregation;
+        assert_eq!(
+            aggregation.unwrap().parameter,
+            Some(Parameter::String(String::from("state")))
+        );
+        let expr = self::expr("quantile_over_time(0.5, latency[5m] offset 1h)");
+        assert_eq!(expr.window, Some(Duration::from_millis(300_000)));
+        assert_eq!(expr.offset, Some(Duration::from_millis(3_600_000)));
+        let function = &expr.projection[0].pipeline.functions[0];
+        assert_eq!(function.name, "quantile_over_time");
+        assert_eq!(function.parameters, vec![Parameter::Number(0.5)]);
+        assert!(matches!(
+            parse("topk(requests)"),
+            Err(Error::InvalidArguments { .. })
+        ));
+        assert!(matches!(
+            parse("quantile_over_time(latency[5m])"),
+            Err(Error::InvalidArguments { .. })
+        ));
+
+        // an aggregation of an aggregation is a node of its own
+        match parse("max(sum by (env) (requests))").unwrap() {
+            Node::Aggregation { aggregation, node } => {
+                assert_eq!(aggregation.operator, "max");
+                assert!(matches!(*node, Node::Expr(expr) if expr.aggregation.is_some()));
+            }
+            node => panic!("unexpected {:?}", node),
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        // functions of a selection are applied in turn
+        let expr = expr("abs(round(rate(requests[5m]), 10))");
+        let functions = &expr.projection[0].pipeline.functions;
+        let names = functions
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["rate", "round", "abs"]);
+        assert_eq!(functions[1].parameters, vec![Parameter::Number(10.0)]);
+
+        let expr = self::expr("label_replace(up, \"dst\", \"$1\", \"src\", \"(.*)\")");
+        let parameters = &expr.projection[0].pipeline.functions[0].parameters;
+        assert_eq!(parameters[0], Parameter::String(String::from("dst")));
+        assert_eq!(parameters.len(), 4);
+
+        match parse("abs(sum(requests))").unwrap() {
+            Node::Function { name, args } => {
+                assert_eq!(name, "abs");
+                assert!(matches!(args.as_slice(), [Node::Expr(_)]));
+            }
+            node => panic!("unexpected {:?}", node),
+        }
+
+        let query = "errors / ignoring (code) group_left (team) requests > bool 0.5";
+        match parse(query).unwrap() {
+            Node::Binary {
+                operator: BinaryOperator::Gt,
+                return_bool: true,
+                matching: None,
+                lhs,
+                rhs,
+            } => {
+                assert!(matches!(*rhs, Node::Number(n) if n == 0.5));
+                match *lhs {
+                    Node::Binary {
+                        operator: BinaryOperator::Div,
+                        matching: Some(matching),
+                        ..
+                    } => {
+                        assert_eq!(matching.action, MatchingAction::Ignoring);
+                        assert_eq!(matching.labels, vec!["code"]);
+                        assert_eq!(
+                            matching.group,
+                            Some((GroupSide::Left, vec![String::from("team")]))
+                        );
+                    }
+                    node => panic!("unexpected {:?}", node),
+                }
+            }
+            node => panic!("unexpected {:?}", node),
+        }
+
+        assert!(matches!(
+            parse("-requests").unwrap(),
+            Node::Negation(node) if matches!(*node, Node::Expr(_))
+        ));
+        assert!(matches!(
+            parse("a and on (env) b").unwrap(),
+            Node::Binary {
+                operator: BinaryOperator::And,
+                matching: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(parse("1 + 2").unwrap(), Node::Binary { .. }));
+
+        for invalid in [
+            "requests and 1",
+            "1 > 2",
+            "a and on (env) group_left b",
+            "1 + on (env) requests",
+        ] {
+            assert!(
+                matches!(parse(invalid), Err(Error::InvalidOperands { .. })),
+                "{}",
+                invalid
+            );
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use common::time::Instant;
+use common::time::{Duration, Instant};
 use common::{LabelType, LabelValue};
 
 #[derive(Debug)]
@@ -16,13 +16,26 @@ pub enum AggregateAction {
 
 #[derive(Debug, Clone)]
 pub struct Aggregation {
+    /// The aggregation operator, like `sum`.
+    pub operator: String,
+    /// The parameter of `topk`, `bottomk`, `quantile` and `count_values`.
+    pub parameter: Option<Parameter>,
     pub action: AggregateAction,
     pub labels: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parameter {
+    Number(f64),
+    String(String),
+}
+
 #[derive(Debug, Clone)]
 pub struct Function {
     pub name: String,
+    /// The literal arguments of the function besides the series it applies to, like the
+    /// quantile of `quantile_over_time`.
+    pub parameters: Vec<Parameter>,
 }
 
 #[derive(Debug)]
@@ -47,11 +60,124 @@ pub struct Range {
 pub struct Expr {
     pub resource: Resource,
     pub filters: Vec<Matcher>,
-    pub range: Range,
+    /// The duration of the windows of a range vector selector, like `5m` in `requests[5m]`.
+    pub window: Option<Duration>,
+    /// How long before the evaluation time series are selected at.
+    pub offset: Option<Duration>,
     pub projection: Vec<Projection>,
     pub aggregation: Option<Aggregation>,
 }
 
+/// A query, series selected by an [`Expr`] combined by functions and operators.
+#[derive(Debug)]
+pub enum Node {
+    /// Series selected from a resource, with the functions and aggregation applied to them.
+    Expr(Expr),
+    Number(f64),
+    String(String),
+    Negation(Box<Node>),
+    /// A function of nodes other than a single selection, like `abs(sum(requests))`.
+    Function {
+        name: String,
+        args: Vec<Node>,
+    },
+    /// An aggregation of a node other than a selection, like `max(sum by (env) (requests))`.
+    Aggregation {
+        aggregation: Aggregation,
+        node: Box<Node>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        /// Whether a comparison returns 0 or 1 instead of filtering, the `bool` modifier.
+        return_bool: bool,
+        matching: Option<VectorMatching>,
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Pow,
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    And,
+    Or,
+    Unless,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Eq
+                | BinaryOperator::Ne
+                | BinaryOperator::Lt
+                | BinaryOperator::Gt
+                | BinaryOperator::Le
+                | BinaryOperator::Ge
+        )
+    }
+
+    pub fn is_set(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Unless
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOperator::Pow => "^",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Eq => "==",
+            BinaryOperator::Ne => "!=",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+            BinaryOperator::Unless => "unless",
+        }
+    }
+}
+
+/// How the series of both sides of a binary operator are matched, by the labels given with `On`
+/// or every label but them with `Ignoring`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatching {
+    pub action: MatchingAction,
+    pub labels: Vec<String>,
+    /// The side many series of may match one of the other, with the labels copied from it.
+    pub group: Option<(GroupSide, Vec<String>)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchingAction {
+    On,
+    Ignoring,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GroupSide {
+    Left,
+    Right,
+}
+
 #[derive(Debug, Clone)]
 pub struct Matcher {
     pub name: String,
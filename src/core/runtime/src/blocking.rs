@@ -0,0 +1,62 @@
+use futures::channel::oneshot;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    /// The thread running the blocking work of the current thread, in the order it is sent.
+    static WORKER: Sender<Job> = {
+        let (sender, recv) = mpsc::channel::<Job>();
+        let name = format!("{}-blocking", thread::current().name().unwrap_or("worker"));
+        thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                // ends once the thread it works for is gone
+                while let Ok(job) = recv.recv() {
+                    // a panic is reported to whoever waits for the job instead
+                    let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                }
+            })
+            .unwrap();
+        sender
+    };
+}
+
+/// Runs `f`, which blocks like file IO does, off the executor of the current thread. Blocking
+/// work of a thread runs one at a time on a thread of its own.
+pub async fn unblock<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (ret, ret_recv) = oneshot::channel();
+    WORKER.with(|worker| {
+        worker
+            .send(Box::new(move || {
+                let _ = ret.send(f());
+            }))
+            .unwrap()
+    });
+    ret_recv.await.expect("blocking work panicked")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::unblock;
+    use futures_lite::future;
+    use std::thread;
+
+    #[test]
+    fn test_unblock() {
+        let current = thread::current().id();
+        let (first, second) = future::block_on(async {
+            let first = unblock(move || thread::current().id() != current);
+            let second = unblock(|| thread::current().id());
+            (first.await, second.await)
+        });
+        assert!(first);
+        assert_ne!(second, current);
+    }
+}
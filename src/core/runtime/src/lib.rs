@@ -2,10 +2,12 @@
 #![feature(cell_update)]
 #![feature(can_vector)]
 
+mod blocking;
 mod error;
 mod executor;
 pub mod io;
 
+pub use blocking::unblock;
 pub use error::RuntimeError;
 pub use executor::spawn;
 pub use futures_lite::future::yield_now;
@@ -0,0 +1,543 @@
+use crate::function::floats;
+use arrow2::array::{
+    Array, BooleanArray, ListArray, MutableArray, MutableUtf8Array, PrimitiveArray, Utf8Array,
+};
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+use ql::rosetta::{AggregateAction, Aggregation, Parameter};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use storage::list_from_rows;
+
+/// The rows of a chunk grouped together, starting at the same slot with the same values of the
+/// labels aggregated by.
+struct Group<'a> {
+    start_at: i64,
+    labels: Vec<Option<&'a str>>,
+    rows: Vec<(&'a Chunk<Arc<dyn Array>>, usize)>,
+}
+
+/// A row of the aggregated chunk, with the series of each scalar column.
+struct Row {
+    start_at: i64,
+    labels: Vec<Option<String>>,
+    values: Vec<Option<Box<dyn Array>>>,
+}
+
+/// The offsets of the label and the scalar columns of `schema`, laid out as a scan returns it.
+fn columns(schema: &Schema) -> (Vec<usize>, Vec<usize>) {
+    (1..schema.fields.len()).partition(|index| schema.fields[*index].data_type == DataType::Utf8)
+}
+
+/// The label of `count_values` holding the values it counts.
+fn value_label_name(aggregation: &Aggregation) -> Option<&str> {
+    match (aggregation.operator.as_str(), &aggregation.parameter) {
+        ("count_values", Some(Parameter::String(label))) => Some(label),
+        _ => None,
+    }
+}
+
+/// The offsets of the label columns of `schema` rows are grouped by.
+fn grouping(aggregation: &Aggregation, schema: &Schema, labels: &[usize]) -> Vec<usize> {
+    let value_label = value_label_name(aggregation);
+    labels
+        .iter()
+        .copied()
+        .filter(|index| {
+            let name = schema.fields[*index].name.as_str();
+            let listed = aggregation.labels.iter().any(|label| label == name);
+            let grouped = match aggregation.action {
+                AggregateAction::With => listed,
+                AggregateAction::Without => !listed,
+            };
+            // the values counted replace the label of the same name
+            grouped && value_label != Some(name)
+        })
+        .collect()
+}
+
+/// Whether the operator selects series rather than aggregating them, keeping their labels.
+fn selects(aggregation: &Aggregation) -> bool {
+    matches!(aggregation.operator.as_str(), "topk" | "bottomk")
+}
+
+/// The schema of the chunk `aggregate` returns.
+pub(crate) fn aggregate_schema(aggregation: &Aggregation, schema: &Schema) -> Schema {
+    let (labels, scalars) = columns(schema);
+    let mut fields = vec![schema.fields[0].clone()];
+    match selects(aggregation) {
+        true => fields.extend(labels.iter().map(|index| schema.fields[*index].clone())),
+        false => fields.extend(
+            grouping(aggregation, schema, &labels)
+                .into_iter()
+                .map(|index| schema.fields[index].clone()),
+        ),
+    }
+    if let Some(label) = value_label_name(aggregation) {
+        fields.push(Field::new(label, DataType::Utf8, true));
+    }
+    let item = Field::new("item", DataType::Float64, true);
+    fields.extend(scalars.iter().map(|index| {
+        let data_type = DataType::List(Box::new(item.clone()));
+        Field::new(&schema.fields[*index].name, data_type, true)
+    }));
+    Schema::from(fields).with_metadata(schema.metadata.clone())
+}
+
+/// Aggregates the rows of `chunks`, laid out as `schema`, grouping them by the labels of
+/// `aggregation` with the chunk window they start at. Series are aggregated slot by slot.
+pub(crate) fn aggregate(
+    aggregation: &Aggregation,
+    schema: &Schema,
+    chunks: &[Chunk<Arc<dyn Array>>],
+) -> Chunk<Arc<dyn Array>> {
+    let (all_labels, scalars) = columns(schema);
+    let grouping = grouping(aggregation, schema, &all_labels);
+    let mut groups = Vec::<Group<'_>>::new();
+    let mut ids = HashMap::new();
+    for chunk in chunks {
+        let start_at = chunk[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i64>>()
+            .unwrap();
+        let label_arrays = grouping
+            .iter()
+            .map(|index| {
+                chunk[*index]
+                    .as_any()
+                    .downcast_ref::<Utf8Array<i32>>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for row in 0..chunk.len() {
+            let labels = label_arrays
+                .iter()
+                .map(|array| array.is_valid(row).then(|| array.value(row)))
+                .collect::<Vec<_>>();
+            let start_at = start_at.value(row);
+            let id = *ids.entry((start_at, labels.clone())).or_insert_with(|| {
+                groups.push(Group {
+                    start_at,
+                    labels,
+                    rows: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            groups[id].rows.push((chunk, row));
+        }
+    }
+
+    let mut rows = Vec::new();
+    for group in &groups {
+        // the series of each scalar column the rows of the group have
+        let series = scalars
+            .iter()
+            .map(|index| {
+                group
+                    .rows
+                    .iter()
+                    .map(|(chunk, row)| {
+                        let list = chunk[*index]
+                            .as_any()
+                            .downcast_ref::<ListArray<i32>>()
+                            .unwrap();
+                        list.is_valid(*row).then(|| list.value(*row))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let labels = group
+            .labels
+            .iter()
+            .map(|label| label.map(str::to_owned))
+            .collect::<Vec<_>>();
+        match (aggregation.operator.as_str(), &aggregation.parameter) {
+            ("topk" | "bottomk", Some(Parameter::Number(k))) => {
+                let top = aggregation.operator == "topk";
+                let mut selected = series
+                    .iter()
+                    .map(|series| select(series, *k, top))
+                    .collect::<Vec<_>>();
+                for (member, (chunk, row)) in group.rows.iter().enumerate() {
+                    let values = selected
+                        .iter_mut()
+                        .map(|selected| selected[member].take())
+                        .collect::<Vec<_>>();
+                    if values.iter().all(Option::is_none) {
+                        continue;
+                    }
+                    // selected series keep every label they have
+                    let labels = all_labels
+                        .iter()
+                        .map(|index| {
+                            let array = chunk[*index]
+                                .as_any()
+                                .downcast_ref::<Utf8Array<i32>>()
+                                .unwrap();
+                            array.is_valid(*row).then(|| array.value(*row).to_owned())
+                        })
+                        .collect();
+                    rows.push(Row {
+                        start_at: group.start_at,
+                        labels,
+                        values,
+                    });
+                }
+            }
+            ("count_values", _) => {
+                let mut counted = BTreeMap::<String, Vec<Option<Box<dyn Array>>>>::new();
+                for (column, series) in series.iter().enumerate() {
+                    for (value, counts) in count_values(series) {
+                        let values = counted
+                            .entry(value)
+                            .or_insert_with(|| scalars.iter().map(|_| None).collect());
+                        values[column] = Some(Box::new(PrimitiveArray::from(counts)));
+                    }
+                }
+                for (value, values) in counted {
+                    let mut labels = labels.clone();
+                    labels.push(Some(value));
+                    rows.push(Row {
+                        start_at: group.start_at,
+                        labels,
+                        values,
+                    });
+                }
+            }
+            (operator, parameter) => {
+                let parameter = match parameter {
+                    Some(Parameter::Number(number)) => Some(*number),
+                    _ => None,
+                };
+                let values = series
+                    .iter()
+                    .map(|series| reduce(operator, parameter, series))
+                    .collect::<Vec<_>>();
+                if values.iter().all(Option::is_none) {
+                    continue;
+                }
+                rows.push(Row {
+                    start_at: group.start_at,
+                    labels,
+                    values,
+                });
+            }
+        }
+    }
+
+    let aggregated = aggregate_schema(aggregation, schema);
+    let label_len = aggregated.fields.len() - scalars.len() - 1;
+    let mut arrays = Vec::<Arc<dyn Array>>::with_capacity(aggregated.fields.len());
+    let start_at = rows.iter().map(|row| row.start_at).collect::<Vec<_>>();
+    arrays.push(Arc::new(PrimitiveArray::from_vec(start_at)));
+    for label in 0..label_len {
+        let mut array = MutableUtf8Array::<i32>::with_capacity(rows.len());
+        for row in &rows {
+            array.push(row.labels[label].as_deref());
+        }
+        arrays.push(array.as_arc());
+    }
+    let mut values = scalars.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+    for row in rows {
+        for (values, value) in values.iter_mut().zip(row.values) {
+            values.push(value);
+        }
+    }
+    for (field, values) in aggregated.fields[label_len + 1..].iter().zip(values) {
+        arrays.push(list_from_rows(&field.data_type, values));
+    }
+    Chunk::new(arrays)
+}
+
+/// The value of the slots of `series` aggregated by `operator`, `None` without any series
+/// having a value.
+fn reduce(
+    operator: &str,
+    parameter: Option<f64>,
+    series: &[Option<Box<dyn Array>>],
+) -> Option<Box<dyn Array>> {
+    let series = series.iter().flatten().collect::<Vec<_>>();
+    let len = series.iter().map(|series| series.len()).max()?;
+    let values = match operator {
+        // any scalar counts, not only numbers
+        "count" | "group" => (0..len)
+            .map(|slot| {
+                let count = series
+                    .iter()
+                    .filter(|series| slot < series.len() && series.is_valid(slot))
+                    .count();
+                match count {
+                    0 => None,
+                    _ if operator == "group" => Some(1.0),
+                    count => Some(count as f64),
+                }
+            })
+            .collect::<Vec<_>>(),
+        _ => {
+            let floats = series
+                .iter()
+                .map(|series| floats(series.as_ref()))
+                .collect::<Vec<_>>();
+            (0..len)
+                .map(|slot| {
+                    let values = floats
+                        .iter()
+                        .filter_map(|floats| floats.get(slot).copied().flatten())
+                        .collect::<Vec<_>>();
+                    (!values.is_empty()).then(|| reduce_values(operator, parameter, values))
+                })
+                .collect()
+        }
+    };
+    if values.iter().all(Option::is_none) {
+        return None;
+    }
+    Some(Box::new(PrimitiveArray::from(values)))
+}
+
+fn reduce_values(operator: &str, parameter: Option<f64>, mut values: Vec<f64>) -> f64 {
+    let len = values.len() as f64;
+    let mean = || values.iter().sum::<f64>() / len;
+    let variance = || {
+        let mean = mean();
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / len
+    };
+    match operator {
+        "sum" => values.iter().sum(),
+        "avg" => mean(),
+        // NaN is only the minimum or maximum if every value is NaN
+        "min" => values.iter().copied().fold(f64::NAN, f64::min),
+        "max" => values.iter().copied().fold(f64::NAN, f64::max),
+        "stdvar" => variance(),
+        "stddev" => variance().sqrt(),
+        "quantile" => quantile(parameter.unwrap_or(f64::NAN), &mut values),
+        _ => unreachable!(),
+    }
+}
+
+/// The `q` quantile of `values`, interpolating linearly between the closest ranks as Prometheus
+/// does.
+pub(crate) fn quantile(q: f64, values: &mut [f64]) -> f64 {
+    if q.is_nan() {
+        return f64::NAN;
+    }
+    if q < 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if q > 1.0 {
+        return f64::INFINITY;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = q * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = (lower + 1).min(values.len() - 1);
+    let weight = rank - rank.floor();
+    values[lower] * (1.0 - weight) + values[upper] * weight
+}
+
+/// The slots of each of `series` among the `k` largest of their slot if `top`, the `k` smallest
+/// otherwise. NaN ranks last either way.
+fn select(series: &[Option<Box<dyn Array>>], k: f64, top: bool) -> Vec<Option<Box<dyn Array>>> {
+    let floats = series
+        .iter()
+        .map(|series| {
+            series
+                .as_ref()
+                .map_or_else(Vec::new, |s| floats(s.as_ref()))
+        })
+        .collect::<Vec<_>>();
+    let len = floats.iter().map(Vec::len).max().unwrap_or(0);
+    let mut selected = floats
+        .iter()
+        .map(|floats| vec![None; floats.len()])
+        .collect::<Vec<_>>();
+    let k = k.max(0.0) as usize;
+    let chosen = (0..len).flat_map(|slot| {
+        let mut ranked = floats
+            .iter()
+            .enumerate()
+            .filter_map(|(member, floats)| Some((member, floats.get(slot).copied().flatten()?)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(_, a), (_, b)| {
+            let order = if top { b.total_cmp(a) } else { a.total_cmp(b) };
+            a.is_nan().cmp(&b.is_nan()).then(order)
+        });
+        ranked.truncate(k);
+        ranked
+            .into_iter()
+            .map(move |(member, value)| (member, slot, value))
+    });
+    for (member, slot, value) in chosen {
+        selected[member][slot] = Some(value);
+    }
+    selected
+        .into_iter()
+        .map(|slots| {
+            let selected = slots.iter().any(Option::is_some);
+            selected.then(|| Box::new(PrimitiveArray::from(slots)) as Box<dyn Array>)
+        })
+        .collect()
+}
+
+/// Counts the slots of `series` having each value, keyed by the value as `count_values` labels
+/// it. Histograms and summaries have no such label and are not counted.
+fn count_values(series: &[Option<Box<dyn Array>>]) -> BTreeMap<String, Vec<Option<f64>>> {
+    let mut counts = BTreeMap::<String, Vec<Option<f64>>>::new();
+    let len = series.iter().flatten().map(|series| series.len()).max();
+    for series in series.iter().flatten() {
+        for slot in 0..series.len() {
+            let value = match value_label(series.as_ref(), slot) {
+                None => continue,
+                Some(value) => value,
+            };
+            let slots = counts
+                .entry(value)
+                .or_insert_with(|| vec![None; len.unwrap_or(0)]);
+            *slots[slot].get_or_insert(0.0) += 1.0;
+        }
+    }
+    counts
+}
+
+/// The value at `slot` of `series` as a label value, formatted like Prometheus formats floats.
+fn value_label(series: &dyn Array, slot: usize) -> Option<String> {
+    if !series.is_valid(slot) {
+        return None;
+    }
+    let series = series.as_any();
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<i64>>() {
+        return Some(series.value(slot).to_string());
+    }
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<f64>>() {
+        let value = series.value(slot);
+        return Some(match value {
+            _ if value == f64::INFINITY => String::from("+Inf"),
+            _ if value == f64::NEG_INFINITY => String::from("-Inf"),
+            _ => value.to_string(),
+        });
+    }
+    if let Some(series) = series.downcast_ref::<BooleanArray>() {
+        return Some(series.value(slot).to_string());
+    }
+    series
+        .downcast_ref::<Utf8Array<i32>>()
+        .map(|series| series.value(slot).to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aggregate::{aggregate, aggregate_schema};
+    use arrow2::array::{
+        Array, ListArray, MutableArray, MutableListArray, MutablePrimitiveArray, MutableUtf8Array,
+        PrimitiveArray, TryExtend, Utf8Array,
+    };
+    use arrow2::chunk::Chunk;
+    use arrow2::datatypes::{DataType, Field, Schema};
+    use ql::rosetta::{AggregateAction, Aggregation, Parameter};
+    use std::sync::Arc;
+
+    /// The labels and slots of every row of an aggregated chunk.
+    fn rows(chunk: &Chunk<Arc<dyn Array>>) -> Vec<String> {
+        (0..chunk.len())
+            .map(|row| {
+                let mut columns = Vec::new();
+                for array in &chunk.arrays()[1..] {
+                    if let Some(labels) = array.as_any().downcast_ref::<Utf8Array<i32>>() {
+                        columns.push(labels.value(row).to_owned());
+                        continue;
+                    }
+                    let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+                    let values = list.value(row);
+                    let values = values
+                        .as_any()
+                        .downcast_ref::<PrimitiveArray<f64>>()
+                        .unwrap();
+                    columns.push(format!("{:?}", values.iter().collect::<Vec<_>>()));
+                }
+                columns.join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aggregations() {
+        let list = DataType::List(Box::new(Field::new("item", DataType::Float64, true)));
+        let schema = Schema::from(vec![
+            Field::new("start_at", DataType::Int64, false),
+            Field::new("env", DataType::Utf8, true),
+            Field::new("instance", DataType::Utf8, true),
+            Field::new("value", list, true),
+        ]);
+        let mut values = MutableListArray::<i32, MutablePrimitiveArray<f64>>::new();
+        values
+            .try_extend([
+                Some(vec![Some(1.0), Some(4.0)]),
+                Some(vec![Some(3.0), None]),
+                Some(vec![Some(2.0), Some(2.0)]),
+            ])
+            .unwrap();
+        let labels = |values: [&str; 3]| {
+            MutableUtf8Array::<i32>::from_iter(values.into_iter().map(Some)).as_arc()
+        };
+        let chunks = [Chunk::new(vec![
+            Arc::new(PrimitiveArray::from_vec(vec![0i64; 3])) as Arc<dyn Array>,
+            labels(["prod", "prod", "dev"]),
+            labels(["a", "b", "c"]),
+            values.into_arc(),
+        ])];
+        let aggregated = |operator: &str, parameter, action, labels: &[&str]| {
+            let aggregation = Aggregation {
+                operator: String::from(operator),
+                parameter,
+                action,
+                labels: labels.iter().map(|label| label.to_string()).collect(),
+            };
+            let chunk = aggregate(&aggregation, &schema, &chunks);
+            let fields = aggregate_schema(&aggregation, &schema).fields;
+            assert_eq!(fields.len(), chunk.arrays().len());
+            rows(&chunk)
+        };
+
+        assert_eq!(
+            aggregated("sum", None, AggregateAction::With, &["env"]),
+            vec!["prod [Some(4.0), Some(4.0)]", "dev [Some(2.0), Some(2.0)]"]
+        );
+        assert_eq!(
+            aggregated("max", None, AggregateAction::Without, &["instance"]),
+            aggregated("max", None, AggregateAction::With, &["env"]),
+        );
+        assert_eq!(
+            aggregated("count", None, AggregateAction::With, &[]),
+            vec!["[Some(3.0), Some(2.0)]"]
+        );
+        assert_eq!(
+            aggregated("stddev", None, AggregateAction::With, &["env"])[1],
+            "dev [Some(0.0), Some(0.0)]"
+        );
+        let median = Some(Parameter::Number(0.5));
+        assert_eq!(
+            aggregated("quantile", median, AggregateAction::With, &[]),
+            vec!["[Some(2.0), Some(3.0)]"]
+        );
+        let k = Some(Parameter::Number(1.0));
+        assert_eq!(
+            aggregated("topk", k, AggregateAction::With, &[]),
+            vec!["prod a [None, Some(4.0)]", "prod b [Some(3.0), None]"]
+        );
+        let label = Some(Parameter::String(String::from("value")));
+        assert_eq!(
+            aggregated(
+                "count_values",
+                label,
+                AggregateAction::Without,
+                &["instance"]
+            ),
+            vec![
+                "prod 1 [Some(1.0), None]",
+                "prod 3 [Some(1.0), None]",
+                "prod 4 [None, Some(1.0)]",
+                "dev 2 [Some(1.0), Some(1.0)]"
+            ]
+        );
+    }
+}
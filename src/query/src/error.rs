@@ -13,4 +13,10 @@ pub enum Error {
     InternalError { err: ArrowError },
     #[snafu(display("no such field: {:?}", name))]
     NoSuchField { name: String },
+    #[snafu(display("unsupported query: {:?}", query))]
+    UnsupportedQuery { query: String },
+    #[snafu(display("unsupported function: {:?}", name))]
+    UnsupportedFunction { name: String },
+    #[snafu(display("invalid query: {}", reason))]
+    InvalidQuery { reason: String },
 }
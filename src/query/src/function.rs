@@ -1,37 +1,807 @@
-use crate::{Error, QueryServer};
-use arrow2::array::Array;
+use crate::aggregate::quantile;
+use arrow2::array::{
+    new_null_array, Array, BinaryArray, BooleanArray, ListArray, MutableArray, MutableUtf8Array,
+    PrimitiveArray, Utf8Array,
+};
 use arrow2::chunk::Chunk;
-use arrow2::datatypes::Schema;
-use ql::rosetta::{Aggregation, Projection};
+use arrow2::compute::aggregate::{max_primitive, min_primitive, sum_primitive};
+use arrow2::compute::arithmetics::basic::{mul, sub_scalar};
+use arrow2::compute::arity::unary;
+use arrow2::compute::concatenate::concatenate;
+use arrow2::datatypes::{DataType, Field, Schema};
+use common::time::{Duration, Instant};
+use context::Alignment;
+use ql::rosetta::{Function, Parameter, Projection, Range};
+use std::collections::HashMap;
 use std::sync::Arc;
+use storage::list_from_rows;
 
-impl QueryServer {
-    pub(crate) fn compute(
-        &self,
-        projections: &[Projection],
-        aggregations: Option<Aggregation>,
-        schema: &Schema,
-        chunks: &[Chunk<Arc<dyn Array>>],
-    ) -> Result<(), Error> {
-        if let Some(aggregations) = aggregations {
-            for projection in aggregations.labels {
-                let offset = schema
-                    .fields
-                    .iter()
-                    .enumerate()
-                    .find(|(_, field)| field.name == projection)
-                    .ok_or_else(|| Error::NoSuchField {
-                        name: projection.clone(),
-                    })?
-                    .0;
-                for chunk in chunks {
-                    let array = &chunk[offset];
+/// A function computing one value out of the samples of a series within a window, `None` if
+/// the series has no value there.
+type RangeFunction = fn(&Window<'_>) -> Option<f64>;
+
+/// The function evaluating `name` over the samples of a table with the `alignment`.
+fn range_function(name: &str, alignment: Alignment) -> Option<RangeFunction> {
+    match name {
+        "rate" => Some(rate),
+        "increase" => Some(increase),
+        "delta" => Some(delta),
+        "irate" => Some(irate),
+        "idelta" => Some(idelta),
+        "changes" => Some(changes),
+        "resets" => Some(resets),
+        "avg_over_time" => Some(avg_over_time),
+        "sum_over_time" => Some(sum_over_time),
+        // each slot of a count rollup holds how many samples fell into it
+        "count_over_time" if alignment == Alignment::Count => Some(sum_over_time),
+        "count_over_time" => Some(count_over_time),
+        "min_over_time" => Some(min_over_time),
+        "max_over_time" => Some(max_over_time),
+        "last_over_time" => Some(last_over_time),
+        "quantile_over_time" => Some(quantile_over_time),
+        "stddev_over_time" => Some(stddev_over_time),
+        "stdvar_over_time" => Some(stdvar_over_time),
+        "present_over_time" => Some(present_over_time),
+        _ => None,
+    }
+}
+
+/// The range function `projections` apply to the column `name`, if any.
+fn projection_function<'a>(projections: &'a [Projection], name: &str) -> Option<&'a Function> {
+    projections
+        .iter()
+        .filter(|projection| projection.name == name)
+        .flat_map(|projection| projection.pipeline.functions.iter())
+        .find(|function| range_function(&function.name, Alignment::LastWrite).is_some())
+}
+
+/// The first function of `projections` `evaluate` cannot compute, any besides a single range
+/// function per projection.
+pub(crate) fn unsupported_function(projections: &[Projection]) -> Option<&str> {
+    projections
+        .iter()
+        .flat_map(|projection| projection.pipeline.functions.iter().enumerate())
+        .find(|(index, function)| {
+            *index > 0 || range_function(&function.name, Alignment::LastWrite).is_none()
+        })
+        .map(|(_, function)| function.name.as_str())
+}
+
+/// Whether any of `projections` applies a range function, which `evaluate` has to compute.
+pub(crate) fn has_range_function(projections: &[Projection]) -> bool {
+    projections
+        .iter()
+        .any(|projection| projection_function(projections, &projection.name).is_some())
+}
+
+/// The samples of a series within the window a range function is evaluated over.
+pub(crate) struct Window<'a> {
+    /// Milliseconds since the epoch the window starts and ends at.
+    start: i64,
+    end: i64,
+    /// The timestamp of each slot of `values`, ascending.
+    timestamps: &'a [i64],
+    values: &'a dyn Array,
+    /// The number parameter of the function, like the quantile of `quantile_over_time`.
+    parameter: Option<f64>,
+}
+
+impl Window<'_> {
+    /// The timestamp and value of every sample of a number, booleans counting as 0 and 1. Other
+    /// scalars have none.
+    fn floats(&self) -> Vec<(i64, f64)> {
+        self.timestamps
+            .iter()
+            .zip(floats(self.values))
+            .filter_map(|(timestamp, value)| Some((*timestamp, value?)))
+            .collect()
+    }
+
+    /// The samples of a number as floats, booleans counting as 0 and 1, `None` for other
+    /// scalars.
+    fn numbers(&self) -> Option<PrimitiveArray<f64>> {
+        let values = self.values.as_any();
+        if let Some(values) = values.downcast_ref::<PrimitiveArray<f64>>() {
+            return Some(values.clone());
+        }
+        if let Some(values) = values.downcast_ref::<PrimitiveArray<i64>>() {
+            return Some(unary(values, |v| v as f64, DataType::Float64));
+        }
+        let values = values.downcast_ref::<BooleanArray>()?;
+        Some(
+            PrimitiveArray::from_trusted_len_values_iter(
+                values.values().iter().map(|v| v as u8 as f64),
+            )
+            .with_validity(values.validity().cloned()),
+        )
+    }
+}
+
+/// The value of every slot of a series of numbers, booleans counting as 0 and 1. Series of
+/// other scalars have none.
+pub(crate) fn floats(series: &dyn Array) -> Vec<Option<f64>> {
+    let series = series.as_any();
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<f64>>() {
+        return series.iter().map(|v| v.copied()).collect();
+    }
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<i64>>() {
+        return series.iter().map(|v| v.map(|v| *v as f64)).collect();
+    }
+    if let Some(series) = series.downcast_ref::<BooleanArray>() {
+        return series.iter().map(|v| v.map(|v| v as u8 as f64)).collect();
+    }
+    Vec::new()
+}
+
+/// The instants a query is evaluated at, every `step` from `start` to `end`, over the samples
+/// within `range` before each, `offset` earlier.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Steps {
+    pub(crate) start: Instant,
+    pub(crate) end: Instant,
+    /// Zero for instant queries, evaluated at `start` only.
+    pub(crate) step: Duration,
+    pub(crate) range: Duration,
+    pub(crate) offset: Duration,
+}
+
+impl Steps {
+    pub(crate) fn len(&self) -> usize {
+        match self.step.as_millis() {
+            0 => 1,
+            step => ((self.end - self.start).as_millis() / step) as usize + 1,
+        }
+    }
+
+    /// Milliseconds since the epoch of each step.
+    fn instants(&self) -> impl Iterator<Item = i64> {
+        let (start, step) = (self.start.as_millis(), self.step.as_millis());
+        (0..self.len() as i64).map(move |index| start + step * index)
+    }
+
+    /// The range to scan for the samples of every step, with the slot of the last instant.
+    pub(crate) fn scan_range(&self, time_interval: Duration) -> Range {
+        Range {
+            start: Some(self.start - self.offset - self.range),
+            end: Some(self.end - self.offset + time_interval),
+        }
+    }
+}
+
+/// The schema of the chunk `evaluate` returns, with the slots of its lists a step apart. The
+/// interval of the scanned rows is left out.
+pub(crate) fn evaluate_schema(
+    projections: &[Projection],
+    schema: &Schema,
+    steps: &Steps,
+) -> Schema {
+    let fields = schema.fields[..1]
+        .iter()
+        .chain(&schema.fields[2..])
+        .map(
+            |field| match projection_function(projections, &field.name) {
+                None => field.clone(),
+                Some(_) => {
+                    let item = Field::new("item", DataType::Float64, true);
+                    Field::new(&field.name, DataType::List(Box::new(item)), true)
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    let mut metadata = schema.metadata.clone();
+    if steps.step.as_millis() > 0 {
+        metadata.insert(
+            String::from("time_interval"),
+            steps.step.as_millis().to_string(),
+        );
+    }
+    Schema::from(fields).with_metadata(metadata)
+}
+
+/// The first slot and the interval of a row of a series, with its chunk and offset.
+type SeriesRow<'a> = (i64, i64, &'a Chunk<Arc<dyn Array>>, usize);
+
+/// Evaluates the series of `chunks`, laid out as `schema` and stored with the `alignment` of
+/// their table, at each of `steps`. The rows of a series in each chunk are put together first.
+/// Columns with a range function get its value over the samples of each window, others the
+/// last sample of each window, which is the lookback of instant vectors. Each series gets a row
+/// starting at the first step with a slot per step. Series without any value are left out.
+pub(crate) fn evaluate(
+    projections: &[Projection],
+    schema: &Schema,
+    chunks: &[Chunk<Arc<dyn Array>>],
+    alignment: Alignment,
+    steps: &Steps,
+) -> Chunk<Arc<dyn Array>> {
+    let (labels, scalars) = (2..schema.fields.len())
+        .partition::<Vec<_>, _>(|index| schema.fields[*index].data_type == DataType::Utf8);
+    // the labels of each series with the first slot, interval, chunk and offset of its rows
+    let mut series = Vec::<(Vec<Option<&str>>, Vec<SeriesRow>)>::new();
+    let mut ids = HashMap::new();
+    for chunk in chunks {
+        let int64 = |array: &Arc<dyn Array>| {
+            array
+                .as_any()
+                .downcast_ref::<PrimitiveArray<i64>>()
+                .unwrap()
+                .clone()
+        };
+        let (start_at, time_interval) = (int64(&chunk[0]), int64(&chunk[1]));
+        let label_arrays = labels
+            .iter()
+            .map(|index| {
+                chunk[*index]
+                    .as_any()
+                    .downcast_ref::<Utf8Array<i32>>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for row in 0..chunk.len() {
+            let key = label_arrays
+                .iter()
+                .map(|array| array.is_valid(row).then(|| array.value(row)))
+                .collect::<Vec<_>>();
+            let id = *ids.entry(key.clone()).or_insert_with(|| {
+                series.push((key, Vec::new()));
+                series.len() - 1
+            });
+            series[id]
+                .1
+                .push((start_at.value(row), time_interval.value(row), chunk, row));
+        }
+    }
+
+    let (range, offset) = (steps.range.as_millis(), steps.offset.as_millis());
+    let mut label_columns = labels
+        .iter()
+        .map(|_| MutableUtf8Array::<i32>::new())
+        .collect::<Vec<_>>();
+    let mut scalar_rows = scalars.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+    let mut len = 0;
+    for (key, mut rows) in series {
+        rows.sort_by_key(|(start_at, ..)| *start_at);
+        let mut values = Vec::with_capacity(scalars.len());
+        for index in &scalars {
+            let field = &schema.fields[*index];
+            let mut timestamps = Vec::new();
+            let mut slices = Vec::with_capacity(rows.len());
+            for (start_at, interval, chunk, row) in &rows {
+                let list = chunk[*index]
+                    .as_any()
+                    .downcast_ref::<ListArray<i32>>()
+                    .unwrap();
+                if list.is_valid(*row) {
+                    let slice = list.value(*row);
+                    let slots = 0..slice.len() as i64;
+                    timestamps.extend(slots.map(|slot| start_at + interval * slot));
+                    slices.push(slice);
                 }
             }
+            let slices = slices
+                .iter()
+                .map(|slice| slice.as_ref())
+                .collect::<Vec<_>>();
+            let samples = match slices.as_slice() {
+                [] => {
+                    values.push(None);
+                    continue;
+                }
+                slices => concatenate(slices).unwrap(),
+            };
+            // the samples of each step are the slots within (end - range, end]
+            let windows = steps.instants().map(|instant| {
+                let end = instant - offset;
+                let first = timestamps.partition_point(|timestamp| *timestamp <= end - range);
+                let last = timestamps.partition_point(|timestamp| *timestamp <= end);
+                (end - range, end, first..last)
+            });
+            values.push(match projection_function(projections, &field.name) {
+                Some(function) => {
+                    let evaluate = range_function(&function.name, alignment).unwrap();
+                    let parameter = match function.parameters.first() {
+                        Some(Parameter::Number(number)) => Some(*number),
+                        _ => None,
+                    };
+                    let results = windows
+                        .map(|(start, end, slots)| {
+                            evaluate(&Window {
+                                start,
+                                end,
+                                timestamps: &timestamps[slots.clone()],
+                                values: samples.slice(slots.start, slots.len()).as_ref(),
+                                parameter,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    results
+                        .iter()
+                        .any(Option::is_some)
+                        .then(|| Box::new(PrimitiveArray::from(results)) as Box<dyn Array>)
+                }
+                None => {
+                    let last = windows
+                        .map(|(_, _, slots)| slots.rev().find(|slot| samples.is_valid(*slot)))
+                        .collect::<Vec<_>>();
+                    last.iter().any(Option::is_some).then(|| {
+                        let data_type = samples.data_type().clone();
+                        let slots = last
+                            .iter()
+                            .map(|slot| match slot {
+                                Some(slot) => samples.slice(*slot, 1),
+                                None => new_null_array(data_type.clone(), 1),
+                            })
+                            .collect::<Vec<_>>();
+                        let slots = slots.iter().map(|slot| slot.as_ref()).collect::<Vec<_>>();
+                        concatenate(&slots).unwrap()
+                    })
+                }
+            });
         }
+        if values.iter().all(Option::is_none) {
+            continue;
+        }
+        len += 1;
+        for (column, label) in label_columns.iter_mut().zip(key) {
+            column.push(label);
+        }
+        for (rows, value) in scalar_rows.iter_mut().zip(values) {
+            rows.push(value);
+        }
+    }
 
-        todo!()
+    let evaluated = evaluate_schema(projections, schema, steps);
+    let mut arrays = Vec::<Arc<dyn Array>>::with_capacity(schema.fields.len());
+    let start_at = steps.start.as_millis();
+    arrays.push(Arc::new(PrimitiveArray::from_vec(vec![start_at; len])));
+    arrays.extend(label_columns.into_iter().map(|mut column| column.as_arc()));
+    for (index, rows) in scalars.iter().zip(scalar_rows) {
+        arrays.push(list_from_rows(
+            &evaluated.fields[*index - 1].data_type,
+            rows,
+        ));
     }
+    Chunk::new(arrays)
+}
+
+/// The difference between the first and the last sample of a counter in the window,
+/// extrapolated to the whole window, per second.
+fn rate(window: &Window<'_>) -> Option<f64> {
+    extrapolated_rate(window, true, true)
+}
+
+/// The difference between the first and the last sample of a counter in the window,
+/// extrapolated to the whole window.
+fn increase(window: &Window<'_>) -> Option<f64> {
+    extrapolated_rate(window, true, false)
+}
+
+/// The difference between the first and the last sample of a gauge in the window,
+/// extrapolated to the whole window.
+fn delta(window: &Window<'_>) -> Option<f64> {
+    extrapolated_rate(window, false, false)
+}
+
+/// The difference between the last two samples of a counter in the window, per second.
+fn irate(window: &Window<'_>) -> Option<f64> {
+    instant_value(window, true)
+}
+
+/// The difference between the last two samples of a gauge in the window.
+fn idelta(window: &Window<'_>) -> Option<f64> {
+    instant_value(window, false)
+}
 
-    fn sum(&self) {}
+/// The difference between the first and the last sample, extrapolated to the bounds of the
+/// window as Prometheus does when the samples get close enough to them. Counters are corrected
+/// for resets and not extrapolated below zero.
+fn extrapolated_rate(window: &Window<'_>, counter: bool, per_second: bool) -> Option<f64> {
+    let samples = window.floats();
+    if samples.len() < 2 {
+        return None;
+    }
+    let (first, last) = (samples[0], samples[samples.len() - 1]);
+    let mut result = last.1 - first.1;
+    if counter {
+        // a counter going down was reset, losing the value it had before
+        result += samples
+            .windows(2)
+            .filter(|pair| pair[1].1 < pair[0].1)
+            .map(|pair| pair[0].1)
+            .sum::<f64>();
+    }
+    let seconds = |millis: i64| millis as f64 / 1000.0;
+    let mut to_start = seconds(first.0 - window.start);
+    let to_end = seconds(window.end - last.0);
+    let sampled = seconds(last.0 - first.0);
+    let average = sampled / (samples.len() - 1) as f64;
+    if counter && result > 0.0 && first.1 >= 0.0 {
+        to_start = to_start.min(sampled * first.1 / result);
+    }
+    // the series is assumed to go on to a bound closer than 1.1 sample intervals, otherwise it
+    // is assumed to start or end half an interval past its samples
+    let threshold = average * 1.1;
+    let mut extrapolated = sampled;
+    extrapolated += if to_start < threshold {
+        to_start
+    } else {
+        average / 2.0
+    };
+    extrapolated += if to_end < threshold {
+        to_end
+    } else {
+        average / 2.0
+    };
+    result *= extrapolated / sampled;
+    if per_second {
+        result /= seconds(window.end - window.start);
+    }
+    Some(result)
+}
+
+/// The difference between the last two samples, per second of their distance for counters,
+/// where a reset makes the last sample the difference.
+fn instant_value(window: &Window<'_>, counter: bool) -> Option<f64> {
+    let samples = window.floats();
+    if samples.len() < 2 {
+        return None;
+    }
+    let (previous, last) = (samples[samples.len() - 2], samples[samples.len() - 1]);
+    if !counter {
+        return Some(last.1 - previous.1);
+    }
+    let difference = if last.1 < previous.1 {
+        last.1
+    } else {
+        last.1 - previous.1
+    };
+    Some(difference / ((last.0 - previous.0) as f64 / 1000.0))
+}
+
+/// How many times the value of a series changed from one sample to the next. Any scalar can
+/// change; histograms and summaries change when any of their fields does.
+fn changes(window: &Window<'_>) -> Option<f64> {
+    let series = window.values.as_any();
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<i64>>() {
+        return count_changes(series.iter(), |a, b| a == b);
+    }
+    if let Some(series) = series.downcast_ref::<PrimitiveArray<f64>>() {
+        // a NaN following a NaN is no change
+        return count_changes(series.iter(), |a, b| a == b || a.is_nan() && b.is_nan());
+    }
+    if let Some(series) = series.downcast_ref::<BooleanArray>() {
+        return count_changes(series.iter(), |a, b| a == b);
+    }
+    if let Some(series) = series.downcast_ref::<Utf8Array<i32>>() {
+        return count_changes(series.iter(), |a, b| a == b);
+    }
+    let series = series.downcast_ref::<BinaryArray<i64>>().unwrap();
+    count_changes(series.iter(), |a, b| a == b)
+}
+
+fn count_changes<T>(
+    samples: impl Iterator<Item = Option<T>>,
+    eq: impl Fn(&T, &T) -> bool,
+) -> Option<f64> {
+    let mut samples = samples.flatten();
+    let mut previous = samples.next()?;
+    let mut changes = 0.0;
+    for sample in samples {
+        if !eq(&previous, &sample) {
+            changes += 1.0;
+        }
+        previous = sample;
+    }
+    Some(changes)
+}
+
+/// How many times a counter went down from one sample to the next.
+fn resets(window: &Window<'_>) -> Option<f64> {
+    let samples = window.floats();
+    samples.first()?;
+    let resets = samples
+        .windows(2)
+        .filter(|pair| pair[1].1 < pair[0].1)
+        .count();
+    Some(resets as f64)
+}
+
+/// The number of samples of any scalar in the window.
+fn count(window: &Window<'_>) -> Option<f64> {
+    let count = window.values.len() - window.values.null_count();
+    (count > 0).then_some(count as f64)
+}
+
+fn sum_over_time(window: &Window<'_>) -> Option<f64> {
+    sum_primitive(&window.numbers()?)
+}
+
+fn count_over_time(window: &Window<'_>) -> Option<f64> {
+    count(window)
+}
+
+fn avg_over_time(window: &Window<'_>) -> Option<f64> {
+    Some(sum_over_time(window)? / count(window)?)
+}
+
+fn min_over_time(window: &Window<'_>) -> Option<f64> {
+    min_primitive(&window.numbers()?)
+}
+
+fn max_over_time(window: &Window<'_>) -> Option<f64> {
+    max_primitive(&window.numbers()?)
+}
+
+fn last_over_time(window: &Window<'_>) -> Option<f64> {
+    window.numbers()?.iter().flatten().last().copied()
+}
+
+/// The quantile `parameter` of the samples in the window.
+fn quantile_over_time(window: &Window<'_>) -> Option<f64> {
+    let mut values = window
+        .numbers()?
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+    Some(quantile(window.parameter.unwrap_or(f64::NAN), &mut values))
+}
+
+/// The population variance of the samples in the window.
+fn stdvar_over_time(window: &Window<'_>) -> Option<f64> {
+    let numbers = window.numbers()?;
+    let count = count(window)?;
+    let deviations = sub_scalar(&numbers, &(sum_primitive(&numbers)? / count));
+    Some(sum_primitive(&mul(&deviations, &deviations))? / count)
+}
+
+fn stddev_over_time(window: &Window<'_>) -> Option<f64> {
+    stdvar_over_time(window).map(f64::sqrt)
+}
+
+/// 1 for series with any sample in the window.
+fn present_over_time(window: &Window<'_>) -> Option<f64> {
+    count(window).map(|_| 1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::function::{
+        changes, delta, evaluate, evaluate_schema, idelta, increase, irate, range_function, rate,
+        resets, Steps, Window,
+    };
+    use arrow2::array::{
+        Array, BooleanArray, ListArray, MutableListArray, MutablePrimitiveArray, MutableUtf8Array,
+        PrimitiveArray, TryExtend, Utf8Array,
+    };
+    use arrow2::chunk::Chunk;
+    use arrow2::datatypes::{DataType, Field, Schema};
+    use common::time::{Duration, Instant};
+    use context::Alignment;
+    use ql::rosetta::{Function, Pipeline, Projection};
+    use std::sync::Arc;
+
+    fn window<'a>(timestamps: &'a [i64], values: &'a dyn Array) -> Window<'a> {
+        Window {
+            start: 0,
+            end: 60_000,
+            timestamps,
+            values,
+            parameter: None,
+        }
+    }
+
+    #[test]
+    fn counter_functions() {
+        let timestamps = [10_000, 20_000, 30_000, 40_000, 50_000];
+        let values = PrimitiveArray::<f64>::from_slice([10.0, 20.0, 5.0, 15.0, 25.0]);
+        let counter = window(&timestamps, &values);
+        assert_eq!(increase(&counter), Some(52.5));
+        assert_eq!(rate(&counter), Some(0.875));
+        assert_eq!(delta(&counter), Some(22.5));
+        assert_eq!(irate(&counter), Some(1.0));
+        assert_eq!(idelta(&counter), Some(10.0));
+
+        // a counter starting close to zero is not extrapolated below it
+        let values = PrimitiveArray::<f64>::from([None, Some(1.0), None, Some(9.0), None]);
+        assert_eq!(increase(&window(&timestamps, &values)), Some(17.0));
+        let values = PrimitiveArray::<f64>::from([None, None, Some(1.0), None, None]);
+        assert_eq!(rate(&window(&timestamps, &values)), None);
+        assert_eq!(irate(&window(&timestamps, &values)), None);
+    }
+
+    #[test]
+    fn evaluate_series() {
+        let list = DataType::List(Box::new(Field::new("item", DataType::Float64, true)));
+        let schema = Schema::from(vec![
+            Field::new("start_at", DataType::Int64, false),
+            Field::new("time_interval", DataType::Int64, false),
+            Field::new("env", DataType::Utf8, true),
+            Field::new("value", list, true),
+        ]);
+        let chunk = |start_at: i64, envs: &[&str], series: Vec<Option<Vec<Option<f64>>>>| {
+            let mut values = MutableListArray::<i32, MutablePrimitiveArray<f64>>::new();
+            values.try_extend(series).unwrap();
+            let envs = envs.iter().map(|env| Some(*env));
+            Chunk::new(vec![
+                Arc::new(PrimitiveArray::from_vec(vec![start_at; envs.len()])) as Arc<dyn Array>,
+                Arc::new(PrimitiveArray::from_vec(vec![10_000i64; envs.len()])),
+                MutableUtf8Array::<i32>::from_iter(envs).into_arc(),
+                values.into_arc(),
+            ])
+        };
+        // the later chunk is scanned first
+        let chunks = [
+            chunk(
+                30_000,
+                &["prod", "dev"],
+                vec![
+                    Some(vec![Some(5.0), Some(15.0), Some(25.0)]),
+                    Some(vec![Some(1.0), None, None]),
+                ],
+            ),
+            chunk(0, &["prod"], vec![Some(vec![None, Some(10.0), Some(20.0)])]),
+        ];
+        let projections = [Projection {
+            name: String::from("value"),
+            pipeline: Pipeline {
+                functions: vec![Function {
+                    name: String::from("rate"),
+                    parameters: Vec::new(),
+                }],
+                breaker: None,
+            },
+        }];
+        let evaluated = evaluate(
+            &projections,
+            &schema,
+            &chunks,
+            Alignment::LastWrite,
+            &Steps {
+                start: Instant::from_millis(60_000),
+                end: Instant::from_millis(60_000),
+                step: Duration::from_millis(0),
+                range: Duration::SECOND * 60u32,
+                offset: Duration::from_millis(0),
+            },
+        );
+        // the series of dev has a single sample, so no rate
+        assert_eq!(evaluated.len(), 1);
+        let envs = evaluated[1]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap();
+        assert_eq!(envs.value(0), "prod");
+        let values = evaluated[2]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap();
+        let value = values.value(0);
+        let value = value
+            .as_any()
+            .downcast_ref::<PrimitiveArray<f64>>()
+            .unwrap();
+        assert_eq!(value.values().as_slice(), &[0.875]);
+
+        // instant vectors get the last sample within the lookback of every step
+        let projections = [Projection {
+            name: String::from("value"),
+            pipeline: Pipeline {
+                functions: Vec::new(),
+                breaker: None,
+            },
+        }];
+        let steps = Steps {
+            start: Instant::from_millis(0),
+            end: Instant::from_millis(60_000),
+            step: Duration::SECOND * 20u32,
+            range: Duration::SECOND * 15u32,
+            offset: Duration::from_millis(0),
+        };
+        let evaluated = evaluate(&projections, &schema, &chunks, Alignment::LastWrite, &steps);
+        assert_eq!(evaluated.len(), 2);
+        let start_at = evaluated[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i64>>()
+            .unwrap();
+        assert_eq!(start_at.values().as_slice(), &[0, 0]);
+        let values = evaluated[2]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap();
+        let series = |row: usize| {
+            let value = values.value(row);
+            let value = value.as_any().downcast_ref::<PrimitiveArray<f64>>();
+            value
+                .unwrap()
+                .iter()
+                .map(|v| v.copied())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(series(0), vec![None, Some(20.0), Some(15.0), Some(25.0)]);
+        assert_eq!(series(1), vec![None, None, Some(1.0), None]);
+        let evaluated = evaluate_schema(&projections, &schema, &steps);
+        assert_eq!(evaluated.metadata["time_interval"], "20000");
+    }
+
+    #[test]
+    fn state_functions() {
+        let mut states = MutableListArray::<i32, MutableUtf8Array<i32>>::new();
+        states
+            .try_extend([
+                Some(vec![Some("OPEN"), None, Some("CLOSED"), Some("OPEN")]),
+                Some(vec![Some("CLOSED"), Some("CLOSED"), None, Some("OPEN")]),
+                None,
+            ])
+            .unwrap();
+        let states: ListArray<i32> = states.into();
+        let timestamps = [0, 1000, 2000, 3000];
+        let series = states.value(0);
+        assert_eq!(changes(&window(&timestamps, series.as_ref())), Some(2.0));
+        let series = states.value(1);
+        assert_eq!(changes(&window(&timestamps, series.as_ref())), Some(1.0));
+        let series = Utf8Array::<i32>::from([None::<&str>]);
+        assert_eq!(changes(&window(&timestamps, &series)), None);
+        let nan = PrimitiveArray::<f64>::from(vec![Some(f64::NAN), Some(f64::NAN), Some(1.0)]);
+        assert_eq!(changes(&window(&timestamps, &nan)), Some(1.0));
+    }
+
+    #[test]
+    fn over_time_functions() {
+        let timestamps = [10_000, 20_000, 30_000, 40_000, 50_000];
+        let values =
+            PrimitiveArray::<f64>::from([Some(3.0), None, Some(1.0), Some(4.0), Some(2.0)]);
+        let evaluate = |name: &str, window: &Window<'_>| {
+            range_function(name, Alignment::LastWrite).unwrap()(window)
+        };
+        let gauge = window(&timestamps, &values);
+        assert_eq!(evaluate("sum_over_time", &gauge), Some(10.0));
+        assert_eq!(evaluate("count_over_time", &gauge), Some(4.0));
+        assert_eq!(evaluate("avg_over_time", &gauge), Some(2.5));
+        assert_eq!(evaluate("min_over_time", &gauge), Some(1.0));
+        assert_eq!(evaluate("max_over_time", &gauge), Some(4.0));
+        assert_eq!(evaluate("last_over_time", &gauge), Some(2.0));
+        assert_eq!(evaluate("stdvar_over_time", &gauge), Some(1.25));
+        assert_eq!(evaluate("stddev_over_time", &gauge), Some(1.25f64.sqrt()));
+        assert_eq!(evaluate("present_over_time", &gauge), Some(1.0));
+        assert_eq!(resets(&gauge), Some(2.0));
+        let median = Window {
+            parameter: Some(0.5),
+            ..window(&timestamps, &values)
+        };
+        assert_eq!(evaluate("quantile_over_time", &median), Some(2.5));
+
+        // the counts of a count rollup are summed
+        let counts = PrimitiveArray::<i64>::from_slice([2, 3, 1, 0, 4]);
+        let rollup = window(&timestamps, &counts);
+        let count_over_time = range_function("count_over_time", Alignment::Count).unwrap();
+        assert_eq!(count_over_time(&rollup), Some(10.0));
+
+        // any scalar is counted, only numbers and booleans are summed
+        let states = Utf8Array::<i32>::from([Some("OPEN"), None, Some("CLOSED")]);
+        assert_eq!(
+            evaluate("count_over_time", &window(&timestamps, &states)),
+            Some(2.0)
+        );
+        assert_eq!(
+            evaluate("sum_over_time", &window(&timestamps, &states)),
+            None
+        );
+        let open = BooleanArray::from([Some(true), Some(false), Some(true)]);
+        assert_eq!(
+            evaluate("sum_over_time", &window(&timestamps, &open)),
+            Some(2.0)
+        );
+        let empty = PrimitiveArray::<f64>::from([None, None]);
+        assert_eq!(
+            evaluate("present_over_time", &window(&timestamps, &empty)),
+            None
+        );
+        assert_eq!(
+            evaluate("max_over_time", &window(&timestamps, &empty)),
+            None
+        );
+    }
 }
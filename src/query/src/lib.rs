@@ -1,17 +1,31 @@
+mod aggregate;
 pub mod error;
 mod function;
 
 use crate::error::Error;
+use crate::function::Steps;
 use arrow2::array::Array;
 use arrow2::chunk::Chunk;
 use arrow2::datatypes::Schema;
 use arrow2::io::ipc::write::{FileWriter, WriteOptions};
+use common::time::{Duration, Instant};
+use context::{Alignment, DEFAULT};
 use flat::query::{Language, QueryRequest};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
 use ql::promql::parse;
-use ql::rosetta::{Expr, Projection};
-use std::sync::Arc;
+use ql::rosetta::{Expr, Node, Range};
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
 use storage::StorageServer;
 
+/// How far back instant vectors look for the last sample of a series by default.
+const DEFAULT_LOOKBACK: Duration = Duration::from_millis(5 * 60 * 1000);
+
+/// The most steps a range query evaluates, as Prometheus allows.
+const MAX_STEPS: i64 = 11_000;
+
 #[derive(Debug)]
 pub struct QueryServer {
     storage: Arc<StorageServer>,
@@ -22,72 +36,348 @@ impl QueryServer {
         Self { storage }
     }
 
+    /// Scans the series `expr` selects within `range` from `table_name`, converting chunks to
+    /// arrow as shards return them. A rollup is scanned along with the samples the table `expr`
+    /// selects has not rolled up yet.
     async fn storage_scan(
         &self,
         expr: &Expr,
-    ) -> Result<(Schema, Vec<Chunk<Arc<dyn Array>>>), Error> {
+        table_name: &str,
+        range: Range,
+    ) -> Result<
+        (
+            Schema,
+            impl Stream<Item = Result<Chunk<Arc<dyn Array>>, Error>> + Unpin,
+        ),
+        Error,
+    > {
         let mut projections = Vec::new();
         for projection in &expr.projection {
             projections.push(projection.name.as_ref())
         }
-        let (schema, chunks) = self
-            .storage
-            .scan(
-                &expr.resource.resource,
-                Some(projections),
-                &expr.filters,
-                expr.range,
-                None,
-            )
-            .await
-            .map_err(|err| Error::StorageError { err })?;
+        let resource = expr.resource.resource.as_str();
+        let scanned = if table_name == resource {
+            self.storage
+                .scan(table_name, Some(projections), &expr.filters, range, None)
+                .await
+        } else {
+            self.storage
+                .scan_rollup(
+                    resource,
+                    table_name,
+                    Some(projections),
+                    &expr.filters,
+                    range,
+                )
+                .await
+        };
+        let (schema, chunks) = scanned.map_err(|err| Error::StorageError { err })?;
+        let arrow_schema = schema.clone();
         let chunks = chunks
-            .into_iter()
-            .map(|chunk| chunk.into_arrow_chunk())
-            .collect();
+            .map_ok(move |chunk| chunk.into_arrow_chunk(&arrow_schema))
+            .map_err(|err| Error::StorageError { err });
         Ok((schema, chunks))
     }
 
-    pub async fn query(&self, request: QueryRequest<'_>) -> Result<Vec<u8>, Error> {
-        let expr = match request.language() {
+    /// The table to scan for `expr` from `start`, a rollup of the table it selects if one keeps
+    /// the aggregate its function needs at least every `step`.
+    fn pick_table(&self, expr: &Expr, start: Instant, step: Option<Duration>) -> String {
+        let aggregate = expr
+            .projection
+            .iter()
+            .find_map(|projection| projection.pipeline.functions.first())
+            .map_or(Alignment::LastWrite, |function| {
+                rollup_aggregate(&function.name)
+            });
+        self.storage.context().pick_table(
+            &expr.resource.resource,
+            Some(start),
+            step,
+            aggregate,
+            Instant::now(),
+        )
+    }
+
+    /// Evaluates `request`, returning the Arrow IPC file of the result in pieces written as
+    /// chunks are evaluated.
+    pub async fn query(
+        &self,
+        request: QueryRequest<'_>,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, Error>> + Send + Unpin, Error> {
+        let node = match request.language() {
             Language::PromQL => {
                 let q = request.q();
                 parse(q).map_err(|err| Error::ParseError { err })
             }
-            _ => {
-                unreachable!()
-            }
+            _ => Err(Error::UnsupportedQuery {
+                query: String::from(request.q()),
+            }),
         }?;
+        let expr = selection(node, request.q())?;
+        let range_function = function::has_range_function(&expr.projection);
+        let steps = steps(&request, &expr, range_function)?;
+        // range vectors without a function are returned as they are stored
+        let raw = expr.window.is_some() && !range_function;
 
-        let (schema, chunks) = self.storage_scan(&expr).await?;
-        self.compute(&expr.projection, &expr.aggregation, &schema, &chunks);
+        let step = (steps.step.as_millis() > 0).then_some(steps.step);
+        let table_name = self.pick_table(&expr, steps.start - steps.offset - steps.range, step);
+        let (time_interval, alignment) = self
+            .storage
+            .context()
+            .get_schema(&table_name)
+            .map_or((DEFAULT.time_interval, DEFAULT.alignment), |schema| {
+                (schema.meta.time_interval, schema.meta.alignment)
+            });
+        let range = steps.scan_range(time_interval);
+        let (schema, chunks) = self.storage_scan(&expr, &table_name, range).await?;
+        let evaluated = if raw {
+            schema.clone()
+        } else {
+            function::evaluate_schema(&expr.projection, &schema, &steps)
+        };
+        let aggregated = match &expr.aggregation {
+            None => evaluated.clone(),
+            Some(aggregation) => aggregate::aggregate_schema(aggregation, &evaluated),
+        };
+        let chunks = if raw {
+            // samples are encoded one chunk at a time as shards return them
+            chunks.boxed()
+        } else {
+            // series are evaluated over every row, which may span chunks, aggregations group
+            // rows of every chunk
+            stream::once(async move {
+                let chunks = chunks.try_collect::<Vec<_>>().await?;
+                let chunk =
+                    function::evaluate(&expr.projection, &schema, &chunks, alignment, &steps);
+                Ok(match &expr.aggregation {
+                    None => chunk,
+                    Some(aggregation) => aggregate::aggregate(aggregation, &evaluated, &[chunk]),
+                })
+            })
+            .boxed()
+        };
+        encode(&aggregated, chunks)
+    }
+}
 
-        let mut buffer = Vec::<u8>::new();
-        let mut writer = FileWriter::try_new(
-            &mut buffer,
-            &schema,
-            None,
-            WriteOptions { compression: None },
-        )
-        .map_err(|err| Error::InternalError { err })?;
-        for chunk in chunks {
-            writer.write(&chunk, None).unwrap();
+/// The selection `node` evaluates, the only queries evaluated for now: series selected from a
+/// resource with at most one range function and an aggregation, like
+/// `sum by (env) (rate(requests[5m]))`. Numbers, strings, negations and binary operators are
+/// `UnsupportedQuery`, as are functions and aggregations of anything but a selection. Functions
+/// besides a single range function, like `abs` in `abs(rate(requests[5m]))`, are
+/// `UnsupportedFunction`.
+fn selection(node: Node, query: &str) -> Result<Expr, Error> {
+    let expr = match node {
+        Node::Expr(expr) => expr,
+        _ => {
+            return Err(Error::UnsupportedQuery {
+                query: String::from(query),
+            })
+        }
+    };
+    if let Some(name) = function::unsupported_function(&expr.projection) {
+        return Err(Error::UnsupportedFunction {
+            name: String::from(name),
+        });
+    }
+    Ok(expr)
+}
+
+/// Encodes `chunks` as an Arrow IPC file, a piece of it for each chunk and one for its footer.
+fn encode(
+    schema: &Schema,
+    chunks: BoxStream<'static, Result<Chunk<Arc<dyn Array>>, Error>>,
+) -> Result<impl Stream<Item = Result<Vec<u8>, Error>> + Send + Unpin, Error> {
+    let buffer = SharedBuffer::default();
+    let writer = FileWriter::try_new(
+        buffer.clone(),
+        schema,
+        None,
+        WriteOptions { compression: None },
+    )
+    .map_err(|err| Error::InternalError { err })?;
+    let pieces = stream::try_unfold(Some((writer, chunks)), move |state| {
+        let buffer = buffer.clone();
+        async move {
+            let (mut writer, mut chunks) = match state {
+                None => return Ok(None),
+                Some(state) => state,
+            };
+            match chunks.try_next().await? {
+                Some(chunk) => {
+                    writer
+                        .write(&chunk, None)
+                        .map_err(|err| Error::InternalError { err })?;
+                    Ok(Some((buffer.take(), Some((writer, chunks)))))
+                }
+                None => {
+                    writer
+                        .finish()
+                        .map_err(|err| Error::InternalError { err })?;
+                    Ok(Some((buffer.take(), None)))
+                }
+            }
+        }
+    });
+    Ok(pieces.boxed())
+}
+
+/// A buffer a writer writes to while its content is taken out.
+#[derive(Debug, Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The instants `request` evaluates `expr` at, every step from its start to its end for range
+/// queries, at its time or now otherwise. Range functions are evaluated over the window of
+/// their range vector, instant vectors over the lookback of the request.
+fn steps(request: &QueryRequest<'_>, expr: &Expr, range_function: bool) -> Result<Steps, Error> {
+    let invalid = |reason: &str| Error::InvalidQuery {
+        reason: String::from(reason),
+    };
+    let (start, end, step) = match (request.start(), request.end(), request.step()) {
+        (None, None, None) => {
+            let time = request.time().unwrap_or_else(|| Instant::now().as_millis());
+            (time, time, 0)
         }
-        Ok(buffer)
+        (Some(start), Some(end), Some(step)) => (start, end, step),
+        _ => return Err(invalid("range queries need a start, an end and a step")),
+    };
+    if step < 0 || step == 0 && request.step().is_some() {
+        return Err(invalid("step must be positive"));
+    }
+    if end < start {
+        return Err(invalid("end is before start"));
+    }
+    if step > 0 && (end - start) / step >= MAX_STEPS {
+        return Err(invalid("too many steps between start and end"));
+    }
+    let raw = expr.window.is_some() && !range_function;
+    if raw && (step > 0 || expr.aggregation.is_some()) {
+        return Err(invalid(
+            "range vectors are only returned by instant queries",
+        ));
+    }
+    if range_function && expr.window.is_none() {
+        return Err(invalid("range functions need a range vector"));
+    }
+    let lookback = request
+        .lookback()
+        .map_or(DEFAULT_LOOKBACK, Duration::from_millis);
+    Ok(Steps {
+        start: Instant::from_millis(start),
+        end: Instant::from_millis(end),
+        step: Duration::from_millis(step),
+        range: expr.window.unwrap_or(lookback),
+        offset: expr.offset.unwrap_or(Duration::from_millis(0)),
+    })
+}
+
+/// The aggregate of rolled up samples `function` can be computed from, the last sample of each
+/// interval for functions not aggregating over time.
+fn rollup_aggregate(function: &str) -> Alignment {
+    match function {
+        "min_over_time" => Alignment::Min,
+        "max_over_time" => Alignment::Max,
+        "sum_over_time" => Alignment::Sum,
+        "count_over_time" => Alignment::Count,
+        _ => Alignment::LastWrite,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::QueryServer;
+    use crate::error::Error;
+    use crate::{encode, selection, QueryServer};
+    use arrow2::array::{Array, Int64Array};
+    use arrow2::chunk::Chunk;
+    use arrow2::datatypes::{DataType, Field, Schema};
+    use arrow2::io::ipc::read::{read_file_metadata, FileReader};
     use arrow2::io::ipc::write::{FileWriter, WriteOptions};
     use common::time::Instant;
     use common::{Label, LabelValue, Scalar, ScalarValue};
     use context::Context;
+    use futures::{stream, StreamExt, TryStreamExt};
     use ql::promql::parse;
+    use ql::rosetta::{Node, Range};
+    use std::io::Cursor;
     use std::sync::Arc;
     use storage::StorageServer;
 
+    #[test]
+    fn test_encode() {
+        let schema = Schema::from(vec![Field::new("value", DataType::Int64, false)]);
+        let chunks = (0..3)
+            .map(|value| {
+                let array: Arc<dyn Array> = Arc::new(Int64Array::from_vec(vec![value]));
+                Ok(Chunk::new(vec![array]))
+            })
+            .collect::<Vec<_>>();
+        let pieces = encode(&schema, stream::iter(chunks).boxed()).unwrap();
+        let pieces = futures_lite::future::block_on(pieces.try_collect::<Vec<_>>()).unwrap();
+        // a piece for each chunk and one for the footer
+        assert_eq!(pieces.len(), 4);
+
+        let mut file = Cursor::new(pieces.concat());
+        let metadata = read_file_metadata(&mut file).unwrap();
+        let values = FileReader::new(file, metadata, None)
+            .map(|chunk| {
+                let chunk = chunk.unwrap();
+                let array = chunk.arrays()[0].as_any().downcast_ref::<Int64Array>();
+                array.unwrap().value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection = |q: &str| selection(parse(q).unwrap(), q);
+        for q in [
+            "requests",
+            "rate(requests[5m])",
+            "sum by (env) (rate(requests[5m]))",
+        ] {
+            assert!(selection(q).is_ok(), "{}", q);
+        }
+        for q in [
+            "1",
+            "-requests",
+            "requests * 2",
+            "requests / on (env) errors",
+            "abs(sum(requests))",
+            "max(sum by (env) (requests))",
+        ] {
+            assert!(
+                matches!(selection(q), Err(Error::UnsupportedQuery { .. })),
+                "{}",
+                q
+            );
+        }
+        for q in ["abs(requests)", "abs(rate(requests[5m]))"] {
+            assert!(
+                matches!(selection(q), Err(Error::UnsupportedFunction { name }) if name == "abs"),
+                "{}",
+                q
+            );
+        }
+    }
+
     #[test]
     fn test_scan() {
         let storage = Arc::new(StorageServer::new(&[0], Arc::new(Context::new())));
@@ -122,8 +412,17 @@ mod test {
         ))
         .unwrap();
         let query = QueryServer::new(Arc::clone(&storage));
-        let expr = parse("test{}[5m]").unwrap();
-        let (schema, chunks) = futures_lite::future::block_on(query.storage_scan(&expr)).unwrap();
+        let expr = match parse("test{}[5m]").unwrap() {
+            Node::Expr(expr) => expr,
+            _ => unreachable!(),
+        };
+        let range = Range {
+            start: None,
+            end: None,
+        };
+        let scan = query.storage_scan(&expr, "test", range);
+        let (schema, chunks) = futures_lite::future::block_on(scan).unwrap();
+        let chunks = futures_lite::future::block_on(chunks.try_collect::<Vec<_>>()).unwrap();
         println!("{:?}, {:?}", schema, chunks);
         let mut buffer = Vec::<u8>::new();
         let mut writer = FileWriter::try_new(
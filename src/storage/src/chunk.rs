@@ -1,20 +1,60 @@
-use crate::column::{LabelColumn, ScalarColumn};
+use crate::column::{
+    list_from_rows, scalar_type, CompressedColumn, DictionaryColumn, Encoded, Filter, LabelColumn,
+    Predicate, ScalarColumn, Series,
+};
 use crate::error::{ScanError, WriteError};
+use crate::immutable::ImmutableChunk;
+use crate::util::codec::{Decoder, Encoder};
 use arrow2::array::{
-    Array, MutableArray, MutableListArray, MutablePrimitiveArray, MutableUtf8Array, PrimitiveArray,
-    TryPush,
+    growable::make_growable, new_null_array, Array, BinaryArray, BooleanArray, ListArray,
+    MutableArray, MutableListArray, MutablePrimitiveArray, MutableUtf8Array, PrimitiveArray,
+    TryPush, Utf8Array,
 };
+use arrow2::bitmap::MutableBitmap;
 use arrow2::chunk::Chunk;
+use arrow2::compute::take;
+use arrow2::datatypes::Schema as ArrowSchema;
+use arrow2::types::NativeType;
+use common::histogram::{Histogram, Summary};
 use common::time::{Duration, Instant};
 use common::util::IndexMap;
-use common::{Label, LabelType, LabelValue, Scalar, ScalarType};
-use context::Schema;
+use common::{Label, LabelType, LabelValue, Scalar, ScalarType, ScalarValue};
+use context::{Alignment, Schema, TIMESTAMP_SCALAR};
 use croaring::Bitmap;
-use ql::rosetta::{MatcherOp, MatcherRef, Range};
+use futures::Stream;
+use hashbrown::HashMap;
+use ql::rosetta::{MatcherOp, Range};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Where a shard sends the chunks it scans.
+pub(crate) type ScanSender = async_channel::Sender<Result<ScanChunk, ScanError>>;
+
+/// The chunks of a scan, received from shards as they scan them. The channel is bounded, so
+/// shards wait for the consumer, and dropping the stream cancels the scan of every shard at
+/// its next chunk.
+#[derive(Debug)]
+pub enum ScanStream {
+    Shards(async_channel::Receiver<Result<ScanChunk, ScanError>>),
+    /// Chunks of a scan with a limit, which are merged before they are returned.
+    Limited(std::vec::IntoIter<ScanChunk>),
+}
+
+impl Stream for ScanStream {
+    type Item = Result<ScanChunk, ScanError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            ScanStream::Shards(recv) => Pin::new(recv).poll_next(cx),
+            ScanStream::Limited(chunks) => Poll::Ready(chunks.next().map(Ok)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ScanChunk {
+    /// The instant of the first slot of every series, the first one the scanned range keeps.
     pub start_at: Instant,
     pub time_interval: Duration,
     pub labels: IndexMap<Arc<str>, Arc<dyn Array>>,
@@ -22,7 +62,7 @@ pub struct ScanChunk {
 }
 
 impl ScanChunk {
-    fn new(start_at: Instant, time_interval: Duration) -> Self {
+    pub(crate) fn new(start_at: Instant, time_interval: Duration) -> Self {
         Self {
             start_at,
             time_interval,
@@ -31,31 +71,647 @@ impl ScanChunk {
         }
     }
 
-    pub fn into_arrow_chunk(self) -> Chunk<Arc<dyn Array>> {
-        let mut arrays =
-            Vec::<Arc<dyn Array>>::with_capacity(self.labels.len() + self.scalars.len() + 1);
-        let len = self.labels.first().map(|c| c.len()).unwrap_or(0);
-        arrays.push(Arc::new(PrimitiveArray::<i64>::from(vec![
-            Some(
-                self.start_at.as_millis()
-            );
+    /// Converts into an arrow chunk laid out as `schema`, the one returned by
+    /// `StorageServer::scan`. Columns this chunk was created without are filled with nulls.
+    pub fn into_arrow_chunk(self, schema: &ArrowSchema) -> Chunk<Arc<dyn Array>> {
+        let mut arrays = Vec::<Arc<dyn Array>>::with_capacity(schema.fields.len());
+        let len = self.len();
+        arrays.push(Arc::new(PrimitiveArray::<i64>::from_vec(vec![
+            self.start_at
+                .as_millis(
+                );
             len
         ])));
-        for label in self.labels {
-            arrays.push(label);
-        }
-        for scalar in self.scalars {
-            arrays.push(scalar);
+        arrays.push(Arc::new(PrimitiveArray::<i64>::from_vec(vec![
+            self.time_interval.as_millis();
+            len
+        ])));
+        for field in schema.fields.iter().skip(2) {
+            let array = self
+                .labels
+                .get(field.name.as_str())
+                .or_else(|| self.scalars.get(field.name.as_str()));
+            match array {
+                Some(array) => arrays.push(Arc::clone(array)),
+                None => arrays.push(Arc::from(new_null_array(field.data_type.clone(), len))),
+            }
         }
         Chunk::new(arrays)
     }
 }
 
+impl ScanChunk {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.labels
+            .first()
+            .or_else(|| self.scalars.first())
+            .map(|c| c.len())
+            .unwrap_or(0)
+    }
+
+    /// The series of every row, identified by their non-empty label values.
+    fn series_keys(&self) -> Vec<SeriesKey<'_>> {
+        let mut columns = self
+            .labels
+            .keys()
+            .into_iter()
+            .zip(self.labels.iter())
+            .map(|(name, array)| {
+                let array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                (name.as_ref(), array)
+            })
+            .collect::<Vec<_>>();
+        columns.sort_by_key(|(name, _)| *name);
+        (0..self.len())
+            .map(|row| {
+                columns
+                    .iter()
+                    .filter(|(_, array)| array.is_valid(row) && !array.value(row).is_empty())
+                    .map(|(name, array)| (*name, array.value(row)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Takes the `rows` of this chunk, in the given order.
+    fn take(&self, rows: impl Iterator<Item = u32>) -> ScanChunk {
+        let indices = PrimitiveArray::<u32>::from_values(rows);
+        let take = |array: &Arc<dyn Array>| -> Arc<dyn Array> {
+            Arc::from(take::take(array.as_ref(), &indices).unwrap())
+        };
+        let mut chunk = ScanChunk::new(self.start_at, self.time_interval);
+        for (name, array) in self.labels.keys().into_iter().zip(self.labels.iter()) {
+            chunk.labels.insert(Arc::clone(name), take(array));
+        }
+        for (name, array) in self.scalars.keys().into_iter().zip(self.scalars.iter()) {
+            chunk.scalars.insert(Arc::clone(name), take(array));
+        }
+        chunk
+    }
+
+    /// The labels and samples of every row, as they are written. Empty slots and the timestamp
+    /// column of exactly aligned tables are skipped.
+    pub(crate) fn series(&self) -> Vec<SeriesSamples<'_>> {
+        let labels = self
+            .labels
+            .keys()
+            .into_iter()
+            .zip(self.labels.iter())
+            .map(|(name, array)| {
+                let array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                (name.as_ref(), array)
+            })
+            .collect::<Vec<_>>();
+        let mut slots = (0..self.len())
+            .map(|_| Vec::<Vec<Scalar>>::new())
+            .collect::<Vec<_>>();
+        for (name, array) in self.scalars.keys().into_iter().zip(self.scalars.iter()) {
+            if name.as_ref() == TIMESTAMP_SCALAR {
+                continue;
+            }
+            let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+            let kind = scalar_type(array.data_type());
+            for (row, series) in list.iter().enumerate() {
+                let series = match series {
+                    None => continue,
+                    Some(series) => series,
+                };
+                let (slots, series) = (&mut slots[row], series.as_any());
+                match kind {
+                    ScalarType::Int(_) => {
+                        let series = series.downcast_ref::<PrimitiveArray<i64>>().unwrap();
+                        let values = series.iter().map(|v| v.map(|v| ScalarType::Int(*v)));
+                        push_samples(name, values, slots)
+                    }
+                    ScalarType::Float(_) => {
+                        let series = series.downcast_ref::<PrimitiveArray<f64>>().unwrap();
+                        let values = series.iter().map(|v| v.map(|v| ScalarType::Float(*v)));
+                        push_samples(name, values, slots)
+                    }
+                    ScalarType::Histogram(_) => {
+                        let series = series.downcast_ref::<BinaryArray<i64>>().unwrap();
+                        let values = series
+                            .iter()
+                            .map(|v| v.and_then(Histogram::decode).map(ScalarType::Histogram));
+                        push_samples(name, values, slots)
+                    }
+                    ScalarType::Summary(_) => {
+                        let series = series.downcast_ref::<BinaryArray<i64>>().unwrap();
+                        let values = series
+                            .iter()
+                            .map(|v| v.and_then(Summary::decode).map(ScalarType::Summary));
+                        push_samples(name, values, slots)
+                    }
+                    ScalarType::Bool(_) => {
+                        let series = series.downcast_ref::<BooleanArray>().unwrap();
+                        push_samples(name, series.iter().map(|v| v.map(ScalarType::Bool)), slots)
+                    }
+                    ScalarType::String(_) => {
+                        let series = series.downcast_ref::<Utf8Array<i32>>().unwrap();
+                        let values = series
+                            .iter()
+                            .map(|v| v.map(|v| ScalarType::String(v.to_owned())));
+                        push_samples(name, values, slots)
+                    }
+                }
+            }
+        }
+        slots
+            .into_iter()
+            .enumerate()
+            .map(|(row, slots)| {
+                let labels = labels
+                    .iter()
+                    .filter(|(_, array)| array.is_valid(row) && !array.value(row).is_empty())
+                    .map(|(name, array)| Label {
+                        name,
+                        value: LabelValue::String(array.value(row)),
+                    })
+                    .collect();
+                let samples = slots
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, scalars)| !scalars.is_empty())
+                    .map(|(slot, scalars)| {
+                        (self.start_at + self.time_interval * slot as i64, scalars)
+                    })
+                    .collect();
+                (labels, samples)
+            })
+            .collect()
+    }
+
+    /// Replaces every number by a count of one, as a count rollup of the chunk would hold.
+    pub(crate) fn counted(mut self) -> ScanChunk {
+        for array in self.scalars.iter_mut() {
+            let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+            let counts: Arc<dyn Array> = match scalar_type(array.data_type()) {
+                ScalarType::Int(_) => Arc::new(ones::<i64>(list.values().as_ref())),
+                ScalarType::Float(_) => Arc::new(ones::<f64>(list.values().as_ref())),
+                _ => continue,
+            };
+            *array = Arc::new(ListArray::<i32>::new(
+                list.data_type().clone(),
+                list.offsets().clone(),
+                counts,
+                list.validity().cloned(),
+            ));
+        }
+        self
+    }
+
+    /// Clears the slots within `range` of the rows matching `filters`, dropping the rows left
+    /// without samples. Returns what is left of the chunk with how many samples were deleted,
+    /// or `None` if nothing was.
+    pub(crate) fn delete(
+        &self,
+        filters: &[Filter],
+        range: std::ops::Range<usize>,
+    ) -> Option<(ScanChunk, u64)> {
+        let len = self.len();
+        let mut matched = vec![true; len];
+        for filter in filters {
+            let array = self
+                .labels
+                .get(filter.name.as_str())
+                .map(|array| array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap());
+            for (row, matched) in matched.iter_mut().enumerate() {
+                let value = match array {
+                    Some(array) if array.is_valid(row) => array.value(row),
+                    _ => "",
+                };
+                *matched &= filter.predicate.matches(value);
+            }
+        }
+        if !matched.contains(&true) {
+            return None;
+        }
+
+        let mut cleared = vec![false; len * range.len()];
+        let mut remaining = vec![false; len];
+        let mut chunk = ScanChunk::new(self.start_at, self.time_interval);
+        for (name, array) in self.labels.keys().into_iter().zip(self.labels.iter()) {
+            chunk.labels.insert(Arc::clone(name), Arc::clone(array));
+        }
+        for (name, array) in self.scalars.keys().into_iter().zip(self.scalars.iter()) {
+            let (matched, range) = (&matched, &range);
+            let array = match scalar_type(array.data_type()) {
+                ScalarType::Int(_) => {
+                    delete_series::<i64>(array, matched, range, &mut cleared, &mut remaining)
+                }
+                ScalarType::Float(_) => {
+                    delete_series::<f64>(array, matched, range, &mut cleared, &mut remaining)
+                }
+                _ => delete_list(array, matched, range, &mut cleared, &mut remaining),
+            };
+            chunk.scalars.insert(Arc::clone(name), array);
+        }
+
+        let deleted = cleared.into_iter().filter(|cleared| *cleared).count() as u64;
+        let kept = (0..len)
+            .filter(|row| !matched[*row] || remaining[*row])
+            .map(|row| row as u32)
+            .collect::<Vec<_>>();
+        if kept.len() == len {
+            return (deleted > 0).then_some((chunk, deleted));
+        }
+        Some((chunk.take(kept.into_iter()), deleted))
+    }
+
+    /// Merges `late`, scanned from the late samples of the same window as this chunk, into it.
+    /// Rows with the same labels are merged slot by slot as `alignment` says, the rest of the
+    /// rows of `late` are appended.
+    pub(crate) fn merge(self, late: ScanChunk, alignment: Alignment) -> ScanChunk {
+        let mut names = self.labels.keys().into_iter().cloned().collect::<Vec<_>>();
+        for name in late.labels.keys() {
+            if self.labels.get(name.as_ref()).is_none() {
+                names.push(Arc::clone(name));
+            }
+        }
+        let label_values = |chunk: &ScanChunk| {
+            let len = chunk.len();
+            let columns = names
+                .iter()
+                .map(|name| {
+                    chunk.labels.get(name.as_ref()).map(|array| {
+                        array
+                            .as_any()
+                            .downcast_ref::<Utf8Array<i32>>()
+                            .unwrap()
+                            .iter()
+                            .map(|value| value.map(String::from))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            (0..len)
+                .map(|row| {
+                    columns
+                        .iter()
+                        .map(|column| column.as_ref().and_then(|values| values[row].clone()))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut rows = label_values(&self);
+        let base_len = rows.len();
+        let mut row_ids = rows
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(id, row)| (row, id))
+            .collect::<HashMap<_, _>>();
+        let mut targets = Vec::new();
+        for row in label_values(&late) {
+            let id = *row_ids.entry(row.clone()).or_insert_with(|| {
+                rows.push(row);
+                rows.len() - 1
+            });
+            targets.push(id);
+        }
+
+        let mut merged = ScanChunk::new(self.start_at, self.time_interval);
+        for (offset, name) in names.into_iter().enumerate() {
+            let mut array = MutableUtf8Array::<i32>::new();
+            for row in &rows {
+                array.push(row[offset].as_deref());
+            }
+            merged.labels.insert(name, array.into_arc());
+        }
+        let mut scalar_names = self.scalars.keys().into_iter().cloned().collect::<Vec<_>>();
+        for name in late.scalars.keys() {
+            if self.scalars.get(name.as_ref()).is_none() {
+                scalar_names.push(Arc::clone(name));
+            }
+        }
+        for name in scalar_names {
+            let base = self.scalars.get(name.as_ref());
+            let late = late.scalars.get(name.as_ref());
+            let data_type = base.or(late).unwrap().data_type();
+            let array = match scalar_type(data_type) {
+                ScalarType::Int(_) => {
+                    merge_series::<i64>(base, base_len, late, &targets, rows.len(), alignment)
+                }
+                ScalarType::Float(_) => {
+                    merge_series::<f64>(base, base_len, late, &targets, rows.len(), alignment)
+                }
+                _ => merge_list(base, base_len, late, &targets, rows.len(), alignment),
+            };
+            merged.scalars.insert(name, array);
+        }
+        merged
+    }
+}
+
+/// The labels of a series with its samples, as they are written.
+pub(crate) type SeriesSamples<'a> = (Vec<Label<'a>>, Vec<(Instant, Vec<Scalar>)>);
+
+/// The label values of a series sorted by label name, which orders series the same in every
+/// chunk and shard.
+pub(crate) type SeriesKey<'a> = Vec<(&'a str, &'a str)>;
+
+/// Keeps the `limit` ids of `ids` whose series come first, without looking at the scalars.
+/// `key` gives the labels of a series as a [`SeriesKey`] would hold them.
+pub(crate) fn limit_ids<'a, I>(ids: Bitmap, limit: Option<usize>, key: impl Fn(u32) -> I) -> Bitmap
+where
+    I: Iterator<Item = (&'a str, &'a str)>,
+{
+    match limit {
+        Some(limit) if ids.cardinality() > limit as u64 => {
+            limit_series(ids.iter().collect(), limit, key)
+                .into_iter()
+                .collect()
+        }
+        _ => ids,
+    }
+}
+
+/// Keeps the `limit` items whose series come first, comparing the labels `key` gives in
+/// place.
+pub(crate) fn limit_series<'a, T, I>(
+    mut items: Vec<T>,
+    limit: usize,
+    key: impl Fn(T) -> I,
+) -> Vec<T>
+where
+    T: Copy + Ord,
+    I: Iterator<Item = (&'a str, &'a str)>,
+{
+    if items.len() > limit {
+        items.select_nth_unstable_by(limit, |a, b| key(*a).cmp(key(*b)).then(a.cmp(b)));
+        items.truncate(limit);
+    }
+    items
+}
+
+/// Keeps the rows of the first `limit` series of `chunks`. The chunks are ordered by time and
+/// their rows by series, so every shard returns the same rows whichever order chunks were
+/// scanned in. A series is kept in every chunk it appears in.
+pub(crate) fn limit_chunks(chunks: Vec<ScanChunk>, limit: Option<usize>) -> Vec<ScanChunk> {
+    let limit = match limit {
+        None => return chunks,
+        Some(limit) => limit,
+    };
+    let keys = chunks
+        .iter()
+        .map(ScanChunk::series_keys)
+        .collect::<Vec<_>>();
+    let mut series = keys.iter().flatten().collect::<Vec<_>>();
+    series.sort_unstable();
+    series.dedup();
+    series.truncate(limit);
+
+    let mut limited = Vec::with_capacity(chunks.len());
+    for (chunk, keys) in chunks.iter().zip(&keys) {
+        let mut rows = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(row, key)| {
+                let position = series.binary_search(&key).ok()?;
+                Some((position, row as u32))
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            continue;
+        }
+        rows.sort_unstable();
+        let first = rows[0].0;
+        let chunk = chunk.take(rows.into_iter().map(|(_, row)| row));
+        limited.push((chunk.start_at.as_millis(), first, chunk));
+    }
+    limited.sort_by_key(|(start_at, first, _)| (*start_at, *first));
+    limited.into_iter().map(|(_, _, chunk)| chunk).collect()
+}
+
+/// Clears the slots within `range` of the `matched` series of a list array, flagging the
+/// slots that had a value in `cleared` and the series with values left in `remaining`.
+/// Adds the samples of `series`, a series of scalar column `name`, to the slots they are in.
+fn push_samples(
+    name: &str,
+    series: impl ExactSizeIterator<Item = Option<ScalarValue>>,
+    slots: &mut Vec<Vec<Scalar>>,
+) {
+    if slots.len() < series.len() {
+        slots.resize_with(series.len(), Vec::new);
+    }
+    for (slot, sample) in series.enumerate() {
+        if let Some(value) = sample {
+            slots[slot].push(Scalar {
+                name: name.to_owned(),
+                value,
+            });
+        }
+    }
+}
+
+/// Like [`delete_series`] for the scalars other than numbers, clearing slots through the
+/// validity of their series.
+/// An array of ones where `values` has a value.
+fn ones<T: NativeType + From<u8>>(values: &dyn Array) -> PrimitiveArray<T> {
+    let values = values.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+    PrimitiveArray::new(
+        values.data_type().clone(),
+        vec![T::from(1); values.len()].into(),
+        values.validity().cloned(),
+    )
+}
+
+fn delete_list(
+    array: &Arc<dyn Array>,
+    matched: &[bool],
+    range: &std::ops::Range<usize>,
+    cleared: &mut [bool],
+    remaining: &mut [bool],
+) -> Arc<dyn Array> {
+    let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+    let rows = list
+        .iter()
+        .enumerate()
+        .map(|(row, series)| {
+            let series = series?;
+            if !matched[row] {
+                return Some(series);
+            }
+            let mut validity = MutableBitmap::with_capacity(series.len());
+            for slot in 0..series.len() {
+                let valid = series.is_valid(slot);
+                if range.contains(&slot) {
+                    cleared[row * range.len() + slot - range.start] |= valid;
+                    validity.push(false);
+                } else {
+                    remaining[row] |= valid;
+                    validity.push(valid);
+                }
+            }
+            Some(series.with_validity(Some(validity.into())))
+        })
+        .collect();
+    list_from_rows(array.data_type(), rows)
+}
+
+fn delete_series<T: NativeType>(
+    array: &Arc<dyn Array>,
+    matched: &[bool],
+    range: &std::ops::Range<usize>,
+    cleared: &mut [bool],
+    remaining: &mut [bool],
+) -> Arc<dyn Array> {
+    let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+    let mut result = MutableListArray::<i32, MutablePrimitiveArray<T>>::new();
+    for (row, series) in list.iter().enumerate() {
+        let series = match series {
+            None => {
+                result.push_null();
+                continue;
+            }
+            Some(series) => series,
+        };
+        let series = series.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+        let values = series.iter().enumerate().map(|(slot, value)| {
+            let value = value.copied();
+            if !matched[row] {
+                return value;
+            }
+            if range.contains(&slot) {
+                cleared[row * range.len() + slot - range.start] |= value.is_some();
+                return None;
+            }
+            remaining[row] |= value.is_some();
+            value
+        });
+        result.try_push(Some(values)).unwrap();
+    }
+    result.into_arc()
+}
+
+fn merge_series<T>(
+    base: Option<&Arc<dyn Array>>,
+    base_len: usize,
+    late: Option<&Arc<dyn Array>>,
+    targets: &[usize],
+    len: usize,
+    alignment: Alignment,
+) -> Arc<dyn Array>
+where
+    T: NativeType + Accumulate,
+{
+    let series = |array: Option<&Arc<dyn Array>>, row: usize| {
+        let list = array?.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+        if list.is_null(row) {
+            return None;
+        }
+        let values = list.value(row);
+        let values = values.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+        Some(values.iter().map(|v| v.copied()).collect::<Vec<_>>())
+    };
+    let mut rows = (0..len)
+        .map(|row| {
+            if row < base_len {
+                series(base, row)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    for (row, target) in targets.iter().enumerate() {
+        let late = match series(late, row) {
+            None => continue,
+            Some(late) => late,
+        };
+        rows[*target] = match rows[*target].take() {
+            None => Some(late),
+            Some(base) => Some(
+                base.into_iter()
+                    .zip(late)
+                    .map(|(base, late)| merge_slot(alignment, base, late))
+                    .collect(),
+            ),
+        };
+    }
+    let mut array = MutableListArray::<i32, MutablePrimitiveArray<T>>::new();
+    for row in rows {
+        array.try_push(row).unwrap();
+    }
+    array.into_arc()
+}
+
+/// Like [`merge_series`] for the scalars other than numbers, which cannot be aggregated, so a
+/// late sample replaces the one in its slot unless the first write is kept.
+fn merge_list<'a>(
+    base: Option<&'a Arc<dyn Array>>,
+    base_len: usize,
+    late: Option<&'a Arc<dyn Array>>,
+    targets: &[usize],
+    len: usize,
+    alignment: Alignment,
+) -> Arc<dyn Array> {
+    let data_type = base.or(late).unwrap().data_type().clone();
+    let list = |array: Option<&'a Arc<dyn Array>>| {
+        array.map(|array| array.as_any().downcast_ref::<ListArray<i32>>().unwrap())
+    };
+    let (base, late) = (list(base), list(late));
+    let mut rows = (0..len)
+        .map(|row| {
+            let base = base.filter(|base| row < base_len && base.is_valid(row))?;
+            Some(base.value(row))
+        })
+        .collect::<Vec<_>>();
+    for (row, target) in targets.iter().enumerate() {
+        let late = match late.filter(|late| late.is_valid(row)) {
+            None => continue,
+            Some(late) => late.value(row),
+        };
+        rows[*target] = match rows[*target].take() {
+            None => Some(late),
+            Some(base) => {
+                let len = base.len().max(late.len());
+                let mut merged = make_growable(&[base.as_ref(), late.as_ref()], true, len);
+                for slot in 0..len {
+                    let valid = |series: &dyn Array| slot < series.len() && series.is_valid(slot);
+                    match (valid(base.as_ref()), valid(late.as_ref())) {
+                        (true, true) if alignment == Alignment::FirstWrite => {
+                            merged.extend(0, slot, 1)
+                        }
+                        (_, true) => merged.extend(1, slot, 1),
+                        (true, false) => merged.extend(0, slot, 1),
+                        (false, false) => merged.extend_validity(1),
+                    }
+                }
+                Some(merged.as_box())
+            }
+        };
+    }
+    list_from_rows(&data_type, rows)
+}
+
+/// Merges a slot with the one of late samples, which were written after it.
+fn merge_slot<G: Accumulate>(alignment: Alignment, slot: Option<G>, late: Option<G>) -> Option<G> {
+    match (slot, late) {
+        (None, slot) | (slot, None) => slot,
+        (Some(slot), Some(late)) => Some(match alignment {
+            Alignment::LastWrite | Alignment::Exact => late,
+            Alignment::FirstWrite => slot,
+            Alignment::Sum | Alignment::Count => slot.accumulate(late),
+            Alignment::Min if late < slot => late,
+            Alignment::Max if late > slot => late,
+            Alignment::Min | Alignment::Max => slot,
+        }),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct MutableChunk {
     pub(crate) info: Info,
     stat: Stat,
     columns: Columns,
+    /// The rows that have not been tombstoned.
+    alive: Bitmap,
+    /// How many rows are alive, the series of the chunk.
+    series_num: u64,
+    /// The heap usage of the columns, grown as rows are pushed and written to.
+    heap_size: usize,
+    alignment: Alignment,
 }
 
 impl MutableChunk {
@@ -67,8 +723,77 @@ impl MutableChunk {
                 series_len: schema.meta.series_len,
             },
             stat: Default::default(),
+            alignment: schema.meta.alignment,
             columns: Columns::new(schema),
+            alive: Bitmap::create(),
+            series_num: 0,
+            heap_size: 0,
+        }
+        .measured()
+    }
+
+    pub(crate) fn encode(&self, encoder: &mut Encoder) {
+        encoder.put_i64(self.info.start_at.as_millis());
+        encoder.put_i64(self.info.time_interval.as_millis());
+        encoder.put_u32(self.info.series_len);
+        encoder.put_u32(self.stat.record_num);
+        encoder.put_u32(self.columns.version);
+        encoder.put_u32(self.columns.labels.len() as u32);
+        for column in self.columns.labels.iter() {
+            column.encode(encoder);
+        }
+        encoder.put_u32(self.columns.scalars.len() as u32);
+        for column in self.columns.scalars.iter() {
+            column.encode(encoder);
+        }
+    }
+
+    pub(crate) fn decode(decoder: &mut Decoder) -> Option<Self> {
+        let info = Info {
+            start_at: Instant::from_millis(decoder.i64()?),
+            time_interval: Duration::from_millis(decoder.i64()?),
+            series_len: decoder.u32()?,
+        };
+        let stat = Stat {
+            record_num: decoder.u32()?,
+        };
+        let mut columns = Columns {
+            version: decoder.u32()?,
+            labels: IndexMap::new(),
+            scalars: IndexMap::new(),
+        };
+        for _ in 0..decoder.u32()? {
+            let column = LabelColumn::decode(decoder)?;
+            columns.labels.insert(Arc::clone(column.name()), column);
         }
+        for _ in 0..decoder.u32()? {
+            let column = ScalarColumn::decode(decoder)?;
+            columns.scalars.insert(Arc::clone(column.name()), column);
+        }
+        let alive = columns.alive(stat.record_num);
+        // taken from the table schema again on the next write
+        Some(
+            Self {
+                info,
+                stat,
+                columns,
+                series_num: alive.cardinality(),
+                alive,
+                heap_size: 0,
+                alignment: Alignment::LastWrite,
+            }
+            .measured(),
+        )
+    }
+
+    /// Grows the columns to match a newer version of the table schema, back-filling the new
+    /// columns with nulls for every existing row.
+    pub(crate) fn evolve(&mut self, schema: &Schema) {
+        if self.columns.version < schema.version {
+            self.columns.evolve(schema, self.stat.record_num);
+            self.heap_size = self.measure_heap_size();
+        }
+        self.alignment = schema.meta.alignment;
     }
 
     pub(crate) fn get_mut(
@@ -77,18 +802,17 @@ impl MutableChunk {
     ) -> Result<Option<Row>, WriteError> {
         let mut filtered = None;
         for (id, label) in labels.iter().enumerate() {
-            self.columns
-                .lookup(
-                    &MatcherRef {
-                        name: self.columns.labels[id].name().as_ref(),
-                        op: MatcherOp::LiteralEqual,
-                        value: label.as_deref(),
-                    },
-                    &mut filtered,
-                )
-                .map_err(|err| WriteError::InternalError {
-                    err: format!("{:?}", err),
+            let predicate =
+                Predicate::new(MatcherOp::LiteralEqual, label.as_deref()).map_err(|err| {
+                    WriteError::InternalError {
+                        err: format!("{:?}", err),
+                    }
                 })?;
+            self.columns.lookup(
+                self.columns.labels[id].name().as_ref(),
+                &predicate,
+                &mut filtered,
+            );
             if let Some(filtered) = &filtered {
                 if filtered.is_empty() {
                     return Ok(None);
@@ -101,20 +825,71 @@ impl MutableChunk {
     }
 
     pub(crate) fn push(&mut self, labels: &[Option<&LabelValue>]) -> Row {
+        let mut growth = 0;
         for (offset, column) in self.columns.labels.iter_mut().enumerate() {
             let label = &labels[offset];
-            match label {
+            growth += match label {
                 None => column.push_zero(),
                 Some(value) => column.push(value),
-            }
+            };
         }
         for column in self.columns.scalars.iter_mut() {
-            column.push_zero();
+            growth += column.push_zero();
         }
+        self.grow(growth);
         let id = self.stat.add_num(1);
+        self.alive.add(id - 1);
+        self.series_num += 1;
         Row::new(self, id - 1)
     }
 
+    #[inline]
+    pub(crate) fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    #[inline]
+    fn grow(&mut self, growth: isize) {
+        self.heap_size = self.heap_size.saturating_add_signed(growth);
+    }
+
+    fn measured(mut self) -> Self {
+        self.heap_size = self.measure_heap_size();
+        self
+    }
+
+    /// Walks every column to measure the heap usage of the chunk, which writes keep track of
+    /// otherwise.
+    fn measure_heap_size(&self) -> usize {
+        self.columns
+            .labels
+            .iter()
+            .map(LabelColumn::heap_size)
+            .chain(self.columns.scalars.iter().map(ScalarColumn::heap_size))
+            .sum()
+    }
+
+    /// The number of series of this chunk, tombstoned ones excluded.
+    #[inline]
+    pub(crate) fn series_num(&self) -> u64 {
+        self.series_num
+    }
+
+    /// The name of the first label of a new series whose value would be one more than its
+    /// column holds at most.
+    pub(crate) fn exceeding_label(
+        &self,
+        labels: &[Option<&LabelValue>],
+        max_values: u32,
+    ) -> Option<&Arc<str>> {
+        self.columns
+            .labels
+            .iter()
+            .zip(labels)
+            .find(|(column, label)| matches!(label, Some(label) if !column.accepts(label, max_values)))
+            .map(|(column, _)| column.name())
+    }
+
     pub(crate) fn align_labels<'a>(
         &self,
         labels: &'a [Label],
@@ -123,7 +898,7 @@ impl MutableChunk {
         for label in labels {
             match self.columns.labels.get_id(label.name) {
                 None => {
-                    unimplemented!()
+                    unreachable!("label {:?} is not in the chunk schema", label.name)
                 }
                 Some(id) => {
                     aligned_labels[id] = Some(&label.value);
@@ -136,23 +911,74 @@ impl MutableChunk {
     pub(crate) async fn scan(
         &self,
         projections: Option<&[String]>,
-        filters: &[MatcherRef<'_>],
+        filters: &[Filter],
         range: Range,
-        _limit: Option<usize>,
+        limit: Option<usize>,
     ) -> Result<Option<ScanChunk>, ScanError> {
-        let mut filtered = Some(Bitmap::from_iter(0..self.stat.record_num));
-        for filter in filters {
-            self.columns.lookup(filter, &mut filtered)?;
-        }
-        match filtered {
-            None => Ok(None),
-            Some(ids) => {
-                let mut chunk = ScanChunk::new(self.info.start_at, self.info.time_interval);
-                self.push_arrow_labels(&ids, &mut chunk);
-                self.push_arrow_scalars(projections, range, &ids, &mut chunk);
-                Ok(Some(chunk))
+        let ids = self.filter(filters);
+        let mut columns = self.columns.labels.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|column| column.name());
+        let ids = limit_ids(ids, limit, |id| {
+            columns
+                .iter()
+                .filter_map(move |column| match column.get(id) {
+                    Some(LabelType::String(value)) if !value.is_empty() => {
+                        Some((column.name().as_ref(), value))
+                    }
+                    _ => None,
+                })
+        });
+        Ok(Some(self.scan_ids(projections, range, &ids)))
+    }
+
+    /// Clears the slots within `range` of the rows matching `filters`, tombstoning the rows
+    /// left without samples. Returns how many samples were deleted.
+    pub(crate) fn delete(&mut self, filters: &[Filter], range: Range) -> u64 {
+        let ids = self.filter(filters);
+        let range = self.info.range_offset(range);
+        let mut deleted = 0;
+        for id in ids.iter() {
+            for index in range.clone() {
+                let mut removed = false;
+                for column in self.columns.scalars.iter_mut() {
+                    removed |= column.remove(id, index as u32);
+                }
+                deleted += removed as u64;
+            }
+            if self
+                .columns
+                .scalars
+                .iter()
+                .all(|column| column.is_empty(id))
+            {
+                for column in self.columns.labels.iter_mut() {
+                    column.remove(id);
+                }
+                // only alive rows are filtered
+                self.alive.remove(id);
+                self.series_num -= 1;
             }
         }
+        self.heap_size = self.measure_heap_size();
+        deleted
+    }
+
+    /// The rows matching every filter, tombstoned rows excluded.
+    fn filter(&self, filters: &[Filter]) -> Bitmap {
+        let mut filtered = Some(self.alive.clone());
+        for filter in filters {
+            self.columns
+                .lookup(&filter.name, &filter.predicate, &mut filtered);
+        }
+        filtered.unwrap()
+    }
+
+    fn scan_ids(&self, projections: Option<&[String]>, range: Range, ids: &Bitmap) -> ScanChunk {
+        let start_at = self.info.range_start(range);
+        let mut chunk = ScanChunk::new(start_at, self.info.time_interval);
+        self.push_arrow_labels(ids, &mut chunk);
+        self.push_arrow_scalars(projections, range, ids, &mut chunk);
+        chunk
     }
 
     fn push_arrow_labels(&self, ids: &Bitmap, chunk: &mut ScanChunk) {
@@ -191,69 +1017,11 @@ impl MutableChunk {
         ids: &Bitmap,
         chunk: &mut ScanChunk,
     ) {
-        let range = self.get_range_offset(range);
+        let range = self.info.range_offset(range);
         let column = &self.columns.scalars[column_id];
-        match column.data_type() {
-            ScalarType::Int(_) => {
-                let mut array = MutableListArray::<i32, MutablePrimitiveArray<i64>>::new();
-                for id in ids.iter() {
-                    let series = column.get(id);
-                    array
-                        .try_push(series.as_ref().map(|series| match series {
-                            ScalarType::Int(series) => series.range(range.clone()).iter().cloned(),
-                            _ => unreachable!(),
-                        }))
-                        .unwrap();
-                }
-                chunk
-                    .scalars
-                    .insert(Arc::clone(column.name()), array.into_arc());
-            }
-            ScalarType::Float(_) => {
-                let mut array = MutableListArray::<i32, MutablePrimitiveArray<f64>>::new();
-                for id in ids.iter() {
-                    let series = column.get(id);
-                    array
-                        .try_push(series.as_ref().map(|series| match series {
-                            ScalarType::Float(series) => {
-                                series.range(range.clone()).iter().cloned()
-                            }
-                            _ => unreachable!(),
-                        }))
-                        .unwrap();
-                }
-                chunk
-                    .scalars
-                    .insert(Arc::clone(column.name()), array.into_arc());
-            }
-        }
-    }
-
-    fn get_range_offset(&self, range: Range) -> std::ops::Range<usize> {
-        let start = match range.start {
-            None => 0,
-            Some(start) => {
-                let offset = (start - self.info.start_at) / self.info.time_interval;
-                if offset <= 0 {
-                    0
-                } else {
-                    offset as usize
-                }
-            }
-        };
-        let series_len = self.info.series_len as usize;
-        let end = match range.end {
-            None => series_len,
-            Some(end) => {
-                let offset = ((end - self.info.start_at) / self.info.time_interval) as usize;
-                if offset >= series_len {
-                    series_len
-                } else {
-                    offset
-                }
-            }
-        };
-        start..end
+        chunk
+            .scalars
+            .insert(Arc::clone(column.name()), column.array(ids.iter(), range));
     }
 
     fn push_arrow_scalars(
@@ -264,34 +1032,53 @@ impl MutableChunk {
         chunk: &mut ScanChunk,
     ) {
         let project_ids = self.get_projection_id(projections);
-        for (offset, projection) in project_ids.into_iter().enumerate() {
-            match projection {
-                None => {
-                    let mut array = MutableListArray::<i32, MutablePrimitiveArray<f64>>::new();
-                    array
-                        .try_push(Some((0..ids.cardinality()).map(|_| None)))
-                        .unwrap();
-                    chunk.scalars.insert(
-                        Arc::from(projections.unwrap()[offset].as_ref()),
-                        array.into_arc(),
-                    );
-                }
-                Some(column_id) => self.push_scalar_column(column_id, range, ids, chunk),
-            }
+        for column_id in project_ids.into_iter().flatten() {
+            self.push_scalar_column(column_id, range, ids, chunk);
         }
     }
 
-    pub(crate) async fn archive(self) {
-        runtime::yield_now().await;
-        // todo
+    /// Scans every sample of this chunk.
+    pub(crate) fn scan_all(&self) -> ScanChunk {
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        self.scan_ids(None, whole, &self.alive)
+    }
+
+    /// Freezes this chunk into an immutable one, dictionary-encoding its label columns and
+    /// compressing every series of its scalar columns.
+    pub(crate) fn archive(self) -> ImmutableChunk {
+        let alive = &self.alive;
+        if self.series_num < self.stat.record_num as u64 {
+            // tombstoned rows are dropped by rebuilding from the rows left
+            let whole = Range {
+                start: None,
+                end: None,
+            };
+            let chunk = self.scan_ids(None, whole, alive);
+            return ImmutableChunk::from_scan(self.info, chunk);
+        }
+        let scalars = self
+            .columns
+            .scalars
+            .into_iter()
+            .map(CompressedColumn::from)
+            .collect();
+        let mut labels = IndexMap::new();
+        for column in self.columns.labels {
+            let name = Arc::clone(column.name());
+            labels.insert(name, DictionaryColumn::from(column));
+        }
+        ImmutableChunk::new(self.info, self.stat.record_num, labels, scalars)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Info {
     pub(crate) start_at: Instant,
-    time_interval: Duration,
-    series_len: u32,
+    pub(crate) time_interval: Duration,
+    pub(crate) series_len: u32,
 }
 
 impl Info {
@@ -299,6 +1086,58 @@ impl Info {
     pub(crate) fn end_at(&self) -> Instant {
         self.start_at + self.time_interval * (self.series_len - 1)
     }
+
+    /// The end of the window of the chunk, exclusive.
+    #[inline]
+    pub(crate) fn window_end(&self) -> Instant {
+        self.start_at + self.time_interval * self.series_len
+    }
+
+    #[inline]
+    pub(crate) fn contains(&self, timestamp: Instant) -> bool {
+        self.start_at <= timestamp && timestamp < self.window_end()
+    }
+
+    #[inline]
+    pub(crate) fn same_window(&self, other: &Self) -> bool {
+        self.start_at == other.start_at
+            && self.time_interval == other.time_interval
+            && self.series_len == other.series_len
+    }
+
+    #[inline]
+    pub(crate) fn overlaps(&self, range: Range) -> bool {
+        if let Some(start) = range.start {
+            if self.end_at() < start {
+                return false;
+            }
+        }
+        if let Some(end) = range.end {
+            if self.start_at > end {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The instant of the first slot `range_offset` keeps of `range`.
+    #[inline]
+    pub(crate) fn range_start(&self, range: Range) -> Instant {
+        self.start_at + self.time_interval * self.range_offset(range).start as u32
+    }
+
+    pub(crate) fn range_offset(&self, range: Range) -> std::ops::Range<usize> {
+        let series_len = self.series_len as i64;
+        let start = match range.start {
+            None => 0,
+            Some(start) => ((start - self.start_at) / self.time_interval).clamp(0, series_len),
+        };
+        let end = match range.end {
+            None => series_len,
+            Some(end) => ((end - self.start_at) / self.time_interval).clamp(start, series_len),
+        };
+        start as usize..end as usize
+    }
 }
 
 #[derive(Debug, Default)]
@@ -316,58 +1155,76 @@ impl Stat {
 
 #[derive(Debug)]
 struct Columns {
+    version: u32,
     labels: IndexMap<Arc<str>, LabelColumn>,
     scalars: IndexMap<Arc<str>, ScalarColumn>,
 }
 
 impl Columns {
     fn new(schema: Arc<Schema>) -> Self {
-        let mut labels = IndexMap::new();
-        let mut scalars = IndexMap::new();
+        let mut columns = Self {
+            version: 0,
+            labels: IndexMap::new(),
+            scalars: IndexMap::new(),
+        };
+        columns.evolve(&schema, 0);
+        columns
+    }
 
-        for label_type in &schema.labels {
-            let column = LabelColumn::new(label_type.clone());
+    fn evolve(&mut self, schema: &Schema, record_num: u32) {
+        for label_type in schema.labels.iter().skip(self.labels.len()) {
+            let mut column = LabelColumn::new(label_type.clone());
+            for _ in 0..record_num {
+                column.push_zero();
+            }
             let name = Arc::clone(column.name());
-            labels.insert(name, column);
+            self.labels.insert(name, column);
         }
 
-        for scalar_type in schema.scalars.iter() {
-            let column = ScalarColumn::new(scalar_type.clone(), schema.meta.series_len);
+        for scalar_type in schema.scalars.iter().skip(self.scalars.len()) {
+            let mut column = ScalarColumn::new(scalar_type.clone(), schema.meta.series_len);
+            for _ in 0..record_num {
+                column.push_zero();
+            }
             let name = Arc::clone(column.name());
-            scalars.insert(name, column);
+            self.scalars.insert(name, column);
         }
 
-        Self { labels, scalars }
+        self.version = schema.version;
     }
 
-    pub(crate) fn lookup(
-        &self,
-        matcher: &MatcherRef,
-        superset: &mut Option<Bitmap>,
-    ) -> Result<(), ScanError> {
-        let ids = self
-            .labels
-            .get(matcher.name)
-            .ok_or_else(|| ScanError::NoSuchLabel {
-                name: matcher.name.into(),
-            })?
-            .lookup(matcher.op, matcher.value);
-        match ids {
+    /// The rows that have not been tombstoned, which are the ones of any label column. Only
+    /// decoded chunks look them up, others keep track of them as rows are pushed and removed.
+    fn alive(&self, record_num: u32) -> Bitmap {
+        match self.labels.first() {
+            None => Bitmap::from_iter(0..record_num),
+            Some(column) => column.ids(),
+        }
+    }
+
+    pub(crate) fn lookup(&self, name: &str, predicate: &Predicate, superset: &mut Option<Bitmap>) {
+        let column = match self.labels.get(name) {
+            Some(column) => column,
             None => {
-                if let Some(superset) = superset {
-                    superset.clear();
+                if !predicate.matches("") {
+                    *superset = Some(Bitmap::create());
                 }
+                return;
+            }
+        };
+        match column.lookup(predicate) {
+            None => {
+                *superset = Some(Bitmap::create());
             }
             Some(ids) => match superset {
                 Some(superset) => {
-                    *superset = superset.and(ids);
+                    superset.and_inplace(&ids);
                 }
                 None => {
-                    *superset = Some(ids.clone());
+                    *superset = Some(ids.into_owned());
                 }
             },
         }
-        Ok(())
     }
 }
 
@@ -382,49 +1239,148 @@ impl<'a> Row<'a> {
         Self { id, chunk }
     }
 
+    /// Stores the samples of `scalars` into the slot `timestamp` falls into, merging them with
+    /// the ones already there as the alignment of the chunk says.
     pub fn insert(&mut self, timestamp: Instant, scalars: &[Scalar]) {
-        let offset = (timestamp - self.chunk.info.start_at) / self.chunk.info.time_interval;
+        let offset =
+            ((timestamp - self.chunk.info.start_at) / self.chunk.info.time_interval) as u32;
+        let alignment = self.chunk.alignment;
+        let mut growth = 0;
         for scalar in scalars {
             let column = self.chunk.columns.scalars.get_mut(scalar.name.as_str());
             match column {
                 None => {
-                    unimplemented!()
+                    unreachable!("scalar {:?} is not in the chunk schema", scalar.name)
                 }
                 Some(column) => {
+                    let before = column.row_heap_size(self.id);
                     let series = column.get_mut(self.id).unwrap();
+                    // strings which are not numbers are not written to columns of numbers
                     match series {
                         ScalarType::Int(series) => {
-                            series.insert(offset as u32, scalar.value.into());
+                            if let Ok(value) = i64::try_from(&scalar.value) {
+                                let value = align(alignment, series.get(offset), value);
+                                series.insert(offset, value);
+                            }
                         }
                         ScalarType::Float(series) => {
-                            series.insert(offset as u32, scalar.value.into());
+                            if let Ok(value) = f64::try_from(&scalar.value) {
+                                let value = align(alignment, series.get(offset), value);
+                                series.insert(offset, value);
+                            }
+                        }
+                        // scalars other than numbers are only written to columns of their type
+                        ScalarType::Histogram(series) => {
+                            if let ScalarValue::Histogram(histogram) = &scalar.value {
+                                align_kept(alignment, series, offset, || {
+                                    let mut value = Encoded::new();
+                                    histogram.encode(&mut value);
+                                    value
+                                });
+                            }
+                        }
+                        ScalarType::Summary(series) => {
+                            if let ScalarValue::Summary(summary) = &scalar.value {
+                                align_kept(alignment, series, offset, || {
+                                    let mut value = Encoded::new();
+                                    summary.encode(&mut value);
+                                    value
+                                });
+                            }
+                        }
+                        ScalarType::Bool(series) => {
+                            if let ScalarValue::Bool(value) = scalar.value {
+                                align_kept(alignment, series, offset, || value);
+                            }
+                        }
+                        ScalarType::String((series, values)) => {
+                            if let ScalarValue::String(value) = &scalar.value {
+                                align_kept(alignment, series, offset, || {
+                                    values.lookup_or_insert(value)
+                                });
+                            }
                         }
                     }
+                    growth += column.row_heap_size(self.id) as isize - before as isize;
+                }
+            }
+        }
+        if alignment == Alignment::Exact {
+            if let Some(column) = self.chunk.columns.scalars.get_mut(TIMESTAMP_SCALAR) {
+                let before = column.row_heap_size(self.id);
+                if let Some(ScalarType::Int(series)) = column.get_mut(self.id) {
+                    series.insert(offset, timestamp.as_millis());
                 }
+                growth += column.row_heap_size(self.id) as isize - before as isize;
             }
         }
+        self.chunk.grow(growth);
+    }
+}
+
+/// The value of a slot once `value` is written to it.
+fn align<G: Accumulate + From<u8>>(alignment: Alignment, slot: Option<G>, value: G) -> G {
+    match (alignment, slot) {
+        (Alignment::Count, None) => G::from(1),
+        (Alignment::Count, Some(count)) => count.accumulate(G::from(1)),
+        (_, None) | (Alignment::LastWrite | Alignment::Exact, _) => value,
+        (Alignment::FirstWrite, Some(first)) => first,
+        (Alignment::Sum, Some(sum)) => sum.accumulate(value),
+        (Alignment::Min, Some(min)) if value < min => value,
+        (Alignment::Max, Some(max)) if value > max => value,
+        (Alignment::Min | Alignment::Max, Some(kept)) => kept,
+    }
+}
+
+/// Numbers summed by the `Sum` and `Count` alignments, integers saturate instead of overflowing.
+trait Accumulate: Copy + PartialOrd {
+    fn accumulate(self, other: Self) -> Self;
+}
+
+impl Accumulate for i64 {
+    #[inline]
+    fn accumulate(self, other: Self) -> Self {
+        self.saturating_add(other)
     }
 }
 
+impl Accumulate for f64 {
+    #[inline]
+    fn accumulate(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+/// Writes a scalar other than a number to a slot. Those cannot be aggregated, so unless the
+/// first write is kept the last one is.
+fn align_kept<G: Default + Clone>(
+    alignment: Alignment,
+    series: &mut Series<G>,
+    offset: u32,
+    value: impl FnOnce() -> G,
+) {
+    if alignment == Alignment::FirstWrite && series.contains(offset) {
+        return;
+    }
+    series.insert(offset, value());
+}
+
 #[cfg(test)]
 mod test {
     use crate::chunk::MutableChunk;
+    use crate::column::{Filter, Predicate};
+    use crate::util::codec::{Decoder, Encoder};
     use common::time::Instant;
     use common::util::IndexMap;
-    use common::{LabelType, Scalar, ScalarType, ScalarValue};
-    use context::{Schema, DEFAULT};
-    use ql::rosetta::{MatcherOp, MatcherRef, Range};
+    use common::{Label, LabelType, LabelValue, Scalar, ScalarType, ScalarValue};
+    use context::{Alignment, Schema, TableMeta, DEFAULT};
+    use croaring::Bitmap;
+    use ql::rosetta::{Matcher, MatcherOp, Range};
     use std::sync::Arc;
 
     #[test]
     fn new_chunk() {
-        let schema = Arc::new(Schema {
-            labels: vec![],
-            scalars: IndexMap::new(),
-            label_arrows: vec![],
-            scalar_arrows: vec![],
-            meta: DEFAULT,
-        });
+        let schema = Arc::new(Schema::new(DEFAULT));
         MutableChunk::new(schema, Instant::now());
     }
 
@@ -435,8 +1391,14 @@ mod test {
             String::from("test2"),
             ScalarType::Int(String::from("test2")),
         );
+        let mut labels = IndexMap::new();
+        labels.insert(
+            String::from("test1"),
+            LabelType::String(String::from("test1")),
+        );
         let schema = Arc::new(Schema {
-            labels: vec![LabelType::String(String::from("test1"))],
+            version: 1,
+            labels,
             scalars,
             label_arrows: vec![],
             scalar_arrows: vec![],
@@ -459,11 +1421,12 @@ mod test {
 
         futures::executor::block_on(async move {
             let projections = Some(vec![String::from("test2")]);
-            let filters = vec![MatcherRef {
-                name: "test1",
+            let filters = vec![Filter::new(&Matcher {
+                name: String::from("test1"),
                 op: MatcherOp::LiteralEqual,
                 value: None,
-            }];
+            })
+            .unwrap()];
             let range = Range {
                 start: None,
                 end: None,
@@ -475,4 +1438,421 @@ mod test {
             println!("{:?}", res);
         });
     }
+
+    #[test]
+    fn chunk_lookup_missing_label() {
+        let labels = [Label {
+            name: "env",
+            value: LabelValue::String("prod"),
+        }];
+        let schema = Arc::new(Schema::new(DEFAULT).evolve(&labels, &[]));
+        let mut chunk = MutableChunk::new(schema, Instant::now());
+        for env in ["prod", "dev"] {
+            let labels = [Label {
+                name: "env",
+                value: LabelValue::String(env),
+            }];
+            let aligned = chunk.align_labels(&labels);
+            chunk.push(&aligned);
+        }
+
+        let lookup = |name, op, value| {
+            let mut filtered = Some(Bitmap::from_iter(0..2));
+            let predicate = Predicate::new(op, Some(&LabelType::String(value))).unwrap();
+            chunk.columns.lookup(name, &predicate, &mut filtered);
+            filtered.unwrap().to_vec()
+        };
+        assert_eq!(lookup("env", MatcherOp::LiteralNotEqual, "dev"), vec![0]);
+        assert_eq!(lookup("env", MatcherOp::RegexMatch, "de.*"), vec![1]);
+        assert_eq!(
+            lookup("env", MatcherOp::LiteralEqual, "test"),
+            Vec::<u32>::new()
+        );
+        assert_eq!(lookup("region", MatcherOp::LiteralEqual, ""), vec![0, 1]);
+        assert_eq!(
+            lookup("region", MatcherOp::LiteralEqual, "us"),
+            Vec::<u32>::new()
+        );
+        assert_eq!(
+            lookup("region", MatcherOp::LiteralNotEqual, "us"),
+            vec![0, 1]
+        );
+        assert_eq!(lookup("region", MatcherOp::RegexMatch, "us|"), vec![0, 1]);
+        assert_eq!(
+            lookup("region", MatcherOp::RegexNotMatch, ".*"),
+            Vec::<u32>::new()
+        );
+    }
+
+    #[test]
+    fn chunk_delete() {
+        let labels = [Label {
+            name: "env",
+            value: LabelValue::String("prod"),
+        }];
+        let start_at = Instant::from_millis(0);
+        let scalars = [(
+            start_at,
+            vec![Scalar {
+                name: String::from("value"),
+                value: ScalarValue::Int(1),
+            }],
+        )];
+        let schema = Arc::new(Schema::new(DEFAULT).evolve(&labels, &scalars));
+        let mut chunk = MutableChunk::new(Arc::clone(&schema), start_at);
+        for env in ["prod", "dev"] {
+            let labels = [Label {
+                name: "env",
+                value: LabelValue::String(env),
+            }];
+            let aligned = chunk.align_labels(&labels);
+            let mut row = chunk.push(&aligned);
+            row.insert(start_at, &scalars[0].1);
+            row.insert(start_at + schema.meta.time_interval, &scalars[0].1);
+        }
+
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        let dev = [Filter::new(&Matcher {
+            name: String::from("env"),
+            op: MatcherOp::LiteralEqual,
+            value: Some(LabelType::String(String::from("dev"))),
+        })
+        .unwrap()];
+        let first_slot = Range {
+            start: None,
+            end: Some(start_at + schema.meta.time_interval),
+        };
+        assert_eq!(chunk.series_num(), 2);
+        assert_eq!(chunk.delete(&dev, first_slot), 1);
+        assert_eq!(chunk.filter(&dev).to_vec(), vec![1]);
+        assert_eq!(chunk.series_num(), 2);
+        assert_eq!(chunk.delete(&dev, whole), 1);
+        assert!(chunk.filter(&dev).is_empty());
+        assert_eq!(chunk.filter(&[]).to_vec(), vec![0]);
+        assert_eq!(chunk.series_num(), 1);
+
+        // written again as a new row
+        let labels = [Label {
+            name: "env",
+            value: LabelValue::String("dev"),
+        }];
+        let aligned = chunk.align_labels(&labels);
+        assert!(chunk.get_mut(&aligned).unwrap().is_none());
+
+        let archived = chunk.archive();
+        assert_eq!(archived.record_num(), 1);
+        let scanned = futures::executor::block_on(archived.scan(None, &[], whole, None))
+            .unwrap()
+            .unwrap();
+        let (left, deleted) = scanned.delete(&[], 0..1).unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(left.len(), 1);
+        let (left, deleted) = left.delete(&[], 0..2).unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(left.len(), 0);
+    }
+
+    #[test]
+    fn chunk_evolve() {
+        let labels = [Label {
+            name: "env",
+            value: LabelValue::String("prod"),
+        }];
+        let scalars = [(
+            Instant::now(),
+            vec![Scalar {
+                name: String::from("value"),
+                value: ScalarValue::Float(1.0),
+            }],
+        )];
+        let schema = Arc::new(Schema::new(DEFAULT).evolve(&labels, &scalars));
+        let mut chunk = MutableChunk::new(Arc::clone(&schema), scalars[0].0);
+        let aligned = chunk.align_labels(&labels);
+        chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1);
+
+        let evolved_labels = [
+            Label {
+                name: "env",
+                value: LabelValue::String("prod"),
+            },
+            Label {
+                name: "region",
+                value: LabelValue::String("us"),
+            },
+        ];
+        let evolved_scalars = [(
+            scalars[0].0,
+            vec![Scalar {
+                name: String::from("count"),
+                value: ScalarValue::Int(1),
+            }],
+        )];
+        let evolved = schema.evolve(&evolved_labels, &evolved_scalars);
+        assert_eq!(evolved.version, schema.version + 1);
+        chunk.evolve(&evolved);
+        assert_eq!(chunk.columns.labels.len(), 2);
+        assert_eq!(chunk.columns.scalars.len(), 2);
+        assert!(chunk.columns.labels[1].get(0).is_none());
+
+        let aligned = chunk.align_labels(&evolved_labels);
+        assert!(chunk.get_mut(&aligned).unwrap().is_none());
+        chunk
+            .push(&aligned)
+            .insert(evolved_scalars[0].0, &evolved_scalars[0].1);
+        assert_eq!(chunk.stat.record_num, 2);
+        let aligned = chunk.align_labels(&labels);
+        assert!(chunk.get_mut(&aligned).unwrap().is_some());
+    }
+
+    #[test]
+    fn chunk_alignment() {
+        let start_at = Instant::from_millis(0);
+        let samples = [(100, 2.0), (700, 1.0), (1300, 3.0)];
+        let aligned = |alignment| {
+            let scalars = [(
+                start_at,
+                vec![Scalar {
+                    name: String::from("value"),
+                    value: ScalarValue::Float(0.0),
+                }],
+            )];
+            let meta = TableMeta {
+                alignment,
+                ..DEFAULT
+            };
+            let schema = Schema::new(DEFAULT).evolve(&[], &scalars).with_meta(meta);
+            let mut chunk = MutableChunk::new(Arc::new(schema), start_at);
+            let mut row = chunk.push(&[]);
+            for (millis, value) in samples {
+                row.insert(
+                    Instant::from_millis(millis),
+                    &[Scalar {
+                        name: String::from("value"),
+                        value: ScalarValue::Float(value),
+                    }],
+                );
+            }
+            let mut slots = Vec::new();
+            for column in chunk.columns.scalars.iter() {
+                match column.get(0).unwrap() {
+                    ScalarType::Int(series) => {
+                        slots.push(format!("{:?}", series.range(0..2).collect::<Vec<_>>()))
+                    }
+                    ScalarType::Float(series) => {
+                        slots.push(format!("{:?}", series.range(0..2).collect::<Vec<_>>()))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            slots.join(" ")
+        };
+        assert_eq!(aligned(Alignment::LastWrite), "[Some(1.0), Some(3.0)]");
+        assert_eq!(aligned(Alignment::FirstWrite), "[Some(2.0), Some(3.0)]");
+        assert_eq!(aligned(Alignment::Sum), "[Some(3.0), Some(3.0)]");
+        assert_eq!(aligned(Alignment::Min), "[Some(1.0), Some(3.0)]");
+        assert_eq!(aligned(Alignment::Max), "[Some(2.0), Some(3.0)]");
+        assert_eq!(aligned(Alignment::Count), "[Some(2.0), Some(1.0)]");
+        assert_eq!(
+            aligned(Alignment::Exact),
+            "[Some(1.0), Some(3.0)] [Some(700), Some(1300)]"
+        );
+    }
+
+    #[test]
+    fn align_saturates() {
+        use super::align;
+        assert_eq!(align(Alignment::Sum, Some(i64::MAX), 1), i64::MAX);
+        assert_eq!(align(Alignment::Sum, Some(i64::MIN), -1), i64::MIN);
+        assert_eq!(align(Alignment::Count, Some(i64::MAX), 7), i64::MAX);
+    }
+
+    #[test]
+    fn chunk_insert_strings() {
+        let start_at = Instant::from_millis(0);
+        let number = |value: ScalarValue| {
+            vec![Scalar {
+                name: String::from("value"),
+                value,
+            }]
+        };
+        let schema =
+            Schema::new(DEFAULT).evolve(&[], &[(start_at, number(ScalarValue::Float(0.0)))]);
+        let mut chunk = MutableChunk::new(Arc::new(schema), start_at);
+        let mut row = chunk.push(&[]);
+        let second = common::time::Duration::SECOND;
+        row.insert(start_at, &number(ScalarValue::String(String::from("open"))));
+        row.insert(
+            start_at + second,
+            &number(ScalarValue::String(String::from("2.5"))),
+        );
+        match chunk.columns.scalars.get("value").unwrap().get(0).unwrap() {
+            ScalarType::Float(series) => {
+                assert_eq!(series.range(0..2).collect::<Vec<_>>(), [None, Some(2.5)])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn chunk_heap_size() {
+        let start_at = Instant::from_millis(0);
+        let mut chunk = None;
+        for id in 0..200u32 {
+            let host = format!("host{}", id % 50);
+            let labels = [
+                Label {
+                    name: "host",
+                    value: LabelValue::String(&host),
+                },
+                Label {
+                    name: "env",
+                    value: LabelValue::String(if id % 3 == 0 { "" } else { "prod" }),
+                },
+            ];
+            let scalars = [(
+                start_at + common::time::Duration::SECOND * (id % 7),
+                vec![
+                    Scalar {
+                        name: String::from("value"),
+                        value: ScalarValue::Int(id as i64),
+                    },
+                    Scalar {
+                        name: String::from("state"),
+                        value: ScalarValue::String(format!("state{}", id % 11)),
+                    },
+                ],
+            )];
+            let chunk = chunk.get_or_insert_with(|| {
+                let schema = Schema::new(DEFAULT).evolve(&labels, &scalars);
+                MutableChunk::new(Arc::new(schema), start_at)
+            });
+            let aligned = chunk.align_labels(&labels);
+            let mut row = match chunk.get_mut(&aligned).unwrap() {
+                Some(row) => row,
+                None => chunk.push(&aligned),
+            };
+            row.insert(scalars[0].0, &scalars[0].1);
+            assert_eq!(chunk.heap_size(), chunk.measure_heap_size());
+        }
+    }
+
+    #[test]
+    fn chunk_codec() {
+        let start_at = Instant::from_millis(0);
+        let mut chunk = None;
+        for (id, env) in ["prod", "dev", ""].into_iter().enumerate() {
+            let labels = [Label {
+                name: "env",
+                value: LabelValue::String(env),
+            }];
+            let scalars = [(
+                start_at + common::time::Duration::SECOND * id as u32,
+                vec![Scalar {
+                    name: String::from("value"),
+                    value: ScalarValue::Int(id as i64),
+                }],
+            )];
+            let chunk = chunk.get_or_insert_with(|| {
+                let schema = Schema::new(DEFAULT).evolve(&labels, &scalars);
+                MutableChunk::new(Arc::new(schema), start_at)
+            });
+            let aligned = chunk.align_labels(&labels);
+            chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1);
+        }
+        let chunk = chunk.unwrap();
+        let mut encoder = Encoder::new();
+        chunk.encode(&mut encoder);
+        let buf = encoder.into_inner();
+        let mut decoder = Decoder::new(&buf);
+        let mut decoded = MutableChunk::decode(&mut decoder).unwrap();
+        assert!(decoder.is_empty());
+
+        assert_eq!(decoded.stat.record_num, 3);
+        assert_eq!(decoded.columns.version, chunk.columns.version);
+        let aligned = decoded.align_labels(&[Label {
+            name: "env",
+            value: LabelValue::String("dev"),
+        }]);
+        let row = decoded.get_mut(&aligned).unwrap().unwrap();
+        assert_eq!(row.id, 1);
+        assert!(decoded.columns.labels[0].get(2).is_none());
+        match decoded.columns.scalars[0].get(1).unwrap() {
+            ScalarType::Int(series) => {
+                assert_eq!(series.range(0..2).collect::<Vec<_>>(), vec![None, Some(1)])
+            }
+            _ => unreachable!(),
+        }
+        assert!(MutableChunk::decode(&mut Decoder::new(&buf[..buf.len() - 1])).is_none());
+    }
+    #[test]
+    fn chunk_states() {
+        let start_at = Instant::from_millis(0);
+        let mut chunk = None;
+        for (id, (door, state)) in [("front", "OPEN"), ("back", "CLOSED"), ("front", "CLOSED")]
+            .into_iter()
+            .enumerate()
+        {
+            let labels = [Label {
+                name: "door",
+                value: LabelValue::String(door),
+            }];
+            let scalars = [(
+                start_at + common::time::Duration::SECOND * id as u32,
+                vec![
+                    Scalar {
+                        name: String::from("state"),
+                        value: ScalarValue::String(String::from(state)),
+                    },
+                    Scalar {
+                        name: String::from("open"),
+                        value: ScalarValue::Bool(state == "OPEN"),
+                    },
+                ],
+            )];
+            let chunk = chunk.get_or_insert_with(|| {
+                let schema = Schema::new(DEFAULT).evolve(&labels, &scalars);
+                MutableChunk::new(Arc::new(schema), start_at)
+            });
+            let aligned = chunk.align_labels(&labels);
+            match chunk.get_mut(&aligned).unwrap() {
+                Some(mut row) => row.insert(scalars[0].0, &scalars[0].1),
+                None => chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1),
+            }
+        }
+        let mut encoder = Encoder::new();
+        chunk.unwrap().encode(&mut encoder);
+        let buf = encoder.into_inner();
+        let chunk = MutableChunk::decode(&mut Decoder::new(&buf)).unwrap();
+
+        let range = Range {
+            start: None,
+            end: None,
+        };
+        let scanned = futures::executor::block_on(chunk.archive().scan(None, &[], range, None))
+            .unwrap()
+            .unwrap();
+        let series = scanned.series();
+        assert_eq!(series.len(), 2);
+        let samples = series[0]
+            .1
+            .iter()
+            .map(|(_, scalars)| {
+                scalars
+                    .iter()
+                    .map(|scalar| format!("{}={:?}", scalar.name, scalar.value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            vec![
+                "state=String(\"OPEN\") open=Bool(true)",
+                "state=String(\"CLOSED\") open=Bool(false)"
+            ]
+        );
+    }
 }
@@ -1,81 +1,246 @@
+use crate::error::ScanError;
+use crate::util::codec::{Decoder, Encoder};
 use crate::util::dictionary::StringDictionary;
-use common::{LabelType, LabelValue, ScalarType};
+use crate::util::gorilla::{self, Gorilla};
+use arrow2::array::{
+    new_empty_array, Array, BinaryArray, BooleanArray, DictionaryArray, ListArray, MutableArray,
+    MutableBinaryArray, MutableBooleanArray, MutableListArray, MutablePrimitiveArray,
+    MutableUtf8Array, PrimitiveArray, TryExtend, TryPush, Utf8Array,
+};
+use arrow2::bitmap::MutableBitmap;
+use arrow2::compute::concatenate::concatenate;
+use arrow2::datatypes::DataType;
+use arrow2::types::NativeType;
+use common::{LabelType, LabelValue, ScalarKind, ScalarType};
+use context::{ScalarColumnType, HISTOGRAM_ITEM, SUMMARY_ITEM};
 use croaring::Bitmap;
 use hashbrown::HashMap;
-use ql::rosetta::MatcherOp;
+use ql::rosetta::{Matcher, MatcherOp};
+use regex::Regex;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::mem;
 use std::ops::Range;
 use std::sync::Arc;
 
+/// The slots of a series, its buffers only growing up to the last slot written, so rows that
+/// receive few samples stay small.
 #[derive(Debug)]
 pub(crate) struct Series<G> {
-    data: Vec<Option<G>>,
+    validity: MutableBitmap,
+    values: Vec<G>,
 }
 
-impl<G: 'static + Default + Clone + Copy> Series<G> {
-    pub(crate) fn new(len: u32) -> Self {
+impl<G: Default + Clone> Series<G> {
+    pub(crate) fn new() -> Self {
         Self {
-            data: vec![Default::default(); len as usize],
+            validity: MutableBitmap::new(),
+            values: Vec::new(),
         }
     }
 
     #[inline]
     pub(crate) fn insert(&mut self, index: u32, scalar: G) {
-        self.data[index as usize] = Some(scalar);
+        let index = index as usize;
+        if index >= self.values.len() {
+            self.validity
+                .extend_constant(index + 1 - self.values.len(), false);
+            self.values.resize(index + 1, G::default());
+        }
+        self.validity.set(index, true);
+        self.values[index] = scalar;
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, index: u32) -> Option<G> {
+        if self.contains(index) {
+            Some(self.values[index as usize].clone())
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub(crate) fn contains(&self, index: u32) -> bool {
+        let index = index as usize;
+        index < self.values.len() && self.validity.get(index)
     }
 
+    /// Empties the slot at `index`, returning whether it had a value.
     #[inline]
-    pub(crate) fn range(&self, range: Range<usize>) -> &[Option<G>] {
-        &self.data[range]
+    pub(crate) fn remove(&mut self, index: u32) -> bool {
+        let index = index as usize;
+        if index < self.values.len() && self.validity.get(index) {
+            self.validity.set(index, false);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.validity.null_count() == self.validity.len()
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.values.capacity() * mem::size_of::<G>() + self.validity.capacity() / 8
+    }
+
+    /// Iterates the slots within `range`, the ones never written are empty.
+    pub(crate) fn range(&self, range: Range<usize>) -> impl Iterator<Item = Option<G>> + '_ {
+        range.map(|index| self.get(index as u32))
+    }
+
+    /// Appends the slots within `range` to `values` and `validity`, copying the validity bits
+    /// in bulk.
+    fn extend(&self, range: Range<usize>, values: &mut Vec<G>, validity: &mut MutableBitmap) {
+        let end = range.end.min(self.values.len());
+        let start = range.start.min(end);
+        values.extend_from_slice(&self.values[start..end]);
+        validity.extend_from_slice(self.validity.as_slice(), start, end - start);
+        let padding = range.len() - (end - start);
+        values.resize(values.len() + padding, G::default());
+        validity.extend_constant(padding, false);
     }
 }
 
+/// A histogram or summary, encoded as [`common::histogram`] does.
+pub(crate) type Encoded = Vec<u8>;
+
+/// The series of a scalar column, one per row.
+type Rows<G> = Vec<Series<G>>;
+
+/// The series of a string column, holding the ids of their values in the dictionary of the
+/// column.
+#[derive(Debug)]
+pub(crate) struct StringRows {
+    rows: Rows<usize>,
+    values: StringDictionary,
+}
+
+impl StringRows {
+    fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            values: StringDictionary::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) type SeriesRef<'a> = ScalarType<
+    &'a Series<i64>,
+    &'a Series<f64>,
+    &'a Series<Encoded>,
+    &'a Series<Encoded>,
+    &'a Series<bool>,
+    &'a Series<usize>,
+>;
+
+pub(crate) type SeriesMut<'a> = ScalarType<
+    &'a mut Series<i64>,
+    &'a mut Series<f64>,
+    &'a mut Series<Encoded>,
+    &'a mut Series<Encoded>,
+    &'a mut Series<bool>,
+    (&'a mut Series<usize>, &'a mut StringDictionary),
+>;
+
+type ScalarRows =
+    ScalarType<Rows<i64>, Rows<f64>, Rows<Encoded>, Rows<Encoded>, Rows<bool>, StringRows>;
+
 #[derive(Debug)]
 pub(crate) struct ScalarColumn {
     name: Arc<str>,
-    data: ScalarType<Vec<Series<i64>>, Vec<Series<f64>>>,
+    data: ScalarRows,
     series_len: u32,
 }
 
 impl ScalarColumn {
-    pub(crate) fn new(column_type: ScalarType<String, String>, series_len: u32) -> Self {
-        match column_type {
-            ScalarType::Int(name) => Self {
-                name: Arc::from(name),
-                data: ScalarType::Int(Vec::new()),
-                series_len,
-            },
-            ScalarType::Float(name) => Self {
-                name: Arc::from(name),
-                data: ScalarType::Float(Vec::new()),
-                series_len,
-            },
+    pub(crate) fn new(column_type: ScalarColumnType, series_len: u32) -> Self {
+        let (name, data) = match column_type {
+            ScalarType::Int(name) => (name, ScalarType::Int(Vec::new())),
+            ScalarType::Float(name) => (name, ScalarType::Float(Vec::new())),
+            ScalarType::Histogram(name) => (name, ScalarType::Histogram(Vec::new())),
+            ScalarType::Summary(name) => (name, ScalarType::Summary(Vec::new())),
+            ScalarType::Bool(name) => (name, ScalarType::Bool(Vec::new())),
+            ScalarType::String(name) => (name, ScalarType::String(StringRows::new())),
+        };
+        Self {
+            name: Arc::from(name),
+            data,
+            series_len,
         }
     }
 
+    /// Appends an empty series, returning how much the heap usage of the column grew.
     #[inline]
-    pub(crate) fn push_zero(&mut self) {
+    pub(crate) fn push_zero(&mut self) -> isize {
+        let before = self.rows_heap_size();
         match &mut self.data {
-            ScalarType::Int(column) => column.push(Series::new(self.series_len)),
-            ScalarType::Float(column) => column.push(Series::new(self.series_len)),
+            ScalarType::Int(column) => column.push(Series::new()),
+            ScalarType::Float(column) => column.push(Series::new()),
+            ScalarType::Histogram(column) | ScalarType::Summary(column) => {
+                column.push(Series::new())
+            }
+            ScalarType::Bool(column) => column.push(Series::new()),
+            ScalarType::String(column) => column.rows.push(Series::new()),
         };
+        self.rows_heap_size() as isize - before as isize
     }
 
-    #[inline]
-    pub(crate) fn get(&self, offset: u32) -> Option<ScalarType<&Series<i64>, &Series<f64>>> {
+    #[cfg(test)]
+    pub(crate) fn get(&self, offset: u32) -> Option<SeriesRef<'_>> {
         return match &self.data {
             ScalarType::Int(data) => data.get(offset as usize).map(ScalarType::Int),
             ScalarType::Float(data) => data.get(offset as usize).map(ScalarType::Float),
+            ScalarType::Histogram(data) => data.get(offset as usize).map(ScalarType::Histogram),
+            ScalarType::Summary(data) => data.get(offset as usize).map(ScalarType::Summary),
+            ScalarType::Bool(data) => data.get(offset as usize).map(ScalarType::Bool),
+            ScalarType::String(data) => data.rows.get(offset as usize).map(ScalarType::String),
         };
     }
 
     #[inline]
-    pub(crate) fn get_mut(
-        &mut self,
-        offset: u32,
-    ) -> Option<ScalarType<&mut Series<i64>, &mut Series<f64>>> {
+    pub(crate) fn get_mut(&mut self, offset: u32) -> Option<SeriesMut<'_>> {
         match &mut self.data {
             ScalarType::Int(data) => data.get_mut(offset as usize).map(ScalarType::Int),
             ScalarType::Float(data) => data.get_mut(offset as usize).map(ScalarType::Float),
+            ScalarType::Histogram(data) => data.get_mut(offset as usize).map(ScalarType::Histogram),
+            ScalarType::Summary(data) => data.get_mut(offset as usize).map(ScalarType::Summary),
+            ScalarType::Bool(data) => data.get_mut(offset as usize).map(ScalarType::Bool),
+            ScalarType::String(StringRows { rows, values }) => rows
+                .get_mut(offset as usize)
+                .map(|series| ScalarType::String((series, values))),
+        }
+    }
+
+    /// Empties the slot at `index` of the series of row `id`, returning whether it had a value.
+    #[inline]
+    pub(crate) fn remove(&mut self, id: u32, index: u32) -> bool {
+        match self.get_mut(id) {
+            None => false,
+            Some(ScalarType::Int(series)) => series.remove(index),
+            Some(ScalarType::Float(series)) => series.remove(index),
+            Some(ScalarType::Histogram(series) | ScalarType::Summary(series)) => {
+                series.remove(index)
+            }
+            Some(ScalarType::Bool(series)) => series.remove(index),
+            Some(ScalarType::String((series, _))) => series.remove(index),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self, id: u32) -> bool {
+        match &self.data {
+            ScalarType::Int(data) => data.get(id as usize).is_none_or(Series::is_empty),
+            ScalarType::Float(data) => data.get(id as usize).is_none_or(Series::is_empty),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => {
+                data.get(id as usize).is_none_or(Series::is_empty)
+            }
+            ScalarType::Bool(data) => data.get(id as usize).is_none_or(Series::is_empty),
+            ScalarType::String(data) => data.rows.get(id as usize).is_none_or(Series::is_empty),
         }
     }
 
@@ -84,13 +249,535 @@ impl ScalarColumn {
         &self.name
     }
 
+    /// The number of rows, with or without a series.
+    fn len(&self) -> usize {
+        match &self.data {
+            ScalarType::Int(data) => data.len(),
+            ScalarType::Float(data) => data.len(),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => data.len(),
+            ScalarType::Bool(data) => data.len(),
+            ScalarType::String(data) => data.rows.len(),
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        match &self.data {
+            ScalarType::Int(data) => rows_heap_size(data, Series::heap_size),
+            ScalarType::Float(data) => rows_heap_size(data, Series::heap_size),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => {
+                rows_heap_size(data, encoded_heap_size)
+            }
+            ScalarType::Bool(data) => rows_heap_size(data, Series::heap_size),
+            ScalarType::String(data) => {
+                rows_heap_size(&data.rows, Series::heap_size) + data.values.heap_size()
+            }
+        }
+    }
+
+    /// The part of the heap usage of the column a write to row `id` changes: its series, and
+    /// the dictionary of a string column.
+    pub(crate) fn row_heap_size(&self, id: u32) -> usize {
+        let id = id as usize;
+        match &self.data {
+            ScalarType::Int(data) => data.get(id).map_or(0, Series::heap_size),
+            ScalarType::Float(data) => data.get(id).map_or(0, Series::heap_size),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => {
+                data.get(id).map_or(0, encoded_heap_size)
+            }
+            ScalarType::Bool(data) => data.get(id).map_or(0, Series::heap_size),
+            ScalarType::String(data) => {
+                data.rows.get(id).map_or(0, Series::heap_size) + data.values.heap_size()
+            }
+        }
+    }
+
+    /// The heap usage of the buffer holding the rows, their series left out.
+    fn rows_heap_size(&self) -> usize {
+        match &self.data {
+            ScalarType::Int(data) => data.capacity() * mem::size_of::<Series<i64>>(),
+            ScalarType::Float(data) => data.capacity() * mem::size_of::<Series<f64>>(),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => {
+                data.capacity() * mem::size_of::<Series<Encoded>>()
+            }
+            ScalarType::Bool(data) => data.capacity() * mem::size_of::<Series<bool>>(),
+            ScalarType::String(data) => data.rows.capacity() * mem::size_of::<Series<usize>>(),
+        }
+    }
+
+    /// Builds a list array of the `range` of each series in `ids`.
+    pub(crate) fn array(
+        &self,
+        ids: impl Iterator<Item = u32>,
+        range: Range<usize>,
+    ) -> Arc<dyn Array> {
+        match &self.data {
+            ScalarType::Int(data) => series_list(data, ids, range),
+            ScalarType::Float(data) => series_list(data, ids, range),
+            ScalarType::Histogram(data) | ScalarType::Summary(data) => {
+                let array = encoded_builder(self.scalar_type());
+                mutable_list(data, ids, range, array, Some)
+            }
+            ScalarType::Bool(data) => {
+                let array = MutableListArray::<i32, MutableBooleanArray>::new();
+                mutable_list(data, ids, range, array, Some)
+            }
+            ScalarType::String(data) => {
+                let array = MutableListArray::<i32, MutableUtf8Array<i32>>::new();
+                mutable_list(&data.rows, ids, range, array, |id| data.values.get(id))
+            }
+        }
+    }
+
     #[inline]
-    pub(crate) fn data_type(&self) -> ScalarType<(), ()> {
-        match self.data {
+    pub(crate) fn scalar_type(&self) -> ScalarKind {
+        match &self.data {
             ScalarType::Int(_) => ScalarType::Int(()),
             ScalarType::Float(_) => ScalarType::Float(()),
+            ScalarType::Histogram(_) => ScalarType::Histogram(()),
+            ScalarType::Summary(_) => ScalarType::Summary(()),
+            ScalarType::Bool(_) => ScalarType::Bool(()),
+            ScalarType::String(_) => ScalarType::String(()),
+        }
+    }
+
+    pub(crate) fn encode(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.name);
+        encoder.put_u32(self.series_len);
+        match &self.data {
+            ScalarType::Int(data) => {
+                encoder.put_u8(0);
+                encode_series(data, self.series_len, encoder, Encoder::put_i64);
+            }
+            ScalarType::Float(data) => {
+                encoder.put_u8(1);
+                encode_series(data, self.series_len, encoder, Encoder::put_f64);
+            }
+            ScalarType::Histogram(data) => {
+                encoder.put_u8(2);
+                encode_series(data, self.series_len, encoder, put_encoded);
+            }
+            ScalarType::Summary(data) => {
+                encoder.put_u8(3);
+                encode_series(data, self.series_len, encoder, put_encoded);
+            }
+            ScalarType::Bool(data) => {
+                encoder.put_u8(4);
+                encode_series(data, self.series_len, encoder, |encoder, value| {
+                    encoder.put_u8(value as u8)
+                });
+            }
+            ScalarType::String(data) => {
+                // values are encoded in place of their ids, which are reassigned on decoding
+                encoder.put_u8(5);
+                encode_series(&data.rows, self.series_len, encoder, |encoder, id| {
+                    encoder.put_str(data.values.get(id).unwrap())
+                });
+            }
         }
     }
+
+    pub(crate) fn decode(decoder: &mut Decoder) -> Option<Self> {
+        let name = Arc::from(decoder.str()?);
+        let series_len = decoder.u32()?;
+        let data = match decoder.u8()? {
+            0 => ScalarType::Int(decode_series(decoder, series_len, Decoder::i64)?),
+            1 => ScalarType::Float(decode_series(decoder, series_len, Decoder::f64)?),
+            2 => ScalarType::Histogram(decode_series(decoder, series_len, encoded)?),
+            3 => ScalarType::Summary(decode_series(decoder, series_len, encoded)?),
+            4 => ScalarType::Bool(decode_series(decoder, series_len, |decoder| {
+                decoder.u8().map(|value| value != 0)
+            })?),
+            5 => {
+                let mut values = StringDictionary::new();
+                let rows = decode_series(decoder, series_len, |decoder| {
+                    decoder.str().map(|value| values.lookup_or_insert(value))
+                })?;
+                ScalarType::String(StringRows { rows, values })
+            }
+            _ => return None,
+        };
+        Some(Self {
+            name,
+            data,
+            series_len,
+        })
+    }
+}
+
+/// The heap usage of the rows of a column, the buffer holding them included.
+fn rows_heap_size<T>(rows: &Vec<T>, heap_size: impl Fn(&T) -> usize) -> usize {
+    rows.capacity() * mem::size_of::<T>() + rows.iter().map(heap_size).sum::<usize>()
+}
+
+/// The heap usage of a series of histograms or summaries, their encodings included.
+fn encoded_heap_size(series: &Series<Encoded>) -> usize {
+    series.heap_size() + series.values.iter().map(Vec::capacity).sum::<usize>()
+}
+
+/// The heap usage of the bitmaps of a label index, approximated by their serialized size.
+fn index_heap_size<'a>(bitmaps: impl Iterator<Item = &'a Bitmap>) -> usize {
+    bitmaps.map(bitmap_heap_size).sum()
+}
+
+#[inline]
+fn bitmap_heap_size(bitmap: &Bitmap) -> usize {
+    mem::size_of::<Bitmap>() + bitmap.get_serialized_size_in_bytes()
+}
+
+fn series_list<G: NativeType + Default>(
+    data: &[Series<G>],
+    ids: impl Iterator<Item = u32>,
+    range: Range<usize>,
+) -> Arc<dyn Array> {
+    let mut offsets = vec![0];
+    let mut values = Vec::new();
+    let mut validity = MutableBitmap::new();
+    let mut rows = MutableBitmap::new();
+    for id in ids {
+        if let Some(series) = data.get(id as usize) {
+            series.extend(range.clone(), &mut values, &mut validity);
+        }
+        rows.push(data.len() > id as usize);
+        offsets.push(values.len() as i32);
+    }
+    let values = PrimitiveArray::from_data(G::PRIMITIVE.into(), values.into(), validity.into());
+    let data_type = ListArray::<i32>::default_datatype(values.data_type().clone());
+    Arc::new(ListArray::from_data(
+        data_type,
+        offsets.into(),
+        Arc::new(values),
+        rows.into(),
+    ))
+}
+
+/// The type of the scalars of a list array of series, histograms and summaries told apart by
+/// the name of their list items.
+pub(crate) fn scalar_type(data_type: &DataType) -> ScalarKind {
+    let item = ListArray::<i32>::get_child_field(data_type);
+    match item.data_type() {
+        DataType::Int64 => ScalarType::Int(()),
+        DataType::Float64 => ScalarType::Float(()),
+        DataType::LargeBinary if item.name == HISTOGRAM_ITEM => ScalarType::Histogram(()),
+        DataType::LargeBinary => ScalarType::Summary(()),
+        DataType::Boolean => ScalarType::Bool(()),
+        DataType::Utf8 => ScalarType::String(()),
+        _ => unreachable!(),
+    }
+}
+
+/// A builder of a list array of histogram or summary series.
+pub(crate) fn encoded_builder(
+    scalar_type: ScalarKind,
+) -> MutableListArray<i32, MutableBinaryArray<i64>> {
+    let item = match scalar_type {
+        ScalarType::Histogram(_) => HISTOGRAM_ITEM,
+        _ => SUMMARY_ITEM,
+    };
+    MutableListArray::new_with_field(MutableBinaryArray::new(), item, true)
+}
+
+/// Pushes the `range` of each series in `ids` to `array`, `value` giving what to push for the
+/// values of the slots.
+fn mutable_list<G, M, T>(
+    data: &[Series<G>],
+    ids: impl Iterator<Item = u32>,
+    range: Range<usize>,
+    mut array: MutableListArray<i32, M>,
+    value: impl Fn(G) -> Option<T>,
+) -> Arc<dyn Array>
+where
+    G: Default + Clone,
+    M: MutableArray + Default + TryExtend<Option<T>> + 'static,
+{
+    for id in ids {
+        match data.get(id as usize) {
+            None => MutableArray::push_null(&mut array),
+            Some(series) => {
+                let values = series
+                    .range(range.clone())
+                    .map(|slot| slot.and_then(&value));
+                array.try_push(Some(values)).unwrap()
+            }
+        }
+    }
+    array.into_arc()
+}
+
+/// Builds a list array of `data_type` out of the series of each row, `None` for the rows
+/// without the column.
+pub fn list_from_rows(data_type: &DataType, rows: Vec<Option<Box<dyn Array>>>) -> Arc<dyn Array> {
+    let mut offsets = Vec::with_capacity(rows.len() + 1);
+    offsets.push(0);
+    let mut validity = MutableBitmap::with_capacity(rows.len());
+    let mut values = Vec::with_capacity(rows.len());
+    for row in &rows {
+        validity.push(row.is_some());
+        let len = row.as_ref().map_or(0, |series| series.len());
+        offsets.push(offsets.last().unwrap() + len as i32);
+        values.extend(row.as_deref());
+    }
+    let values = if values.is_empty() {
+        new_empty_array(ListArray::<i32>::get_child_type(data_type).clone())
+    } else {
+        concatenate(&values).unwrap()
+    };
+    Arc::new(ListArray::from_data(
+        data_type.clone(),
+        offsets.into(),
+        Arc::from(values),
+        validity.into(),
+    ))
+}
+
+/// Takes the `range` of each series in `ids` of `list`, the rows out of it have no series.
+pub(crate) fn take_list(
+    list: &ListArray<i32>,
+    ids: impl Iterator<Item = u32>,
+    range: Range<usize>,
+) -> Arc<dyn Array> {
+    let rows = ids
+        .map(|id| {
+            let id = id as usize;
+            if id >= list.len() || list.is_null(id) {
+                return None;
+            }
+            let series = list.value(id);
+            let end = range.end.min(series.len());
+            let start = range.start.min(end);
+            Some(series.slice(start, end - start))
+        })
+        .collect();
+    list_from_rows(list.data_type(), rows)
+}
+
+fn put_encoded(encoder: &mut Encoder, value: Encoded) {
+    encoder.put_bytes(&value)
+}
+
+fn encoded(decoder: &mut Decoder) -> Option<Encoded> {
+    decoder.bytes().map(<[u8]>::to_vec)
+}
+
+fn encode_series<G: Default + Clone>(
+    data: &[Series<G>],
+    series_len: u32,
+    encoder: &mut Encoder,
+    put: impl Fn(&mut Encoder, G),
+) {
+    encoder.put_u32(data.len() as u32);
+    for series in data {
+        for value in series.range(0..series_len as usize) {
+            match value {
+                None => encoder.put_u8(0),
+                Some(value) => {
+                    encoder.put_u8(1);
+                    put(encoder, value);
+                }
+            }
+        }
+    }
+}
+
+fn decode_series<'a, G: Default + Clone>(
+    decoder: &mut Decoder<'a>,
+    series_len: u32,
+    mut get: impl FnMut(&mut Decoder<'a>) -> Option<G>,
+) -> Option<Vec<Series<G>>> {
+    let len = decoder.u32()?;
+    let mut data = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let mut series = Series::new();
+        for index in 0..series_len {
+            if decoder.u8()? == 1 {
+                series.insert(index, get(decoder)?);
+            }
+        }
+        data.push(series);
+    }
+    Some(data)
+}
+
+/// An archived series, the values of its non-empty slots compressed one after another.
+#[derive(Debug)]
+pub(crate) struct CompressedSeries<G> {
+    validity: arrow2::bitmap::Bitmap,
+    data: Box<[u8]>,
+    _marker: PhantomData<G>,
+}
+
+impl<G: Gorilla> CompressedSeries<G> {
+    pub(crate) fn new(slots: impl Iterator<Item = Option<G>>) -> Self {
+        let mut validity = MutableBitmap::new();
+        let data = gorilla::compress(slots.filter_map(|slot| {
+            validity.push(slot.is_some());
+            slot
+        }));
+        Self {
+            validity: validity.into(),
+            data: data.into_boxed_slice(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.validity.len().div_ceil(8) + self.data.len()
+    }
+
+    /// Decodes the slots within `range`, the values before it are decoded and skipped.
+    pub(crate) fn range(&self, range: Range<usize>) -> impl Iterator<Item = Option<G>> + '_ {
+        let len = self.validity.len() - self.validity.null_count();
+        let mut values = gorilla::decompress::<G>(&self.data, len);
+        let end = range.end.min(self.validity.len());
+        self.validity
+            .iter()
+            .take(end)
+            .map(move |valid| if valid { values.next() } else { None })
+            .skip(range.start)
+    }
+}
+
+/// Rows without the column have no series.
+type CompressedRows<G> = Vec<Option<CompressedSeries<G>>>;
+
+/// Scalars other than numbers stay as the list array of every series.
+type CompressedData = ScalarType<
+    CompressedRows<i64>,
+    CompressedRows<f64>,
+    ListArray<i32>,
+    ListArray<i32>,
+    ListArray<i32>,
+    ListArray<i32>,
+>;
+
+/// An archived scalar column, every series of it compressed.
+#[derive(Debug)]
+pub(crate) struct CompressedColumn {
+    name: Arc<str>,
+    data: CompressedData,
+}
+
+impl CompressedColumn {
+    /// Compresses a list array of series, one per row.
+    pub(crate) fn from_list(name: Arc<str>, array: &dyn Array) -> Self {
+        let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+        let data = match scalar_type(array.data_type()) {
+            ScalarType::Int(_) => ScalarType::Int(compress_list(list)),
+            ScalarType::Float(_) => ScalarType::Float(compress_list(list)),
+            ScalarType::Histogram(_) => ScalarType::Histogram(list.clone()),
+            ScalarType::Summary(_) => ScalarType::Summary(list.clone()),
+            ScalarType::Bool(_) => ScalarType::Bool(list.clone()),
+            ScalarType::String(_) => ScalarType::String(list.clone()),
+        };
+        Self { name, data }
+    }
+
+    #[inline]
+    pub(crate) fn name(&self) -> &Arc<str> {
+        &self.name
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        match &self.data {
+            ScalarType::Int(data) => rows_heap_size(data, |series| {
+                series.as_ref().map_or(0, CompressedSeries::heap_size)
+            }),
+            ScalarType::Float(data) => rows_heap_size(data, |series| {
+                series.as_ref().map_or(0, CompressedSeries::heap_size)
+            }),
+            ScalarType::Histogram(list)
+            | ScalarType::Summary(list)
+            | ScalarType::Bool(list)
+            | ScalarType::String(list) => list_heap_size(list),
+        }
+    }
+
+    /// Decodes the `range` of each series in `ids` into a list array.
+    pub(crate) fn array(
+        &self,
+        ids: impl Iterator<Item = u32>,
+        range: Range<usize>,
+    ) -> Arc<dyn Array> {
+        match &self.data {
+            ScalarType::Int(data) => decompress_list(data, ids, range),
+            ScalarType::Float(data) => decompress_list(data, ids, range),
+            ScalarType::Histogram(list)
+            | ScalarType::Summary(list)
+            | ScalarType::Bool(list)
+            | ScalarType::String(list) => take_list(list, ids, range),
+        }
+    }
+}
+
+impl From<ScalarColumn> for CompressedColumn {
+    fn from(column: ScalarColumn) -> Self {
+        let data = match column.data {
+            ScalarType::Int(data) => ScalarType::Int(compress_series(data, column.series_len)),
+            ScalarType::Float(data) => ScalarType::Float(compress_series(data, column.series_len)),
+            _ => {
+                let ids = 0..column.len() as u32;
+                let array = column.array(ids, 0..column.series_len as usize);
+                return CompressedColumn::from_list(column.name, array.as_ref());
+            }
+        };
+        Self {
+            name: column.name,
+            data,
+        }
+    }
+}
+
+/// The heap usage of a list array of series kept as is, its offsets and validity included.
+fn list_heap_size(list: &ListArray<i32>) -> usize {
+    let validity = |validity: Option<&arrow2::bitmap::Bitmap>| validity.map_or(0, |v| v.len() / 8);
+    let values = list.values().as_any();
+    let values = if let Some(values) = values.downcast_ref::<BinaryArray<i64>>() {
+        values.offsets().len() * mem::size_of::<i64>()
+            + values.values().len()
+            + validity(values.validity())
+    } else if let Some(values) = values.downcast_ref::<Utf8Array<i32>>() {
+        values.offsets().len() * mem::size_of::<i32>()
+            + values.values().len()
+            + validity(values.validity())
+    } else {
+        let values = values.downcast_ref::<BooleanArray>().unwrap();
+        values.len() / 8 + validity(values.validity())
+    };
+    list.offsets().len() * mem::size_of::<i32>() + validity(list.validity()) + values
+}
+
+fn compress_series<G: Gorilla + Default>(
+    data: Vec<Series<G>>,
+    series_len: u32,
+) -> CompressedRows<G> {
+    data.into_iter()
+        .map(|series| Some(CompressedSeries::new(series.range(0..series_len as usize))))
+        .collect()
+}
+
+fn compress_list<G: Gorilla + NativeType>(list: &ListArray<i32>) -> CompressedRows<G> {
+    list.iter()
+        .map(|series| {
+            series.map(|series| {
+                let series = series.as_any().downcast_ref::<PrimitiveArray<G>>().unwrap();
+                CompressedSeries::new(series.iter().map(|value| value.copied()))
+            })
+        })
+        .collect()
+}
+
+fn decompress_list<G: Gorilla + NativeType>(
+    data: &[Option<CompressedSeries<G>>],
+    ids: impl Iterator<Item = u32>,
+    range: Range<usize>,
+) -> Arc<dyn Array> {
+    let mut array = MutableListArray::<i32, MutablePrimitiveArray<G>>::new();
+    for id in ids {
+        match data.get(id as usize).and_then(Option::as_ref) {
+            None => array.push_null(),
+            Some(series) => array.try_push(Some(series.range(range.clone()))).unwrap(),
+        }
+    }
+    array.into_arc()
 }
 
 #[derive(Debug)]
@@ -128,48 +815,134 @@ impl LabelColumn {
         }
     }
 
-    pub(crate) fn lookup(&self, op: MatcherOp, value: Option<&LabelType<&str>>) -> Option<&Bitmap> {
+    pub(crate) fn lookup(&self, predicate: &Predicate) -> Option<Cow<'_, Bitmap>> {
         match &self.data {
-            LabelType::String(data) => match op {
-                MatcherOp::LiteralEqual => {
-                    let id = match value {
-                        Some(s) => {
-                            let LabelType::String(s) = s;
-                            data.values.lookup(s)?
-                        }
-                        None => 0,
-                    };
-                    self.index.get(&id)
+            LabelType::String(data) => {
+                let ids = match &predicate.pattern {
+                    Pattern::Literal(value) => self.lookup_literal(data, value).map(Cow::Borrowed),
+                    Pattern::Regex(regex) => Some(Cow::Owned(self.lookup_regex(data, regex))),
+                };
+                if predicate.negated {
+                    Some(Cow::Owned(complement(
+                        ids.as_deref(),
+                        data.data.len() as u32,
+                    )))
+                } else {
+                    ids
                 }
-                _ => unimplemented!(),
-            },
+            }
+        }
+    }
+
+    fn lookup_literal(&self, data: &StringArray, value: &str) -> Option<&Bitmap> {
+        let id = if value.is_empty() {
+            0
+        } else {
+            data.values.lookup(value)?
+        };
+        self.index.get(&id)
+    }
+
+    fn lookup_regex(&self, data: &StringArray, regex: &Regex) -> Bitmap {
+        let mut matched = Vec::new();
+        if regex.is_match("") {
+            matched.push(&self.index[&0]);
         }
+        for (id, value) in data.values.iter() {
+            if regex.is_match(value) {
+                if let Some(ids) = self.index.get(&id) {
+                    matched.push(ids);
+                }
+            }
+        }
+        Bitmap::fast_or(&matched)
     }
 
+    /// Appends a row of value `label`, returning how much the heap usage of the column grew.
     #[inline]
-    pub(crate) fn push(&mut self, label: &LabelValue) {
-        match &mut self.data {
+    pub(crate) fn push(&mut self, label: &LabelValue) -> isize {
+        let before = self.buffers_heap_size();
+        let (id, row) = match &mut self.data {
             LabelType::String(data) => match label {
                 LabelValue::String(s) => {
-                    let id = data.values.lookup_or_insert(s);
+                    let id = if s.is_empty() {
+                        0
+                    } else {
+                        data.values.lookup_or_insert(s)
+                    };
                     data.data.push(id);
-                    let bitmap = self.index.entry(id).or_insert_with(Bitmap::create);
-                    bitmap.add(data.data.len() as u32 - 1);
+                    (id, data.data.len() as u32 - 1)
                 }
             },
-        }
+        };
+        let bitmap_before = self.index.get(&id).map_or(0, bitmap_heap_size);
+        let bitmap = self.index.entry(id).or_insert_with(Bitmap::create);
+        bitmap.add(row);
+        let bitmap_after = bitmap_heap_size(bitmap);
+        (self.buffers_heap_size() + bitmap_after) as isize - (before + bitmap_before) as isize
     }
 
+    /// Appends a row without a value, returning how much the heap usage of the column grew.
     #[inline]
-    pub(crate) fn push_zero(&mut self) {
-        match &mut self.data {
+    pub(crate) fn push_zero(&mut self) -> isize {
+        let before = self.buffers_heap_size();
+        let row = match &mut self.data {
             LabelType::String(data) => {
                 data.data.push(0);
-                self.index.entry(0).and_modify(|map| {
-                    map.add(data.data.len() as u32 - 1);
-                });
+                data.data.len() as u32 - 1
             }
+        };
+        let mut growth = 0;
+        if let Some(bitmap) = self.index.get_mut(&0) {
+            growth -= bitmap_heap_size(bitmap) as isize;
+            bitmap.add(row);
+            growth += bitmap_heap_size(bitmap) as isize;
         }
+        growth + self.buffers_heap_size() as isize - before as isize
+    }
+
+    /// Whether `label` is already a value of this column, or there is room for one more value.
+    pub(crate) fn accepts(&self, label: &LabelValue, max_values: u32) -> bool {
+        match (&self.data, label) {
+            (LabelType::String(data), LabelValue::String(s)) => {
+                s.is_empty()
+                    || data.values.len() < max_values as usize
+                    || data.values.lookup(s).is_some()
+            }
+        }
+    }
+
+    /// Tombstones row `id`, which no lookup returns any more.
+    pub(crate) fn remove(&mut self, id: u32) {
+        match &mut self.data {
+            LabelType::String(data) => {
+                if let Some(value) = data.data.get_mut(id as usize) {
+                    if let Some(ids) = self.index.get_mut(value) {
+                        ids.remove(id);
+                    }
+                    *value = 0;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.buffers_heap_size() + index_heap_size(self.index.values())
+    }
+
+    /// The heap usage of the column, the bitmaps of its index left out.
+    fn buffers_heap_size(&self) -> usize {
+        let data = match &self.data {
+            LabelType::String(data) => {
+                data.data.capacity() * mem::size_of::<usize>() + data.values.heap_size()
+            }
+        };
+        data + self.index.capacity() * mem::size_of::<usize>()
+    }
+
+    /// The rows that have not been tombstoned.
+    pub(crate) fn ids(&self) -> Bitmap {
+        Bitmap::fast_or(&self.index.values().collect::<Vec<_>>())
     }
 
     #[inline]
@@ -193,26 +966,316 @@ impl LabelColumn {
     pub(crate) fn name(&self) -> &Arc<str> {
         &self.name
     }
+
+    pub(crate) fn encode(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.name);
+        match &self.data {
+            LabelType::String(data) => {
+                encoder.put_u8(0);
+                let values = data.values.iter().collect::<Vec<_>>();
+                encoder.put_u32(values.len() as u32);
+                for (_, value) in values {
+                    encoder.put_str(value);
+                }
+                encoder.put_u32(data.data.len() as u32);
+                for id in &data.data {
+                    encoder.put_u32(*id as u32);
+                }
+            }
+        }
+        encoder.put_u32(self.index.len() as u32);
+        for (id, ids) in &self.index {
+            encoder.put_u32(*id as u32);
+            encoder.put_bytes(&ids.serialize());
+        }
+    }
+
+    pub(crate) fn decode(decoder: &mut Decoder) -> Option<Self> {
+        let name = Arc::from(decoder.str()?);
+        let data = match decoder.u8()? {
+            0 => {
+                let mut data = StringArray::new();
+                for id in 1..=decoder.u32()? as usize {
+                    if data.values.lookup_or_insert(decoder.str()?) != id {
+                        return None;
+                    }
+                }
+                let len = decoder.u32()?;
+                data.data.reserve(len as usize);
+                for _ in 0..len {
+                    data.data.push(decoder.u32()? as usize);
+                }
+                LabelType::String(data)
+            }
+            _ => return None,
+        };
+        let len = decoder.u32()?;
+        let mut index = HashMap::with_capacity(len as usize);
+        for _ in 0..len {
+            let id = decoder.u32()? as usize;
+            index.insert(id, Bitmap::try_deserialize(decoder.bytes()?)?);
+        }
+        Some(Self { name, data, index })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct DictionaryColumn {
+    name: Arc<str>,
+    data: LabelType<DictionaryArray<u32>>,
+    index: Vec<Bitmap>,
+    nulls: Bitmap,
+}
+
+impl DictionaryColumn {
+    pub(crate) fn lookup(&self, predicate: &Predicate) -> Bitmap {
+        match &self.data {
+            LabelType::String(data) => {
+                let values = Self::values(data);
+                let mut matched = Vec::new();
+                if predicate.pattern.is_match("") {
+                    matched.push(&self.nulls);
+                }
+                for (key, ids) in self.index.iter().enumerate() {
+                    if predicate.pattern.is_match(values.value(key)) {
+                        matched.push(ids);
+                    }
+                }
+                let ids = Bitmap::fast_or(&matched);
+                if predicate.negated {
+                    complement(Some(&ids), data.len() as u32)
+                } else {
+                    ids
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, id: u32) -> Option<LabelType<&str>> {
+        match &self.data {
+            LabelType::String(data) => {
+                let keys = data.keys();
+                if keys.is_null(id as usize) {
+                    None
+                } else {
+                    let key = keys.value(id as usize) as usize;
+                    Some(LabelType::String(Self::values(data).value(key)))
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn data_type(&self) -> LabelType<()> {
+        match self.data {
+            LabelType::String(_) => LabelType::String(()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn name(&self) -> &Arc<str> {
+        &self.name
+    }
+
+    #[inline]
+    pub(crate) fn array(&self) -> Arc<dyn Array> {
+        match &self.data {
+            LabelType::String(data) => Arc::new(data.clone()),
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        let data = match &self.data {
+            LabelType::String(data) => {
+                let values = Self::values(data);
+                data.keys().len() * mem::size_of::<u32>()
+                    + values.offsets().len() * mem::size_of::<i32>()
+                    + values.values().len()
+            }
+        };
+        data + index_heap_size(self.index.iter().chain([&self.nulls]))
+    }
+
+    #[inline]
+    fn values(data: &DictionaryArray<u32>) -> &Utf8Array<i32> {
+        data.values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+    }
+}
+
+impl From<LabelColumn> for DictionaryColumn {
+    fn from(column: LabelColumn) -> Self {
+        let mut index = column.index;
+        let nulls = index.remove(&0).unwrap_or_else(Bitmap::create);
+        match column.data {
+            LabelType::String(data) => {
+                let keys = data
+                    .data
+                    .iter()
+                    .map(|id| id.checked_sub(1).map(|key| key as u32))
+                    .collect::<PrimitiveArray<u32>>();
+                let mut values = MutableUtf8Array::<i32>::new();
+                let mut bitmaps = Vec::new();
+                for (id, value) in data.values.iter() {
+                    values.push(Some(value));
+                    let mut ids = index.remove(&id).unwrap_or_else(Bitmap::create);
+                    ids.run_optimize();
+                    bitmaps.push(ids);
+                }
+                let values: Utf8Array<i32> = values.into();
+                Self {
+                    name: column.name,
+                    data: LabelType::String(DictionaryArray::from_data(keys, Arc::new(values))),
+                    index: bitmaps,
+                    nulls,
+                }
+            }
+        }
+    }
+}
+
+/// A label matcher, compiled once and evaluated against distinct label values. Rows without
+/// the label are matched as if their value was empty, like Prometheus does.
+#[derive(Debug, Clone)]
+pub(crate) struct Predicate<'a> {
+    pattern: Pattern<'a>,
+    negated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern<'a> {
+    Literal(Cow<'a, str>),
+    Regex(Regex),
+}
+
+/// The predicate of a matcher on label `name`, compiled once per scan or delete for every
+/// chunk it filters.
+#[derive(Debug, Clone)]
+pub(crate) struct Filter {
+    pub(crate) name: String,
+    pub(crate) predicate: Predicate<'static>,
+}
+
+impl Filter {
+    pub(crate) fn new(matcher: &Matcher) -> Result<Self, ScanError> {
+        let value = matcher
+            .value
+            .as_ref()
+            .map(|LabelType::String(value)| LabelType::String(value.as_str()));
+        let predicate = Predicate::new(matcher.op, value.as_ref())?;
+        Ok(Self {
+            name: matcher.name.clone(),
+            predicate: predicate.into_owned(),
+        })
+    }
+}
+
+/// Compiles `matchers`, failing on the first invalid regex.
+pub(crate) fn compile_filters(matchers: &[Matcher]) -> Result<Vec<Filter>, ScanError> {
+    matchers.iter().map(Filter::new).collect()
+}
+
+impl<'a> Predicate<'a> {
+    pub(crate) fn new(
+        op: MatcherOp,
+        value: Option<&LabelType<&'a str>>,
+    ) -> Result<Self, ScanError> {
+        let value = match value {
+            Some(LabelType::String(s)) => *s,
+            None => "",
+        };
+        let (pattern, negated) = match op {
+            MatcherOp::LiteralEqual => (Pattern::Literal(Cow::Borrowed(value)), false),
+            MatcherOp::LiteralNotEqual => (Pattern::Literal(Cow::Borrowed(value)), true),
+            MatcherOp::RegexMatch => (Pattern::Regex(compile_regex(value)?), false),
+            MatcherOp::RegexNotMatch => (Pattern::Regex(compile_regex(value)?), true),
+        };
+        Ok(Self { pattern, negated })
+    }
+
+    #[inline]
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        self.pattern.is_match(value) != self.negated
+    }
+
+    fn into_owned(self) -> Predicate<'static> {
+        let pattern = match self.pattern {
+            Pattern::Literal(literal) => Pattern::Literal(Cow::Owned(literal.into_owned())),
+            Pattern::Regex(regex) => Pattern::Regex(regex),
+        };
+        Predicate {
+            pattern,
+            negated: self.negated,
+        }
+    }
+}
+
+impl<'a> Pattern<'a> {
+    #[inline]
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Literal(literal) => literal == value,
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, ScanError> {
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(|err| ScanError::InvalidRegex {
+        pattern: pattern.to_owned(),
+        err: err.to_string(),
+    })
+}
+
+fn complement(ids: Option<&Bitmap>, len: u32) -> Bitmap {
+    let mut all = Bitmap::create();
+    all.add_range(0..len as u64);
+    if let Some(ids) = ids {
+        all.andnot_inplace(ids);
+    }
+    all
 }
 
 #[cfg(test)]
 mod test {
-    use crate::column::{LabelColumn, ScalarColumn};
+    use crate::column::{LabelColumn, Predicate, ScalarColumn};
+    use arrow2::array::{Array, ListArray, PrimitiveArray};
     use common::{LabelType, LabelValue, ScalarType};
     use ql::rosetta::MatcherOp;
 
     #[test]
     fn test_scalar_column() {
-        let mut scalar = ScalarColumn::new(ScalarType::Float(String::from("test")), 1);
+        let mut scalar = ScalarColumn::new(ScalarType::Float(String::from("test")), 4);
         scalar.push_zero();
         let series = scalar.get_mut(0);
         if let Some(ScalarType::Float(series)) = series {
-            assert_eq!(series.data.len(), 1);
-            series.insert(0, 1.);
-            assert_eq!(series.data[0], Some(1.))
+            assert!(series.values.is_empty());
+            series.insert(1, 1.);
+            assert_eq!(series.values.len(), 2);
+            assert_eq!(series.get(0), None);
+            assert_eq!(series.get(1), Some(1.));
+            assert_eq!(series.get(3), None);
         } else {
             unreachable!()
         }
+        scalar.push_zero();
+
+        let array = scalar.array([1, 0, 2].into_iter(), 0..3);
+        let array = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+        assert_eq!(array.len(), 3);
+        assert!(array.is_null(2));
+        let values = array.values();
+        let values = values
+            .as_any()
+            .downcast_ref::<PrimitiveArray<f64>>()
+            .unwrap();
+        assert_eq!(
+            values.iter().map(|v| v.copied()).collect::<Vec<_>>(),
+            vec![None, None, None, None, Some(1.), None]
+        );
     }
 
     #[test]
@@ -231,11 +1294,50 @@ mod test {
         assert!(label.get(0).is_none());
         label.push(&LabelValue::String("test"));
         assert!(label.get(1).is_some());
-        let id = label.lookup(MatcherOp::LiteralEqual, None);
+        let predicate = Predicate::new(MatcherOp::LiteralEqual, None).unwrap();
+        let id = label.lookup(&predicate);
         assert!(id.is_some());
         assert_eq!(id.map(|id| id.iter().next().unwrap()), Some(0));
-        let id = label.lookup(MatcherOp::LiteralEqual, Some(&LabelType::String("test")));
+        let predicate =
+            Predicate::new(MatcherOp::LiteralEqual, Some(&LabelType::String("test"))).unwrap();
+        let id = label.lookup(&predicate);
         assert!(id.is_some());
         assert_eq!(id.map(|id| id.iter().next().unwrap()), Some(1));
     }
+
+    #[test]
+    fn test_label_lookup_ops() {
+        let mut label = LabelColumn::new(LabelType::String(String::from("job")));
+        label.push(&LabelValue::String("api"));
+        label.push_zero();
+        label.push(&LabelValue::String("api-gateway"));
+        label.push(&LabelValue::String("db"));
+        label.push(&LabelValue::String(""));
+
+        let lookup = |op, value| {
+            let predicate = Predicate::new(op, Some(&LabelType::String(value))).unwrap();
+            label
+                .lookup(&predicate)
+                .map(|ids| ids.to_vec())
+                .unwrap_or_default()
+        };
+        assert_eq!(lookup(MatcherOp::LiteralEqual, "api"), vec![0]);
+        assert_eq!(lookup(MatcherOp::LiteralEqual, ""), vec![1, 4]);
+        assert_eq!(lookup(MatcherOp::LiteralEqual, "web"), Vec::<u32>::new());
+        assert_eq!(lookup(MatcherOp::LiteralNotEqual, "api"), vec![1, 2, 3, 4]);
+        assert_eq!(lookup(MatcherOp::LiteralNotEqual, ""), vec![0, 2, 3]);
+        assert_eq!(
+            lookup(MatcherOp::LiteralNotEqual, "web"),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(lookup(MatcherOp::RegexMatch, "api"), vec![0]);
+        assert_eq!(lookup(MatcherOp::RegexMatch, "api.*"), vec![0, 2]);
+        assert_eq!(lookup(MatcherOp::RegexMatch, "api|db"), vec![0, 3]);
+        assert_eq!(lookup(MatcherOp::RegexMatch, "db|"), vec![1, 3, 4]);
+        assert_eq!(lookup(MatcherOp::RegexMatch, ".*"), vec![0, 1, 2, 3, 4]);
+        assert_eq!(lookup(MatcherOp::RegexMatch, ".+"), vec![0, 2, 3]);
+        assert_eq!(lookup(MatcherOp::RegexNotMatch, "api.*"), vec![1, 3, 4]);
+        assert_eq!(lookup(MatcherOp::RegexNotMatch, ".*"), Vec::<u32>::new());
+        assert!(Predicate::new(MatcherOp::RegexMatch, Some(&LabelType::String("("))).is_err());
+    }
 }
@@ -1,24 +1,176 @@
 use crate::chunk::ScanChunk;
-use crate::error::{ScanError, WriteError};
-use crate::table::Table;
+use crate::column::{compile_filters, Filter};
+use crate::error::{DeleteError, ScanError, SnapshotError, WriteError};
+use crate::file::FileChunk;
+use crate::metrics::{TableMemory, WriteMetrics};
+use crate::snapshot::{self, ShardSnapshot};
+use crate::table::{Rewrite, Table, TableEvent, TableScan};
+use crate::util::codec::Encoder;
+use crate::wal::{DeleteRecord, Entry, Record, Wal};
+use crate::Options;
+use async_channel::{Receiver, Sender};
 use common::time::Instant;
-use common::{Label, Scalar};
-use context::Context;
+use common::{Label, LabelType, LabelValue, Scalar};
+use context::{Alignment, Context, Rollup, Schema, TableMeta};
+use futures::channel::oneshot;
+use futures::executor;
 use hashbrown::HashMap;
-use ql::rosetta::{MatcherRef, Range};
+use ql::rosetta::{Matcher, Range};
+use runtime::unblock;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tracing::{error, info, warn};
+
+pub(crate) type DeleteSender = oneshot::Sender<Result<u64, DeleteError>>;
+
+/// A delete waiting for the archived chunks it deletes from to be rewritten.
+struct PendingDelete {
+    table_name: Arc<str>,
+    range: Range,
+    /// The sequence number of the delete in the log, if logged.
+    seq: Option<u64>,
+    /// Taken once the rewrite starts.
+    rewrite: Option<Rewrite>,
+    /// How many samples were deleted from the mutable and late chunks.
+    deleted: u64,
+    /// Nobody waits for the deletes of rollup tables.
+    ret: Option<DeleteSender>,
+}
 
 pub(crate) struct Shard {
+    id: usize,
+    shard_num: usize,
     tables: HashMap<Arc<str>, Table>,
     context: Arc<Context>,
+    data_dir: Option<PathBuf>,
+    wal: Option<Wal>,
+    metrics: Arc<WriteMetrics>,
+    /// The share of this shard of the memory limit.
+    memory_limit: Option<usize>,
+    /// Heap usage, grown by every write and measured again once chunks are archived, dropped
+    /// or rewritten.
+    memory_used: usize,
+    /// Heap usage right after the last relief, while over the limit.
+    relieved_at: Option<usize>,
+    events: (Sender<TableEvent>, Receiver<TableEvent>),
+    /// Deletes rewriting archived chunks one after another, the first one being rewritten.
+    deletes: VecDeque<PendingDelete>,
+    /// Logged deletes whose rewritten chunks are being written, by sequence number, with the
+    /// table and range they deleted from.
+    persisting: Vec<(u64, Arc<str>, Range)>,
 }
 
 impl Shard {
-    pub(crate) fn new(context: Arc<Context>) -> Self {
-        Self {
+    pub(crate) fn new(
+        id: usize,
+        shard_num: usize,
+        context: Arc<Context>,
+        options: &Options,
+        metrics: Arc<WriteMetrics>,
+    ) -> Self {
+        let mut shard = Self {
+            id,
+            shard_num,
             tables: HashMap::new(),
             context,
+            data_dir: options.data_dir.clone(),
+            wal: None,
+            metrics,
+            memory_limit: options.memory_limit.map(|limit| limit / shard_num),
+            memory_used: 0,
+            relieved_at: None,
+            events: async_channel::unbounded(),
+            deletes: VecDeque::new(),
+            persisting: Vec::new(),
+        };
+        if let Err(err) = shard.load() {
+            error!("shard {} load data files error: {}", id, err);
+        }
+        let mut wal_seq = None;
+        if let Some(dir) = &options.restore {
+            match shard.restore(dir) {
+                Ok(seq) => wal_seq = seq,
+                Err(err) => error!("shard {} restore snapshot error: {}", id, err),
+            }
+        }
+        shard.expire(Instant::now());
+        if let Some(wal_options) = &options.wal {
+            let replay_from = wal_seq.unwrap_or(0);
+            match Wal::open(wal_options, id, replay_from, |entry| match entry {
+                Entry::Write(record) => shard.replay(record),
+                Entry::Delete(record) => shard.replay_delete(record),
+            }) {
+                Ok(wal) => shard.wal = Some(wal),
+                Err(err) => error!("shard {} open write ahead log error: {}", id, err),
+            }
+        }
+        shard.measure_memory();
+        shard
+    }
+
+    /// Loads the chunk files this shard persisted, one directory per table.
+    fn load(&mut self) -> io::Result<()> {
+        let data_dir = match &self.data_dir {
+            Some(data_dir) if data_dir.exists() => data_dir,
+            _ => return Ok(()),
+        };
+        for entry in fs::read_dir(data_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let table_name: Arc<str> = match entry.file_name().to_str() {
+                Some(table_name) => Arc::from(table_name),
+                None => continue,
+            };
+            let mut chunks = Vec::new();
+            for file in fs::read_dir(entry.path())? {
+                let path = file?.path();
+                if FileChunk::shard_id(&path) != Some(self.id) {
+                    continue;
+                }
+                match FileChunk::open(path.clone()) {
+                    Ok((chunk, schema)) => {
+                        self.context.restore_schema(&table_name, schema);
+                        chunks.push(chunk);
+                    }
+                    Err(err) => error!("open data file {:?} error: {}", path, err),
+                }
+            }
+            if chunks.is_empty() {
+                continue;
+            }
+            chunks.sort_by_key(|chunk| (chunk.info.start_at.as_millis(), chunk.seq()));
+            // a window rewritten right before restart may have left the file it replaced
+            let mut restored: Vec<FileChunk> = Vec::with_capacity(chunks.len());
+            for chunk in chunks {
+                match restored.last_mut() {
+                    Some(last) if last.info.same_window(&chunk.info) => {
+                        mem::replace(last, chunk).remove();
+                    }
+                    _ => restored.push(chunk),
+                }
+            }
+            let schema = self.context.get_schema(&table_name).unwrap();
+            let dir = entry.path();
+            self.tables
+                .entry(Arc::clone(&table_name))
+                .or_insert_with(|| {
+                    Table::new(
+                        table_name,
+                        schema,
+                        Some(dir),
+                        self.id,
+                        self.events.0.clone(),
+                    )
+                })
+                .restore(restored);
         }
+        Ok(())
     }
 
     pub(crate) fn write(
@@ -27,31 +179,740 @@ impl Shard {
         labels: &[Label],
         scalars: &[(Instant, Vec<Scalar>)],
     ) -> Result<(), WriteError> {
-        let schema = self
+        if let Some(limit) = self.memory_limit {
+            self.check_memory(limit)?;
+        }
+        let wal = match &mut self.wal {
+            None => return self.apply(table_name, labels, scalars),
+            Some(wal) => wal,
+        };
+        wal.append(table_name, labels, scalars)
+            .map_err(|err| WriteError::Wal {
+                err: err.to_string(),
+            })?;
+        let result = self.apply(table_name, labels, scalars);
+        self.truncate_wal();
+        result
+    }
+
+    /// Removes the segments of the write ahead log whose samples and deletes have all been
+    /// persisted. Only the log keeps samples when there is no data directory, until they
+    /// expire. Rollups are not logged, so the samples of a table are kept until its rollup
+    /// tables have persisted them too.
+    fn truncate_wal(&mut self) {
+        let (wal, tables, context) = match &mut self.wal {
+            None => return,
+            Some(wal) => (wal, &self.tables, &self.context),
+        };
+        let has_files = self.data_dir.is_some();
+        self.persisting.retain(|(_, table_name, range)| {
+            matches!(tables.get(table_name), Some(table) if table.is_replacing(*range))
+        });
+        let pending_delete = self
+            .deletes
+            .iter()
+            .filter_map(|delete| delete.seq)
+            .chain(self.persisting.iter().map(|(seq, ..)| *seq))
+            .min();
+        let persisted = |table_name: &str, from, to| match tables.get(table_name) {
+            None => true,
+            Some(table) if has_files => table.is_persisted(from, to),
+            Some(table) => table.is_expired(to),
+        };
+        let truncated = wal.truncate(
+            |table_name, from, to| {
+                persisted(table_name, from, to)
+                    && match context.get_schema(table_name) {
+                        None => true,
+                        Some(schema) => rollups(table_name, &schema.meta)
+                            .all(|(name, ..)| persisted(&name, from, to)),
+                    }
+            },
+            pending_delete,
+        );
+        if let Err(err) = truncated {
+            error!("shard {} truncate write ahead log error: {}", self.id, err);
+        }
+    }
+
+    /// Flushes the write ahead log to disk, as timed by [`SyncPolicy::Interval`].
+    ///
+    /// [`SyncPolicy::Interval`]: crate::SyncPolicy::Interval
+    pub(crate) fn sync_wal(&mut self) {
+        if let Some(wal) = &mut self.wal {
+            if let Err(err) = wal.sync() {
+                error!("shard {} sync write ahead log error: {}", self.id, err);
+            }
+        }
+    }
+
+    /// Heap usage of every table of this shard.
+    pub(crate) fn memory(&self) -> Vec<(Arc<str>, TableMemory)> {
+        self.tables
+            .iter()
+            .map(|(table_name, table)| (Arc::clone(table_name), table.memory()))
+            .collect()
+    }
+
+    /// Checks the heap usage of this shard against `limit`. Over it, tables archive their
+    /// mutable chunks early and drop the memory copies of their persisted chunks, and do so
+    /// again each time usage grows by another sixteenth of the limit. Writes are rejected while
+    /// chunk files are still being written, whose memory copies are dropped once they are;
+    /// with nothing left to relieve, they are accepted over the limit.
+    fn check_memory(&mut self, limit: usize) -> Result<(), WriteError> {
+        if self.memory_used <= limit {
+            self.relieved_at = None;
+            return Ok(());
+        }
+        let relieve = match self.relieved_at {
+            None => true,
+            Some(relieved_at) => self.memory_used >= relieved_at + limit / 16,
+        };
+        let pending = if relieve {
+            let used = self.memory_used;
+            let relieved = self.relieve();
+            self.relieved_at = Some(self.memory_used);
+            warn!(
+                "shard {} memory usage {} is over its limit {}, archived {} chunks early, {} left",
+                self.id, used, limit, relieved, self.memory_used
+            );
+            if self.memory_used <= limit {
+                return Ok(());
+            }
+            let pending = self.tables.values().any(Table::is_writing);
+            if !pending {
+                warn!(
+                    "shard {} has no memory left to relieve, accepting writes over its limit",
+                    self.id
+                );
+            }
+            pending
+        } else {
+            self.tables.values().any(Table::is_writing)
+        };
+        if pending {
+            self.metrics.add_rejected_memory();
+            return Err(WriteError::MemoryLimitExceeded {
+                used: self.memory_used,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Relieves every table, returning how many chunks were archived early.
+    fn relieve(&mut self) -> usize {
+        let relieved = self.tables.values_mut().map(Table::relieve).sum();
+        self.metrics.add_relieved_chunks(relieved);
+        self.roll_up_tables();
+        self.measure_memory();
+        relieved
+    }
+
+    /// Measures the heap usage of every table, after work that may have changed any of them.
+    fn measure_memory(&mut self) {
+        let used = self
+            .tables
+            .values()
+            .map(|table| table.memory().total())
+            .sum();
+        self.set_memory_used(used);
+    }
+
+    fn set_memory_used(&mut self, used: usize) {
+        self.metrics.update_memory_used(self.memory_used, used);
+        self.memory_used = used;
+    }
+
+    /// Drops the chunks of every table that are out of its retention at `now`.
+    pub(crate) fn expire(&mut self, now: Instant) {
+        for (table_name, table) in self.tables.iter_mut() {
+            let retention = self.context.retention(table_name);
+            let expired = table.expire(retention.map(|retention| now - retention));
+            if expired > 0 {
+                info!(
+                    "shard {} dropped {} expired chunks of table {:?}",
+                    self.id, expired, table_name
+                );
+            }
+        }
+        self.roll_up_tables();
+        self.measure_memory();
+        self.truncate_wal();
+    }
+
+    /// Compacts the late samples of every table that can no longer be written to. Skipped while
+    /// deletes rewrite archived chunks, which compacting would replace.
+    pub(crate) async fn compact(&mut self) {
+        if !self.deletes.is_empty() {
+            return;
+        }
+        for (table_name, table) in self.tables.iter_mut() {
+            if let Some(schema) = self.context.get_schema(table_name) {
+                table.update_schema(schema);
+            }
+            if let Err(err) = table.compact().await {
+                error!(
+                    "shard {} compact late samples of table {:?} error: {}",
+                    self.id, table_name, err
+                );
+            }
+        }
+        self.roll_up_tables();
+        self.measure_memory();
+    }
+
+    /// Takes a snapshot of this shard into `dir`. The write-ahead log, if any, rolls to a new
+    /// segment, from which a shard restored from the snapshot replays.
+    pub(crate) fn snapshot(&mut self, dir: &Path) -> Result<(), SnapshotError> {
+        let mut encoder = Encoder::new();
+        encoder.put_u32(self.id as u32);
+        encoder.put_u32(self.shard_num as u32);
+        match &mut self.wal {
+            None => encoder.put_u8(0),
+            Some(wal) => {
+                encoder.put_u8(1);
+                encoder.put_u64(wal.roll().map_err(snapshot::io_error)?);
+            }
+        }
+        encoder.put_u32(self.tables.len() as u32);
+        for table in self.tables.values() {
+            table
+                .snapshot(dir, &mut encoder)
+                .map_err(snapshot::io_error)?;
+        }
+        snapshot::write(&snapshot::path(dir, self.id), &encoder.into_inner())
+    }
+
+    /// Restores the snapshot of this shard in `dir`, returning the write-ahead log segment to
+    /// replay from. Archived chunk files are copied into the data directory if there is one,
+    /// they are read from the snapshot otherwise.
+    fn restore(&mut self, dir: &Path) -> Result<Option<u64>, SnapshotError> {
+        let path = snapshot::path(dir, self.id);
+        let ShardSnapshot {
+            id,
+            shard_num,
+            wal_seq,
+            tables,
+        } = snapshot::read(&path)?;
+        if id != self.id || shard_num != self.shard_num {
+            return Err(SnapshotError::Invalid {
+                path,
+                reason: format!("taken by shard {} of {}", id, shard_num),
+            });
+        }
+
+        for table_snapshot in tables {
+            let name: Arc<str> = Arc::from(table_snapshot.name);
+            let schema = self.context.restore_schema(&name, table_snapshot.schema);
+            let mut files = Vec::with_capacity(table_snapshot.files.len());
+            for file_name in table_snapshot.files {
+                let source = dir.join(name.as_ref()).join(&file_name);
+                let path = match self.table_dir(&name) {
+                    None => source,
+                    Some(table_dir) => {
+                        let target = table_dir.join(&file_name);
+                        // loaded with the data directory already
+                        if target.exists() {
+                            continue;
+                        }
+                        fs::create_dir_all(&table_dir).map_err(snapshot::io_error)?;
+                        fs::copy(&source, &target).map_err(snapshot::io_error)?;
+                        target
+                    }
+                };
+                let (chunk, _) = FileChunk::open(path).map_err(snapshot::io_error)?;
+                files.push(chunk);
+            }
+            let dir = self.table_dir(&name);
+            let table_name = Arc::clone(&name);
+            let events = self.events.0.clone();
+            let table = self
+                .tables
+                .entry(name)
+                .or_insert_with(|| Table::new(table_name, schema, dir, id, events));
+            table.restore(files);
+            table.restore_mutable(table_snapshot.mutable_chunks, table_snapshot.late_chunks);
+        }
+        Ok(wal_seq)
+    }
+
+    /// Events of the work tables of this shard do off it, to be handled by [`Shard::handle`].
+    pub(crate) fn events(&self) -> Receiver<TableEvent> {
+        self.events.1.clone()
+    }
+
+    pub(crate) fn handle(&mut self, event: TableEvent) {
+        match event {
+            TableEvent::Persisted {
+                table_name,
+                id,
+                file,
+            } => {
+                if let Some(table) = self.tables.get_mut(&table_name) {
+                    table.persisted(id, file);
+                    // over the limit, the memory copies of written files are dropped right away
+                    if self.relieved_at.is_some() {
+                        self.metrics.add_relieved_chunks(table.relieve());
+                    }
+                }
+                self.truncate_wal();
+            }
+            TableEvent::Rewritten {
+                table_name,
+                rewritten,
+            } => {
+                let delete = self.deletes.pop_front().unwrap();
+                let result = match (self.tables.get_mut(&table_name), rewritten) {
+                    (Some(table), Ok(rewritten)) => {
+                        if let Some(seq) = delete.seq {
+                            self.persisting.push((seq, table_name, delete.range));
+                        }
+                        Ok(delete.deleted + table.rewritten(rewritten))
+                    }
+                    (None, Ok(_)) => Ok(delete.deleted),
+                    (_, Err(err)) => Err(DeleteError::Scan { err }),
+                };
+                match delete.ret {
+                    Some(ret) => {
+                        let _ = ret.send(result);
+                    }
+                    None => {
+                        if let Err(err) = result {
+                            error!(
+                                "shard {} delete from rollup {:?} error: {}",
+                                self.id, delete.table_name, err
+                            );
+                        }
+                    }
+                }
+                self.rewrite();
+            }
+        }
+        self.roll_up_tables();
+        self.measure_memory();
+    }
+
+    /// Waits for the files tables of this shard are writing, and the deletes rewriting them.
+    pub(crate) async fn flush(&mut self) {
+        while !self.deletes.is_empty() || self.tables.values().any(Table::is_writing) {
+            match self.events.1.recv().await {
+                Ok(event) => self.handle(event),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn table_dir(&self, table_name: &str) -> Option<PathBuf> {
+        self.data_dir
+            .as_ref()
+            .map(|data_dir| data_dir.join(table_name))
+    }
+
+    /// Applies a logged write, dropping the samples that were archived before restart. Late
+    /// samples still within the out-of-order window are applied again, as they may not have
+    /// been compacted. Archived samples are rolled up again into the rollup tables that have not
+    /// archived them, as rollups are not logged.
+    fn replay(&mut self, record: Record) {
+        let Record {
+            table_name,
+            labels,
+            scalars,
+        } = record;
+        let labels = labels
+            .iter()
+            .map(|(name, value)| Label {
+                name,
+                value: match value {
+                    LabelType::String(value) => LabelValue::String(value),
+                },
+            })
+            .collect::<Vec<_>>();
+        let (scalars, archived) = match self.tables.get(table_name.as_str()) {
+            None => (scalars, Vec::new()),
+            Some(table) => scalars.into_iter().partition(|(timestamp, _)| {
+                !table.is_archived(*timestamp) || table.accepts_late(*timestamp)
+            }),
+        };
+        if !archived.is_empty() {
+            self.replay_rollups(&table_name, &labels, archived);
+        }
+        if scalars.is_empty() {
+            return;
+        }
+        if let Err(err) = self.apply(&table_name, &labels, &scalars) {
+            warn!("shard {} replay write ahead log error: {}", self.id, err);
+        }
+    }
+
+    /// Writes samples table `table_name` archived before restart into its rollup tables, the
+    /// ones they archived already left out.
+    fn replay_rollups(
+        &mut self,
+        table_name: &str,
+        labels: &[Label],
+        mut scalars: Vec<(Instant, Vec<Scalar>)>,
+    ) {
+        let schema = match self.context.get_schema(table_name) {
+            Some(schema) if !schema.meta.rollups.is_empty() => schema,
+            _ => return,
+        };
+        scalars.sort_by_key(|(timestamp, _)| timestamp.as_millis());
+        for name in self.rollup_tables(table_name, &schema.meta) {
+            // rollups take no late samples, so what they archived is before what they did not
+            let archived = match self.tables.get(name.as_str()) {
+                None => 0,
+                Some(table) => {
+                    scalars.partition_point(|(timestamp, _)| table.is_archived(*timestamp))
+                }
+            };
+            if archived == scalars.len() {
+                continue;
+            }
+            if let Err(err) = self.apply(&name, labels, &scalars[archived..]) {
+                warn!(
+                    "shard {} replay roll up into {:?} error: {}",
+                    self.id, name, err
+                );
+            }
+        }
+    }
+
+    /// Applies a logged delete to the table and its rollup tables, rewriting the archived
+    /// chunks in place as its rewrite may not have been persisted before restart.
+    fn replay_delete(&mut self, record: DeleteRecord) {
+        let mut table_names = vec![record.table_name.clone()];
+        if let Some(schema) = self.context.get_schema(&record.table_name) {
+            table_names.extend(rollups(&record.table_name, &schema.meta).map(|(name, ..)| name));
+        }
+        for table_name in table_names {
+            let (table_name, table) = match self.tables.get_key_value_mut(table_name.as_str()) {
+                None => continue,
+                Some((table_name, table)) => (Arc::clone(table_name), table),
+            };
+            let result = compile_filters(&record.matchers).and_then(|filters| {
+                let (_, rewrite) = table.delete(&filters, record.range);
+                executor::block_on(rewrite.run())
+            });
+            match result {
+                Ok(rewritten) => {
+                    table.rewritten(rewritten);
+                    self.persisting.push((record.seq, table_name, record.range));
+                }
+                Err(err) => warn!("shard {} replay write ahead log error: {}", self.id, err),
+            }
+        }
+    }
+
+    fn apply(
+        &mut self,
+        table_name: &str,
+        labels: &[Label],
+        scalars: &[(Instant, Vec<Scalar>)],
+    ) -> Result<(), WriteError> {
+        let mut schema = self
             .context
             .get_schema_or_else(table_name, || Context::create_schema(labels, scalars));
-        let table_name = Arc::from(table_name);
-        let table = self
-            .tables
-            .entry(Arc::clone(&table_name))
-            .or_insert_with(|| Table::new(table_name, schema));
-        table.write(labels, scalars)
+        if !schema.contains(labels, scalars) {
+            schema = self.context.evolve_schema(table_name, labels, scalars);
+        }
+        let name: Arc<str> = Arc::from(table_name);
+        let table = self.tables.entry(Arc::clone(&name)).or_insert_with(|| {
+            let dir = self
+                .data_dir
+                .as_ref()
+                .map(|data_dir| data_dir.join(table_name));
+            Table::new(
+                name,
+                Arc::clone(&schema),
+                dir,
+                self.id,
+                self.events.0.clone(),
+            )
+        });
+        let max_series = schema
+            .meta
+            .max_series
+            .map(|max_series| max_series.div_ceil(self.shard_num as u32));
+        let before = table.memory().total();
+        let result = table.write(
+            Arc::clone(&schema),
+            labels,
+            scalars,
+            max_series,
+            &self.metrics,
+        );
+        let after = table.memory().total();
+        let chunks = table.take_rollup_chunks();
+        self.set_memory_used(self.memory_used.saturating_sub(before) + after);
+        if !chunks.is_empty() {
+            self.roll_up(table_name, &schema.meta, chunks);
+        }
+        result
+    }
+
+    /// Writes the samples of chunks table `table_name` archived, compacted or expired into its
+    /// rollup tables, where the alignment of each aggregates them. Rollups are not logged
+    /// ahead, replaying the log of the table rolls it up again. Rejected samples are dropped.
+    fn roll_up(&mut self, table_name: &str, meta: &TableMeta, chunks: Vec<ScanChunk>) {
+        let tables = self.rollup_tables(table_name, meta);
+        for chunk in &chunks {
+            for (labels, scalars) in chunk.series() {
+                for name in &tables {
+                    if let Err(err) = self.apply(name, &labels, &scalars) {
+                        warn!("shard {} roll up into {:?} error: {}", self.id, name, err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rolls up what every table archived, compacted or expired since its last write.
+    fn roll_up_tables(&mut self) {
+        let mut archived = Vec::new();
+        for (table_name, table) in self.tables.iter_mut() {
+            let chunks = table.take_rollup_chunks();
+            if !chunks.is_empty() {
+                archived.push((Arc::clone(table_name), chunks));
+            }
+        }
+        for (table_name, chunks) in archived {
+            if let Some(schema) = self.context.get_schema(&table_name) {
+                self.roll_up(&table_name, &schema.meta, chunks);
+            }
+        }
+    }
+
+    /// The names of the rollup tables of table `table_name`, declared if they are not yet.
+    fn rollup_tables(&self, table_name: &str, meta: &TableMeta) -> Vec<String> {
+        rollups(table_name, meta)
+            .map(|(name, rollup, aggregate)| {
+                self.context
+                    .get_schema_or_else(&name, || Schema::new(rollup.meta(meta, aggregate)));
+                name
+            })
+            .collect()
+    }
+
+    /// Deletes the samples within `range` of the series matching `matchers`, logging the delete
+    /// ahead of applying it. Sends how many samples were deleted to `ret` once the archived
+    /// chunks it deleted from have been rewritten off the shard.
+    pub(crate) fn delete(
+        &mut self,
+        table_name: &str,
+        matchers: &[Matcher],
+        filters: &[Filter],
+        range: Range,
+        ret: DeleteSender,
+    ) {
+        let result = self.log_delete(table_name, matchers, filters, range);
+        let logged = match &result {
+            Ok(delete) => Some(delete.as_ref().and_then(|delete| delete.seq)),
+            Err(_) => None,
+        };
+        match result {
+            Ok(Some(mut delete)) if matches!(&delete.rewrite, Some(rewrite) if !rewrite.is_empty()) =>
+            {
+                delete.ret = Some(ret);
+                self.deletes.push_back(delete);
+            }
+            result => {
+                let _ = ret.send(result.map(|delete| delete.map_or(0, |delete| delete.deleted)));
+            }
+        }
+        if let Some(seq) = logged {
+            self.delete_rollups(table_name, filters, range, seq);
+        }
+        self.measure_memory();
+        // unless one is being rewritten already
+        if matches!(self.deletes.front(), Some(delete) if delete.rewrite.is_some()) {
+            self.rewrite();
+        }
+    }
+
+    /// Applies a delete of table `table_name` to its rollup tables, whose slots holding any of
+    /// `range` are deleted as a whole. Their archived chunks are rewritten after the ones of the
+    /// table, under the sequence number `seq` of the delete in the log.
+    fn delete_rollups(
+        &mut self,
+        table_name: &str,
+        filters: &[Filter],
+        range: Range,
+        seq: Option<u64>,
+    ) {
+        let schema = match self.context.get_schema(table_name) {
+            Some(schema) => schema,
+            None => return,
+        };
+        for (name, ..) in rollups(table_name, &schema.meta) {
+            let (name, table) = match self.tables.get_key_value_mut(name.as_str()) {
+                None => continue,
+                Some((name, table)) => (Arc::clone(name), table),
+            };
+            let (_, rewrite) = table.delete(filters, range);
+            if !rewrite.is_empty() {
+                self.deletes.push_back(PendingDelete {
+                    table_name: name,
+                    range,
+                    seq,
+                    rewrite: Some(rewrite),
+                    deleted: 0,
+                    ret: None,
+                });
+            }
+        }
+    }
+
+    /// Logs a delete and applies it to the mutable and late chunks, returning it with the
+    /// rewrite of the archived chunks left, if the table has any series on this shard.
+    fn log_delete(
+        &mut self,
+        table_name: &str,
+        matchers: &[Matcher],
+        filters: &[Filter],
+        range: Range,
+    ) -> Result<Option<PendingDelete>, DeleteError> {
+        let (table_name, table) = match self.tables.get_key_value_mut(table_name) {
+            // no series of the table hashed to this shard
+            None => return Ok(None),
+            Some((table_name, table)) => (Arc::clone(table_name), table),
+        };
+        let seq = match &mut self.wal {
+            None => None,
+            Some(wal) => Some(
+                wal.append_delete(&table_name, matchers, range)
+                    .map_err(|err| DeleteError::WalAppend {
+                        err: err.to_string(),
+                    })?,
+            ),
+        };
+        let (deleted, rewrite) = table.delete(filters, range);
+        Ok(Some(PendingDelete {
+            table_name,
+            range,
+            seq,
+            rewrite: Some(rewrite),
+            deleted,
+            ret: None,
+        }))
+    }
+
+    /// Starts rewriting the archived chunks of the first pending delete on the blocking pool.
+    fn rewrite(&mut self) {
+        let delete = match self.deletes.front_mut() {
+            None => return,
+            Some(delete) => delete,
+        };
+        let (table_name, rewrite) = (Arc::clone(&delete.table_name), delete.rewrite.take());
+        let rewrite = rewrite.unwrap();
+        let events = self.events.0.clone();
+        runtime::spawn(async move {
+            let rewritten = unblock(move || executor::block_on(rewrite.run())).await;
+            let _ = events
+                .send(TableEvent::Rewritten {
+                    table_name,
+                    rewritten,
+                })
+                .await;
+        })
+        .detach();
     }
 
     pub(crate) async fn scan(
         &self,
         table_name: &str,
         projections: Option<&[String]>,
-        filters: &[MatcherRef<'_>],
+        filters: &[Filter],
         range: Range,
         limit: Option<usize>,
-    ) -> Result<Vec<ScanChunk>, ScanError> {
-        self.tables
-            .get(table_name)
-            .ok_or_else(|| ScanError::NoSuchTable {
-                name: table_name.to_owned(),
-            })?
-            .scan(projections, filters, range, limit)
-            .await
+    ) -> Result<Option<TableScan>, ScanError> {
+        match self.tables.get(table_name) {
+            // no series of the table hashed to this shard
+            None => Ok(None),
+            Some(table) => table
+                .scan(projections, filters, range, limit)
+                .await
+                .map(Some),
+        }
     }
+
+    /// Scans rollup table `rollup` of table `table_name` up to where the table archived and
+    /// rolled up its samples, and the table itself from there on, each with the range it is
+    /// scanned within. The slot of the rollup where the table starts holds only what was
+    /// rolled up of it. Samples of the table are sent as the rollup would hold them.
+    pub(crate) async fn scan_rollup(
+        &self,
+        table_name: &str,
+        rollup: &str,
+        projections: Option<&[String]>,
+        filters: &[Filter],
+        range: Range,
+        limit: Option<usize>,
+    ) -> Result<Vec<(TableScan, Range)>, ScanError> {
+        let (interval, alignment) = match self.context.get_schema(rollup) {
+            None => return Ok(Vec::new()),
+            Some(schema) => (schema.meta.time_interval.as_millis(), schema.meta.alignment),
+        };
+        let mutable_from = self.tables.get(table_name).and_then(Table::mutable_from);
+        // the end of the slot of the rollup the table starts in
+        let rolled_up = mutable_from.map(|mutable_from| {
+            Instant::from_millis((mutable_from.as_millis() + interval - 1) / interval * interval)
+        });
+        let mut scans = Vec::with_capacity(2);
+        if !matches!((range.start, rolled_up), (Some(start), Some(end)) if start >= end) {
+            let end = match (range.end, rolled_up) {
+                (Some(end), Some(rolled_up)) if rolled_up < end => Some(rolled_up),
+                (Some(end), _) => Some(end),
+                (None, rolled_up) => rolled_up,
+            };
+            let range = Range {
+                start: range.start,
+                end,
+            };
+            if let Some(scan) = self
+                .scan(rollup, projections, filters, range, limit)
+                .await?
+            {
+                scans.push((scan, range));
+            }
+        }
+        if let Some(mutable_from) = mutable_from {
+            if !matches!(range.end, Some(end) if end < mutable_from) {
+                let range = Range {
+                    start: match range.start {
+                        Some(start) if start > mutable_from => Some(start),
+                        _ => Some(mutable_from),
+                    },
+                    end: range.end,
+                };
+                if let Some(scan) = self
+                    .scan(table_name, projections, filters, range, limit)
+                    .await?
+                {
+                    let scan = match alignment {
+                        Alignment::Count => scan.counted(),
+                        _ => scan,
+                    };
+                    scans.push((scan, range));
+                }
+            }
+        }
+        Ok(scans)
+    }
+}
+
+/// The rollup tables of table `table_name` by name, with the rollup and aggregate of each.
+fn rollups<'a>(
+    table_name: &'a str,
+    meta: &'a TableMeta,
+) -> impl Iterator<Item = (String, &'a Rollup, Alignment)> + 'a {
+    meta.rollups.iter().flat_map(move |rollup| {
+        rollup.aggregates.iter().map(move |aggregate| {
+            (
+                rollup.table_name(table_name, *aggregate),
+                rollup,
+                *aggregate,
+            )
+        })
+    })
 }
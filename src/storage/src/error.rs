@@ -1,21 +1,62 @@
 use common::time::Instant;
 use snafu::Snafu;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[derive(Snafu, Debug)]
 pub enum WriteError {
     #[snafu(display("timestamp: {} of table: {:?} has been archived", t, table_name))]
     TimestampArchived { t: Instant, table_name: Arc<str> },
+    #[snafu(display("timestamp: {} of table: {:?} is out of retention", t, table_name))]
+    TimestampExpired { t: Instant, table_name: Arc<str> },
+    #[snafu(display("timestamp: {} of table: {:?} is too far in the future", t, table_name))]
+    TimestampTooNew { t: Instant, table_name: Arc<str> },
+    #[snafu(display("table: {:?} has reached its limit of {} series", table_name, limit))]
+    SeriesLimitExceeded { table_name: Arc<str>, limit: u32 },
+    #[snafu(display(
+        "label: {:?} of table: {:?} has reached its limit of {} values",
+        label,
+        table_name,
+        limit
+    ))]
+    LabelValueLimitExceeded {
+        label: Arc<str>,
+        table_name: Arc<str>,
+        limit: u32,
+    },
+    #[snafu(display("shard memory usage: {} is over its limit: {}", used, limit))]
+    MemoryLimitExceeded { used: usize, limit: usize },
     #[snafu(display("internal error: {:?}", err))]
     InternalError { err: String },
+    #[snafu(display("write ahead log error: {}", err))]
+    Wal { err: String },
 }
 
 #[derive(Snafu, Debug)]
 pub enum ScanError {
     #[snafu(display("table {:?} does not exist", name))]
     NoSuchTable { name: String },
-    #[snafu(display("label {:?} does not exist", name))]
-    NoSuchLabel { name: String },
     #[snafu(display("scalar {:?} does not exist", name))]
     NoSuchScalar { name: String },
+    #[snafu(display("invalid regex {:?}: {}", pattern, err))]
+    InvalidRegex { pattern: String, err: String },
+    #[snafu(display("read file {:?} error: {}", path, err))]
+    ReadFile { path: PathBuf, err: String },
+}
+
+#[derive(Snafu, Debug)]
+pub enum DeleteError {
+    /// Also returned for a table that does not exist or an invalid matcher.
+    #[snafu(display("{}", err))]
+    Scan { err: ScanError },
+    #[snafu(display("write ahead log error: {}", err))]
+    WalAppend { err: String },
+}
+
+#[derive(Snafu, Debug)]
+pub enum SnapshotError {
+    #[snafu(display("snapshot io error: {}", err))]
+    Io { err: String },
+    #[snafu(display("snapshot {:?} is invalid: {}", path, reason))]
+    Invalid { path: PathBuf, reason: String },
 }
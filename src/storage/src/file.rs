@@ -0,0 +1,749 @@
+use crate::chunk::{limit_series, Info, ScanChunk};
+use crate::column::{scalar_type, take_list, Filter};
+use crate::error::ScanError;
+use crate::immutable::{push_series, ImmutableChunk};
+use arrow2::array::{
+    new_empty_array, Array, DictionaryArray, ListArray, MutableArray, MutableListArray,
+    MutablePrimitiveArray, MutableUtf8Array, PrimitiveArray, Utf8Array,
+};
+use arrow2::chunk::Chunk;
+use arrow2::compute::concatenate::concatenate;
+use arrow2::datatypes::{DataType, Field, Schema as ArrowSchema};
+use arrow2::error::{ArrowError, Result as ArrowResult};
+use arrow2::io::parquet::read::statistics::{deserialize_statistics, PrimitiveStatistics};
+use arrow2::io::parquet::read::{self, RowGroupDeserializer, RowGroupMetaData};
+use arrow2::io::parquet::write::{
+    Compression, Encoding, FileWriter, KeyValue, RowGroupIterator, Version, WriteOptions,
+};
+use common::time::{Duration, Instant};
+use common::ScalarType;
+use context::Schema;
+use croaring::Bitmap;
+use ql::rosetta::Range;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::error;
+
+const ROW_GROUP_SIZE: usize = 4096;
+const FIRST_AT: &str = "first_at";
+const LAST_AT: &str = "last_at";
+const SCHEMA_KEY: &str = "t0.schema";
+const START_AT_KEY: &str = "t0.start_at";
+const TIME_INTERVAL_KEY: &str = "t0.time_interval";
+const SERIES_LEN_KEY: &str = "t0.series_len";
+
+/// The series of scalars other than numbers are taken from each row group as they are, then
+/// concatenated.
+type ScalarBuilder = ScalarType<
+    MutableListArray<i32, MutablePrimitiveArray<i64>>,
+    MutableListArray<i32, MutablePrimitiveArray<f64>>,
+    Vec<Arc<dyn Array>>,
+    Vec<Arc<dyn Array>>,
+    Vec<Arc<dyn Array>>,
+    Vec<Arc<dyn Array>>,
+>;
+
+/// A row group with its label arrays and the ids of the series matching the filters.
+type FilteredRowGroup<'a> = (&'a RowGroupMetaData, Vec<Arc<dyn Array>>, Bitmap);
+
+/// An archived chunk persisted as a Parquet file, one row per series.
+///
+/// Besides the series labels and scalars, every row carries the timestamps of its first and
+/// last samples, whose row group statistics allow skipping row groups out of the scanned time
+/// range.
+#[derive(Debug)]
+pub(crate) struct FileChunk {
+    pub(crate) info: Info,
+    path: PathBuf,
+    schema: ArrowSchema,
+    row_groups: Vec<RowGroupMetaData>,
+    /// Set once the file is replaced or expired. It is removed when the last reference to it
+    /// is dropped, as scans and rewrites off the shard may still read it.
+    removed: AtomicBool,
+}
+
+impl FileChunk {
+    /// Writes `chunk` of a table with `schema` to a new file in `dir`.
+    pub(crate) fn create(
+        dir: &Path,
+        shard_id: usize,
+        chunk: &ImmutableChunk,
+        schema: &Schema,
+    ) -> ArrowResult<Self> {
+        fs::create_dir_all(dir)?;
+        let (path, file) = Self::create_file(dir, shard_id, chunk.info.start_at)?;
+
+        let record_num = chunk.record_num() as usize;
+        let scalar_arrays = chunk.scalar_arrays().collect::<Vec<_>>();
+        let (first_at, last_at) = sample_bounds(&chunk.info, &scalar_arrays, record_num);
+        let mut fields = vec![
+            Field::new(FIRST_AT, DataType::Int64, true),
+            Field::new(LAST_AT, DataType::Int64, true),
+        ];
+        let mut arrays: Vec<Arc<dyn Array>> = vec![Arc::new(first_at), Arc::new(last_at)];
+        let mut encodings = vec![Encoding::Plain, Encoding::Plain];
+        for (name, array) in chunk.label_arrays() {
+            fields.push(Field::new(name.as_ref(), array.data_type().clone(), true));
+            arrays.push(array);
+            encodings.push(Encoding::RleDictionary);
+        }
+        for (name, array) in scalar_arrays {
+            fields.push(Field::new(name.as_ref(), array.data_type().clone(), true));
+            arrays.push(array);
+            encodings.push(Encoding::Plain);
+        }
+        let arrow_schema = ArrowSchema::from(fields);
+
+        let options = WriteOptions {
+            write_statistics: true,
+            compression: Compression::Snappy,
+            version: Version::V2,
+        };
+        let row_groups = (0..record_num).step_by(ROW_GROUP_SIZE).map(|offset| {
+            let len = ROW_GROUP_SIZE.min(record_num - offset);
+            Ok(Chunk::new(
+                arrays
+                    .iter()
+                    .map(|array| Arc::from(array.slice(offset, len)))
+                    .collect::<Vec<Arc<dyn Array>>>(),
+            ))
+        });
+        let row_groups = RowGroupIterator::try_new(row_groups, &arrow_schema, options, encodings)?;
+        let mut writer = FileWriter::try_new(file, arrow_schema, options)?;
+        writer.start()?;
+        for row_group in row_groups {
+            let (row_group, len) = row_group?;
+            writer.write(row_group, len)?;
+        }
+        let key_value = [
+            (SCHEMA_KEY, schema.encode()),
+            (START_AT_KEY, chunk.info.start_at.as_millis().to_string()),
+            (
+                TIME_INTERVAL_KEY,
+                chunk.info.time_interval.as_millis().to_string(),
+            ),
+            (SERIES_LEN_KEY, chunk.info.series_len.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| KeyValue {
+            key: String::from(key),
+            value: Some(value),
+        })
+        .collect();
+        let (_, file) = writer.end(Some(key_value))?;
+        file.sync_all()?;
+
+        Self::open(path).map(|(chunk, _)| chunk)
+    }
+
+    /// Opens a file written by [`FileChunk::create`], returning it with the table schema
+    /// it was written with.
+    pub(crate) fn open(path: PathBuf) -> ArrowResult<(Self, Schema)> {
+        let mut file = File::open(&path)?;
+        let metadata = read::read_metadata(&mut file)?;
+        let schema = read::infer_schema(&metadata)?;
+        let key_value = |key: &str| {
+            metadata
+                .key_value_metadata()
+                .as_ref()
+                .and_then(|key_value| key_value.iter().find(|kv| kv.key == key))
+                .and_then(|kv| kv.value.as_deref())
+                .ok_or_else(|| ArrowError::OutOfSpec(format!("missing file metadata {:?}", key)))
+        };
+        let parse = |key: &str| {
+            key_value(key)?
+                .parse::<i64>()
+                .map_err(|err| ArrowError::OutOfSpec(format!("file metadata {:?}: {}", key, err)))
+        };
+        let info = Info {
+            start_at: Instant::from_millis(parse(START_AT_KEY)?),
+            time_interval: Duration::from_millis(parse(TIME_INTERVAL_KEY)?),
+            series_len: parse(SERIES_LEN_KEY)? as u32,
+        };
+        let table_schema = Schema::decode(key_value(SCHEMA_KEY)?)
+            .ok_or_else(|| ArrowError::OutOfSpec(String::from("invalid table schema")))?;
+        Ok((
+            Self {
+                info,
+                path,
+                schema,
+                row_groups: metadata.row_groups,
+                removed: AtomicBool::new(false),
+            },
+            table_schema,
+        ))
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the file once nothing reads it anymore.
+    pub(crate) fn remove(&self) {
+        self.removed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the id of the shard that wrote the file at `path`, if it is a chunk file.
+    pub(crate) fn shard_id(path: &Path) -> Option<usize> {
+        if path.extension()? != "parquet" {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        stem.split('-').next()?.parse().ok()
+    }
+
+    /// The sequence number of the file among the files of its window, the greatest one being
+    /// written last.
+    pub(crate) fn seq(&self) -> u64 {
+        self.path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.rsplit('-').next()?.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn create_file(dir: &Path, shard_id: usize, start_at: Instant) -> ArrowResult<(PathBuf, File)> {
+        // a rewritten window is written to a new file before the previous one is removed, which
+        // the new one supersedes on load
+        let prefix = format!("{}-{}-", shard_id, start_at.as_millis());
+        let first = fs::read_dir(dir)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                let seq = name
+                    .to_str()?
+                    .strip_prefix(&prefix)?
+                    .strip_suffix(".parquet")?;
+                seq.parse::<u64>().ok()
+            })
+            .max()
+            .map_or(0, |seq| seq + 1);
+        for seq in first.. {
+            let path = dir.join(format!(
+                "{}-{}-{}.parquet",
+                shard_id,
+                start_at.as_millis(),
+                seq
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((path, file)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        unreachable!()
+    }
+
+    pub(crate) async fn scan(
+        &self,
+        projections: Option<&[String]>,
+        filters: &[Filter],
+        range: Range,
+        limit: Option<usize>,
+    ) -> Result<Option<ScanChunk>, ScanError> {
+        let mut reader = File::open(&self.path).map_err(|err| self.read_error(err))?;
+        let label_fields = self
+            .schema
+            .fields
+            .iter()
+            .filter(|field| matches!(field.data_type(), DataType::Dictionary(..)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let scalar_fields = self
+            .schema
+            .fields
+            .iter()
+            .filter(|field| matches!(field.data_type(), DataType::List(_)))
+            .filter(|field| match projections {
+                None => true,
+                Some(projections) => projections.contains(&field.name),
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut labels = label_fields
+            .iter()
+            .map(|_| MutableUtf8Array::<i32>::new())
+            .collect::<Vec<_>>();
+        let mut scalars = scalar_fields
+            .iter()
+            .map(|field| match scalar_type(field.data_type()) {
+                ScalarType::Int(_) => ScalarBuilder::Int(MutableListArray::new()),
+                ScalarType::Float(_) => ScalarBuilder::Float(MutableListArray::new()),
+                ScalarType::Histogram(_) => ScalarBuilder::Histogram(Vec::new()),
+                ScalarType::Summary(_) => ScalarBuilder::Summary(Vec::new()),
+                ScalarType::Bool(_) => ScalarBuilder::Bool(Vec::new()),
+                ScalarType::String(_) => ScalarBuilder::String(Vec::new()),
+            })
+            .collect::<Vec<_>>();
+        let mut row_groups = Vec::new();
+        for row_group in &self.row_groups {
+            if !self.row_group_overlaps(row_group, range)? {
+                continue;
+            }
+            let label_arrays = self.read_row_group(&mut reader, row_group, &label_fields)?;
+            let ids = Self::filter(
+                &label_fields,
+                &label_arrays,
+                filters,
+                row_group.num_rows() as u32,
+            );
+            if !ids.is_empty() {
+                row_groups.push((row_group, label_arrays, ids));
+            }
+        }
+        if let Some(limit) = limit {
+            Self::limit(&label_fields, &mut row_groups, limit);
+        }
+
+        let offset = self.info.range_offset(range);
+        for (row_group, label_arrays, ids) in &row_groups {
+            for (array, builder) in label_arrays.iter().zip(labels.iter_mut()) {
+                let array = array
+                    .as_any()
+                    .downcast_ref::<DictionaryArray<u32>>()
+                    .unwrap();
+                let values = array
+                    .values()
+                    .as_any()
+                    .downcast_ref::<Utf8Array<i32>>()
+                    .unwrap();
+                let keys = array.keys();
+                for id in ids.iter() {
+                    if keys.is_null(id as usize) {
+                        builder.push_null();
+                    } else {
+                        builder.push(Some(values.value(keys.value(id as usize) as usize)));
+                    }
+                }
+            }
+            let scalar_arrays = self.read_row_group(&mut reader, row_group, &scalar_fields)?;
+            for (array, builder) in scalar_arrays.iter().zip(scalars.iter_mut()) {
+                let list = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+                match builder {
+                    ScalarType::Int(builder) => push_series(list, ids, offset.clone(), builder),
+                    ScalarType::Float(builder) => push_series(list, ids, offset.clone(), builder),
+                    ScalarType::Histogram(lists)
+                    | ScalarType::Summary(lists)
+                    | ScalarType::Bool(lists)
+                    | ScalarType::String(lists) => {
+                        lists.push(take_list(list, ids.iter(), offset.clone()))
+                    }
+                }
+            }
+        }
+
+        let start_at = self.info.range_start(range);
+        let mut chunk = ScanChunk::new(start_at, self.info.time_interval);
+        for (field, mut builder) in label_fields.iter().zip(labels) {
+            chunk
+                .labels
+                .insert(Arc::from(field.name.as_str()), builder.as_arc());
+        }
+        for (field, builder) in scalar_fields.iter().zip(scalars) {
+            let array = match builder {
+                ScalarType::Int(mut builder) => builder.as_arc(),
+                ScalarType::Float(mut builder) => builder.as_arc(),
+                ScalarType::Histogram(lists)
+                | ScalarType::Summary(lists)
+                | ScalarType::Bool(lists)
+                | ScalarType::String(lists) => {
+                    let lists = lists.iter().map(Arc::as_ref).collect::<Vec<_>>();
+                    match lists.is_empty() {
+                        true => Arc::from(new_empty_array(field.data_type().clone())),
+                        false => Arc::from(concatenate(&lists).unwrap()),
+                    }
+                }
+            };
+            chunk.scalars.insert(Arc::from(field.name.as_str()), array);
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Keeps the ids of the first `limit` series of all `row_groups`, with their label arrays.
+    fn limit(label_fields: &[Field], row_groups: &mut Vec<FilteredRowGroup>, limit: usize) {
+        let kept = {
+            let columns = row_groups
+                .iter()
+                .map(|(_, label_arrays, _)| {
+                    let mut columns = label_fields
+                        .iter()
+                        .zip(label_arrays)
+                        .map(|(field, array)| {
+                            let array = array
+                                .as_any()
+                                .downcast_ref::<DictionaryArray<u32>>()
+                                .unwrap();
+                            let values = array
+                                .values()
+                                .as_any()
+                                .downcast_ref::<Utf8Array<i32>>()
+                                .unwrap();
+                            (field.name.as_str(), array.keys(), values)
+                        })
+                        .collect::<Vec<_>>();
+                    columns.sort_by_key(|(name, ..)| *name);
+                    columns
+                })
+                .collect::<Vec<_>>();
+            let rows = row_groups
+                .iter()
+                .enumerate()
+                .flat_map(|(group, (_, _, ids))| ids.iter().map(move |id| (group, id)))
+                .collect();
+            limit_series(rows, limit, |(group, id)| {
+                columns[group]
+                    .iter()
+                    .filter_map(move |(name, keys, values)| {
+                        if keys.is_null(id as usize) {
+                            return None;
+                        }
+                        let value = values.value(keys.value(id as usize) as usize);
+                        (!value.is_empty()).then_some((*name, value))
+                    })
+            })
+        };
+        let mut ids = vec![Bitmap::create(); row_groups.len()];
+        for (group, id) in kept {
+            ids[group].add(id);
+        }
+        for ((.., row_group_ids), ids) in row_groups.iter_mut().zip(ids) {
+            *row_group_ids = ids;
+        }
+        row_groups.retain(|(.., ids)| !ids.is_empty());
+    }
+
+    fn row_group_overlaps(
+        &self,
+        row_group: &RowGroupMetaData,
+        range: Range,
+    ) -> Result<bool, ScanError> {
+        let (first_at, _) = self.statistics(row_group, 0)?;
+        let (_, last_at) = self.statistics(row_group, 1)?;
+        let (first_at, last_at) = match (first_at, last_at) {
+            (Some(first_at), Some(last_at)) => (first_at, last_at),
+            _ => return Ok(true),
+        };
+        Ok(
+            !matches!(range.start, Some(start) if last_at < start.as_millis())
+                && !matches!(range.end, Some(end) if first_at > end.as_millis()),
+        )
+    }
+
+    /// The smallest and largest values of column `index` of a row group, if known.
+    fn statistics(
+        &self,
+        row_group: &RowGroupMetaData,
+        index: usize,
+    ) -> Result<(Option<i64>, Option<i64>), ScanError> {
+        let statistics =
+            deserialize_statistics(&self.schema.fields[index], &row_group.columns()[index..])
+                .map_err(|err| self.read_error(err))?;
+        Ok(statistics
+            .first()
+            .and_then(|statistics| statistics.as_ref())
+            .and_then(|statistics| {
+                statistics
+                    .as_any()
+                    .downcast_ref::<PrimitiveStatistics<i64>>()
+            })
+            .map_or((None, None), |statistics| {
+                (statistics.min_value, statistics.max_value)
+            }))
+    }
+
+    fn read_row_group<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        row_group: &RowGroupMetaData,
+        fields: &[Field],
+    ) -> Result<Vec<Arc<dyn Array>>, ScanError> {
+        if fields.is_empty() {
+            return Ok(Vec::new());
+        }
+        let columns = read::read_columns_many(reader, row_group, fields.to_vec(), None)
+            .map_err(|err| self.read_error(err))?;
+        let mut row_group = RowGroupDeserializer::new(columns, row_group.num_rows() as usize, None);
+        match row_group.next() {
+            None => Ok(Vec::new()),
+            Some(chunk) => Ok(chunk.map_err(|err| self.read_error(err))?.into_arrays()),
+        }
+    }
+
+    fn filter(
+        fields: &[Field],
+        arrays: &[Arc<dyn Array>],
+        filters: &[Filter],
+        num_rows: u32,
+    ) -> Bitmap {
+        let mut ids = Bitmap::from_iter(0..num_rows);
+        for filter in filters {
+            let predicate = &filter.predicate;
+            let position = match fields.iter().position(|field| field.name == filter.name) {
+                Some(position) => position,
+                None => {
+                    if !predicate.matches("") {
+                        ids.clear();
+                    }
+                    continue;
+                }
+            };
+            let array = arrays[position]
+                .as_any()
+                .downcast_ref::<DictionaryArray<u32>>()
+                .unwrap();
+            let matched = array
+                .values()
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .unwrap()
+                .iter()
+                .map(|value| predicate.matches(value.unwrap_or("")))
+                .collect::<Vec<_>>();
+            let null_matched = predicate.matches("");
+            let keys = array.keys();
+            ids = ids
+                .iter()
+                .filter(|id| {
+                    if keys.is_null(*id as usize) {
+                        null_matched
+                    } else {
+                        matched[keys.value(*id as usize) as usize]
+                    }
+                })
+                .collect();
+        }
+        ids
+    }
+
+    fn read_error(&self, err: impl ToString) -> ScanError {
+        ScanError::ReadFile {
+            path: self.path.clone(),
+            err: err.to_string(),
+        }
+    }
+}
+
+impl Drop for FileChunk {
+    fn drop(&mut self) {
+        if *self.removed.get_mut() {
+            if let Err(err) = fs::remove_file(&self.path) {
+                error!("remove data file {:?} error: {}", self.path, err);
+            }
+        }
+    }
+}
+
+/// The timestamps of the first and the last sample of every row, null for rows without any.
+fn sample_bounds(
+    info: &Info,
+    scalar_arrays: &[(&Arc<str>, Arc<dyn Array>)],
+    record_num: usize,
+) -> (PrimitiveArray<i64>, PrimitiveArray<i64>) {
+    let lists = scalar_arrays
+        .iter()
+        .map(|(_, array)| array.as_any().downcast_ref::<ListArray<i32>>().unwrap())
+        .collect::<Vec<_>>();
+    let timestamp =
+        |slot: usize| info.start_at.as_millis() + info.time_interval.as_millis() * slot as i64;
+    let (mut first_at, mut last_at) = (
+        Vec::with_capacity(record_num),
+        Vec::with_capacity(record_num),
+    );
+    for row in 0..record_num {
+        let mut bounds: Option<(usize, usize)> = None;
+        for list in lists.iter().filter(|list| list.is_valid(row)) {
+            let series = list.value(row);
+            let first = (0..series.len()).find(|slot| series.is_valid(*slot));
+            let last = (0..series.len()).rev().find(|slot| series.is_valid(*slot));
+            if let (Some(first), Some(last)) = (first, last) {
+                bounds = Some(match bounds {
+                    None => (first, last),
+                    Some((min, max)) => (min.min(first), max.max(last)),
+                });
+            }
+        }
+        first_at.push(bounds.map(|(first, _)| timestamp(first)));
+        last_at.push(bounds.map(|(_, last)| timestamp(last)));
+    }
+    (
+        PrimitiveArray::from(first_at),
+        PrimitiveArray::from(last_at),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chunk::MutableChunk;
+    use crate::column::Filter;
+    use crate::file::FileChunk;
+    use crate::util::TempDir;
+    use arrow2::array::{ListArray, Utf8Array};
+    use common::histogram::{Buckets, Histogram, Summary};
+    use common::time::{Duration, Instant};
+    use common::{Label, LabelType, LabelValue, Scalar, ScalarType, ScalarValue};
+    use context::{Schema, DEFAULT};
+    use ql::rosetta::{Matcher, MatcherOp, Range};
+    use std::sync::Arc;
+
+    #[test]
+    fn file_scan() {
+        let dir = TempDir::new("file-scan");
+        let start_at = Instant::from_millis(0);
+        let mut chunk = None;
+        let mut schema = None;
+        for (id, env) in ["prod", "dev", ""].into_iter().enumerate() {
+            let labels = [Label {
+                name: "env",
+                value: LabelValue::String(env),
+            }];
+            let scalars = [(
+                start_at + Duration::SECOND * id as u32,
+                vec![Scalar {
+                    name: String::from("value"),
+                    value: ScalarValue::Float(id as f64),
+                }],
+            )];
+            let chunk = chunk.get_or_insert_with(|| {
+                let s = Arc::new(Schema::new(DEFAULT).evolve(&labels, &scalars));
+                schema = Some(Arc::clone(&s));
+                MutableChunk::new(s, start_at)
+            });
+            let aligned = chunk.align_labels(&labels);
+            chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1);
+        }
+        let chunk = chunk.unwrap().archive();
+        let schema = schema.unwrap();
+        FileChunk::create(&dir, 0, &chunk, &schema).unwrap();
+        let path = dir.join("0-0-1.parquet");
+        let file = FileChunk::create(&dir, 0, &chunk, &schema).unwrap();
+        assert_eq!(file.path, path);
+        assert_eq!(FileChunk::shard_id(&path), Some(0));
+        assert_eq!(file.seq(), 1);
+
+        let (file, restored) = FileChunk::open(path).unwrap();
+        assert_eq!(restored.encode(), schema.encode());
+        assert_eq!(file.info.series_len, DEFAULT.series_len);
+
+        let filters = [Filter::new(&Matcher {
+            name: String::from("env"),
+            op: MatcherOp::LiteralNotEqual,
+            value: Some(LabelType::String(String::from("dev"))),
+        })
+        .unwrap()];
+        let range = Range {
+            start: Some(start_at + Duration::SECOND),
+            end: Some(start_at + Duration::SECOND * 3u32),
+        };
+        let scanned = futures::executor::block_on(file.scan(None, &filters, range, None))
+            .unwrap()
+            .unwrap();
+        let labels = scanned.labels.get("env").unwrap();
+        let labels = labels.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), vec![Some("prod"), None]);
+        let values = scanned.scalars.get("value").unwrap();
+        let values = values.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values.value(1).len(), 2);
+
+        let limited = futures::executor::block_on(file.scan(None, &[], range, Some(2)))
+            .unwrap()
+            .unwrap();
+        let labels = limited.labels.get("env").unwrap();
+        let labels = labels.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), vec![Some("dev"), None]);
+        assert_eq!(limited.scalars.get("value").unwrap().len(), 2);
+
+        let outside = Range {
+            start: Some(start_at + DEFAULT.chunk_duration()),
+            end: None,
+        };
+        assert!(!file
+            .row_group_overlaps(&file.row_groups[0], outside)
+            .unwrap());
+        // the last sample of the window is at its third second
+        let after = Range {
+            start: Some(start_at + Duration::SECOND * 3u32),
+            end: None,
+        };
+        assert!(!file.row_group_overlaps(&file.row_groups[0], after).unwrap());
+        assert!(file.row_group_overlaps(&file.row_groups[0], range).unwrap());
+        let scanned = futures::executor::block_on(file.scan(
+            Some(&[String::from("missing")]),
+            &[],
+            outside,
+            None,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(scanned.labels.get("env").unwrap().len(), 0);
+        assert!(scanned.scalars.get("value").is_none());
+
+        // a removed file is still read by the scans holding it
+        let file = Arc::new(file);
+        let scan = Arc::clone(&file);
+        file.remove();
+        drop(file);
+        assert!(scan.path.exists());
+        assert!(futures::executor::block_on(scan.scan(None, &[], range, None)).is_ok());
+        let path = scan.path.clone();
+        drop(scan);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn file_histograms() {
+        let dir = TempDir::new("file-histograms");
+        let start_at = Instant::from_millis(0);
+        let histogram = Histogram {
+            count: 3.0,
+            sum: 4.5,
+            buckets: Buckets::Explicit(vec![(1.0, 1.0), (2.0, 3.0), (f64::INFINITY, 3.0)]),
+        };
+        let summary = Summary {
+            count: 3.0,
+            sum: 4.5,
+            quantiles: vec![(0.5, 1.5)],
+        };
+        let labels = [Label {
+            name: "env",
+            value: LabelValue::String("prod"),
+        }];
+        let scalars = [(
+            start_at + Duration::SECOND,
+            vec![
+                Scalar {
+                    name: String::from("histogram"),
+                    value: ScalarValue::Histogram(histogram.clone()),
+                },
+                Scalar {
+                    name: String::from("summary"),
+                    value: ScalarValue::Summary(summary.clone()),
+                },
+            ],
+        )];
+        let schema = Arc::new(Schema::new(DEFAULT).evolve(&labels, &scalars));
+        let mut chunk = MutableChunk::new(Arc::clone(&schema), start_at);
+        let aligned = chunk.align_labels(&labels);
+        chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1);
+        let file = FileChunk::create(&dir, 0, &chunk.archive(), &schema).unwrap();
+
+        let (file, _) = FileChunk::open(file.path.clone()).unwrap();
+        let range = Range {
+            start: None,
+            end: None,
+        };
+        let scanned = futures::executor::block_on(file.scan(None, &[], range, None))
+            .unwrap()
+            .unwrap();
+        let series = scanned.series();
+        assert_eq!(series.len(), 1);
+        let (timestamp, samples) = &series[0].1[0];
+        assert_eq!(*timestamp, scalars[0].0);
+        assert!(matches!(&samples[0].value, ScalarType::Histogram(h) if *h == histogram));
+        assert!(matches!(&samples[1].value, ScalarType::Summary(s) if *s == summary));
+        assert_eq!(histogram.quantile(0.5), 1.25);
+    }
+}
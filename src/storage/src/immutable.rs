@@ -0,0 +1,294 @@
+use crate::chunk::{limit_ids, Info, ScanChunk};
+use crate::column::{CompressedColumn, DictionaryColumn, Filter, LabelColumn};
+use crate::error::ScanError;
+use arrow2::array::{
+    Array, ListArray, MutableArray, MutableListArray, MutablePrimitiveArray, MutableUtf8Array,
+    PrimitiveArray, TryPush, Utf8Array,
+};
+use arrow2::types::NativeType;
+use common::util::IndexMap;
+use common::LabelType;
+use croaring::Bitmap;
+use ql::rosetta::Range;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub(crate) struct ImmutableChunk {
+    pub(crate) info: Info,
+    record_num: u32,
+    labels: IndexMap<Arc<str>, DictionaryColumn>,
+    scalars: IndexMap<Arc<str>, CompressedColumn>,
+    /// Measured once, as the chunk never changes.
+    heap_size: usize,
+}
+
+impl ImmutableChunk {
+    pub(crate) fn new(
+        info: Info,
+        record_num: u32,
+        labels: IndexMap<Arc<str>, DictionaryColumn>,
+        scalar_columns: Vec<CompressedColumn>,
+    ) -> Self {
+        let mut scalars = IndexMap::new();
+        for column in scalar_columns {
+            scalars.insert(Arc::clone(column.name()), column);
+        }
+        let heap_size = labels
+            .iter()
+            .map(DictionaryColumn::heap_size)
+            .chain(scalars.iter().map(CompressedColumn::heap_size))
+            .sum();
+        Self {
+            info,
+            record_num,
+            labels,
+            scalars,
+            heap_size,
+        }
+    }
+
+    /// Builds a chunk of window `info` from a scan of every series of it.
+    pub(crate) fn from_scan(info: Info, chunk: ScanChunk) -> Self {
+        let record_num = chunk.len() as u32;
+        let mut labels = IndexMap::new();
+        for (name, array) in chunk.labels.keys().into_iter().zip(chunk.labels.iter()) {
+            let array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+            let mut column = LabelColumn::new(LabelType::String(name.to_string()));
+            for value in array.iter() {
+                match value {
+                    None => column.push_zero(),
+                    Some(value) => column.push(&LabelType::String(value)),
+                };
+            }
+            labels.insert(Arc::clone(name), DictionaryColumn::from(column));
+        }
+        let scalars = chunk
+            .scalars
+            .keys()
+            .into_iter()
+            .zip(chunk.scalars.iter())
+            .map(|(name, array)| CompressedColumn::from_list(Arc::clone(name), array.as_ref()))
+            .collect();
+        Self::new(info, record_num, labels, scalars)
+    }
+
+    #[inline]
+    pub(crate) fn record_num(&self) -> u32 {
+        self.record_num
+    }
+
+    #[inline]
+    pub(crate) fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    pub(crate) fn label_arrays(&self) -> impl Iterator<Item = (&Arc<str>, Arc<dyn Array>)> {
+        self.labels
+            .iter()
+            .map(|column| (column.name(), column.array()))
+    }
+
+    /// Decodes every scalar column into a list array of whole series.
+    pub(crate) fn scalar_arrays(&self) -> impl Iterator<Item = (&Arc<str>, Arc<dyn Array>)> {
+        let range = 0..self.info.series_len as usize;
+        self.scalars.iter().map(move |column| {
+            let array = column.array(0..self.record_num, range.clone());
+            (column.name(), array)
+        })
+    }
+
+    pub(crate) async fn scan(
+        &self,
+        projections: Option<&[String]>,
+        filters: &[Filter],
+        range: Range,
+        limit: Option<usize>,
+    ) -> Result<Option<ScanChunk>, ScanError> {
+        let mut ids = Bitmap::from_iter(0..self.record_num);
+        for filter in filters {
+            match self.labels.get(filter.name.as_str()) {
+                Some(column) => ids.and_inplace(&column.lookup(&filter.predicate)),
+                None => {
+                    if !filter.predicate.matches("") {
+                        ids.clear();
+                    }
+                }
+            }
+        }
+
+        let mut columns = self.labels.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|column| column.name());
+        let ids = limit_ids(ids, limit, |id| {
+            columns
+                .iter()
+                .filter_map(move |column| match column.get(id) {
+                    Some(LabelType::String(value)) if !value.is_empty() => {
+                        Some((column.name().as_ref(), value))
+                    }
+                    _ => None,
+                })
+        });
+
+        let start_at = self.info.range_start(range);
+        let mut chunk = ScanChunk::new(start_at, self.info.time_interval);
+        self.push_arrow_labels(&ids, &mut chunk);
+        self.push_arrow_scalars(projections, range, &ids, &mut chunk);
+        Ok(Some(chunk))
+    }
+
+    fn push_arrow_labels(&self, ids: &Bitmap, chunk: &mut ScanChunk) {
+        for column in self.labels.iter() {
+            let mut array = match column.data_type() {
+                LabelType::String(_) => MutableUtf8Array::<i32>::new(),
+            };
+            for id in ids.iter() {
+                match column.get(id) {
+                    None => array.push_null(),
+                    Some(value) => match value {
+                        LabelType::String(s) => array.push(Some(s)),
+                    },
+                }
+            }
+            chunk
+                .labels
+                .insert(Arc::clone(column.name()), array.into_arc());
+        }
+    }
+
+    fn push_arrow_scalars(
+        &self,
+        projections: Option<&[String]>,
+        range: Range,
+        ids: &Bitmap,
+        chunk: &mut ScanChunk,
+    ) {
+        let column_ids = match projections {
+            None => (0..self.scalars.len()).collect(),
+            Some(projections) => projections
+                .iter()
+                .filter_map(|column_name| self.scalars.get_id(column_name.as_str()))
+                .collect::<Vec<_>>(),
+        };
+        let range = self.info.range_offset(range);
+        for column_id in column_ids {
+            let column = &self.scalars[column_id];
+            let array = column.array(ids.iter(), range.clone());
+            chunk.scalars.insert(Arc::clone(column.name()), array);
+        }
+    }
+}
+
+/// Appends the `range` of each series in `ids` of `list` to `array`.
+pub(crate) fn push_series<T: NativeType>(
+    list: &ListArray<i32>,
+    ids: &Bitmap,
+    range: std::ops::Range<usize>,
+    array: &mut MutableListArray<i32, MutablePrimitiveArray<T>>,
+) {
+    let values = list
+        .values()
+        .as_any()
+        .downcast_ref::<PrimitiveArray<T>>()
+        .unwrap();
+    let offsets = list.offsets();
+    for id in ids.iter() {
+        let id = id as usize;
+        if list.is_null(id) {
+            array.push_null();
+            continue;
+        }
+        let start = offsets[id] as usize;
+        let end = offsets[id + 1] as usize;
+        let series = (start + range.start).min(end)..(start + range.end).min(end);
+        array
+            .try_push(Some(series.map(|offset| {
+                if values.is_null(offset) {
+                    None
+                } else {
+                    Some(values.value(offset))
+                }
+            })))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chunk::MutableChunk;
+    use crate::column::Filter;
+    use arrow2::array::{ListArray, PrimitiveArray, Utf8Array};
+    use common::time::{Duration, Instant};
+    use common::{Label, LabelType, LabelValue, Scalar, ScalarValue};
+    use context::{Schema, DEFAULT};
+    use ql::rosetta::{Matcher, MatcherOp, Range};
+    use std::sync::Arc;
+
+    #[test]
+    fn archive_scan() {
+        let start_at = Instant::from_millis(0);
+        let mut chunk = None;
+        for (id, env) in ["prod", "dev", ""].into_iter().enumerate() {
+            let labels = [Label {
+                name: "env",
+                value: LabelValue::String(env),
+            }];
+            let scalars = [(
+                start_at + Duration::SECOND * id as u32,
+                vec![Scalar {
+                    name: String::from("value"),
+                    value: ScalarValue::Float(id as f64),
+                }],
+            )];
+            let chunk = chunk.get_or_insert_with(|| {
+                let schema = Schema::new(DEFAULT).evolve(&labels, &scalars);
+                MutableChunk::new(Arc::new(schema), start_at)
+            });
+            let aligned = chunk.align_labels(&labels);
+            chunk.push(&aligned).insert(scalars[0].0, &scalars[0].1);
+        }
+        let chunk = chunk.unwrap().archive();
+
+        let filters = [Filter::new(&Matcher {
+            name: String::from("env"),
+            op: MatcherOp::RegexMatch,
+            value: Some(LabelType::String(String::from("prod|"))),
+        })
+        .unwrap()];
+        let range = Range {
+            start: Some(start_at),
+            end: Some(start_at + Duration::SECOND * 3u32),
+        };
+        let scanned = futures::executor::block_on(chunk.scan(None, &filters, range, None))
+            .unwrap()
+            .unwrap();
+        let labels = scanned.labels.get("env").unwrap();
+        let labels = labels.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), vec![Some("prod"), None]);
+        let values = scanned.scalars.get("value").unwrap();
+        let values = values.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+        assert_eq!(values.len(), 2);
+        let series = values.value(1);
+        let series = series
+            .as_any()
+            .downcast_ref::<PrimitiveArray<f64>>()
+            .unwrap();
+        assert_eq!(
+            series.iter().map(|v| v.copied()).collect::<Vec<_>>(),
+            vec![None, None, Some(2.0)]
+        );
+
+        let scanned = futures::executor::block_on(chunk.scan(
+            Some(&[String::from("value")]),
+            &[],
+            Range {
+                start: None,
+                end: None,
+            },
+            None,
+        ))
+        .unwrap()
+        .unwrap();
+        let values = scanned.scalars.get("value").unwrap();
+        assert_eq!(values.len(), 3);
+    }
+}
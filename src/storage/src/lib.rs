@@ -2,32 +2,66 @@ mod chunk;
 mod column;
 mod db;
 pub mod error;
+mod file;
+mod immutable;
+mod metrics;
+mod snapshot;
 mod table;
 mod util;
+mod wal;
 
-use crate::chunk::ScanChunk;
+use crate::chunk::{limit_chunks, ScanSender};
+use crate::column::{compile_filters, Filter};
 use crate::db::Shard;
-use crate::error::{ScanError, WriteError};
+use crate::error::{DeleteError, ScanError, SnapshotError, WriteError};
 use crate::util::{hash_combine, jump_consistent_hash, HashReduce};
 use arrow2::datatypes::{DataType, Field, Schema};
+use common::histogram::{Buckets, Histogram, Summary};
 use common::time::Instant;
-use common::{Label, LabelType, LabelValue, Scalar, ScalarValue};
+use common::{Label, LabelValue, Scalar, ScalarValue};
 use context::Context;
 use futures::channel::oneshot;
-use ql::rosetta::{Matcher, MatcherRef, Range};
-use runtime::Runtime;
+use futures::future::{self, Either};
+use ql::rosetta::{Matcher, Range};
+use runtime::{Delay, Runtime};
+use std::collections::BTreeMap;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time;
 use tracing::error;
 
+pub use crate::chunk::{ScanChunk, ScanStream};
+pub use crate::column::list_from_rows;
+pub use crate::metrics::{MemoryUsage, TableMemory, WriteMetrics};
+pub use crate::wal::{SyncPolicy, WalOptions};
+
+const EXPIRE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+/// The scalars remote writes are stored in. Histograms and summaries are kept apart from the
+/// other samples of a series, which cannot be converted to them nor they to numbers.
+const VALUE_SCALAR: &str = "value";
+const HISTOGRAM_SCALAR: &str = "histogram";
+const SUMMARY_SCALAR: &str = "summary";
+
 #[derive(Debug)]
 struct ScanRequest {
     table_name: String,
+    /// The rollup table of `table_name` to scan up to where the table starts.
+    rollup: Option<String>,
     projections: Option<Vec<String>>,
-    filters: Vec<Matcher>,
+    filters: Vec<Filter>,
     range: Range,
     limit: Option<usize>,
-    ret: async_channel::Sender<Result<Vec<ScanChunk>, ScanError>>,
+    ret: ScanSender,
+}
+
+#[derive(Debug)]
+struct DeleteRequest {
+    table_name: String,
+    matchers: Vec<Matcher>,
+    filters: Vec<Filter>,
+    range: Range,
 }
 
 #[derive(Debug)]
@@ -41,6 +75,61 @@ enum Request<'a> {
     Scan {
         inner: Arc<ScanRequest>,
     },
+    Delete {
+        inner: Arc<DeleteRequest>,
+        ret: oneshot::Sender<Result<u64, DeleteError>>,
+    },
+    Snapshot {
+        dir: Arc<Path>,
+        ret: oneshot::Sender<Result<(), SnapshotError>>,
+    },
+    Memory {
+        ret: oneshot::Sender<Vec<(Arc<str>, TableMemory)>>,
+    },
+    Expire {
+        now: Instant,
+        ret: oneshot::Sender<()>,
+    },
+    Flush {
+        ret: oneshot::Sender<()>,
+    },
+}
+
+/// Periodic work of a shard.
+#[derive(Debug, Clone, Copy)]
+enum Tick {
+    Expire,
+    Sync,
+}
+
+/// Sends `tick` every `interval` from a task of the current shard.
+fn tick_every(interval: time::Duration, tick: Tick, ticks: async_channel::Sender<Tick>) {
+    runtime::spawn(async move {
+        loop {
+            Delay::new(interval).await;
+            // skip the tick if the last one has not been handled yet
+            if let Err(async_channel::TrySendError::Closed(_)) = ticks.try_send(tick) {
+                break;
+            }
+        }
+    })
+    .detach();
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Directory archived chunks are persisted to as Parquet files, they are only kept in
+    /// memory if unset.
+    pub data_dir: Option<PathBuf>,
+    /// Logs writes ahead of applying them, to be replayed on restart.
+    pub wal: Option<WalOptions>,
+    /// Restores shards from the snapshot in this directory on startup.
+    pub restore: Option<PathBuf>,
+    /// How often shards drop the chunks out of retention and compact late samples, every
+    /// minute if unset.
+    pub expire_interval: Option<time::Duration>,
+    /// Bytes of heap the tables of all shards may use, split evenly between shards.
+    pub memory_limit: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -48,18 +137,71 @@ pub struct StorageServer {
     runtime: Runtime<Request<'static>>,
     cores: usize,
     context: Arc<Context>,
+    metrics: Arc<WriteMetrics>,
 }
 
 impl StorageServer {
     pub fn new(cores: &[usize], context: Arc<Context>) -> Self {
+        Self::with_options(cores, context, Options::default())
+    }
+
+    pub fn with_options(cores: &[usize], context: Arc<Context>, options: Options) -> Self {
+        let metrics = Arc::new(WriteMetrics::default());
         let mut storage = Self {
             runtime: Runtime::new(cores).unwrap(),
             cores: cores.len(),
             context: Arc::clone(&context),
+            metrics: Arc::clone(&metrics),
         };
-        storage.runtime.run(move |_, recv| async move {
-            let mut db_shard = Shard::new(Arc::clone(&context));
-            while let Ok(request) = recv.recv().await {
+        let core_ids = cores.to_vec();
+        let (loaded, loaded_recv) = std::sync::mpsc::channel();
+        storage.runtime.run(move |core_id, recv| async move {
+            let shard_id = core_ids.iter().position(|id| *id == core_id).unwrap();
+            let mut db_shard = Shard::new(
+                shard_id,
+                core_ids.len(),
+                Arc::clone(&context),
+                &options,
+                Arc::clone(&metrics),
+            );
+            loaded.send(()).unwrap();
+
+            let (tick, ticks) = async_channel::bounded(2);
+            let interval = options.expire_interval.unwrap_or(EXPIRE_INTERVAL);
+            tick_every(interval, Tick::Expire, tick.clone());
+            if let Some(WalOptions {
+                sync: SyncPolicy::Interval(interval),
+                ..
+            }) = options.wal
+            {
+                tick_every(interval, Tick::Sync, tick);
+            }
+
+            let events = db_shard.events();
+            loop {
+                let others = future::select(ticks.recv(), events.recv());
+                let request = match future::select(recv.recv(), others).await {
+                    Either::Left((Ok(request), _)) => request,
+                    Either::Left((Err(_), _)) => break,
+                    Either::Right((Either::Left((tick, _)), _)) => {
+                        match tick {
+                            Ok(Tick::Expire) => {
+                                db_shard.expire(Instant::now());
+                                db_shard.compact().await;
+                            }
+                            Ok(Tick::Sync) => db_shard.sync_wal(),
+                            Err(_) => {}
+                        }
+                        continue;
+                    }
+                    Either::Right((Either::Right((event, _)), _)) => {
+                        // the shard keeps a sender, events never end
+                        if let Ok(event) = event {
+                            db_shard.handle(event);
+                        }
+                        continue;
+                    }
+                };
                 match request {
                     Request::Write {
                         table_name,
@@ -72,45 +214,101 @@ impl StorageServer {
                         ret.send(result).unwrap();
                     }
                     Request::Scan { inner } => {
-                        let mut filter_refs = Vec::with_capacity(inner.filters.len());
-                        let mut filter_values = Vec::with_capacity(inner.filters.len());
-                        for filter in &inner.filters {
-                            let value = match &filter.value {
-                                None => None,
-                                Some(value) => match value {
-                                    LabelType::String(s) => Some(LabelType::String(s.as_ref())),
-                                },
+                        let scans = match &inner.rollup {
+                            None => db_shard
+                                .scan(
+                                    &inner.table_name,
+                                    inner.projections.as_deref(),
+                                    &inner.filters,
+                                    inner.range,
+                                    inner.limit,
+                                )
+                                .await
+                                .map(|scan| {
+                                    scan.map(|scan| (scan, inner.range)).into_iter().collect()
+                                }),
+                            Some(rollup) => {
+                                db_shard
+                                    .scan_rollup(
+                                        &inner.table_name,
+                                        rollup,
+                                        inner.projections.as_deref(),
+                                        &inner.filters,
+                                        inner.range,
+                                        inner.limit,
+                                    )
+                                    .await
+                            }
+                        };
+                        // archived chunks are scanned and every chunk sent without holding up
+                        // the shard for a slow consumer
+                        runtime::spawn(async move {
+                            let send = async {
+                                // the rollup first, then the table it has not rolled up yet
+                                for (scan, range) in scans? {
+                                    scan.send(
+                                        inner.projections.as_deref(),
+                                        &inner.filters,
+                                        range,
+                                        inner.limit,
+                                        &inner.ret,
+                                    )
+                                    .await?;
+                                }
+                                Ok(())
                             };
-                            filter_values.push(value);
-                        }
-
-                        for (id, value) in filter_values.iter().enumerate() {
-                            let filter = &inner.filters[id];
-                            filter_refs.push(MatcherRef {
-                                name: &filter.name,
-                                op: filter.op,
-                                value: value.as_ref(),
-                            });
-                        }
-                        let result = db_shard
-                            .scan(
-                                &inner.table_name,
-                                inner.projections.as_deref(),
-                                &filter_refs,
-                                inner.range,
-                                inner.limit,
-                            )
-                            .await;
-                        if let Err(error) = inner.ret.send(result).await {
-                            error!("storage send response error: {:?}", error)
-                        }
+                            let result: Result<(), ScanError> = send.await;
+                            // the scan has been cancelled if nobody receives the error
+                            if let Err(err) = result {
+                                let _ = inner.ret.send(Err(err)).await;
+                            }
+                        })
+                        .detach();
+                    }
+                    Request::Delete { inner, ret } => {
+                        db_shard.delete(
+                            &inner.table_name,
+                            &inner.matchers,
+                            &inner.filters,
+                            inner.range,
+                            ret,
+                        );
+                    }
+                    Request::Snapshot { dir, ret } => {
+                        ret.send(db_shard.snapshot(&dir)).unwrap();
+                    }
+                    Request::Memory { ret } => {
+                        ret.send(db_shard.memory()).unwrap();
+                    }
+                    Request::Expire { now, ret } => {
+                        db_shard.expire(now);
+                        db_shard.compact().await;
+                        ret.send(()).unwrap();
+                    }
+                    Request::Flush { ret } => {
+                        db_shard.flush().await;
+                        ret.send(()).unwrap();
                     }
                 }
             }
+            // acknowledged writes may be archived without their files written yet
+            db_shard.flush().await;
         });
+        // schemas of persisted tables are restored once every shard has loaded its files
+        for _ in cores {
+            loaded_recv.recv().unwrap();
+        }
         storage
     }
 
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    pub fn write_metrics(&self) -> &WriteMetrics {
+        &self.metrics
+    }
+
     fn hash_labels(labels: &[Label]) -> u64 {
         let mut label_vec = labels.iter().collect::<Vec<_>>();
         label_vec.sort_by_key(|label| &label.name);
@@ -161,7 +359,36 @@ impl StorageServer {
         filters: &[Matcher],
         range: Range,
         limit: Option<usize>,
-    ) -> Result<(Schema, Vec<ScanChunk>), ScanError> {
+    ) -> Result<(Schema, ScanStream), ScanError> {
+        self.scan_tables(table_name, None, projections, filters, range, limit)
+            .await
+    }
+
+    /// Scans rollup table `rollup` of `table_name` as laid out as the table, with the samples
+    /// the table has not archived and rolled up yet scanned from the table itself. Those are
+    /// sent as the rollup would hold them.
+    #[tracing::instrument]
+    pub async fn scan_rollup(
+        &self,
+        table_name: &str,
+        rollup: &str,
+        projections: Option<Vec<&str>>,
+        filters: &[Matcher],
+        range: Range,
+    ) -> Result<(Schema, ScanStream), ScanError> {
+        self.scan_tables(table_name, Some(rollup), projections, filters, range, None)
+            .await
+    }
+
+    async fn scan_tables(
+        &self,
+        table_name: &str,
+        rollup: Option<&str>,
+        projections: Option<Vec<&str>>,
+        filters: &[Matcher],
+        range: Range,
+        limit: Option<usize>,
+    ) -> Result<(Schema, ScanStream), ScanError> {
         let schema = self
             .context
             .get_schema(table_name.as_ref())
@@ -169,8 +396,10 @@ impl StorageServer {
                 name: table_name.into(),
             })?;
         let mut arrow_fields =
-            Vec::with_capacity(schema.label_arrows.len() + schema.scalar_arrows.len() + 1);
+            Vec::with_capacity(schema.label_arrows.len() + schema.scalar_arrows.len() + 2);
         arrow_fields.push(Field::new("start_at", DataType::Int64, false));
+        // chunks archived before a change of layout keep their interval
+        arrow_fields.push(Field::new("time_interval", DataType::Int64, false));
         arrow_fields.extend_from_slice(&schema.label_arrows);
         match projections {
             None => arrow_fields.extend_from_slice(&schema.scalar_arrows),
@@ -188,11 +417,14 @@ impl StorageServer {
             }
         }
 
+        // compiled once for every chunk of every shard
+        let filters = compile_filters(filters)?;
         let (ret, ret_recv) = async_channel::bounded(self.cores);
         let request = Arc::new(ScanRequest {
             table_name: table_name.into(),
+            rollup: rollup.map(String::from),
             projections: projections.map(|ps| ps.iter().map(|p| p.to_string()).collect()),
-            filters: filters.to_vec(),
+            filters,
             range,
             limit,
             ret,
@@ -208,19 +440,173 @@ impl StorageServer {
                 .await
                 .unwrap();
         }
+        // the stream ends once every shard is done with the request
+        drop(request);
+
+        let stream = match limit {
+            None => ScanStream::Shards(ret_recv),
+            Some(_) => {
+                // shards return their first series only, which are merged here
+                let mut chunks = Vec::new();
+                while let Ok(chunk) = ret_recv.recv().await {
+                    chunks.push(chunk?);
+                }
+                ScanStream::Limited(limit_chunks(chunks, limit).into_iter())
+            }
+        };
+        let mut arrow_schema = Schema::from(arrow_fields);
+        arrow_schema.metadata.extend([
+            (
+                String::from("time_interval"),
+                schema.meta.time_interval.as_millis().to_string(),
+            ),
+            (
+                String::from("series_len"),
+                schema.meta.series_len.to_string(),
+            ),
+            (
+                String::from("mutable_chunk_num"),
+                schema.meta.mutable_chunk_num.to_string(),
+            ),
+            (String::from("alignment"), schema.meta.alignment.to_string()),
+        ]);
+        Ok((arrow_schema, stream))
+    }
 
-        let mut chunks = Vec::new();
-        for _ in 0..self.cores {
-            chunks.append(&mut ret_recv.recv().await.unwrap()?);
+    /// Deletes the samples within `range` of the series of `table_name` matching `matchers`,
+    /// returning how many were deleted. Rows of mutable chunks left without samples are
+    /// tombstoned, archived chunks with samples to delete are rewritten.
+    pub async fn delete(
+        &self,
+        table_name: &str,
+        matchers: &[Matcher],
+        range: Range,
+    ) -> Result<u64, DeleteError> {
+        if self.context.get_schema(table_name).is_none() {
+            return Err(DeleteError::Scan {
+                err: ScanError::NoSuchTable {
+                    name: table_name.into(),
+                },
+            });
+        }
+        // invalid matchers are rejected before they are logged
+        let filters = compile_filters(matchers).map_err(|err| DeleteError::Scan { err })?;
+        let request = Arc::new(DeleteRequest {
+            table_name: table_name.into(),
+            matchers: matchers.to_vec(),
+            filters,
+            range,
+        });
+        let mut rets = Vec::with_capacity(self.cores);
+        for shard_id in 0..self.cores {
+            let (ret, ret_recv) = oneshot::channel();
+            self.runtime
+                .send(
+                    shard_id,
+                    Request::Delete {
+                        inner: Arc::clone(&request),
+                        ret,
+                    },
+                )
+                .await
+                .unwrap();
+            rets.push(ret_recv);
         }
-        ret_recv.close();
+        let mut deleted = 0;
+        for ret in rets {
+            deleted += ret.await.unwrap()?;
+        }
+        Ok(deleted)
+    }
 
-        let mut arrow_schema = Schema::from(arrow_fields);
-        arrow_schema.metadata.insert(
-            String::from("time_interval"),
-            schema.meta.time_interval.as_millis().to_string(),
-        );
-        Ok((arrow_schema, chunks))
+    /// Takes a snapshot of every shard into directory `dir`, which can be restored from with
+    /// `Options::restore` by a server with the same number of cores. Shards are snapshotted
+    /// concurrently, each blocking only its own writes while it is being serialized.
+    pub async fn snapshot(&self, dir: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let dir: Arc<Path> = Arc::from(dir.as_ref());
+        std::fs::create_dir_all(&dir).map_err(|err| SnapshotError::Io {
+            err: err.to_string(),
+        })?;
+        let mut rets = Vec::with_capacity(self.cores);
+        for shard_id in 0..self.cores {
+            let (ret, ret_recv) = oneshot::channel();
+            self.runtime
+                .send(
+                    shard_id,
+                    Request::Snapshot {
+                        dir: Arc::clone(&dir),
+                        ret,
+                    },
+                )
+                .await
+                .unwrap();
+            rets.push(ret_recv);
+        }
+        for ret in rets {
+            ret.await.unwrap()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the chunks out of retention at `now` and compacts late samples on every shard,
+    /// as shards do every `expire_interval`.
+    pub async fn expire(&self, now: Instant) {
+        let mut rets = Vec::with_capacity(self.cores);
+        for shard_id in 0..self.cores {
+            let (ret, ret_recv) = oneshot::channel();
+            self.runtime
+                .send(shard_id, Request::Expire { now, ret })
+                .await
+                .unwrap();
+            rets.push(ret_recv);
+        }
+        for ret in rets {
+            ret.await.unwrap();
+        }
+    }
+
+    /// Waits for every shard to persist the chunks it has archived so far.
+    pub async fn flush(&self) {
+        let mut rets = Vec::with_capacity(self.cores);
+        for shard_id in 0..self.cores {
+            let (ret, ret_recv) = oneshot::channel();
+            self.runtime
+                .send(shard_id, Request::Flush { ret })
+                .await
+                .unwrap();
+            rets.push(ret_recv);
+        }
+        for ret in rets {
+            ret.await.unwrap();
+        }
+    }
+
+    /// Measures the heap usage of every shard and table.
+    pub async fn memory_usage(&self) -> MemoryUsage {
+        let mut rets = Vec::with_capacity(self.cores);
+        for shard_id in 0..self.cores {
+            let (ret, ret_recv) = oneshot::channel();
+            self.runtime
+                .send(shard_id, Request::Memory { ret })
+                .await
+                .unwrap();
+            rets.push(ret_recv);
+        }
+        let mut usage = MemoryUsage::default();
+        let mut tables = BTreeMap::<String, TableMemory>::new();
+        for ret in rets {
+            let shard = ret.await.unwrap();
+            usage
+                .shards
+                .push(shard.iter().map(|(_, memory)| memory.total()).sum());
+            for (table_name, memory) in shard {
+                let table = tables.entry(table_name.to_string()).or_default();
+                table.mutable += memory.mutable;
+                table.archived += memory.archived;
+            }
+        }
+        usage.tables = tables.into_iter().collect();
+        usage
     }
 
     pub async fn write(&self, request: flat::write::WriteRequest<'_>) {
@@ -241,17 +627,92 @@ impl StorageServer {
 
             let name = name.unwrap();
 
-            let scalars = timeseries
+            let value = |timestamp, value: ScalarValue| {
+                let name = match value {
+                    ScalarValue::Histogram(_) => HISTOGRAM_SCALAR,
+                    ScalarValue::Summary(_) => SUMMARY_SCALAR,
+                    _ => VALUE_SCALAR,
+                };
+                let scalars = vec![Scalar {
+                    name: String::from(name),
+                    value,
+                }];
+                (Instant::from_millis(timestamp), scalars)
+            };
+            let samples = timeseries
                 .samples()
                 .iter()
+                .map(|sample| value(sample.timestamp(), ScalarValue::Float(sample.value())));
+            let histograms = timeseries
+                .histograms()
+                .into_iter()
+                .flatten()
+                .map(|histogram| {
+                    let buckets = match histogram.buckets() {
+                        Some(buckets) => Buckets::Explicit(
+                            buckets
+                                .iter()
+                                .map(|bucket| (bucket.upper_bound(), bucket.count()))
+                                .collect(),
+                        ),
+                        None => {
+                            let buckets = |buckets: Option<&[flat::write::ExponentialBucket]>| {
+                                buckets
+                                    .unwrap_or_default()
+                                    .iter()
+                                    .map(|bucket| (bucket.index(), bucket.count()))
+                                    .collect()
+                            };
+                            Buckets::Exponential {
+                                schema: histogram.schema(),
+                                zero_threshold: histogram.zero_threshold(),
+                                zero_count: histogram.zero_count(),
+                                positive: buckets(histogram.positive_buckets()),
+                                negative: buckets(histogram.negative_buckets()),
+                            }
+                        }
+                    };
+                    let histogram_value = Histogram {
+                        count: histogram.count(),
+                        sum: histogram.sum(),
+                        buckets,
+                    };
+                    value(
+                        histogram.timestamp(),
+                        ScalarValue::Histogram(histogram_value),
+                    )
+                });
+            let summaries = timeseries.summaries().into_iter().flatten().map(|summary| {
+                let summary_value = Summary {
+                    count: summary.count(),
+                    sum: summary.sum(),
+                    quantiles: summary
+                        .quantiles()
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|quantile| (quantile.quantile(), quantile.value()))
+                        .collect(),
+                };
+                value(summary.timestamp(), ScalarValue::Summary(summary_value))
+            });
+            let bools = timeseries
+                .bool_samples()
+                .unwrap_or_default()
+                .iter()
+                .map(|sample| value(sample.timestamp(), ScalarValue::Bool(sample.value())));
+            let strings = timeseries
+                .string_samples()
+                .into_iter()
+                .flatten()
                 .map(|sample| {
-                    let timestamp = Instant::from_millis(sample.timestamp());
-                    let scalars = vec![Scalar {
-                        name: String::from("value"),
-                        value: ScalarValue::Float(sample.value()),
-                    }];
-                    (timestamp, scalars)
-                })
+                    let string = ScalarValue::String(sample.value().to_owned());
+                    value(sample.timestamp(), string)
+                });
+            let scalars = samples
+                .chain(histograms)
+                .chain(summaries)
+                .chain(bools)
+                .chain(strings)
                 .collect();
 
             let result = self.inner_write(name, labels, scalars).await;
@@ -264,13 +725,47 @@ impl StorageServer {
 
 #[cfg(test)]
 mod test {
-    use crate::StorageServer;
-    use common::time::Instant;
-    use common::{Label, LabelValue, Scalar, ScalarValue};
-    use context::Context;
-    use ql::rosetta::Range;
+    use crate::error::{DeleteError, ScanError, WriteError};
+    use crate::util::TempDir;
+    use crate::{Options, ScanChunk, ScanStream, StorageServer, SyncPolicy, WalOptions};
+    use arrow2::array::{ListArray, PrimitiveArray, Utf8Array};
+    use arrow2::datatypes::Schema;
+    use common::time::{Duration, Instant};
+    use common::{Label, LabelType, LabelValue, Scalar, ScalarValue};
+    use context::{Alignment, Context, TableMeta, DEFAULT};
+    use flat::write::{
+        self, Bucket, HistogramArgs, LabelArgs, SummaryArgs, Timeseries, TimeseriesArgs,
+        WriteRequest, WriteRequestArgs,
+    };
+    use flatbuffers::FlatBufferBuilder;
+    use futures::TryStreamExt;
+    use ql::rosetta::{Matcher, MatcherOp, Range};
+    use std::fs;
     use std::sync::Arc;
 
+    fn collect((schema, stream): (Schema, ScanStream)) -> (Schema, Vec<ScanChunk>) {
+        let chunks = futures_lite::future::block_on(stream.try_collect()).unwrap();
+        (schema, chunks)
+    }
+
+    /// The values of `scalar1` of every series in the chunks.
+    fn series(chunks: &[ScanChunk]) -> Vec<Vec<Option<i64>>> {
+        let mut series = vec![];
+        for chunk in chunks {
+            let array = chunk.scalars.get("scalar1").unwrap();
+            let array = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+            for id in 0..array.len() {
+                let values = array.value(id);
+                let values = values
+                    .as_any()
+                    .downcast_ref::<PrimitiveArray<i64>>()
+                    .unwrap();
+                series.push(values.iter().map(|value| value.copied()).collect());
+            }
+        }
+        series
+    }
+
     #[test]
     fn storage_scan() {
         let storage = StorageServer::new(&[0], Arc::new(Context::new()));
@@ -289,7 +784,193 @@ mod test {
         ))
         .unwrap();
 
-        let result = futures_lite::future::block_on(storage.scan(
+        let result = collect(
+            futures_lite::future::block_on(storage.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        println!("{:?}", result);
+    }
+
+    #[test]
+    fn storage_write_kinds() {
+        let mut builder = FlatBufferBuilder::new();
+        let labels = [("__name__", "requests"), ("host", "host0")].map(|(name, value)| {
+            let args = LabelArgs {
+                name: Some(builder.create_string(name)),
+                value: Some(builder.create_string(value)),
+            };
+            write::Label::create(&mut builder, &args)
+        });
+        let labels = builder.create_vector(&labels);
+        let now = Instant::now().as_millis();
+        let samples = builder.create_vector(&[write::Sample::new(1.0, now)]);
+        let buckets =
+            builder.create_vector(&[Bucket::new(1.0, 1.0), Bucket::new(f64::INFINITY, 2.0)]);
+        let histogram = write::Histogram::create(
+            &mut builder,
+            &HistogramArgs {
+                timestamp: now + 1000,
+                count: 2.0,
+                sum: 3.0,
+                buckets: Some(buckets),
+                ..Default::default()
+            },
+        );
+        let histograms = builder.create_vector(&[histogram]);
+        let summary = write::Summary::create(
+            &mut builder,
+            &SummaryArgs {
+                timestamp: now + 2000,
+                count: 2.0,
+                sum: 3.0,
+                quantiles: None,
+            },
+        );
+        let summaries = builder.create_vector(&[summary]);
+        let timeseries = Timeseries::create(
+            &mut builder,
+            &TimeseriesArgs {
+                labels: Some(labels),
+                samples: Some(samples),
+                histograms: Some(histograms),
+                summaries: Some(summaries),
+                ..Default::default()
+            },
+        );
+        let timeseries = builder.create_vector(&[timeseries]);
+        let request = WriteRequest::create(
+            &mut builder,
+            &WriteRequestArgs {
+                timeseries: Some(timeseries),
+            },
+        );
+        builder.finish(request, None);
+
+        let storage = StorageServer::new(&[0], Arc::new(Context::new()));
+        let request = write::root_as_write_request(builder.finished_data()).unwrap();
+        futures_lite::future::block_on(storage.write(request));
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(storage.scan("requests", None, &[], whole, None))
+                .unwrap(),
+        );
+        // every kind of sample is kept in a scalar of its own
+        let names = schema.fields[3..]
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["value", "histogram", "summary"]);
+        assert_eq!(chunks.len(), 1);
+        for name in names {
+            let array = chunks[0].scalars.get(name).unwrap();
+            let array = array.as_any().downcast_ref::<ListArray<i32>>().unwrap();
+            let slots = array.value(0);
+            let written = (0..slots.len()).filter(|slot| slots.is_valid(*slot));
+            assert_eq!(written.count(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn storage_scan_limit() {
+        let storage = StorageServer::new(&[0, 0], Arc::new(Context::new()));
+        let start_at = Instant::from_millis(0);
+        let hosts = (0..10).map(|id| format!("host{}", id)).collect::<Vec<_>>();
+        for host in hosts.iter().rev() {
+            for minute in [0u32, 3] {
+                futures_lite::future::block_on(storage.inner_write(
+                    "test",
+                    vec![Label {
+                        name: "host",
+                        value: LabelValue::String(host),
+                    }],
+                    vec![(
+                        start_at + Duration::SECOND * (minute * 60),
+                        vec![Scalar {
+                            name: String::from("scalar1"),
+                            value: ScalarValue::Float(minute as f64),
+                        }],
+                    )],
+                ))
+                .unwrap();
+            }
+        }
+
+        let scan = |limit| {
+            collect(
+                futures_lite::future::block_on(storage.scan(
+                    "test",
+                    None,
+                    &[],
+                    Range {
+                        start: None,
+                        end: None,
+                    },
+                    limit,
+                ))
+                .unwrap(),
+            )
+        };
+        let (_, chunks) = scan(None);
+        assert_eq!(chunks.iter().map(|chunk| chunk.len()).sum::<usize>(), 20);
+
+        let (_, chunks) = scan(Some(3));
+        let mut windows = Vec::<(i64, Vec<&str>)>::new();
+        for chunk in &chunks {
+            let hosts = chunk.labels.get("host").unwrap();
+            let hosts = hosts.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+            let hosts = hosts.iter().flatten().collect::<Vec<_>>();
+            assert!(hosts.windows(2).all(|pair| pair[0] < pair[1]));
+            match windows.last_mut() {
+                Some((start_at, series)) if *start_at == chunk.start_at.as_millis() => {
+                    series.extend(hosts)
+                }
+                _ => windows.push((chunk.start_at.as_millis(), hosts)),
+            }
+        }
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].0 < windows[1].0);
+        for (_, mut series) in windows {
+            series.sort_unstable();
+            assert_eq!(series, vec!["host0", "host1", "host2"]);
+        }
+    }
+
+    #[test]
+    fn storage_scan_cancel() {
+        let storage = StorageServer::new(&[0], Arc::new(Context::new()));
+        let write = |minute: u32| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    Instant::from_millis(0) + Duration::SECOND * (minute * 60),
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(minute as f64),
+                    }],
+                )],
+            ))
+        };
+        for minute in 0..20 {
+            write(minute).unwrap();
+        }
+
+        let (_, mut stream) = futures_lite::future::block_on(storage.scan(
             "test",
             None,
             &[],
@@ -300,6 +981,1137 @@ mod test {
             None,
         ))
         .unwrap();
-        println!("{:?}", result);
+        let first = futures_lite::future::block_on(stream.try_next()).unwrap();
+        assert_eq!(first.unwrap().start_at, Instant::from_millis(0));
+        // the shard keeps taking requests while the stream is not read
+        write(20).unwrap();
+        // the scan stops instead of waiting for the stream forever
+        drop(stream);
+        write(21).unwrap();
+    }
+
+    #[test]
+    fn storage_scan_archived() {
+        let storage = StorageServer::new(&[0], Arc::new(Context::new()));
+        let start_at = Instant::from_millis(0);
+        for minute in 0..20u32 {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    start_at + Duration::SECOND * (minute * 60),
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(minute as f64),
+                    }],
+                )],
+            ))
+            .unwrap();
+        }
+
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(storage.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        assert_eq!(chunks.len(), 10);
+        assert_eq!(chunks[0].start_at, start_at);
+        for chunk in chunks {
+            let chunk = chunk.into_arrow_chunk(&schema);
+            assert_eq!(chunk.len(), 1);
+        }
+    }
+
+    #[test]
+    fn storage_delete() {
+        let storage = StorageServer::new(&[0, 0], Arc::new(Context::new()));
+        let start_at = Instant::from_millis(0);
+        for minute in 0..20u32 {
+            for host in ["host0", "host1"] {
+                futures_lite::future::block_on(storage.inner_write(
+                    "test",
+                    vec![Label {
+                        name: "host",
+                        value: LabelValue::String(host),
+                    }],
+                    vec![(
+                        start_at + Duration::SECOND * (minute * 60),
+                        vec![Scalar {
+                            name: String::from("scalar1"),
+                            value: ScalarValue::Float(minute as f64),
+                        }],
+                    )],
+                ))
+                .unwrap();
+            }
+        }
+        let host0 = [Matcher {
+            name: String::from("host"),
+            op: MatcherOp::LiteralEqual,
+            value: Some(LabelType::String(String::from("host0"))),
+        }];
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        let hosts = || {
+            let (_, chunks) = collect(
+                futures_lite::future::block_on(storage.scan("test", None, &[], whole, None))
+                    .unwrap(),
+            );
+            let mut hosts = Vec::new();
+            for chunk in chunks {
+                let array = chunk.labels.get("host").unwrap();
+                let array = array.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+                hosts.extend(array.iter().flatten().map(String::from));
+            }
+            hosts
+        };
+
+        // the first half is archived
+        let first_half = Range {
+            start: None,
+            end: Some(start_at + Duration::SECOND * 600u32),
+        };
+        let deleted =
+            futures_lite::future::block_on(storage.delete("test", &host0, first_half)).unwrap();
+        assert_eq!(deleted, 10);
+        let hosts_left = hosts();
+        assert_eq!(hosts_left.len(), 15);
+        assert_eq!(hosts_left.iter().filter(|host| *host == "host0").count(), 5);
+
+        let deleted =
+            futures_lite::future::block_on(storage.delete("test", &host0, whole)).unwrap();
+        assert_eq!(deleted, 10);
+        let hosts_left = hosts();
+        assert_eq!(hosts_left.len(), 10);
+        assert!(hosts_left.iter().all(|host| host == "host1"));
+
+        assert!(futures_lite::future::block_on(storage.delete("missing", &host0, whole)).is_err());
+
+        // invalid regexes are rejected before reaching any shard
+        let invalid = [Matcher {
+            name: String::from("host"),
+            op: MatcherOp::RegexMatch,
+            value: Some(LabelType::String(String::from("("))),
+        }];
+        assert!(matches!(
+            futures_lite::future::block_on(storage.delete("test", &invalid, whole)),
+            Err(DeleteError::Scan {
+                err: ScanError::InvalidRegex { .. }
+            })
+        ));
+        assert!(matches!(
+            futures_lite::future::block_on(storage.scan("test", None, &invalid, whole, None)),
+            Err(ScanError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn storage_delete_replay() {
+        let (wal_dir, dir) = (
+            TempDir::new("delete-replay-wal"),
+            TempDir::new("delete-replay"),
+        );
+        for data_dir in [None, Some(dir.to_path_buf())] {
+            let _ = fs::remove_dir_all(&wal_dir);
+            if let Some(data_dir) = &data_dir {
+                let _ = fs::remove_dir_all(data_dir);
+            }
+            // a segment per write, truncated once its samples are persisted
+            let mut wal = WalOptions::new(wal_dir.to_path_buf());
+            wal.segment_size = 1;
+            let options = Options {
+                data_dir: data_dir.clone(),
+                wal: Some(wal),
+                restore: None,
+                expire_interval: None,
+                memory_limit: None,
+            };
+            let storage =
+                StorageServer::with_options(&[0], Arc::new(Context::new()), options.clone());
+            for minute in 0..20u32 {
+                for host in ["host0", "host1"] {
+                    futures_lite::future::block_on(storage.inner_write(
+                        "test",
+                        vec![Label {
+                            name: "host",
+                            value: LabelValue::String(host),
+                        }],
+                        vec![(
+                            Instant::from_millis(0) + Duration::SECOND * (minute * 60),
+                            vec![Scalar {
+                                name: String::from("scalar1"),
+                                value: ScalarValue::Float(minute as f64),
+                            }],
+                        )],
+                    ))
+                    .unwrap();
+                }
+            }
+            let host0 = [Matcher {
+                name: String::from("host"),
+                op: MatcherOp::LiteralEqual,
+                value: Some(LabelType::String(String::from("host0"))),
+            }];
+            let whole = Range {
+                start: None,
+                end: None,
+            };
+            let deleted =
+                futures_lite::future::block_on(storage.delete("test", &host0, whole)).unwrap();
+            assert_eq!(deleted, 20);
+            drop(storage);
+
+            // the samples replayed, or left in the files the delete rewrites, are deleted again
+            let restored = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+            let (_, chunks) = collect(
+                futures_lite::future::block_on(restored.scan("test", None, &host0, whole, None))
+                    .unwrap(),
+            );
+            assert!(chunks.iter().all(|chunk| chunk.len() == 0));
+            drop(restored);
+        }
+    }
+
+    #[test]
+    fn storage_schema_evolution() {
+        let storage = StorageServer::new(&[0], Arc::new(Context::new()));
+        let now = Instant::now();
+        futures_lite::future::block_on(storage.inner_write(
+            "test",
+            vec![Label {
+                name: "label1",
+                value: LabelValue::String("value1"),
+            }],
+            vec![(
+                now,
+                vec![Scalar {
+                    name: String::from("scalar1"),
+                    value: ScalarValue::Float(1.0),
+                }],
+            )],
+        ))
+        .unwrap();
+        futures_lite::future::block_on(storage.inner_write(
+            "test",
+            vec![
+                Label {
+                    name: "label1",
+                    value: LabelValue::String("value2"),
+                },
+                Label {
+                    name: "label2",
+                    value: LabelValue::String("value3"),
+                },
+            ],
+            vec![(
+                now,
+                vec![Scalar {
+                    name: String::from("scalar2"),
+                    value: ScalarValue::Int(1),
+                }],
+            )],
+        ))
+        .unwrap();
+
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(storage.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        let names = schema
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "start_at",
+                "time_interval",
+                "label1",
+                "label2",
+                "scalar1",
+                "scalar2"
+            ]
+        );
+        for chunk in chunks {
+            let chunk = chunk.into_arrow_chunk(&schema);
+            assert_eq!(chunk.arrays().len(), schema.fields.len());
+            assert_eq!(chunk.len(), 2);
+        }
+    }
+
+    #[test]
+    fn storage_persist_restore() {
+        let data_dir = TempDir::new("persist-restore");
+        let options = Options {
+            data_dir: Some(data_dir.to_path_buf()),
+            wal: None,
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let storage = StorageServer::with_options(&[0], Arc::new(Context::new()), options.clone());
+        let start_at = Instant::from_millis(0);
+        for minute in 0..20u32 {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    start_at + Duration::SECOND * (minute * 60),
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(minute as f64),
+                    }],
+                )],
+            ))
+            .unwrap();
+        }
+        futures_lite::future::block_on(storage.flush());
+        assert_eq!(fs::read_dir(data_dir.join("test")).unwrap().count(), 5);
+        // as if a rewrite of the first window was written right before restart
+        let table_dir = data_dir.join("test");
+        fs::copy(
+            table_dir.join("0-0-0.parquet"),
+            table_dir.join("0-0-1.parquet"),
+        )
+        .unwrap();
+
+        let restored = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(restored.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: Some(start_at + Duration::SECOND * (3 * 60u32)),
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].start_at, start_at);
+        for chunk in chunks {
+            let chunk = chunk.into_arrow_chunk(&schema);
+            assert_eq!(chunk.len(), 1);
+        }
+        assert!(!table_dir.join("0-0-0.parquet").exists());
+    }
+
+    #[test]
+    fn storage_table_meta() {
+        let context = Arc::new(Context::new());
+        let storage = StorageServer::new(&[0], Arc::clone(&context));
+        let tables = TableMeta::parse_config(
+            "[declared]\ntime_interval = 15s\nseries_len = 240\nmutable_chunk_num = 2\nalignment = sum\n",
+        )
+        .unwrap();
+        for (name, config) in tables {
+            context.configure_table(&name, &config).unwrap();
+        }
+        let write = |table_name: &'static str, timestamps: &[i64]| {
+            futures_lite::future::block_on(
+                storage.inner_write(
+                    table_name,
+                    vec![Label {
+                        name: "label1",
+                        value: LabelValue::String("value1"),
+                    }],
+                    timestamps
+                        .iter()
+                        .map(|millis| {
+                            (
+                                Instant::from_millis(*millis),
+                                vec![Scalar {
+                                    name: String::from("scalar1"),
+                                    value: ScalarValue::Float(*millis as f64),
+                                }],
+                            )
+                        })
+                        .collect(),
+                ),
+            )
+            .unwrap()
+        };
+        let scan = |table_name: &str| {
+            collect(
+                futures_lite::future::block_on(storage.scan(
+                    table_name,
+                    None,
+                    &[],
+                    Range {
+                        start: None,
+                        end: None,
+                    },
+                    None,
+                ))
+                .unwrap(),
+            )
+        };
+        write("declared", &[0, 10, 20]);
+        // too few samples to infer from
+        write("default", &[0, 10, 20]);
+        // the median spacing, whole seconds, despite the jittered samples
+        write(
+            "inferred",
+            &[0, 10_000, 20_000, 30_500, 30_501, 40_000, 50_000, 60_000],
+        );
+
+        let (schema, chunks) = scan("declared");
+        assert_eq!(schema.metadata["time_interval"], "15000");
+        assert_eq!(schema.metadata["series_len"], "240");
+        assert_eq!(schema.metadata["mutable_chunk_num"], "2");
+        assert_eq!(schema.metadata["alignment"], "sum");
+        assert_eq!(chunks.len(), 1);
+
+        let (schema, _) = scan("default");
+        assert_eq!(schema.metadata["time_interval"], "1000");
+
+        let (schema, chunks) = scan("inferred");
+        assert_eq!(schema.metadata["time_interval"], "10000");
+        assert_eq!(schema.metadata["series_len"], "120");
+        let chunk = chunks.into_iter().next().unwrap().into_arrow_chunk(&schema);
+        let values = format!("{:?}", chunk.arrays()[3]);
+        assert!(
+            values.starts_with("ListArray[[0, 10000, 20000, 30501, 40000, 50000, 60000, None"),
+            "{}",
+            values
+        );
+
+        // declarations keep the fields they leave unset
+        let config = TableMeta::parse_config("[inferred]\nmutable_chunk_num = 3\n").unwrap();
+        let schema = context.configure_table("inferred", &config[0].1).unwrap();
+        assert_eq!(schema.meta.time_interval, Duration::SECOND * 10u32);
+        assert_eq!(schema.meta.mutable_chunk_num, 3);
+
+        // chunks switch to the new layout from the next write on
+        let mut meta = context.get_schema("inferred").unwrap().meta.clone();
+        meta.time_interval = Duration::SECOND;
+        context.declare_table("inferred", meta);
+        write("inferred", &[1_200_000]);
+        let (schema, chunks) = scan("inferred");
+        assert_eq!(schema.metadata["time_interval"], "1000");
+        let mut intervals = chunks
+            .into_iter()
+            .map(|chunk| format!("{:?}", chunk.into_arrow_chunk(&schema).arrays()[1]))
+            .collect::<Vec<_>>();
+        intervals.sort();
+        assert_eq!(intervals, ["Int64[10000]", "Int64[1000]"]);
+    }
+
+    #[test]
+    fn storage_cardinality_limits() {
+        let context = Arc::new(Context::new());
+        let storage = StorageServer::new(&[0], Arc::clone(&context));
+        context.declare_table(
+            "test",
+            TableMeta {
+                max_series: Some(3),
+                max_label_values: Some(2),
+                ..DEFAULT
+            },
+        );
+        let now = Instant::now();
+        let write = |host: &'static str, region: &'static str| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![
+                    Label {
+                        name: "host",
+                        value: LabelValue::String(host),
+                    },
+                    Label {
+                        name: "region",
+                        value: LabelValue::String(region),
+                    },
+                ],
+                vec![(
+                    now,
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Int(1),
+                    }],
+                )],
+            ))
+        };
+        write("host0", "us").unwrap();
+        write("host1", "eu").unwrap();
+        assert!(matches!(
+            write("host1", "ap"),
+            Err(WriteError::LabelValueLimitExceeded { label, .. }) if label.as_ref() == "region"
+        ));
+        write("host0", "eu").unwrap();
+        assert!(matches!(
+            write("host1", "us"),
+            Err(WriteError::SeriesLimitExceeded { limit: 3, .. })
+        ));
+        // existing series are still written to
+        write("host0", "us").unwrap();
+        assert_eq!(storage.write_metrics().rejected_label_values(), 1);
+        assert_eq!(storage.write_metrics().rejected_series(), 1);
+    }
+
+    #[test]
+    fn storage_rollup() {
+        let context = Arc::new(Context::new());
+        let storage = StorageServer::new(&[0], Arc::clone(&context));
+        let mut meta = TableMeta {
+            series_len: 60,
+            mutable_chunk_num: 1,
+            out_of_order: Duration::SECOND * 60u32,
+            ..DEFAULT
+        };
+        meta.set("rollup", "1m:max,sum,count").unwrap();
+        context.declare_table("test", meta);
+        let start_at = Instant::from_millis(0);
+        let write = |second: u32, value: &'static str| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String(value),
+                }],
+                vec![(
+                    start_at + Duration::SECOND * second,
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Int(second as i64),
+                    }],
+                )],
+            ))
+            .unwrap();
+        };
+        // writing the second minute archives the first one, which is rolled up
+        for second in 0..61u32 {
+            write(second, "value1");
+        }
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        let rolled_up = |table_name: &str| {
+            let (_, chunks) = collect(
+                futures_lite::future::block_on(storage.scan(table_name, None, &[], whole, None))
+                    .unwrap(),
+            );
+            series(&chunks)
+        };
+        assert_eq!(rolled_up("test:1m:max")[0][..2], [Some(59), None]);
+        assert_eq!(rolled_up("test:1m:sum")[0][..2], [Some(1770), None]);
+        assert_eq!(rolled_up("test:1m:count")[0][..2], [Some(60), None]);
+
+        // the window not rolled up yet is scanned from the table itself
+        let scan_rollup = |rollup: &str| {
+            let (_, chunks) = collect(
+                futures_lite::future::block_on(storage.scan_rollup(
+                    "test",
+                    rollup,
+                    None,
+                    &[],
+                    whole,
+                ))
+                .unwrap(),
+            );
+            series(&chunks)
+        };
+        let sum = scan_rollup("test:1m:sum");
+        assert_eq!(sum.len(), 2);
+        assert_eq!(sum[0][..1], [Some(1770)]);
+        assert_eq!(sum[1][..2], [Some(60), None]);
+        let count = scan_rollup("test:1m:count");
+        assert_eq!(count[0][..1], [Some(60)]);
+        assert_eq!(count[1][..2], [Some(1), None]);
+
+        // late samples are rolled up once compacted
+        write(30, "value2");
+        write(120, "value1");
+        futures_lite::future::block_on(storage.expire(Instant::now()));
+        let sum = rolled_up("test:1m:sum");
+        assert_eq!(sum.len(), 2);
+        assert_eq!(sum[0][..3], [Some(1770), Some(60), None]);
+        assert_eq!(sum[1][..2], [Some(30), None]);
+
+        // deletes are propagated to the rollups
+        let value2 = [Matcher {
+            name: String::from("label1"),
+            op: MatcherOp::LiteralEqual,
+            value: Some(LabelType::String(String::from("value2"))),
+        }];
+        let deleted =
+            futures_lite::future::block_on(storage.delete("test", &value2, whole)).unwrap();
+        assert_eq!(deleted, 1);
+        let sum = rolled_up("test:1m:sum");
+        assert_eq!(sum.len(), 1);
+        assert_eq!(sum[0][..2], [Some(1770), Some(60)]);
+
+        let now = Instant::now();
+        let step = Some(Duration::SECOND * 300u32);
+        assert_eq!(
+            context.pick_table("test", None, step, Alignment::Max, now),
+            "test:1m:max"
+        );
+        assert_eq!(
+            context.pick_table("test", None, None, Alignment::Max, now),
+            "test"
+        );
+        assert_eq!(
+            context.pick_table("test", None, step, Alignment::Min, now),
+            "test"
+        );
+        assert_eq!(
+            context.pick_table("test", None, Some(Duration::SECOND), Alignment::Max, now),
+            "test"
+        );
+    }
+
+    #[test]
+    fn storage_rollup_replay() {
+        let (wal_dir, dir) = (
+            TempDir::new("rollup-replay-wal"),
+            TempDir::new("rollup-replay"),
+        );
+        for data_dir in [None, Some(dir.to_path_buf())] {
+            let _ = fs::remove_dir_all(&wal_dir);
+            if let Some(data_dir) = &data_dir {
+                let _ = fs::remove_dir_all(data_dir);
+            }
+            let mut wal = WalOptions::new(wal_dir.to_path_buf());
+            wal.sync = SyncPolicy::Always;
+            let options = Options {
+                data_dir: data_dir.clone(),
+                wal: Some(wal),
+                restore: None,
+                expire_interval: None,
+                memory_limit: None,
+            };
+            let context = || {
+                let context = Arc::new(Context::new());
+                let mut meta = TableMeta {
+                    series_len: 60,
+                    mutable_chunk_num: 1,
+                    ..DEFAULT
+                };
+                meta.set("rollup", "1m:sum").unwrap();
+                context.declare_table("test", meta);
+                context
+            };
+            let storage = StorageServer::with_options(&[0], context(), options.clone());
+            let start_at = Instant::from_millis(0);
+            for second in 0..121u32 {
+                futures_lite::future::block_on(storage.inner_write(
+                    "test",
+                    vec![Label {
+                        name: "label1",
+                        value: LabelValue::String("value1"),
+                    }],
+                    vec![(
+                        start_at + Duration::SECOND * second,
+                        vec![Scalar {
+                            name: String::from("scalar1"),
+                            value: ScalarValue::Int(second as i64),
+                        }],
+                    )],
+                ))
+                .unwrap();
+            }
+            futures_lite::future::block_on(storage.flush());
+            drop(storage);
+
+            // replaying the log rolls the archived samples up again, exactly once
+            let restored = StorageServer::with_options(&[0], context(), options);
+            let (_, chunks) = collect(
+                futures_lite::future::block_on(restored.scan(
+                    "test:1m:sum",
+                    None,
+                    &[],
+                    Range {
+                        start: None,
+                        end: None,
+                    },
+                    None,
+                ))
+                .unwrap(),
+            );
+            assert_eq!(series(&chunks)[0][..3], [Some(1770), Some(5370), None]);
+        }
+    }
+
+    #[test]
+    fn storage_memory_limit() {
+        let dir = TempDir::new("memory-limit");
+        for data_dir in [None, Some(dir.to_path_buf())] {
+            let options = Options {
+                data_dir: data_dir.clone(),
+                wal: None,
+                memory_limit: Some(1),
+                ..Options::default()
+            };
+            let storage = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+            let start_at = Instant::from_millis(0);
+            let write = |minute: u32| {
+                futures_lite::future::block_on(storage.inner_write(
+                    "test",
+                    vec![Label {
+                        name: "label1",
+                        value: LabelValue::String("value1"),
+                    }],
+                    vec![(
+                        start_at + Duration::SECOND * (minute * 60),
+                        vec![Scalar {
+                            name: String::from("scalar1"),
+                            value: ScalarValue::Int(minute as i64),
+                        }],
+                    )],
+                ))
+            };
+            // a chunk of two minutes is archived early once the next one is written to
+            for minute in 0..3 {
+                write(minute).unwrap();
+            }
+            let metrics = storage.write_metrics();
+            match data_dir {
+                // nothing is left to relieve, writes go on over the limit
+                None => {
+                    for minute in 3..16 {
+                        write(minute).unwrap();
+                    }
+                    assert_eq!(metrics.rejected_memory(), 0);
+                    assert_eq!(metrics.relieved_chunks(), 7);
+                }
+                // writes are rejected until the archived chunk is written to its file
+                Some(_) => {
+                    assert!(matches!(
+                        write(3),
+                        Err(WriteError::MemoryLimitExceeded { limit: 1, .. })
+                    ));
+                    futures_lite::future::block_on(storage.flush());
+                    write(3).unwrap();
+                    assert_eq!(metrics.rejected_memory(), 1);
+                    assert_eq!(metrics.relieved_chunks(), 1);
+                }
+            }
+
+            let usage = futures_lite::future::block_on(storage.memory_usage());
+            assert_eq!(usage.shards.len(), 1);
+            assert_eq!(usage.tables.len(), 1);
+            assert_eq!(usage.tables[0].0, "test");
+            assert!(usage.tables[0].1.mutable > 0);
+            assert_eq!(usage.shards[0], usage.tables[0].1.total());
+            assert_eq!(metrics.memory_used(), usage.shards[0] as u64);
+        }
+    }
+
+    #[test]
+    fn storage_late_samples() {
+        let data_dir = TempDir::new("late-samples");
+        let options = Options {
+            data_dir: Some(data_dir.to_path_buf()),
+            wal: None,
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let context = Arc::new(Context::new());
+        let mut meta = DEFAULT;
+        meta.out_of_order = Duration::SECOND * (10 * 60u32);
+        context.declare_table("test", meta.clone());
+        let storage = StorageServer::with_options(&[0], Arc::clone(&context), options);
+        let start_at = Instant::from_millis(0);
+        let write = |timestamp: Instant, value: &'static str| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String(value),
+                }],
+                vec![(
+                    timestamp,
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(timestamp.as_millis() as f64),
+                    }],
+                )],
+            ))
+        };
+        let scan = || {
+            let (schema, chunks) = collect(
+                futures_lite::future::block_on(storage.scan(
+                    "test",
+                    None,
+                    &[],
+                    Range {
+                        start: None,
+                        end: Some(start_at + Duration::SECOND * 119u32),
+                    },
+                    None,
+                ))
+                .unwrap(),
+            );
+            chunks
+                .into_iter()
+                .map(|chunk| format!("{:?}", chunk.into_arrow_chunk(&schema).arrays()[2]))
+                .collect::<Vec<_>>()
+        };
+        for minute in 0..20u32 {
+            write(start_at + Duration::SECOND * (minute * 60), "value1").unwrap();
+        }
+        // mutable chunks start at the 10th minute, the first window is persisted already
+        write(start_at + Duration::SECOND * 30u32, "value1").unwrap();
+        write(start_at + Duration::SECOND * 90u32, "value2").unwrap();
+        assert_eq!(scan(), vec!["Utf8Array[value1, value2]"]);
+
+        assert!(matches!(
+            write(Instant::now() + Duration::SECOND * 7200u32, "value1"),
+            Err(WriteError::TimestampTooNew { .. })
+        ));
+        meta.out_of_order = Duration::SECOND * 60u32;
+        context.declare_table("test", meta);
+        assert!(matches!(
+            write(start_at, "value1"),
+            Err(WriteError::TimestampArchived { .. })
+        ));
+        let metrics = storage.write_metrics();
+        assert_eq!(metrics.late(), 2);
+        assert_eq!(metrics.rejected_future(), 1);
+        assert_eq!(metrics.rejected_archived(), 1);
+
+        // the late samples are compacted into the file of their window, which replaces the
+        // previous one once written
+        futures_lite::future::block_on(storage.expire(Instant::now()));
+        futures_lite::future::block_on(storage.flush());
+        let window_files = fs::read_dir(data_dir.join("test"))
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_str().unwrap().starts_with("0-0-")
+            })
+            .count();
+        assert_eq!(window_files, 1);
+        drop(storage);
+        let options = Options {
+            data_dir: Some(data_dir.to_path_buf()),
+            wal: None,
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let storage = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(storage.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: Some(start_at + Duration::SECOND * 119u32),
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        assert_eq!(chunks.len(), 1);
+        let chunk = chunks.into_iter().next().unwrap().into_arrow_chunk(&schema);
+        assert_eq!(
+            format!("{:?}", chunk.arrays()[2]),
+            "Utf8Array[value1, value2]"
+        );
+        let values = format!("{:?}", chunk.arrays()[3]);
+        assert!(values.starts_with("ListArray[[0, None"), "{}", values);
+        assert!(values.contains("30000"), "{}", values);
+    }
+
+    #[test]
+    fn storage_retention() {
+        let data_dir = TempDir::new("retention");
+        let options = Options {
+            data_dir: Some(data_dir.to_path_buf()),
+            wal: None,
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let context = Arc::new(Context::new());
+        let storage = StorageServer::with_options(&[0], Arc::clone(&context), options);
+        let start_at = Instant::from_millis(0);
+        let now = start_at + Duration::SECOND * (30 * 60u32);
+        let write = |minute: u32| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    start_at + Duration::SECOND * (minute * 60),
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(minute as f64),
+                    }],
+                )],
+            ))
+        };
+        for minute in 0..20u32 {
+            write(minute).unwrap();
+        }
+        futures_lite::future::block_on(storage.flush());
+        assert_eq!(fs::read_dir(data_dir.join("test")).unwrap().count(), 5);
+
+        // keeps samples since the 10th minute
+        let retained_from = start_at + Duration::SECOND * (10 * 60u32);
+        context.set_retention("test", Some(now - retained_from));
+        futures_lite::future::block_on(storage.expire(now));
+
+        assert_eq!(fs::read_dir(data_dir.join("test")).unwrap().count(), 0);
+        let (_, chunks) = collect(
+            futures_lite::future::block_on(storage.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|chunk| chunk.start_at >= retained_from));
+        assert!(matches!(write(5), Err(WriteError::TimestampExpired { .. })));
+
+        // extending the retention accepts those samples again
+        context.set_retention("test", Some(now - start_at));
+        futures_lite::future::block_on(storage.expire(now));
+        assert!(!matches!(
+            write(5),
+            Err(WriteError::TimestampExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn storage_wal_replay() {
+        let wal_dir = TempDir::new("wal-replay");
+        let mut wal = WalOptions::new(wal_dir.to_path_buf());
+        wal.sync = SyncPolicy::Always;
+        let options = Options {
+            data_dir: None,
+            wal: Some(wal),
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let storage = StorageServer::with_options(&[0], Arc::new(Context::new()), options.clone());
+        // within a single chunk window
+        let start_at = Instant::from_millis(0);
+        for id in 0..3 {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    start_at + Duration::SECOND * id as u32,
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Int(id),
+                    }],
+                )],
+            ))
+            .unwrap();
+        }
+
+        let restored = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(restored.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        let rows = chunks
+            .into_iter()
+            .map(|chunk| chunk.into_arrow_chunk(&schema).len())
+            .sum::<usize>();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn storage_wal_truncate() {
+        let root = TempDir::new("wal-truncate");
+        let segments = || {
+            fs::read_dir(root.join("wal").join("0"))
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "wal")
+                .count()
+        };
+        let rows = |storage: &StorageServer| {
+            let (schema, chunks) = collect(
+                futures_lite::future::block_on(storage.scan(
+                    "test",
+                    None,
+                    &[],
+                    Range {
+                        start: None,
+                        end: None,
+                    },
+                    None,
+                ))
+                .unwrap(),
+            );
+            chunks
+                .into_iter()
+                .map(|chunk| chunk.into_arrow_chunk(&schema).len())
+                .sum::<usize>()
+        };
+        for data_dir in [None, Some(root.join("data"))] {
+            let mut wal = WalOptions::new(root.join("wal"));
+            // every write starts a segment
+            wal.segment_size = 1;
+            let options = Options {
+                data_dir: data_dir.clone(),
+                wal: Some(wal),
+                restore: None,
+                expire_interval: None,
+                memory_limit: None,
+            };
+            let context = Arc::new(Context::new());
+            let storage = StorageServer::with_options(&[0], Arc::clone(&context), options.clone());
+            for minute in 0..20u32 {
+                futures_lite::future::block_on(storage.inner_write(
+                    "test",
+                    vec![Label {
+                        name: "label1",
+                        value: LabelValue::String("value1"),
+                    }],
+                    vec![(
+                        Instant::from_millis(0) + Duration::SECOND * (minute * 60),
+                        vec![Scalar {
+                            name: String::from("scalar1"),
+                            value: ScalarValue::Float(minute as f64),
+                        }],
+                    )],
+                ))
+                .unwrap();
+            }
+            futures_lite::future::block_on(storage.flush());
+            // segments are only removed once their samples are in files, or expired
+            let rows_left = match data_dir {
+                None => {
+                    assert_eq!(segments(), 20);
+                    let now = Instant::from_millis(0) + Duration::SECOND * (30 * 60u32);
+                    context.set_retention("test", Some(Duration::SECOND * (20 * 60u32)));
+                    futures_lite::future::block_on(storage.expire(now));
+                    assert_eq!(segments(), 10);
+                    5
+                }
+                Some(_) => {
+                    assert!(segments() < 20);
+                    10
+                }
+            };
+            drop(storage);
+
+            // one series row a chunk window
+            let restored = StorageServer::with_options(&[0], Arc::new(Context::new()), options);
+            assert_eq!(rows(&restored), rows_left);
+            drop(restored);
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    #[test]
+    fn storage_snapshot_restore() {
+        let root = TempDir::new("snapshot-restore");
+        let options = Options {
+            data_dir: None,
+            wal: Some(WalOptions::new(root.join("wal"))),
+            restore: None,
+            expire_interval: None,
+            memory_limit: None,
+        };
+        let write = |storage: &StorageServer, minute: u32| {
+            futures_lite::future::block_on(storage.inner_write(
+                "test",
+                vec![Label {
+                    name: "label1",
+                    value: LabelValue::String("value1"),
+                }],
+                vec![(
+                    Instant::from_millis(0) + Duration::SECOND * (minute * 60),
+                    vec![Scalar {
+                        name: String::from("scalar1"),
+                        value: ScalarValue::Float(minute as f64),
+                    }],
+                )],
+            ))
+            .unwrap();
+        };
+        let storage = StorageServer::with_options(&[0], Arc::new(Context::new()), options.clone());
+        for minute in 0..20 {
+            write(&storage, minute);
+        }
+        futures_lite::future::block_on(storage.snapshot(root.join("snapshot"))).unwrap();
+        // logged after the snapshot, restored by replaying the log
+        write(&storage, 20);
+
+        let restored = StorageServer::with_options(
+            &[0],
+            Arc::new(Context::new()),
+            Options {
+                restore: Some(root.join("snapshot")),
+                ..options
+            },
+        );
+        let (schema, chunks) = collect(
+            futures_lite::future::block_on(restored.scan(
+                "test",
+                None,
+                &[],
+                Range {
+                    start: None,
+                    end: None,
+                },
+                None,
+            ))
+            .unwrap(),
+        );
+        assert_eq!(chunks.len(), 11);
+        for chunk in chunks {
+            let chunk = chunk.into_arrow_chunk(&schema);
+            assert_eq!(chunk.len(), 1);
+        }
     }
 }
@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of written samples, shared by every shard.
+#[derive(Debug, Default)]
+pub struct WriteMetrics {
+    late: AtomicU64,
+    rejected_archived: AtomicU64,
+    rejected_expired: AtomicU64,
+    rejected_future: AtomicU64,
+    rejected_series: AtomicU64,
+    rejected_label_values: AtomicU64,
+    rejected_memory: AtomicU64,
+    relieved_chunks: AtomicU64,
+    memory_used: AtomicU64,
+}
+
+impl WriteMetrics {
+    /// Late samples accepted into windows that were archived already.
+    pub fn late(&self) -> u64 {
+        self.late.load(Ordering::Relaxed)
+    }
+
+    /// Samples rejected for being older than the out-of-order window.
+    pub fn rejected_archived(&self) -> u64 {
+        self.rejected_archived.load(Ordering::Relaxed)
+    }
+
+    /// Samples rejected for being out of retention.
+    pub fn rejected_expired(&self) -> u64 {
+        self.rejected_expired.load(Ordering::Relaxed)
+    }
+
+    /// Samples rejected for being too far ahead of the wall clock.
+    pub fn rejected_future(&self) -> u64 {
+        self.rejected_future.load(Ordering::Relaxed)
+    }
+
+    /// Writes of new series rejected for exceeding the series limit of their table.
+    pub fn rejected_series(&self) -> u64 {
+        self.rejected_series.load(Ordering::Relaxed)
+    }
+
+    /// Writes of new series rejected for exceeding the label value limit of their table.
+    pub fn rejected_label_values(&self) -> u64 {
+        self.rejected_label_values.load(Ordering::Relaxed)
+    }
+
+    /// Writes rejected for their shard being over its share of the memory limit.
+    pub fn rejected_memory(&self) -> u64 {
+        self.rejected_memory.load(Ordering::Relaxed)
+    }
+
+    /// Mutable chunks archived early to get back under the memory limit.
+    pub fn relieved_chunks(&self) -> u64 {
+        self.relieved_chunks.load(Ordering::Relaxed)
+    }
+
+    /// Heap usage of every shard in bytes, kept up to date as samples are written and chunks
+    /// archived or dropped.
+    pub fn memory_used(&self) -> u64 {
+        self.memory_used.load(Ordering::Relaxed)
+    }
+
+    /// Every counter by name, with the memory gauge.
+    pub fn counters(&self) -> [(&'static str, u64); 9] {
+        [
+            ("late", self.late()),
+            ("rejected_archived", self.rejected_archived()),
+            ("rejected_expired", self.rejected_expired()),
+            ("rejected_future", self.rejected_future()),
+            ("rejected_series", self.rejected_series()),
+            ("rejected_label_values", self.rejected_label_values()),
+            ("rejected_memory", self.rejected_memory()),
+            ("relieved_chunks", self.relieved_chunks()),
+            ("memory_used", self.memory_used()),
+        ]
+    }
+
+    #[inline]
+    pub(crate) fn add_late(&self) {
+        self.late.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_archived(&self) {
+        self.rejected_archived.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_expired(&self) {
+        self.rejected_expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_future(&self) {
+        self.rejected_future.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_series(&self) {
+        self.rejected_series.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_label_values(&self) {
+        self.rejected_label_values.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_rejected_memory(&self) {
+        self.rejected_memory.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_relieved_chunks(&self, n: usize) {
+        self.relieved_chunks.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Moves the memory gauge by how much the usage of a shard changed from `from` to `to`.
+    #[inline]
+    pub(crate) fn update_memory_used(&self, from: usize, to: usize) {
+        if to >= from {
+            self.memory_used
+                .fetch_add((to - from) as u64, Ordering::Relaxed);
+        } else {
+            self.memory_used
+                .fetch_sub((from - to) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Heap usage of a table in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TableMemory {
+    /// Mutable and late chunks.
+    pub mutable: usize,
+    /// Archived chunks kept in memory.
+    pub archived: usize,
+}
+
+impl TableMemory {
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.mutable + self.archived
+    }
+}
+
+/// Heap usage of the storage engine, as reported by `StorageServer::memory_usage`.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryUsage {
+    /// Bytes used by each shard.
+    pub shards: Vec<usize>,
+    /// Usage of every table summed over shards, sorted by table name.
+    pub tables: Vec<(String, TableMemory)>,
+}
@@ -0,0 +1,132 @@
+use crate::chunk::MutableChunk;
+use crate::error::SnapshotError;
+use crate::util::codec::{Decoder, Encoder};
+use context::Schema;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"T0SNAPSHOT";
+const VERSION: u32 = 2;
+
+/// The state of a shard read back from its snapshot file.
+#[derive(Debug)]
+pub(crate) struct ShardSnapshot {
+    pub(crate) id: usize,
+    pub(crate) shard_num: usize,
+    /// The first write-ahead log segment written after the snapshot was taken.
+    pub(crate) wal_seq: Option<u64>,
+    pub(crate) tables: Vec<TableSnapshot>,
+}
+
+#[derive(Debug)]
+pub(crate) struct TableSnapshot {
+    pub(crate) name: String,
+    pub(crate) schema: Schema,
+    /// Archived chunk files, relative to the table directory of the snapshot.
+    pub(crate) files: Vec<String>,
+    pub(crate) mutable_chunks: Vec<MutableChunk>,
+    /// Late samples of archived windows, missing in snapshots of version 1.
+    pub(crate) late_chunks: Vec<MutableChunk>,
+}
+
+#[inline]
+pub(crate) fn path(dir: &Path, shard_id: usize) -> PathBuf {
+    dir.join(format!("{}.snapshot", shard_id))
+}
+
+/// Writes a shard snapshot `body`, encoded by `Shard::snapshot`, with a versioned header and
+/// a trailing checksum. The file is replaced atomically.
+pub(crate) fn write(path: &Path, body: &[u8]) -> Result<(), SnapshotError> {
+    let mut encoder = Encoder::new();
+    encoder.put_bytes(MAGIC);
+    encoder.put_u32(VERSION);
+    encoder.put_bytes(body);
+    encoder.put_u32(crc32fast::hash(body));
+
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp).map_err(io_error)?;
+    file.write_all(&encoder.into_inner()).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    fs::rename(&tmp, path).map_err(io_error)
+}
+
+pub(crate) fn read(path: &Path) -> Result<ShardSnapshot, SnapshotError> {
+    let invalid = |reason: &str| SnapshotError::Invalid {
+        path: path.to_path_buf(),
+        reason: reason.to_owned(),
+    };
+    let buf = fs::read(path).map_err(io_error)?;
+    let mut decoder = Decoder::new(&buf);
+    if decoder.bytes() != Some(MAGIC) {
+        return Err(invalid("not a snapshot file"));
+    }
+    let version = match decoder.u32() {
+        Some(version @ 1..=VERSION) => version,
+        Some(version) => return Err(invalid(&format!("unsupported version {}", version))),
+        None => return Err(invalid("truncated")),
+    };
+    let body = decoder.bytes().ok_or_else(|| invalid("truncated"))?;
+    if decoder.u32() != Some(crc32fast::hash(body)) {
+        return Err(invalid("checksum mismatch"));
+    }
+    let mut decoder = Decoder::new(body);
+    match decode(&mut decoder, version) {
+        Some(snapshot) if decoder.is_empty() => Ok(snapshot),
+        _ => Err(invalid("corrupted")),
+    }
+}
+
+fn decode(decoder: &mut Decoder, version: u32) -> Option<ShardSnapshot> {
+    let id = decoder.u32()? as usize;
+    let shard_num = decoder.u32()? as usize;
+    let wal_seq = match decoder.u8()? {
+        0 => None,
+        _ => Some(decoder.u64()?),
+    };
+    let table_num = decoder.u32()?;
+    let mut tables = Vec::with_capacity(table_num as usize);
+    for _ in 0..table_num {
+        let name = decoder.string()?;
+        let schema = Schema::decode(decoder.str()?)?;
+        let file_num = decoder.u32()?;
+        let mut files = Vec::with_capacity(file_num as usize);
+        for _ in 0..file_num {
+            files.push(decoder.string()?);
+        }
+        let mutable_chunks = decode_chunks(decoder)?;
+        let late_chunks = if version > 1 {
+            decode_chunks(decoder)?
+        } else {
+            Vec::new()
+        };
+        tables.push(TableSnapshot {
+            name,
+            schema,
+            files,
+            mutable_chunks,
+            late_chunks,
+        });
+    }
+    Some(ShardSnapshot {
+        id,
+        shard_num,
+        wal_seq,
+        tables,
+    })
+}
+
+fn decode_chunks(decoder: &mut Decoder) -> Option<Vec<MutableChunk>> {
+    let chunk_num = decoder.u32()?;
+    let mut chunks = Vec::with_capacity(chunk_num as usize);
+    for _ in 0..chunk_num {
+        chunks.push(MutableChunk::decode(decoder)?);
+    }
+    Some(chunks)
+}
+
+pub(crate) fn io_error(err: impl ToString) -> SnapshotError {
+    SnapshotError::Io {
+        err: err.to_string(),
+    }
+}
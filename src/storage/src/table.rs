@@ -1,119 +1,958 @@
-use crate::chunk::{MutableChunk, ScanChunk};
+use crate::chunk::{limit_chunks, Info, MutableChunk, ScanChunk, ScanSender};
+use crate::column::Filter;
 use crate::error::{ScanError, WriteError};
+use crate::file::FileChunk;
+use crate::immutable::ImmutableChunk;
+use crate::metrics::{TableMemory, WriteMetrics};
+use crate::util::codec::Encoder;
+use arrow2::error::Result as ArrowResult;
+use async_channel::Sender;
 use common::time::{Instant, EPOCH};
-use common::{Label, Scalar};
-use context::Schema;
-use ql::rosetta::{MatcherRef, Range};
+use common::{Label, LabelValue, Scalar};
+use context::{Alignment, Schema};
+use ql::rosetta::Range;
+use runtime::unblock;
 use std::collections::VecDeque;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tracing::error;
+
+/// The outcome of work a table did off its shard, handled by the shard.
+#[derive(Debug)]
+pub(crate) enum TableEvent {
+    Persisted {
+        table_name: Arc<str>,
+        id: u64,
+        file: ArrowResult<FileChunk>,
+    },
+    Rewritten {
+        table_name: Arc<str>,
+        rewritten: Result<Rewritten, ScanError>,
+    },
+}
 
 #[derive(Debug)]
 pub(crate) struct Table {
     name: Arc<str>,
     mutable_chunks: VecDeque<MutableChunk>,
+    archived_chunks: VecDeque<ArchivedChunk>,
+    /// Late samples of archived windows, sorted by window and merged into the archived chunks
+    /// on scan, until they are compacted into them.
+    late_chunks: VecDeque<MutableChunk>,
+    /// Samples before this have been dropped by retention.
+    retained_from: Option<Instant>,
+    /// Samples moved out of the mutable and late chunks since they were last taken, archived,
+    /// compacted or expired, left to be rolled up into the rollup tables.
+    rollup_chunks: Vec<ScanChunk>,
+    /// The id of the next archived chunk.
+    next_id: u64,
+    /// How many files are being written.
+    writing: usize,
+    events: Sender<TableEvent>,
 
     schema: Arc<Schema>,
+    dir: Option<PathBuf>,
+    shard_id: usize,
+}
+
+/// A scan of a table, with its archived chunks left to scan together with the late samples of
+/// their windows.
+#[derive(Debug)]
+pub(crate) struct TableScan {
+    archived: Vec<(ArchivedChunk, Option<ScanChunk>)>,
+    scanned: Vec<ScanChunk>,
+    alignment: Alignment,
+    /// Whether slots are sent as how many samples they hold, see [`ScanChunk::counted`].
+    counted: bool,
+}
+
+impl TableScan {
+    /// Sends the chunks as a count rollup of the table would hold them, to be scanned along
+    /// one.
+    pub(crate) fn counted(mut self) -> Self {
+        self.counted = true;
+        self
+    }
+
+    /// Scans the archived chunks one after another, sending each to `ret` as soon as it is
+    /// scanned, then the chunks scanned already. Stops early once `ret` is closed. With a
+    /// `limit`, the chunks are collected first to keep only the first series of the table.
+    pub(crate) async fn send(
+        self,
+        projections: Option<&[String]>,
+        filters: &[Filter],
+        range: Range,
+        limit: Option<usize>,
+        ret: &ScanSender,
+    ) -> Result<(), ScanError> {
+        let counted = self.counted;
+        let convert = |chunk: ScanChunk| if counted { chunk.counted() } else { chunk };
+        let mut chunks = Vec::new();
+        for (chunk, late) in self.archived {
+            let scanned = match (chunk.scan(projections, filters, range, limit).await?, late) {
+                (Some(scanned), Some(late)) => Some(scanned.merge(late, self.alignment)),
+                (scanned, late) => scanned.or(late),
+            };
+            let scanned = scanned.map(convert);
+            match (scanned, limit) {
+                (None, _) => {}
+                (Some(scanned), Some(_)) => chunks.push(scanned),
+                (Some(scanned), None) => {
+                    if ret.send(Ok(scanned)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        chunks.extend(self.scanned.into_iter().map(convert));
+        for chunk in limit_chunks(chunks, limit) {
+            if ret.send(Ok(chunk)).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The archived chunks a delete may delete from, rewritten without the deleted samples off the
+/// shard, see [`Table::rewritten`].
+#[derive(Debug)]
+pub(crate) struct Rewrite {
+    chunks: Vec<ArchivedChunk>,
+    filters: Vec<Filter>,
+    range: Range,
+}
+
+#[derive(Debug)]
+pub(crate) struct Rewritten {
+    /// The chunks with samples deleted, by the id of the chunk they were rewritten from.
+    chunks: Vec<(u64, ImmutableChunk)>,
+    deleted: u64,
+}
+
+impl Rewrite {
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Rewrites only the chunks with samples to delete.
+    pub(crate) async fn run(self) -> Result<Rewritten, ScanError> {
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        let mut rewritten = Rewritten {
+            chunks: Vec::new(),
+            deleted: 0,
+        };
+        for chunk in &self.chunks {
+            match chunk.scan(None, &self.filters, self.range, None).await? {
+                Some(matched) if matched.len() > 0 => {}
+                _ => continue,
+            }
+            let scanned = match chunk.scan(None, &[], whole, None).await? {
+                None => continue,
+                Some(scanned) => scanned,
+            };
+            let info = chunk.info().clone();
+            let (left, deleted) = match scanned.delete(&self.filters, info.range_offset(self.range))
+            {
+                None => continue,
+                Some(left) => left,
+            };
+            rewritten.deleted += deleted;
+            rewritten
+                .chunks
+                .push((chunk.id, ImmutableChunk::from_scan(info, left)));
+        }
+        Ok(rewritten)
+    }
+}
+
+/// The file of an archived chunk, written on the blocking pool once the chunk is archived.
+#[derive(Debug, Clone)]
+enum ChunkFile {
+    /// Not to be written, the table has no directory or the chunk no record.
+    None,
+    Writing,
+    Written(Arc<FileChunk>),
+    /// Writing failed, it is retried on the next compaction.
+    Failed,
+}
+
+/// An archived chunk window, kept in memory until it has been persisted and is evicted.
+#[derive(Debug, Clone)]
+struct ArchivedChunk {
+    id: u64,
+    info: Info,
+    memory: Option<Arc<ImmutableChunk>>,
+    file: ChunkFile,
+    /// The file of the chunk this one replaced, kept until the file of this one is written.
+    replaced: Option<Arc<FileChunk>>,
+}
+
+impl ArchivedChunk {
+    #[inline]
+    fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Whether the samples of the chunk are in a file, or there is none.
+    #[inline]
+    fn is_persisted(&self) -> bool {
+        match self.file {
+            ChunkFile::Written(_) => true,
+            ChunkFile::None => !matches!(&self.memory, Some(chunk) if chunk.record_num() > 0),
+            ChunkFile::Writing | ChunkFile::Failed => false,
+        }
+    }
+
+    async fn scan(
+        &self,
+        projections: Option<&[String]>,
+        filters: &[Filter],
+        range: Range,
+        limit: Option<usize>,
+    ) -> Result<Option<ScanChunk>, ScanError> {
+        match (&self.memory, &self.file) {
+            (Some(chunk), _) => chunk.scan(projections, filters, range, limit).await,
+            (None, ChunkFile::Written(file)) => file.scan(projections, filters, range, limit).await,
+            // evicted from memory only once written, so there are no samples left to scan
+            (None, _) => Ok(None),
+        }
+    }
 }
 
 impl Table {
-    pub(crate) fn new(name: Arc<str>, schema: Arc<Schema>) -> Self {
-        let mutable_chunks = VecDeque::new();
+    pub(crate) fn new(
+        name: Arc<str>,
+        schema: Arc<Schema>,
+        dir: Option<PathBuf>,
+        shard_id: usize,
+        events: Sender<TableEvent>,
+    ) -> Self {
         Self {
             name,
-            mutable_chunks,
+            mutable_chunks: VecDeque::new(),
+            archived_chunks: VecDeque::new(),
+            late_chunks: VecDeque::new(),
+            retained_from: None,
+            rollup_chunks: Vec::new(),
+            next_id: 0,
+            writing: 0,
+            events,
             schema,
+            dir,
+            shard_id,
+        }
+    }
+
+    /// Adds archived chunks loaded from files, which must be older than the mutable chunks
+    /// of this table.
+    pub(crate) fn restore(&mut self, chunks: Vec<FileChunk>) {
+        for chunk in chunks {
+            let id = self.next_id();
+            self.archived_chunks.push_back(ArchivedChunk {
+                id,
+                info: chunk.info.clone(),
+                memory: None,
+                file: ChunkFile::Written(Arc::new(chunk)),
+                replaced: None,
+            });
+        }
+        self.archived_chunks
+            .make_contiguous()
+            .sort_by_key(|chunk| chunk.info().start_at.as_millis());
+    }
+
+    /// Adds the mutable and late chunks of a snapshot, skipping the windows that were archived
+    /// or compacted since.
+    pub(crate) fn restore_mutable(
+        &mut self,
+        chunks: Vec<MutableChunk>,
+        late_chunks: Vec<MutableChunk>,
+    ) {
+        for chunk in chunks {
+            if !self.is_archived(chunk.info.start_at) {
+                self.mutable_chunks.push_back(chunk);
+            }
+        }
+        for chunk in late_chunks {
+            if self.is_open(&chunk.info) {
+                self.late_chunks.push_back(chunk);
+            }
         }
     }
 
+    /// Encodes the table for a snapshot into `encoder`. Archived chunks are linked, or
+    /// written if they are only kept in memory, as files into the table directory in `dir`.
+    pub(crate) fn snapshot(&self, dir: &Path, encoder: &mut Encoder) -> ArrowResult<()> {
+        let dir = dir.join(self.name.as_ref());
+        fs::create_dir_all(&dir)?;
+        let mut files = Vec::with_capacity(self.archived_chunks.len());
+        for chunk in &self.archived_chunks {
+            let path = match (&chunk.file, &chunk.memory) {
+                (ChunkFile::Written(file), _) => {
+                    let path = dir.join(file.path().file_name().unwrap());
+                    if fs::hard_link(file.path(), &path).is_err() {
+                        fs::copy(file.path(), &path)?;
+                    }
+                    path
+                }
+                (_, Some(chunk)) => {
+                    if chunk.record_num() == 0 {
+                        continue;
+                    }
+                    FileChunk::create(&dir, self.shard_id, chunk, &self.schema)?
+                        .path()
+                        .to_path_buf()
+                }
+                (_, None) => unreachable!(),
+            };
+            files.push(path);
+        }
+
+        encoder.put_str(&self.name);
+        encoder.put_str(&self.schema.encode());
+        encoder.put_u32(files.len() as u32);
+        for path in files {
+            encoder.put_str(path.file_name().unwrap().to_str().unwrap());
+        }
+        for chunks in [&self.mutable_chunks, &self.late_chunks] {
+            encoder.put_u32(chunks.len() as u32);
+            for chunk in chunks {
+                chunk.encode(encoder);
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches to a newer version of the table schema.
+    pub(crate) fn update_schema(&mut self, schema: Arc<Schema>) {
+        if schema.version <= self.schema.version {
+            return;
+        }
+        let relayout = !schema.meta.same_layout(&self.schema.meta);
+        self.schema = schema;
+        // chunks of the new layout are created from the next write on
+        if relayout {
+            while let Some(chunk) = self.mutable_chunks.pop_front() {
+                self.archive(chunk);
+            }
+        }
+    }
+
+    /// Writes the samples of a series. Samples that are rejected are counted and skipped, the
+    /// error of the first one is returned once the others are written. `max_series` is the
+    /// share of this shard of the series limit of the table.
     pub(crate) fn write(
         &mut self,
+        schema: Arc<Schema>,
         labels: &[Label],
         scalars: &[(Instant, Vec<Scalar>)],
+        max_series: Option<u32>,
+        metrics: &WriteMetrics,
     ) -> Result<(), WriteError> {
+        self.update_schema(schema);
+        let schema = Arc::clone(&self.schema);
+        let table_name = Arc::clone(&self.name);
+        let now = Instant::now();
+        let mut result = Ok(());
+        let mut limited = false;
         for (timestamp, scalars) in scalars {
-            let chunk = self.lookup_mutable_chunk(*timestamp)?;
+            let chunk = match self.lookup_chunk(*timestamp, now, metrics) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                    continue;
+                }
+            };
+            chunk.evolve(&schema);
             let aligned_labels = chunk.align_labels(labels);
-            let mut row = match chunk.get_mut(&aligned_labels)? {
+            let row = match chunk.get_mut(&aligned_labels) {
+                Ok(row) => row,
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                    continue;
+                }
+            };
+            let mut row = match row {
                 Some(row) => row,
-                None => chunk.push(&aligned_labels),
+                None => {
+                    let checked = check_cardinality(
+                        chunk,
+                        &aligned_labels,
+                        max_series,
+                        schema.meta.max_label_values,
+                        &table_name,
+                    );
+                    if let Err(err) = checked {
+                        // counted once per series
+                        if !limited {
+                            match err {
+                                WriteError::SeriesLimitExceeded { .. } => {
+                                    metrics.add_rejected_series()
+                                }
+                                _ => metrics.add_rejected_label_values(),
+                            }
+                            limited = true;
+                        }
+                        if result.is_ok() {
+                            result = Err(err);
+                        }
+                        continue;
+                    }
+                    chunk.push(&aligned_labels)
+                }
             };
             row.insert(*timestamp, scalars);
         }
-        Ok(())
+        result
+    }
+
+    /// Takes the chunks archived, compacted or expired since the last call, to be rolled up.
+    pub(crate) fn take_rollup_chunks(&mut self) -> Vec<ScanChunk> {
+        mem::take(&mut self.rollup_chunks)
+    }
+
+    /// Keeps the samples of `chunk` to be rolled up, if the table has rollups.
+    fn roll_up(&mut self, chunk: &MutableChunk) {
+        if !self.schema.meta.rollups.is_empty() && chunk.series_num() > 0 {
+            self.rollup_chunks.push(chunk.scan_all());
+        }
+    }
+
+    pub(crate) fn memory(&self) -> TableMemory {
+        TableMemory {
+            mutable: self
+                .mutable_chunks
+                .iter()
+                .chain(self.late_chunks.iter())
+                .map(MutableChunk::heap_size)
+                .sum(),
+            archived: self
+                .archived_chunks
+                .iter()
+                .filter_map(|chunk| chunk.memory.as_deref())
+                .map(ImmutableChunk::heap_size)
+                .sum(),
+        }
+    }
+
+    /// Frees memory under pressure, archiving every mutable chunk but the newest one and
+    /// dropping the memory copies of every persisted archived chunk. Returns how many chunks
+    /// were archived.
+    pub(crate) fn relieve(&mut self) -> usize {
+        let mut archived = 0;
+        while self.mutable_chunks.len() > 1 {
+            let chunk = self.mutable_chunks.pop_front().unwrap();
+            self.archive(chunk);
+            archived += 1;
+        }
+        for chunk in self.archived_chunks.iter_mut() {
+            if let ChunkFile::Written(_) = chunk.file {
+                chunk.memory = None;
+            }
+        }
+        archived
+    }
+
+    /// Whether samples from `from` to `to` have all been archived and written to files, or
+    /// expired, in every window in between.
+    pub(crate) fn is_persisted(&self, from: Instant, to: Instant) -> bool {
+        if self.is_expired(to) {
+            return true;
+        }
+        let within = |info: &Info| info.start_at <= to && info.window_end() > from;
+        self.is_archived(to)
+            && !self.late_chunks.iter().any(|chunk| within(&chunk.info))
+            && self
+                .archived_chunks
+                .iter()
+                .filter(|chunk| within(chunk.info()))
+                .all(ArchivedChunk::is_persisted)
+    }
+
+    /// Whether samples at `timestamp` have been dropped by retention.
+    #[inline]
+    pub(crate) fn is_expired(&self, timestamp: Instant) -> bool {
+        matches!(self.retained_from, Some(retained_from) if timestamp < retained_from)
+    }
+
+    /// Whether archived chunks overlapping `range` replace others whose files are kept until
+    /// theirs are written.
+    pub(crate) fn is_replacing(&self, range: Range) -> bool {
+        self.archived_chunks
+            .iter()
+            .any(|chunk| chunk.replaced.is_some() && chunk.info().overlaps(range))
     }
 
+    /// Whether samples at `timestamp` have been moved out of the mutable and late chunks.
+    pub(crate) fn is_archived(&self, timestamp: Instant) -> bool {
+        if matches!(self.retained_from, Some(retained_from) if timestamp < retained_from) {
+            return true;
+        }
+        match self.mutable_from() {
+            Some(mutable_from) if timestamp < mutable_from => !self
+                .late_chunks
+                .iter()
+                .any(|chunk| chunk.info.contains(timestamp)),
+            _ => false,
+        }
+    }
+
+    /// Whether samples at `timestamp` are late, but still within the out-of-order window.
+    pub(crate) fn accepts_late(&self, timestamp: Instant) -> bool {
+        match (self.late_from(), self.mutable_from()) {
+            (Some(late_from), Some(mutable_from)) => {
+                late_from <= timestamp && timestamp < mutable_from
+            }
+            _ => false,
+        }
+    }
+
+    /// The first instant late samples are accepted from.
+    fn late_from(&self) -> Option<Instant> {
+        let late_from = self.mutable_from()? - self.schema.meta.out_of_order;
+        Some(match self.retained_from {
+            Some(retained_from) if retained_from > late_from => retained_from,
+            _ => late_from,
+        })
+    }
+
+    /// Whether late samples are still accepted into some slot of window `info`.
+    fn is_open(&self, info: &Info) -> bool {
+        matches!(self.late_from(), Some(late_from) if info.window_end() > late_from)
+    }
+
+    /// The first instant that has not been archived.
+    pub(crate) fn mutable_from(&self) -> Option<Instant> {
+        match self.mutable_chunks.front() {
+            Some(chunk) => Some(chunk.info.start_at),
+            None => self
+                .archived_chunks
+                .back()
+                .map(|chunk| chunk.info().end_at() + chunk.info().time_interval),
+        }
+    }
+
+    /// Drops the chunks, in memory or on disk, whose samples are all before `retained_from`,
+    /// none if the table has no retention. Returns how many chunks were dropped.
+    pub(crate) fn expire(&mut self, retained_from: Option<Instant>) -> usize {
+        // an extended retention takes effect, even though dropped chunks are not restored
+        self.retained_from = retained_from;
+        let retained_from = match retained_from {
+            None => return 0,
+            Some(retained_from) => retained_from,
+        };
+        let mut expired = 0;
+        while let Some(chunk) = self.archived_chunks.front() {
+            if chunk.info().end_at() >= retained_from {
+                break;
+            }
+            let chunk = self.archived_chunks.pop_front().unwrap();
+            if let ChunkFile::Written(file) = &chunk.file {
+                file.remove();
+            }
+            if let Some(file) = &chunk.replaced {
+                file.remove();
+            }
+            expired += 1;
+        }
+        for chunks in [&mut self.late_chunks, &mut self.mutable_chunks] {
+            while let Some(chunk) = chunks.front() {
+                if chunk.info.end_at() >= retained_from {
+                    break;
+                }
+                let chunk = chunks.pop_front().unwrap();
+                // rollups may be kept longer
+                if !self.schema.meta.rollups.is_empty() && chunk.series_num() > 0 {
+                    self.rollup_chunks.push(chunk.scan_all());
+                }
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    /// Folds the late chunks of the windows that left the out-of-order window into their
+    /// archived chunks, rewriting their files. Returns how many windows were compacted.
+    pub(crate) async fn compact(&mut self) -> Result<usize, ScanError> {
+        self.retry_persist();
+        let mut compacted = 0;
+        while let Some(late) = self.late_chunks.front() {
+            if self.is_open(&late.info) {
+                break;
+            }
+            let whole = Range {
+                start: None,
+                end: None,
+            };
+            let late_scanned = late.scan(None, &[], whole, None).await?;
+            let position = self
+                .archived_chunks
+                .iter()
+                .position(|chunk| chunk.info().same_window(&late.info));
+            let merged = match position {
+                None => late_scanned,
+                Some(position) => {
+                    let archived = &self.archived_chunks[position];
+                    match (archived.scan(None, &[], whole, None).await?, late_scanned) {
+                        (Some(archived), Some(late)) => {
+                            Some(archived.merge(late, self.schema.meta.alignment))
+                        }
+                        (archived, late) => archived.or(late),
+                    }
+                }
+            };
+            let late = self.late_chunks.pop_front().unwrap();
+            // the window was rolled up without its late samples
+            self.roll_up(&late);
+            compacted += 1;
+            let merged = match merged {
+                None => continue,
+                Some(merged) => ImmutableChunk::from_scan(late.info, merged),
+            };
+            let chunk = self.persist(merged);
+            match position {
+                None => {
+                    let position = self
+                        .archived_chunks
+                        .iter()
+                        .position(|archived| archived.info().start_at > chunk.info().start_at)
+                        .unwrap_or(self.archived_chunks.len());
+                    self.archived_chunks.insert(position, chunk);
+                }
+                Some(position) => self.replace(position, chunk),
+            }
+        }
+        if compacted > 0 {
+            self.evict();
+        }
+        Ok(compacted)
+    }
+
+    /// Deletes the samples within `range` of the series matching `filters` from the mutable and
+    /// late chunks in place, returning how many were deleted and the [`Rewrite`] of the archived
+    /// chunks left to delete from.
+    pub(crate) fn delete(&mut self, filters: &[Filter], range: Range) -> (u64, Rewrite) {
+        let deleted = self.delete_mutable(filters, range);
+        let chunks = self
+            .archived_chunks
+            .iter()
+            .filter(|chunk| chunk.info().overlaps(range))
+            .cloned()
+            .collect();
+        let rewrite = Rewrite {
+            chunks,
+            filters: filters.to_vec(),
+            range,
+        };
+        (deleted, rewrite)
+    }
+
+    /// Takes the archived chunks rewritten by a delete in place of the ones they were rewritten
+    /// from, unless those expired in the meantime. Returns how many samples were deleted.
+    pub(crate) fn rewritten(&mut self, rewritten: Rewritten) -> u64 {
+        for (id, chunk) in rewritten.chunks {
+            let position = self.archived_chunks.iter().position(|chunk| chunk.id == id);
+            if let Some(position) = position {
+                let chunk = self.persist(chunk);
+                self.replace(position, chunk);
+            }
+        }
+        self.evict();
+        rewritten.deleted
+    }
+
+    fn delete_mutable(&mut self, filters: &[Filter], range: Range) -> u64 {
+        let mut deleted = 0;
+        for chunk in self
+            .mutable_chunks
+            .iter_mut()
+            .chain(self.late_chunks.iter_mut())
+        {
+            if chunk.info.overlaps(range) {
+                deleted += chunk.delete(filters, range);
+            }
+        }
+        deleted
+    }
+
+    /// Scans the mutable and late chunks overlapping `range`, leaving the archived ones to the
+    /// returned [`TableScan`], which holds on to them and runs off the shard.
     pub(crate) async fn scan(
         &self,
         projections: Option<&[String]>,
-        filters: &[MatcherRef<'_>],
+        filters: &[Filter],
         range: Range,
         limit: Option<usize>,
-    ) -> Result<Vec<ScanChunk>, ScanError> {
-        let mut chunks = Vec::new();
-        let mut tasks = Vec::new();
-        for chunk in &self.mutable_chunks {
-            if let Some(start) = range.start {
-                if chunk.info.end_at() < start {
-                    continue;
-                }
+    ) -> Result<TableScan, ScanError> {
+        let mut archived = Vec::new();
+        for chunk in &self.archived_chunks {
+            if !chunk.info().overlaps(range) {
+                continue;
             }
-            if let Some(end) = range.end {
-                if chunk.info.start_at > end {
-                    continue;
-                }
+            let late = match self
+                .late_chunks
+                .iter()
+                .find(|late| late.info.same_window(chunk.info()))
+            {
+                None => None,
+                Some(late) => late.scan(projections, filters, range, limit).await?,
+            };
+            archived.push((chunk.clone(), late));
+        }
+        let mut scanned = Vec::new();
+        for chunk in &self.late_chunks {
+            if !chunk.info.overlaps(range)
+                || self
+                    .archived_chunks
+                    .iter()
+                    .any(|archived| archived.info().same_window(&chunk.info))
+            {
+                continue;
             }
-            tasks.push(runtime::spawn(chunk.scan(
-                projections,
-                filters,
-                range,
-                limit,
-            )));
+            scanned.extend(chunk.scan(projections, filters, range, limit).await?);
         }
-        for task in tasks {
-            if let Some(chunk) = task.await? {
-                chunks.push(chunk);
+        for chunk in &self.mutable_chunks {
+            if !chunk.info.overlaps(range) {
+                continue;
             }
+            scanned.extend(chunk.scan(projections, filters, range, limit).await?);
         }
-        Ok(chunks)
+        Ok(TableScan {
+            archived,
+            scanned,
+            alignment: self.schema.meta.alignment,
+            counted: false,
+        })
     }
 
-    fn lookup_mutable_chunk(
+    /// Looks up the chunk a sample at `timestamp` is written to, a late chunk if its window has
+    /// been archived.
+    fn lookup_chunk(
         &mut self,
         timestamp: Instant,
+        now: Instant,
+        metrics: &WriteMetrics,
     ) -> Result<&mut MutableChunk, WriteError> {
-        match self.mutable_chunks.front() {
-            Some(first_chunk) => {
-                if timestamp < first_chunk.info.start_at {
+        if matches!(self.retained_from, Some(retained_from) if timestamp < retained_from) {
+            metrics.add_rejected_expired();
+            return Err(WriteError::TimestampExpired {
+                t: timestamp,
+                table_name: Arc::clone(&self.name),
+            });
+        }
+        if timestamp > now + self.schema.meta.future_tolerance {
+            metrics.add_rejected_future();
+            return Err(WriteError::TimestampTooNew {
+                t: timestamp,
+                table_name: Arc::clone(&self.name),
+            });
+        }
+        match self.mutable_from() {
+            Some(mutable_from) if timestamp < mutable_from => {
+                if !self.accepts_late(timestamp) {
+                    metrics.add_rejected_archived();
                     return Err(WriteError::TimestampArchived {
                         t: timestamp,
                         table_name: Arc::clone(&self.name),
                     });
                 }
-                let mut offset =
-                    (timestamp - first_chunk.info.start_at) / self.schema.meta.chunk_duration();
-                let delta = offset - (self.mutable_chunks.len() as i64 - 1);
-                if delta > 0 {
-                    for i in 0..delta {
-                        self.mutable_chunks.push_back(
-                            self.new_mutable_chunk(timestamp - self.schema.meta.time_interval * i),
-                        );
-                    }
-                }
-                let delta =
-                    self.mutable_chunks.len() as i64 - self.schema.meta.mutable_chunk_num as i64;
-                if delta > 0 {
-                    for _ in 0..delta {
-                        let chunk = self.mutable_chunks.pop_front().unwrap();
-                        runtime::spawn(chunk.archive()).detach();
-                    }
-                    offset -= delta;
-                }
-                Ok(&mut self.mutable_chunks[offset as usize])
+                metrics.add_late();
+                Ok(self.lookup_late_chunk(timestamp))
             }
+            _ => Ok(self.lookup_mutable_chunk(timestamp)),
+        }
+    }
+
+    fn lookup_late_chunk(&mut self, timestamp: Instant) -> &mut MutableChunk {
+        let position = self
+            .late_chunks
+            .iter()
+            .position(|chunk| chunk.info.window_end() > timestamp);
+        match position {
+            Some(position) if self.late_chunks[position].info.contains(timestamp) => {
+                &mut self.late_chunks[position]
+            }
+            _ => {
+                let position = position.unwrap_or(self.late_chunks.len());
+                let chunk = self.new_mutable_chunk(timestamp);
+                self.late_chunks.insert(position, chunk);
+                &mut self.late_chunks[position]
+            }
+        }
+    }
+
+    fn lookup_mutable_chunk(&mut self, timestamp: Instant) -> &mut MutableChunk {
+        let first_start_at = match self.mutable_chunks.front() {
+            Some(first_chunk) => first_chunk.info.start_at,
             None => {
                 self.mutable_chunks
                     .push_back(self.new_mutable_chunk(timestamp));
-                Ok(self.mutable_chunks.back_mut().unwrap())
+                return self.mutable_chunks.back_mut().unwrap();
+            }
+        };
+        let chunk_duration = self.schema.meta.chunk_duration();
+        let mutable_chunk_num = self.schema.meta.mutable_chunk_num as i64;
+        let mut offset = (timestamp - first_start_at) / chunk_duration;
+        let len = self.mutable_chunks.len() as i64;
+        if offset >= len + mutable_chunk_num {
+            // every chunk in between would be archived empty right away
+            while let Some(chunk) = self.mutable_chunks.pop_front() {
+                self.archive(chunk);
+            }
+            self.mutable_chunks
+                .push_back(self.new_mutable_chunk(timestamp));
+            return self.mutable_chunks.back_mut().unwrap();
+        }
+        for i in len..=offset {
+            self.mutable_chunks.push_back(MutableChunk::new(
+                Arc::clone(&self.schema),
+                first_start_at + chunk_duration * i,
+            ));
+        }
+        let delta = self.mutable_chunks.len() as i64 - mutable_chunk_num;
+        if delta > 0 {
+            for _ in 0..delta {
+                let chunk = self.mutable_chunks.pop_front().unwrap();
+                self.archive(chunk);
+            }
+            offset -= delta;
+        }
+        &mut self.mutable_chunks[offset as usize]
+    }
+
+    fn archive(&mut self, chunk: MutableChunk) {
+        self.roll_up(&chunk);
+        let chunk = self.persist(chunk.archive());
+        self.archived_chunks.push_back(chunk);
+        self.evict();
+    }
+
+    /// Persists `chunk` to a file if the table has a directory and the chunk has any record.
+    /// The file is written on the blocking pool, see [`Table::persisted`].
+    fn persist(&mut self, chunk: ImmutableChunk) -> ArchivedChunk {
+        let mut archived = ArchivedChunk {
+            id: self.next_id(),
+            info: chunk.info.clone(),
+            memory: Some(Arc::new(chunk)),
+            file: ChunkFile::None,
+            replaced: None,
+        };
+        self.write_file(&mut archived);
+        archived
+    }
+
+    fn write_file(&mut self, chunk: &mut ArchivedChunk) {
+        let (dir, memory) = match (&self.dir, &chunk.memory) {
+            (Some(dir), Some(memory)) if memory.record_num() > 0 => {
+                (dir.clone(), Arc::clone(memory))
+            }
+            _ => return,
+        };
+        let (shard_id, schema) = (self.shard_id, Arc::clone(&self.schema));
+        let (table_name, id, events) = (Arc::clone(&self.name), chunk.id, self.events.clone());
+        chunk.file = ChunkFile::Writing;
+        self.writing += 1;
+        runtime::spawn(async move {
+            let file = unblock(move || FileChunk::create(&dir, shard_id, &memory, &schema)).await;
+            let _ = events
+                .send(TableEvent::Persisted {
+                    table_name,
+                    id,
+                    file,
+                })
+                .await;
+        })
+        .detach();
+    }
+
+    /// Writes again the files that failed to be written.
+    fn retry_persist(&mut self) {
+        let mut chunks = mem::take(&mut self.archived_chunks);
+        for chunk in chunks.iter_mut() {
+            if let ChunkFile::Failed = chunk.file {
+                self.write_file(chunk);
+            }
+        }
+        self.archived_chunks = chunks;
+    }
+
+    /// Takes the outcome of writing the file of archived chunk `id`, removing the file of the
+    /// chunk it replaced once written. The file is removed if the chunk was replaced or expired
+    /// in the meantime.
+    pub(crate) fn persisted(&mut self, id: u64, file: ArrowResult<FileChunk>) {
+        self.writing -= 1;
+        let chunk = self.archived_chunks.iter_mut().find(|chunk| chunk.id == id);
+        match (chunk, file) {
+            (Some(chunk), Ok(file)) => {
+                chunk.file = ChunkFile::Written(Arc::new(file));
+                if let Some(replaced) = chunk.replaced.take() {
+                    replaced.remove();
+                }
+                self.evict();
+            }
+            (Some(chunk), Err(err)) => {
+                error!("table {:?} persist chunk error: {}", self.name, err);
+                chunk.file = ChunkFile::Failed;
+            }
+            (None, Ok(file)) => file.remove(),
+            (None, Err(_)) => {}
+        }
+    }
+
+    /// Whether files of archived chunks are being written.
+    #[inline]
+    pub(crate) fn is_writing(&self) -> bool {
+        self.writing > 0
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Replaces the archived chunk at `position`. The file of the replaced one is removed once
+    /// the file of `chunk` is written, see [`Table::persisted`], as the log may no longer hold
+    /// its samples.
+    fn replace(&mut self, position: usize, mut chunk: ArchivedChunk) {
+        let replaced = &mut self.archived_chunks[position];
+        let file = match &replaced.file {
+            ChunkFile::Written(file) => Some(Arc::clone(file)),
+            // not written yet, the file it replaced in turn is the one to keep
+            _ => replaced.replaced.take(),
+        };
+        match (&chunk.file, file) {
+            (ChunkFile::Writing, file) => chunk.replaced = file,
+            (_, Some(file)) => file.remove(),
+            (_, None) => {}
+        }
+        self.archived_chunks[position] = chunk;
+    }
+
+    /// Evicts the memory copies of the oldest persisted chunks beyond `immutable_chunk_num`.
+    fn evict(&mut self) {
+        let mut cached = self
+            .archived_chunks
+            .iter()
+            .filter(|chunk| chunk.memory.is_some())
+            .count();
+        for chunk in self.archived_chunks.iter_mut() {
+            if cached <= self.schema.meta.immutable_chunk_num as usize {
+                break;
+            }
+            if let (Some(_), ChunkFile::Written(_)) = (&chunk.memory, &chunk.file) {
+                chunk.memory = None;
+                cached -= 1;
             }
         }
     }
@@ -125,3 +964,32 @@ impl Table {
         MutableChunk::new(Arc::clone(&self.schema), start_at)
     }
 }
+
+/// Checks that a new series of `labels` fits within the series and label value limits of
+/// `chunk`.
+fn check_cardinality(
+    chunk: &MutableChunk,
+    labels: &[Option<&LabelValue>],
+    max_series: Option<u32>,
+    max_label_values: Option<u32>,
+    table_name: &Arc<str>,
+) -> Result<(), WriteError> {
+    if let Some(limit) = max_series {
+        if chunk.series_num() >= limit as u64 {
+            return Err(WriteError::SeriesLimitExceeded {
+                table_name: Arc::clone(table_name),
+                limit,
+            });
+        }
+    }
+    if let Some(limit) = max_label_values {
+        if let Some(label) = chunk.exceeding_label(labels, limit) {
+            return Err(WriteError::LabelValueLimitExceeded {
+                label: Arc::clone(label),
+                table_name: Arc::clone(table_name),
+                limit,
+            });
+        }
+    }
+    Ok(())
+}
@@ -56,6 +56,22 @@ impl StringDictionary {
         }
     }
 
+    /// The number of values, the null id excluded.
+    pub fn len(&self) -> usize {
+        self.dedup.len()
+    }
+
+    /// The heap usage in bytes, taking every bucket of the dedup table as one id plus its
+    /// control byte.
+    pub fn heap_size(&self) -> usize {
+        self.data.heap_size() + self.dedup.capacity() * (std::mem::size_of::<usize>() + 1)
+    }
+
+    /// Iterates over all `(id, value)` pairs, skipping the reserved null id `0`.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        (1..=self.dedup.len()).map(|id| (id, self.data.get(id - 1).unwrap()))
+    }
+
     fn hash_str(state: &RandomState, value: &str) -> u64 {
         let mut hasher = state.build_hasher();
         value.hash(&mut hasher);
@@ -78,5 +94,7 @@ mod tests {
         let v1 = dict.get(id);
         let v2 = dict.get(id2);
         assert_eq!(v1, v2);
+        let values = dict.iter().collect::<Vec<_>>();
+        assert_eq!(values, vec![(id, "hello, world"), (id3, "hello world")]);
     }
 }
@@ -0,0 +1,327 @@
+use std::marker::PhantomData;
+
+/// Appends bits to a byte buffer, most significant bit first.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    /// Appends the lowest `n` bits of `value`.
+    #[inline]
+    pub fn push(&mut self, value: u64, mut n: u32) {
+        while n > 0 {
+            let used = (self.len % 8) as u32;
+            if used == 0 {
+                self.buf.push(0);
+            }
+            let free = 8 - used;
+            let take = free.min(n);
+            let bits = (value >> (n - take)) & ((1 << take) - 1);
+            *self.buf.last_mut().unwrap() |= (bits as u8) << (free - take);
+            self.len += take as usize;
+            n -= take;
+        }
+    }
+
+    #[inline]
+    pub fn push_bit(&mut self, bit: bool) {
+        self.push(bit as u64, 1);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads what [`BitWriter`] wrote, returning `None` once the input is exhausted.
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    #[inline]
+    pub fn read(&mut self, mut n: u32) -> Option<u64> {
+        let mut value = 0;
+        while n > 0 {
+            let byte = *self.buf.get(self.pos / 8)?;
+            let used = (self.pos % 8) as u32;
+            let take = (8 - used).min(n);
+            let bits = (byte >> (8 - used - take)) as u64 & ((1 << take) - 1);
+            value = (value << take) | bits;
+            self.pos += take as usize;
+            n -= take;
+        }
+        Some(value)
+    }
+
+    #[inline]
+    pub fn read_bit(&mut self) -> Option<bool> {
+        self.read(1).map(|bit| bit == 1)
+    }
+}
+
+/// What encoding a value depends on of the ones before it.
+#[derive(Debug, Default)]
+pub struct State {
+    len: usize,
+    prev: u64,
+    delta: u64,
+    leading: u32,
+    trailing: u32,
+}
+
+/// Values compressed against the previous one of the same sequence: ints by delta-of-delta,
+/// floats by XOR, as described by the Gorilla paper.
+pub trait Gorilla: Copy {
+    fn encode(writer: &mut BitWriter, state: &mut State, value: Self);
+
+    fn decode(reader: &mut BitReader, state: &mut State) -> Option<Self>;
+}
+
+/// Delta-of-delta buckets of zigzag encoded widths, each behind a unary prefix of its index.
+const DOD_BUCKETS: [u32; 4] = [7, 9, 12, 64];
+
+impl Gorilla for i64 {
+    fn encode(writer: &mut BitWriter, state: &mut State, value: Self) {
+        let value = value as u64;
+        if state.len == 0 {
+            writer.push(value, 64);
+        } else {
+            let delta = value.wrapping_sub(state.prev);
+            let dod = delta.wrapping_sub(state.delta) as i64;
+            let zigzag = ((dod << 1) ^ (dod >> 63)) as u64;
+            if zigzag == 0 {
+                writer.push_bit(false);
+            } else {
+                for (i, width) in DOD_BUCKETS.into_iter().enumerate() {
+                    if width == 64 || zigzag < 1 << width {
+                        // the last bucket needs no terminating zero
+                        let prefix = i as u32 + 1 + (width != 64) as u32;
+                        writer.push((1 << prefix) - 2 + (width == 64) as u64, prefix);
+                        writer.push(zigzag, width);
+                        break;
+                    }
+                }
+            }
+            state.delta = delta;
+        }
+        state.prev = value;
+        state.len += 1;
+    }
+
+    fn decode(reader: &mut BitReader, state: &mut State) -> Option<Self> {
+        if state.len > 0 {
+            let mut zigzag = 0;
+            if reader.read_bit()? {
+                let mut width = DOD_BUCKETS[DOD_BUCKETS.len() - 1];
+                for bucket in DOD_BUCKETS.into_iter().take(DOD_BUCKETS.len() - 1) {
+                    if !reader.read_bit()? {
+                        width = bucket;
+                        break;
+                    }
+                }
+                zigzag = reader.read(width)?;
+            }
+            let dod = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            state.delta = state.delta.wrapping_add(dod as u64);
+            state.prev = state.prev.wrapping_add(state.delta);
+        } else {
+            state.prev = reader.read(64)?;
+        }
+        state.len += 1;
+        Some(state.prev as i64)
+    }
+}
+
+impl Gorilla for f64 {
+    fn encode(writer: &mut BitWriter, state: &mut State, value: Self) {
+        let value = value.to_bits();
+        if state.len == 0 {
+            writer.push(value, 64);
+            state.leading = u32::MAX;
+        } else {
+            let xor = value ^ state.prev;
+            if xor == 0 {
+                writer.push_bit(false);
+            } else {
+                writer.push_bit(true);
+                let leading = xor.leading_zeros().min(31);
+                let trailing = xor.trailing_zeros();
+                if state.leading != u32::MAX
+                    && leading >= state.leading
+                    && trailing >= state.trailing
+                {
+                    writer.push_bit(false);
+                    writer.push(xor >> state.trailing, 64 - state.leading - state.trailing);
+                } else {
+                    let meaningful = 64 - leading - trailing;
+                    writer.push_bit(true);
+                    writer.push(leading as u64, 5);
+                    writer.push(meaningful as u64 - 1, 6);
+                    writer.push(xor >> trailing, meaningful);
+                    state.leading = leading;
+                    state.trailing = trailing;
+                }
+            }
+        }
+        state.prev = value;
+        state.len += 1;
+    }
+
+    fn decode(reader: &mut BitReader, state: &mut State) -> Option<Self> {
+        if state.len > 0 {
+            if reader.read_bit()? {
+                if reader.read_bit()? {
+                    state.leading = reader.read(5)? as u32;
+                    let meaningful = reader.read(6)? as u32 + 1;
+                    state.trailing = 64 - state.leading - meaningful;
+                }
+                let xor = reader.read(64 - state.leading - state.trailing)? << state.trailing;
+                state.prev ^= xor;
+            }
+        } else {
+            state.prev = reader.read(64)?;
+        }
+        state.len += 1;
+        Some(f64::from_bits(state.prev))
+    }
+}
+
+pub fn compress<G: Gorilla>(values: impl IntoIterator<Item = G>) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    let mut state = State::default();
+    for value in values {
+        G::encode(&mut writer, &mut state, value);
+    }
+    writer.into_inner()
+}
+
+/// Decodes the first `len` values of `buf`.
+pub fn decompress<G: Gorilla>(buf: &[u8], len: usize) -> Values<'_, G> {
+    Values {
+        reader: BitReader::new(buf),
+        state: State::default(),
+        remaining: len,
+        _marker: PhantomData,
+    }
+}
+
+#[derive(Debug)]
+pub struct Values<'a, G> {
+    reader: BitReader<'a>,
+    state: State,
+    remaining: usize,
+    _marker: PhantomData<G>,
+}
+
+impl<G: Gorilla> Iterator for Values<'_, G> {
+    type Item = G;
+
+    #[inline]
+    fn next(&mut self) -> Option<G> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        G::decode(&mut self.reader, &mut self.state)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, BitReader, BitWriter};
+
+    #[test]
+    fn test_bits() {
+        let mut writer = BitWriter::default();
+        writer.push_bit(true);
+        writer.push(0b101, 3);
+        writer.push(u64::MAX, 64);
+        writer.push(0, 12);
+        let buf = writer.into_inner();
+        assert_eq!(buf.len(), 10);
+
+        let mut reader = BitReader::new(&buf);
+        assert_eq!(reader.read_bit(), Some(true));
+        assert_eq!(reader.read(3), Some(0b101));
+        assert_eq!(reader.read(64), Some(u64::MAX));
+        assert_eq!(reader.read(12), Some(0));
+        assert_eq!(reader.read(1), None);
+    }
+
+    #[test]
+    fn test_ints() {
+        let values = vec![
+            1_650_000_000_000,
+            1_650_000_015_000,
+            1_650_000_030_000,
+            1_650_000_045_001,
+            1_650_000_059_990,
+            1_650_000_059_990,
+            -3,
+            i64::MAX,
+            i64::MIN,
+            0,
+        ];
+        let buf = compress(values.iter().copied());
+        assert_eq!(
+            decompress::<i64>(&buf, values.len()).collect::<Vec<_>>(),
+            values
+        );
+
+        let counter = (0..120).map(|i| 1000 + i * 10).collect::<Vec<i64>>();
+        let buf = compress(counter.iter().copied());
+        // one bit per sample once the delta settles
+        assert_eq!(buf.len(), 24);
+        assert_eq!(
+            decompress::<i64>(&buf, counter.len()).collect::<Vec<_>>(),
+            counter
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        let values = vec![
+            12.0,
+            12.0,
+            24.5,
+            -0.1,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            0.0,
+            f64::INFINITY,
+            12.0,
+        ];
+        let buf = compress(values.iter().copied());
+        assert_eq!(
+            decompress::<f64>(&buf, values.len()).collect::<Vec<_>>(),
+            values
+        );
+
+        let nan = decompress::<f64>(&compress([1.0, f64::NAN]), 2).collect::<Vec<_>>();
+        assert!(nan[1].is_nan());
+
+        let gauge = (0..120)
+            .map(|i| if i % 10 == 0 { 0.5 } else { 0.25 })
+            .collect::<Vec<f64>>();
+        let buf = compress(gauge.iter().copied());
+        assert!(buf.len() < 120 * 2);
+        assert_eq!(
+            decompress::<f64>(&buf, gauge.len()).collect::<Vec<_>>(),
+            gauge
+        );
+    }
+}
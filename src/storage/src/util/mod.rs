@@ -1,6 +1,16 @@
 use std::num::Wrapping;
+#[cfg(test)]
+use std::ops::Deref;
+#[cfg(test)]
+use std::path::{Path, PathBuf};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(test)]
+use std::{env, fs, process};
 
+pub mod codec;
 pub mod dictionary;
+pub mod gorilla;
 pub mod string;
 
 #[inline]
@@ -44,6 +54,45 @@ pub fn jump_consistent_hash(key: u64, buckets: usize) -> u32 {
     b as u32
 }
 
+/// A directory under the system temporary directory of a test's own, unique across processes
+/// and tests running at the same time, removed with everything in it when dropped.
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("t0-{}-{}-{}", name, process::id(), id));
+        let _ = fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+#[cfg(test)]
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::hash_combine;
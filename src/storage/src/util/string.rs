@@ -31,6 +31,10 @@ impl StringArray {
         id
     }
 
+    pub fn heap_size(&self) -> usize {
+        self.offsets.capacity() * std::mem::size_of::<usize>() + self.data.capacity()
+    }
+
     #[inline]
     pub fn get(&self, id: usize) -> Option<&str> {
         let offset = self.offsets.get(id)?;
@@ -0,0 +1,699 @@
+use crate::util::codec::{Decoder, Encoder};
+use common::histogram::{Histogram, Summary};
+use common::time::Instant;
+use common::{Label, LabelType, Scalar, ScalarType, ScalarValue};
+use hashbrown::HashMap;
+use ql::rosetta::{Matcher, MatcherOp, Range};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time;
+use tracing::warn;
+
+const SEGMENT_EXTENSION: &str = "wal";
+const RECORD_HEADER_LEN: usize = 8;
+/// Takes the place of the label count in delete records, which write records never reach.
+const DELETE_MARKER: u32 = u32::MAX;
+
+/// When the write-ahead log is flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPolicy {
+    /// Before every write is acknowledged.
+    Always,
+    /// Every interval, by a timer of the shard, if anything has been written since.
+    Interval(time::Duration),
+    /// Never, leaving it to the OS; writes survive a process crash but not a host crash.
+    Never,
+}
+
+impl FromStr for SyncPolicy {
+    type Err = String;
+
+    /// Parses `always`, `never`, or an interval in milliseconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            millis => millis
+                .parse()
+                .map(|millis| Self::Interval(time::Duration::from_millis(millis)))
+                .map_err(|_| format!("invalid sync policy {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalOptions {
+    /// Root directory of the log, each shard logs into its own sub directory.
+    pub dir: PathBuf,
+    pub sync: SyncPolicy,
+    /// Size in bytes a segment grows to before a new one is started.
+    pub segment_size: u64,
+}
+
+impl WalOptions {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            sync: SyncPolicy::Interval(time::Duration::from_secs(1)),
+            segment_size: 64 << 20,
+        }
+    }
+}
+
+/// A write request read back from the log.
+#[derive(Debug)]
+pub(crate) struct Record {
+    pub(crate) table_name: String,
+    pub(crate) labels: Vec<(String, LabelType<String>)>,
+    pub(crate) scalars: Vec<(Instant, Vec<Scalar>)>,
+}
+
+/// A delete request read back from the log.
+#[derive(Debug)]
+pub(crate) struct DeleteRecord {
+    /// The sequence number of the delete, see [`Wal::append_delete`].
+    pub(crate) seq: u64,
+    pub(crate) table_name: String,
+    pub(crate) matchers: Vec<Matcher>,
+    pub(crate) range: Range,
+}
+
+#[derive(Debug)]
+pub(crate) enum Entry {
+    Write(Record),
+    Delete(DeleteRecord),
+}
+
+/// Per-shard write-ahead log, a sequence of segment files holding length-prefixed and
+/// checksummed write requests.
+///
+/// Every segment remembers the earliest and latest sample timestamps it holds for each table,
+/// so it can be removed once every window in between has been persisted, and the last delete
+/// it holds, so it is kept until that delete has been persisted.
+#[derive(Debug)]
+pub(crate) struct Wal {
+    dir: PathBuf,
+    options: WalOptions,
+    segments: VecDeque<Segment>,
+    active: Segment,
+    file: File,
+    size: u64,
+    /// Whether anything has been written since the last flush.
+    unsynced: bool,
+    /// The sequence number of the next delete.
+    next_delete: u64,
+}
+
+#[derive(Debug)]
+struct Segment {
+    seq: u64,
+    tables: HashMap<Arc<str>, (Instant, Instant)>,
+    last_delete: Option<u64>,
+}
+
+impl Wal {
+    /// Opens the log of shard `shard_id`, handing every record already logged, from segment
+    /// `replay_from` on, to `replay`.
+    pub(crate) fn open(
+        options: &WalOptions,
+        shard_id: usize,
+        replay_from: u64,
+        mut replay: impl FnMut(Entry),
+    ) -> io::Result<Self> {
+        let dir = options.dir.join(shard_id.to_string());
+        fs::create_dir_all(&dir)?;
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if matches!(path.extension(), Some(ext) if ext == SEGMENT_EXTENSION) {
+                if let Some(seq) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok())
+                {
+                    seqs.push(seq);
+                }
+            }
+        }
+        seqs.sort_unstable();
+
+        let mut segments = VecDeque::with_capacity(seqs.len());
+        let mut next_delete = 0;
+        for seq in seqs {
+            let mut segment = Segment {
+                seq,
+                tables: HashMap::new(),
+                last_delete: None,
+            };
+            let mut buf = Vec::new();
+            File::open(Self::segment_path(&dir, seq))?.read_to_end(&mut buf)?;
+            let mut offset = 0;
+            while offset < buf.len() {
+                match decode(&buf[offset..]) {
+                    Some((mut entry, len)) => {
+                        offset += len;
+                        match &mut entry {
+                            Entry::Write(record) => {
+                                segment.track(&record.table_name, &record.scalars)
+                            }
+                            Entry::Delete(record) => {
+                                record.seq = next_delete;
+                                segment.last_delete = Some(next_delete);
+                                next_delete += 1;
+                            }
+                        }
+                        if seq >= replay_from {
+                            replay(entry);
+                        }
+                    }
+                    None => {
+                        warn!("wal segment {} is torn at offset {}", seq, offset);
+                        break;
+                    }
+                }
+            }
+            segments.push_back(segment);
+        }
+
+        let seq = segments.back().map_or(0, |segment| segment.seq + 1);
+        let file = Self::create_segment(&dir, seq)?;
+        Ok(Self {
+            dir,
+            options: options.clone(),
+            segments,
+            active: Segment {
+                seq,
+                tables: HashMap::new(),
+                last_delete: None,
+            },
+            file,
+            size: 0,
+            unsynced: false,
+            next_delete,
+        })
+    }
+
+    pub(crate) fn append(
+        &mut self,
+        table_name: &str,
+        labels: &[Label],
+        scalars: &[(Instant, Vec<Scalar>)],
+    ) -> io::Result<()> {
+        if self.size >= self.options.segment_size {
+            self.roll()?;
+        }
+        self.write(&encode(table_name, labels, scalars))?;
+        self.active.track(table_name, scalars);
+        Ok(())
+    }
+
+    /// Logs a delete, which is replayed in order with the writes around it, returning its
+    /// sequence number. Its segment is kept until the archived chunks it rewrites have been
+    /// persisted, see [`Wal::truncate`].
+    pub(crate) fn append_delete(
+        &mut self,
+        table_name: &str,
+        matchers: &[Matcher],
+        range: Range,
+    ) -> io::Result<u64> {
+        if self.size >= self.options.segment_size {
+            self.roll()?;
+        }
+        self.write(&encode_delete(table_name, matchers, range))?;
+        let seq = self.next_delete;
+        self.active.last_delete = Some(seq);
+        self.next_delete += 1;
+        Ok(seq)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        self.unsynced = true;
+        match self.options.sync {
+            SyncPolicy::Always => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the oldest segments whose samples are all persisted, as told by `persisted` for
+    /// the earliest and latest of each table, and whose deletes are all before
+    /// `pending_delete`, the first delete not persisted yet.
+    pub(crate) fn truncate(
+        &mut self,
+        persisted: impl Fn(&str, Instant, Instant) -> bool,
+        pending_delete: Option<u64>,
+    ) -> io::Result<()> {
+        while let Some(segment) = self.segments.front() {
+            if matches!((segment.last_delete, pending_delete), (Some(last), Some(pending)) if last >= pending)
+                || !segment
+                    .tables
+                    .iter()
+                    .all(|(table_name, (from, to))| persisted(table_name, *from, *to))
+            {
+                break;
+            }
+            fs::remove_file(Self::segment_path(&self.dir, segment.seq))?;
+            self.segments.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Starts a new segment, returning its sequence number.
+    pub(crate) fn roll(&mut self) -> io::Result<u64> {
+        self.sync()?;
+        let seq = self.active.seq + 1;
+        self.file = Self::create_segment(&self.dir, seq)?;
+        self.size = 0;
+        let segment = std::mem::replace(
+            &mut self.active,
+            Segment {
+                seq,
+                tables: HashMap::new(),
+                last_delete: None,
+            },
+        );
+        self.segments.push_back(segment);
+        Ok(seq)
+    }
+
+    /// Flushes what has been written since the last flush to disk.
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        if !self.unsynced {
+            return Ok(());
+        }
+        self.file.sync_data()?;
+        self.unsynced = false;
+        Ok(())
+    }
+
+    fn create_segment(dir: &Path, seq: u64) -> io::Result<File> {
+        OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(Self::segment_path(dir, seq))
+    }
+
+    fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+        dir.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION))
+    }
+}
+
+impl Segment {
+    fn track(&mut self, table_name: &str, scalars: &[(Instant, Vec<Scalar>)]) {
+        for (timestamp, _) in scalars {
+            match self.tables.get_mut(table_name) {
+                Some((earliest, latest)) => {
+                    if timestamp < earliest {
+                        *earliest = *timestamp;
+                    }
+                    if timestamp > latest {
+                        *latest = *timestamp;
+                    }
+                }
+                None => {
+                    self.tables
+                        .insert(Arc::from(table_name), (*timestamp, *timestamp));
+                }
+            }
+        }
+    }
+}
+
+fn encode(table_name: &str, labels: &[Label], scalars: &[(Instant, Vec<Scalar>)]) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_str(table_name);
+    encoder.put_u32(labels.len() as u32);
+    for label in labels {
+        encoder.put_str(label.name);
+        match label.value {
+            LabelType::String(value) => {
+                encoder.put_u8(0);
+                encoder.put_str(value);
+            }
+        }
+    }
+    encoder.put_u32(scalars.len() as u32);
+    for (timestamp, scalars) in scalars {
+        encoder.put_i64(timestamp.as_millis());
+        encoder.put_u32(scalars.len() as u32);
+        for scalar in scalars {
+            encoder.put_str(&scalar.name);
+            match &scalar.value {
+                ScalarType::Int(value) => {
+                    encoder.put_u8(0);
+                    encoder.put_i64(*value);
+                }
+                ScalarType::Float(value) => {
+                    encoder.put_u8(1);
+                    encoder.put_f64(*value);
+                }
+                ScalarType::Histogram(histogram) => {
+                    encoder.put_u8(2);
+                    let mut buf = Vec::new();
+                    histogram.encode(&mut buf);
+                    encoder.put_bytes(&buf);
+                }
+                ScalarType::Summary(summary) => {
+                    encoder.put_u8(3);
+                    let mut buf = Vec::new();
+                    summary.encode(&mut buf);
+                    encoder.put_bytes(&buf);
+                }
+                ScalarType::Bool(value) => {
+                    encoder.put_u8(4);
+                    encoder.put_u8(*value as u8);
+                }
+                ScalarType::String(value) => {
+                    encoder.put_u8(5);
+                    encoder.put_str(value);
+                }
+            }
+        }
+    }
+    frame(encoder.into_inner())
+}
+
+fn encode_delete(table_name: &str, matchers: &[Matcher], range: Range) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_str(table_name);
+    encoder.put_u32(DELETE_MARKER);
+    encoder.put_u32(matchers.len() as u32);
+    for matcher in matchers {
+        encoder.put_str(&matcher.name);
+        encoder.put_u8(match matcher.op {
+            MatcherOp::LiteralEqual => 0,
+            MatcherOp::LiteralNotEqual => 1,
+            MatcherOp::RegexMatch => 2,
+            MatcherOp::RegexNotMatch => 3,
+        });
+        match &matcher.value {
+            None => encoder.put_u8(0),
+            Some(LabelType::String(value)) => {
+                encoder.put_u8(1);
+                encoder.put_str(value);
+            }
+        }
+    }
+    for bound in [range.start, range.end] {
+        match bound {
+            None => encoder.put_u8(0),
+            Some(bound) => {
+                encoder.put_u8(1);
+                encoder.put_i64(bound.as_millis());
+            }
+        }
+    }
+    frame(encoder.into_inner())
+}
+
+/// Prefixes `payload` with its length and checksum.
+fn frame(payload: Vec<u8>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+/// Decodes the record at the start of `buf`, returning it with its encoded length, or
+/// `None` if it is incomplete or corrupted.
+fn decode(buf: &[u8]) -> Option<(Entry, usize)> {
+    let mut header = Decoder::new(buf);
+    let len = header.u32()? as usize;
+    let checksum = header.u32()?;
+    let payload = header.take(len)?;
+    if crc32fast::hash(payload) != checksum {
+        return None;
+    }
+
+    let mut decoder = Decoder::new(payload);
+    let table_name = decoder.string()?;
+    let label_num = decoder.u32()?;
+    if label_num == DELETE_MARKER {
+        let record = decode_delete(&mut decoder, table_name)?;
+        return Some((Entry::Delete(record), RECORD_HEADER_LEN + len));
+    }
+    let mut labels = Vec::with_capacity(label_num as usize);
+    for _ in 0..label_num {
+        let name = decoder.string()?;
+        let value = match decoder.u8()? {
+            0 => LabelType::String(decoder.string()?),
+            _ => return None,
+        };
+        labels.push((name, value));
+    }
+    let sample_num = decoder.u32()?;
+    let mut scalars = Vec::with_capacity(sample_num as usize);
+    for _ in 0..sample_num {
+        let timestamp = Instant::from_millis(decoder.i64()?);
+        let scalar_num = decoder.u32()?;
+        let mut sample = Vec::with_capacity(scalar_num as usize);
+        for _ in 0..scalar_num {
+            let name = decoder.string()?;
+            let value: ScalarValue = match decoder.u8()? {
+                0 => ScalarType::Int(decoder.i64()?),
+                1 => ScalarType::Float(decoder.f64()?),
+                2 => ScalarType::Histogram(Histogram::decode(decoder.bytes()?)?),
+                3 => ScalarType::Summary(Summary::decode(decoder.bytes()?)?),
+                4 => ScalarType::Bool(decoder.u8()? != 0),
+                5 => ScalarType::String(decoder.string()?),
+                _ => return None,
+            };
+            sample.push(Scalar { name, value });
+        }
+        scalars.push((timestamp, sample));
+    }
+    Some((
+        Entry::Write(Record {
+            table_name,
+            labels,
+            scalars,
+        }),
+        RECORD_HEADER_LEN + len,
+    ))
+}
+
+fn decode_delete(decoder: &mut Decoder, table_name: String) -> Option<DeleteRecord> {
+    let matcher_num = decoder.u32()?;
+    let mut matchers = Vec::with_capacity(matcher_num as usize);
+    for _ in 0..matcher_num {
+        let name = decoder.string()?;
+        let op = match decoder.u8()? {
+            0 => MatcherOp::LiteralEqual,
+            1 => MatcherOp::LiteralNotEqual,
+            2 => MatcherOp::RegexMatch,
+            3 => MatcherOp::RegexNotMatch,
+            _ => return None,
+        };
+        let value = match decoder.u8()? {
+            0 => None,
+            _ => Some(LabelType::String(decoder.string()?)),
+        };
+        matchers.push(Matcher { name, op, value });
+    }
+    let mut bound = || match decoder.u8()? {
+        0 => Some(None),
+        _ => Some(Some(Instant::from_millis(decoder.i64()?))),
+    };
+    let range = Range {
+        start: bound()?,
+        end: bound()?,
+    };
+    Some(DeleteRecord {
+        // numbered as it is read back
+        seq: 0,
+        table_name,
+        matchers,
+        range,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::TempDir;
+    use crate::wal::{decode, encode, encode_delete, Entry, SyncPolicy, Wal, WalOptions};
+    use common::histogram::Summary;
+    use common::time::{Duration, Instant};
+    use common::{Label, LabelType, LabelValue, Scalar, ScalarType, ScalarValue};
+    use ql::rosetta::{Matcher, MatcherOp, Range};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn write(wal: &mut Wal, table_name: &str, timestamp: Instant) {
+        let labels = [Label {
+            name: "label1",
+            value: LabelValue::String("value1"),
+        }];
+        let scalars = [(
+            timestamp,
+            vec![Scalar {
+                name: String::from("scalar1"),
+                value: ScalarValue::Float(1.0),
+            }],
+        )];
+        wal.append(table_name, &labels, &scalars).unwrap();
+    }
+
+    #[test]
+    fn wal_codec() {
+        let labels = [Label {
+            name: "label1",
+            value: LabelValue::String("value1"),
+        }];
+        let scalars = [(
+            Instant::from_millis(7),
+            vec![
+                Scalar {
+                    name: String::from("int"),
+                    value: ScalarValue::Int(-1),
+                },
+                Scalar {
+                    name: String::from("float"),
+                    value: ScalarValue::Float(0.5),
+                },
+                Scalar {
+                    name: String::from("state"),
+                    value: ScalarValue::String(String::from("OPEN")),
+                },
+                Scalar {
+                    name: String::from("summary"),
+                    value: ScalarValue::Summary(Summary {
+                        count: 2.0,
+                        sum: 3.0,
+                        quantiles: vec![(0.5, 1.0), (0.99, 2.0)],
+                    }),
+                },
+            ],
+        )];
+        let buf = encode("test", &labels, &scalars);
+        let (record, len) = match decode(&buf).unwrap() {
+            (Entry::Write(record), len) => (record, len),
+            _ => unreachable!(),
+        };
+        assert_eq!(len, buf.len());
+        assert_eq!(record.table_name, "test");
+        assert!(
+            matches!(&record.labels[..], [(name, LabelType::String(value))] if name == "label1" && value == "value1")
+        );
+        assert_eq!(record.scalars[0].0.as_millis(), 7);
+        assert!(matches!(record.scalars[0].1[0].value, ScalarType::Int(-1)));
+        assert!(matches!(record.scalars[0].1[1].value, ScalarType::Float(v) if v == 0.5));
+        assert!(matches!(&record.scalars[0].1[2].value, ScalarType::String(v) if v == "OPEN"));
+        assert!(
+            matches!(&record.scalars[0].1[3].value, ScalarType::Summary(summary) if summary.quantiles == [(0.5, 1.0), (0.99, 2.0)])
+        );
+
+        assert!(decode(&buf[..buf.len() - 1]).is_none());
+        let mut corrupted = buf.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(decode(&corrupted).is_none());
+
+        let matchers = [Matcher {
+            name: String::from("label1"),
+            op: MatcherOp::RegexNotMatch,
+            value: Some(LabelType::String(String::from("value.*"))),
+        }];
+        let range = Range {
+            start: None,
+            end: Some(Instant::from_millis(7)),
+        };
+        let buf = encode_delete("test", &matchers, range);
+        match decode(&buf).unwrap() {
+            (Entry::Delete(record), len) => {
+                assert_eq!(len, buf.len());
+                assert_eq!(record.table_name, "test");
+                assert_eq!(record.matchers[0].name, "label1");
+                assert!(matches!(record.matchers[0].op, MatcherOp::RegexNotMatch));
+                assert!(
+                    matches!(&record.matchers[0].value, Some(LabelType::String(value)) if value == "value.*")
+                );
+                assert!(record.range.start.is_none());
+                assert_eq!(record.range.end.unwrap().as_millis(), 7);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn wal_replay_truncate() {
+        let dir = TempDir::new("wal-replay-truncate");
+        let options = WalOptions {
+            dir: dir.to_path_buf(),
+            sync: SyncPolicy::Never,
+            segment_size: 1,
+        };
+        let start_at = Instant::from_millis(0);
+        let mut wal = Wal::open(&options, 0, 0, |_| unreachable!()).unwrap();
+        for id in 0..3u32 {
+            write(&mut wal, "test", start_at + Duration::SECOND * id);
+        }
+        assert_eq!(wal.segments.len(), 2);
+        drop(wal);
+
+        // a torn write at the tail is skipped
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join("0").join(format!("{:020}.wal", 2)))
+            .unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+
+        let mut replayed = Vec::new();
+        let mut wal = Wal::open(&options, 0, 0, |record| replayed.push(record)).unwrap();
+        assert_eq!(replayed.len(), 3);
+        assert_eq!(wal.segments.len(), 3);
+
+        wal.truncate(|_, _, to| to < start_at + Duration::SECOND * 2u32, None)
+            .unwrap();
+        assert_eq!(wal.segments.len(), 1);
+        assert_eq!(fs::read_dir(dir.join("0")).unwrap().count(), 2);
+
+        // a segment with a delete is kept until the delete is persisted
+        let matchers = [Matcher {
+            name: String::from("label1"),
+            op: MatcherOp::LiteralEqual,
+            value: Some(LabelType::String(String::from("value1"))),
+        }];
+        let whole = Range {
+            start: None,
+            end: None,
+        };
+        assert_eq!(wal.append_delete("test", &matchers, whole).unwrap(), 0);
+        wal.roll().unwrap();
+        wal.truncate(|_, _, _| true, Some(0)).unwrap();
+        assert_eq!(wal.segments.len(), 1);
+        wal.truncate(|_, _, _| true, None).unwrap();
+        assert_eq!(wal.segments.len(), 0);
+
+        // the earliest samples of a segment are checked too, late ones in particular
+        let labels = [Label {
+            name: "label1",
+            value: LabelValue::String("value1"),
+        }];
+        let scalars = [9, 1].map(|second: u32| {
+            (
+                start_at + Duration::SECOND * second,
+                vec![Scalar {
+                    name: String::from("scalar1"),
+                    value: ScalarValue::Float(1.0),
+                }],
+            )
+        });
+        wal.append("test", &labels, &scalars).unwrap();
+        wal.roll().unwrap();
+        let persisted_from = start_at + Duration::SECOND * 5u32;
+        wal.truncate(|_, from, _| from >= persisted_from, None)
+            .unwrap();
+        assert_eq!(wal.segments.len(), 1);
+        wal.truncate(
+            |_, from, to| from.as_millis() == 1000 && to.as_millis() == 9000,
+            None,
+        )
+        .unwrap();
+        assert_eq!(wal.segments.len(), 0);
+    }
+}